@@ -1,59 +1,73 @@
-use chess::{board::*, multi_thread_eval};
+use chess::{
+    board::*, multi_thread_eval, PositionMoves, SearchConfig, Searcher, Square, MAX_PIECE_MOVES,
+};
 use criterion::{criterion_group, criterion_main, Criterion};
 use fnv::FnvHashSet;
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     let board = Board::example();
     let other_board = Board::new();
-    let mut moves_list = [None; 28];
-    let mut all_pieces_moves_list = [None; 16];
+    let mut moves_list = [None; MAX_PIECE_MOVES];
+    let mut all_pieces_moves_list = PositionMoves::new();
 
-    c.bench_function("instantiate_board", |b| b.iter(|| Board::new()));
+    c.bench_function("instantiate_board", |b| b.iter(Board::new));
     c.bench_function("pawn", |b| {
         b.iter(|| {
-            board.board[9]
-                .unwrap()
-                .get_moves(&board, 9, &mut moves_list)
+            board.piece_at(Square::new(9).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(9).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("knight", |b| {
         b.iter(|| {
-            board.board[19]
-                .unwrap()
-                .get_moves(&board, 19, &mut moves_list)
+            board.piece_at(Square::new(19).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(19).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("bishop", |b| {
         b.iter(|| {
-            board.board[36]
-                .unwrap()
-                .get_moves(&board, 36, &mut moves_list)
+            board.piece_at(Square::new(36).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(36).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("rook", |b| {
         b.iter(|| {
-            board.board[18]
-                .unwrap()
-                .get_moves(&board, 18, &mut moves_list)
+            board.piece_at(Square::new(18).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(18).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("queen", |b| {
         b.iter(|| {
-            board.board[11]
-                .unwrap()
-                .get_moves(&board, 11, &mut moves_list)
+            board.piece_at(Square::new(11).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(11).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("king", |b| {
         b.iter(|| {
-            board.board[17]
-                .unwrap()
-                .get_moves(&board, 17, &mut moves_list)
+            board.piece_at(Square::new(17).unwrap()).unwrap().get_moves(
+                &board,
+                Square::new(17).unwrap(),
+                &mut moves_list,
+            )
         })
     });
     c.bench_function("calculate_moves white", |b| {
         b.iter(|| {
-            other_board.generate_moves(
+            other_board.generate_moves_for(
                 chess::Color::White,
                 &mut moves_list,
                 &mut all_pieces_moves_list,
@@ -62,7 +76,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
     });
     c.bench_function("calculate_moves_black", |b| {
         b.iter(|| {
-            other_board.generate_moves(
+            other_board.generate_moves_for(
                 chess::Color::Black,
                 &mut moves_list,
                 &mut all_pieces_moves_list,
@@ -70,54 +84,22 @@ pub fn criterion_benchmark(c: &mut Criterion) {
         })
     });
     c.bench_function("one_move_into_the_future", |b| {
-        b.iter(|| {
-            multi_thread_eval(
-                &other_board,
-                1,
-                chess::Color::White,
-                &mut FnvHashSet::default(),
-            )
-        })
+        b.iter(|| multi_thread_eval(&other_board, 1, &mut FnvHashSet::default()))
     });
     c.bench_function("two_moves_into_the_future", |b| {
-        b.iter(|| {
-            multi_thread_eval(
-                &other_board,
-                2,
-                chess::Color::White,
-                &mut FnvHashSet::default(),
-            )
-        })
+        b.iter(|| multi_thread_eval(&other_board, 2, &mut FnvHashSet::default()))
     });
     c.bench_function("three_moves_into_the_future", |b| {
-        b.iter(|| {
-            multi_thread_eval(
-                &other_board,
-                3,
-                chess::Color::White,
-                &mut FnvHashSet::default(),
-            )
-        })
+        b.iter(|| multi_thread_eval(&other_board, 3, &mut FnvHashSet::default()))
     });
     c.bench_function("four_moves_into_the_future", |b| {
-        b.iter(|| {
-            multi_thread_eval(
-                &other_board,
-                4,
-                chess::Color::White,
-                &mut FnvHashSet::default(),
-            )
-        })
+        b.iter(|| multi_thread_eval(&other_board, 4, &mut FnvHashSet::default()))
     });
     c.bench_function("five_moves_into_the_future", |b| {
-        b.iter(|| {
-            multi_thread_eval(
-                &other_board,
-                5,
-                chess::Color::White,
-                &mut FnvHashSet::default(),
-            )
-        })
+        b.iter(|| multi_thread_eval(&other_board, 5, &mut FnvHashSet::default()))
+    });
+    c.bench_function("search_depth_five", |b| {
+        b.iter(|| Searcher::new(SearchConfig::default(), 1).search(&other_board, 5))
     });
 }
 
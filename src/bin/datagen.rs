@@ -0,0 +1,97 @@
+use chess::book::OpeningBook;
+use chess::datagen::{self, DatagenConfig, GameGenerator};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::mpsc;
+use std::{env, process, thread};
+
+// datagen <output> <games> [threads] [nodes] [seed] [book] [random plies]
+// Plays games of the engine against itself and writes their quiet positions with the search
+// score and the game result. An output name ending in ".bin" gets the packed format of
+// chess::datagen, anything else one "FEN | score | result" line per position. Every game is
+// played from a seed of its own and the games are written in order, so the same arguments
+// always give the same file, whatever the number of threads. The book is a Polyglot file, or
+// "-" for random openings only.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: datagen <output> <games> [threads] [nodes] [seed] [book] [random plies]");
+        process::exit(1);
+    }
+    let number = |index: usize, default: u64| -> u64 {
+        args.get(index).map_or(default, |arg| {
+            arg.parse().unwrap_or_else(|_| {
+                eprintln!("not a number: {arg}");
+                process::exit(1);
+            })
+        })
+    };
+    let games = number(2, 0);
+    let threads = number(3, 1).max(1);
+    let mut config = DatagenConfig::default();
+    config.nodes = number(4, config.nodes);
+    let seed = number(5, 1);
+    let book = args.get(6).filter(|path| *path != "-").map(|path| {
+        OpeningBook::open(path).unwrap_or_else(|error| {
+            eprintln!("can't open {path}: {error}");
+            process::exit(1);
+        })
+    });
+    config.random_plies = number(7, config.random_plies as u64) as usize;
+
+    let path = &args[1];
+    let packed = path.ends_with(".bin");
+    let mut output = BufWriter::new(File::create(path).unwrap_or_else(|error| {
+        eprintln!("can't create {path}: {error}");
+        process::exit(1);
+    }));
+
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for thread_index in 0..threads {
+            let sender = sender.clone();
+            let book = book.clone();
+            scope.spawn(move || {
+                let mut generator = GameGenerator::new(config, book);
+                for game in (thread_index..games).step_by(threads as usize) {
+                    let played = generator.play(datagen::game_seed(seed, game));
+                    if sender.send((game, played)).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Games finish out of order, so hold on to them until it's their turn.
+        let mut waiting = BTreeMap::new();
+        let mut next_game = 0;
+        let mut positions = 0;
+        for (game, played) in receiver {
+            waiting.insert(game, played);
+            while let Some((game_positions, result)) = waiting.remove(&next_game) {
+                for position in &game_positions {
+                    let written = if packed {
+                        output.write_all(&datagen::pack(position, result))
+                    } else {
+                        writeln!(output, "{}", datagen::text_line(position, result))
+                    };
+                    if let Err(error) = written {
+                        eprintln!("can't write {path}: {error}");
+                        process::exit(1);
+                    }
+                }
+                positions += game_positions.len();
+                next_game += 1;
+                if next_game % 10 == 0 || next_game == games {
+                    println!("{next_game}/{games} games, {positions} positions");
+                }
+            }
+        }
+    });
+    if let Err(error) = output.flush() {
+        eprintln!("can't write {path}: {error}");
+        process::exit(1);
+    }
+}
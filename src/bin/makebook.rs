@@ -0,0 +1,26 @@
+use chess::book::BookWriter;
+use std::{env, fs, process};
+
+// makebook <games.pgn> <book.bin> [min frequency] [max ply]
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: makebook <games.pgn> <book.bin> [min frequency] [max ply]");
+        process::exit(1);
+    }
+    let min_frequency = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(1);
+    let max_ply = args.get(4).and_then(|arg| arg.parse().ok()).unwrap_or(20);
+
+    let pgn = fs::read_to_string(&args[1]).unwrap_or_else(|error| {
+        eprintln!("can't read {}: {error}", args[1]);
+        process::exit(1);
+    });
+    let mut writer = BookWriter::new(min_frequency, max_ply);
+    let games = writer.add_pgn(&pgn);
+    let book = writer.build();
+    if let Err(error) = book.save(&args[2]) {
+        eprintln!("can't write {}: {error}", args[2]);
+        process::exit(1);
+    }
+    println!("{games} games, {} book entries", book.len());
+}
@@ -0,0 +1,253 @@
+use chess::sprt::{MatchScore, Sprt, SprtStatus};
+use chess::syzygy::Tablebases;
+use chess::tournament::{self, Adjudication, MatchLimits, PlayerSpec};
+use chess::Color;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{env, process, thread};
+
+const USAGE: &str = "usage: match --engine name=<name> [cmd=<path>] [option.<name>=<value>]... \
+--engine ... [--openings <file>] [--games <n>] [--concurrency <n>] [--nodes <n>] \
+[--depth <n>] [--movetime <ms>] [--tc <seconds>+<increment>] \
+[--sprt [elo0=<elo>] [elo1=<elo>] [alpha=<p>] [beta=<p>]] [--syzygy <path>] \
+[--resign <score> <plies>] [--draw <score> <plies> <after ply>] [--max-plies <n>]";
+
+// match --engine name=new option.EvalFile=tuned.txt --engine name=base --openings book.epd
+//       --nodes 5000 --concurrency 4 --sprt elo0=0 elo1=5
+// Plays the first engine against the second. An engine with a cmd is an external UCI engine,
+// one without is this engine searched in-process, with the options of
+// chess::tournament::InternalPlayer. Every opening is played twice, with the colors reversed,
+// and the results are given for the first engine. With --sprt the match stops as soon as the
+// test is decided, and goes on until then unless --games says otherwise.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut engines = Vec::new();
+    let mut openings_path = None;
+    let mut games: Option<u64> = None;
+    let mut concurrency = 1;
+    let mut limits = MatchLimits::default();
+    let mut sprt = None;
+    let mut syzygy_path = None;
+    let mut adjudication = Adjudication::default();
+
+    let mut index = 0;
+    while index < args.len() {
+        let flag = args[index].as_str();
+        // The words after the flag, up to the next one.
+        let values: Vec<&str> = args[index + 1..]
+            .iter()
+            .take_while(|arg| !arg.starts_with("--"))
+            .map(|arg| arg.as_str())
+            .collect();
+        index += 1 + values.len();
+        match flag {
+            "--engine" => engines.push(parse_engine(&values)),
+            "--openings" => openings_path = Some(single(flag, &values).to_string()),
+            "--games" => games = Some(number(flag, single(flag, &values))),
+            "--concurrency" => concurrency = number::<u64>(flag, single(flag, &values)).max(1),
+            "--nodes" => limits.nodes = Some(number(flag, single(flag, &values))),
+            "--depth" => limits.depth = Some(number(flag, single(flag, &values))),
+            "--movetime" => {
+                limits.move_time = Some(Duration::from_millis(number(flag, single(flag, &values))))
+            }
+            "--tc" => {
+                let tc = single(flag, &values);
+                let (base, increment) = tc.split_once('+').unwrap_or((tc, "0"));
+                limits.clock = Some((
+                    Duration::from_secs_f64(number(flag, base)),
+                    Duration::from_secs_f64(number(flag, increment)),
+                ));
+            }
+            "--sprt" => sprt = Some(parse_sprt(&values)),
+            "--syzygy" => syzygy_path = Some(single(flag, &values).to_string()),
+            "--resign" => match values[..] {
+                [score, plies] => {
+                    adjudication.resign_score = Some(number(flag, score));
+                    adjudication.resign_plies = number(flag, plies);
+                }
+                _ => fail(&format!("{flag} takes a score and a number of plies")),
+            },
+            "--draw" => match values[..] {
+                [score, plies, after] => {
+                    adjudication.draw_score = Some(number(flag, score));
+                    adjudication.draw_plies = number(flag, plies);
+                    adjudication.draw_after_ply = number(flag, after);
+                }
+                _ => fail(&format!(
+                    "{flag} takes a score, a number of plies and the ply it starts at"
+                )),
+            },
+            "--max-plies" => adjudication.max_plies = number(flag, single(flag, &values)),
+            _ => fail(&format!("unknown argument {flag}\n{USAGE}")),
+        }
+    }
+    let [first, second] = &engines[..] else {
+        fail(USAGE);
+    };
+    if limits == MatchLimits::default() {
+        limits.nodes = Some(10000);
+    }
+    let openings = match &openings_path {
+        Some(path) => tournament::load_openings(path)
+            .unwrap_or_else(|error| fail(&format!("can't read {path}: {error}"))),
+        None => vec!["rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string()],
+    };
+    if openings.is_empty() {
+        fail("no openings found");
+    }
+    let pairs = match games {
+        Some(games) => games.div_ceil(2),
+        None if sprt.is_some() => u64::MAX,
+        None => 50,
+    };
+    if let Some(path) = &syzygy_path {
+        if let Err(error) = Tablebases::open(path) {
+            fail(&format!("can't open tablebases {path}: {error}"));
+        }
+    }
+
+    let next_pair = AtomicU64::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..concurrency {
+            let sender = sender.clone();
+            let (next_pair, stop, openings) = (&next_pair, &stop, &openings);
+            let syzygy_path = &syzygy_path;
+            scope.spawn(move || {
+                let start = |spec: &PlayerSpec| {
+                    spec.start().unwrap_or_else(|error| {
+                        fail(&format!("can't start {}: {error}", spec.name))
+                    })
+                };
+                let mut players = [start(first), start(second)];
+                let mut tablebases = syzygy_path
+                    .as_ref()
+                    .and_then(|path| Tablebases::open(path).ok());
+                loop {
+                    let pair = next_pair.fetch_add(1, Ordering::Relaxed);
+                    if pair >= pairs || stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    let opening = &openings[(pair % openings.len() as u64) as usize];
+                    for first_is_white in [true, false] {
+                        let [a, b] = &mut players;
+                        let (white, black) = if first_is_white { (a, b) } else { (b, a) };
+                        let outcome = tournament::play_game(
+                            [white.as_mut(), black.as_mut()],
+                            opening,
+                            &limits,
+                            &adjudication,
+                            tablebases.as_mut(),
+                        );
+                        if sender.send((first_is_white, outcome)).is_err() {
+                            return;
+                        }
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        let mut score = MatchScore::default();
+        for (first_is_white, outcome) in receiver {
+            let (white, black) = if first_is_white {
+                (&first.name, &second.name)
+            } else {
+                (&second.name, &first.name)
+            };
+            let points = outcome.result.points_for(if first_is_white {
+                Color::White
+            } else {
+                Color::Black
+            });
+            match points {
+                2 => score.wins += 1,
+                1 => score.draws += 1,
+                _ => score.losses += 1,
+            }
+            println!(
+                "game {}: {white} - {black} {} ({}, {} plies)",
+                score.games(),
+                outcome.result.to_pgn(),
+                outcome.reason,
+                outcome.plies
+            );
+            println!("{} vs {}: {score}", first.name, second.name);
+            if let Some(sprt) = &sprt {
+                let (lower, upper) = sprt.bounds();
+                println!(
+                    "SPRT [{}, {}]: LLR {:.2} ({lower:.2}, {upper:.2})",
+                    sprt.elo0,
+                    sprt.elo1,
+                    sprt.llr(&score)
+                );
+                let verdict = match sprt.status(&score) {
+                    SprtStatus::Continue => continue,
+                    SprtStatus::AcceptH0 => "H0 accepted",
+                    SprtStatus::AcceptH1 => "H1 accepted",
+                };
+                println!("{verdict}");
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+        }
+    });
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
+
+fn single<'a>(flag: &str, values: &[&'a str]) -> &'a str {
+    match values {
+        [value] => value,
+        _ => fail(&format!("{flag} takes one value")),
+    }
+}
+
+fn number<T: std::str::FromStr>(flag: &str, value: &str) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| fail(&format!("{flag}: not a number: {value}")))
+}
+
+// name=<name> [cmd=<path>] [option.<name>=<value>]...
+fn parse_engine(values: &[&str]) -> PlayerSpec {
+    let mut spec = PlayerSpec::default();
+    for value in values {
+        match value.split_once('=') {
+            Some(("name", name)) => spec.name = name.to_string(),
+            Some(("cmd", command)) => spec.command = Some(command.to_string()),
+            Some((key, option_value)) if key.starts_with("option.") => spec
+                .options
+                .push((key["option.".len()..].to_string(), option_value.to_string())),
+            _ => fail(&format!("--engine: don't know what {value} is")),
+        }
+    }
+    if spec.name.is_empty() {
+        spec.name = spec.command.clone().unwrap_or_else(|| "chess".to_string());
+    }
+    spec
+}
+
+// [elo0=<elo>] [elo1=<elo>] [alpha=<p>] [beta=<p>]
+fn parse_sprt(values: &[&str]) -> Sprt {
+    let mut sprt = Sprt::default();
+    for value in values {
+        let (key, number_text) = value
+            .split_once('=')
+            .unwrap_or_else(|| fail(&format!("--sprt: don't know what {value} is")));
+        let field = match key {
+            "elo0" => &mut sprt.elo0,
+            "elo1" => &mut sprt.elo1,
+            "alpha" => &mut sprt.alpha,
+            "beta" => &mut sprt.beta,
+            _ => fail(&format!("--sprt: unknown parameter {key}")),
+        };
+        *field = number("--sprt", number_text);
+    }
+    sprt
+}
@@ -0,0 +1,67 @@
+use chess::nnue::{self, Network, NnueStack};
+use chess::Board;
+use std::{env, process};
+
+// nnuecheck [network] [positions] [seed]
+// Plays random games and checks, position by position, that the incrementally updated
+// accumulators give exactly the evaluation of the plain reference implementation. Uses a
+// random network when none is given.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let network = match args.get(1).filter(|path| *path != "random") {
+        Some(path) => Network::load(path).unwrap_or_else(|error| {
+            eprintln!("can't load {path}: {error}");
+            process::exit(1);
+        }),
+        None => Network::random(256, 1),
+    };
+    let positions: usize = args
+        .get(2)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(10000);
+    let mut seed: u64 = args.get(3).and_then(|arg| arg.parse().ok()).unwrap_or(1);
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        seed
+    };
+
+    const MAX_GAME_LENGTH: usize = 200;
+    let mut stack = NnueStack::new(&network, MAX_GAME_LENGTH + 1);
+    let mut checked = 0;
+    let mut mismatches = 0;
+    while checked < positions {
+        let mut board = Board::new();
+        stack.set_root(&network, &board);
+        for ply in 0..=MAX_GAME_LENGTH {
+            // Skip some plies, so the accumulators also get brought up to date several
+            // moves at a time.
+            if random() % 3 != 0 {
+                let incremental = stack.evaluate(&network, ply, board.side_to_move());
+                let reference = nnue::reference_evaluate(&network, &board);
+                checked += 1;
+                if incremental != reference {
+                    mismatches += 1;
+                    println!(
+                        "{}: incremental {incremental}, reference {reference}",
+                        board.to_fen()
+                    );
+                }
+            }
+            let moves = board.legal_moves();
+            if moves.is_empty() || checked >= positions {
+                break;
+            }
+            let (start, a_move) = moves[random() as usize % moves.len()];
+            if ply < MAX_GAME_LENGTH {
+                stack.push(ply + 1, board.piece_changes(start, a_move));
+            }
+            board = board.make_move(start, a_move);
+        }
+    }
+    println!("{checked} positions, {mismatches} mismatches");
+    if mismatches > 0 {
+        process::exit(1);
+    }
+}
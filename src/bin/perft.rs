@@ -0,0 +1,262 @@
+use chess::fairy::PieceSet;
+use chess::geometry::{ShapedPosition, ShapedVariant};
+use chess::variant::{Variant, VariantPosition};
+use std::time::Instant;
+use std::{env, process};
+
+// Published counts, from the perft suites of python-chess and Fairy-Stockfish. Each entry is
+// the variant, the position and the counts from depth 1 on.
+const PUBLISHED: [(Variant, &str, &[u64]); 15] = [
+    (
+        Variant::Standard,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4865609],
+    ),
+    (
+        Variant::ThreeCheck,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+        &[20, 400, 8902, 197281, 4865609],
+    ),
+    (
+        Variant::ThreeCheck,
+        "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 0 1",
+        &[26, 562, 13410],
+    ),
+    (
+        Variant::KingOfTheHill,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4865609],
+    ),
+    (
+        Variant::KingOfTheHill,
+        "rnbq1bnr/ppp2ppp/3k4/4p2Q/3PK3/8/PPP2PPP/RNB2BNR b - - 0 1",
+        &[0],
+    ),
+    (
+        Variant::Antichess,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+        &[20, 400, 8067, 153299, 2732672],
+    ),
+    (
+        Variant::Atomic,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        &[20, 400, 8902, 197326, 4864979],
+    ),
+    (
+        Variant::Atomic,
+        "rn2kb1r/1pp1p2p/p2q1pp1/3P4/2P3b1/4PN2/PP3PPP/R2QKB1R b KQkq - 0 1",
+        &[40, 1238, 45237, 1434825],
+    ),
+    (
+        Variant::Atomic,
+        "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+        &[28, 833, 23353, 714499],
+    ),
+    (
+        Variant::Horde,
+        "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+        &[8, 128, 1274, 23310, 265223],
+    ),
+    (
+        Variant::Horde,
+        "4k3/pp4q1/3P2p1/8/P3PP2/PPP2r2/PPP5/PPPP4 b - - 0 1",
+        &[30, 241, 6633, 56539],
+    ),
+    (
+        Variant::RacingKings,
+        "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+        &[21, 421, 11264, 296242, 9472927],
+    ),
+    (
+        Variant::Crazyhouse,
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        &[20, 400, 8902, 197281, 4888832],
+    ),
+    (
+        Variant::Crazyhouse,
+        "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+        &[42, 1347, 58057, 2083382],
+    ),
+    (
+        Variant::Crazyhouse,
+        "4k3/1Q~6/8/8/4b3/8/Kpp5/8/ b - - 0 1",
+        &[20, 360],
+    ),
+];
+
+// The same for variants on boards other than 8x8.
+const SHAPED_PUBLISHED: [(ShapedVariant, &str, &[u64]); 4] = [
+    (
+        ShapedVariant::Capablanca,
+        "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+        &[28, 784, 25228, 805128, 28741319],
+    ),
+    (
+        ShapedVariant::Gothic,
+        "rnbqckabnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNBQCKABNR w KQkq - 0 1",
+        &[28, 784, 25283, 808984],
+    ),
+    (
+        ShapedVariant::LosAlamos,
+        "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1",
+        &[10, 100, 1212, 14332, 191846],
+    ),
+    (
+        ShapedVariant::Gardner,
+        "rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1",
+        &[7, 53, 506, 4775, 52512],
+    ),
+];
+
+const USAGE: &str = "usage: perft <variant> <depth> [fen]\n       perft check [max depth]";
+
+// perft atomic 4
+// perft 3check 3 r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 1+1 0 1
+// perft capablanca 3
+// perft check 4
+// The first form prints the count under every root move and the total. The second goes
+// through the published counts up to the depth (4 by default) and fails if any differ.
+// The builtin fairy pieces come with the 8x8 variants, so their letters can be used in the FEN.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        Some("check") => {
+            let max_depth = args.get(1).map_or(4, |depth| number(depth));
+            if !check(max_depth) {
+                process::exit(1);
+            }
+        }
+        Some(name) => {
+            let depth = number(args.get(1).unwrap_or_else(|| fail(USAGE)));
+            let fen = (args.len() > 2).then(|| args[2..].join(" "));
+            if let Some(variant) = Variant::from_name(name) {
+                let fen = fen.as_deref().unwrap_or(variant.start_fen());
+                let position =
+                    VariantPosition::from_fen_with_pieces(variant, fen, PieceSet::builtin())
+                        .unwrap_or_else(|error| fail(&error.to_string()));
+                divide(
+                    position
+                        .legal_moves()
+                        .into_iter()
+                        .map(|(start_square, a_move)| {
+                            let nodes = position
+                                .make_move(start_square, a_move)
+                                .perft(depth.saturating_sub(1));
+                            (a_move.to_uci(start_square, position.side_to_move()), nodes)
+                        }),
+                );
+            } else if let Some(variant) = ShapedVariant::from_name(name) {
+                let fen = fen.as_deref().unwrap_or(variant.start_fen());
+                let position = ShapedPosition::from_fen(variant, fen)
+                    .unwrap_or_else(|error| fail(&error.to_string()));
+                divide(
+                    position
+                        .legal_moves()
+                        .into_iter()
+                        .map(|(start_square, a_move)| {
+                            let nodes = position
+                                .make_move(start_square, a_move)
+                                .perft(depth.saturating_sub(1));
+                            (position.move_to_uci(start_square, a_move), nodes)
+                        }),
+                );
+            } else {
+                fail(&format!("unknown variant {name}\n{USAGE}"));
+            }
+        }
+        None => fail(USAGE),
+    }
+}
+
+// Prints the count under each root move as it comes, then the total.
+fn divide(root_moves: impl Iterator<Item = (String, u64)>) {
+    let start = Instant::now();
+    let mut total = 0;
+    for (uci, nodes) in root_moves {
+        println!("{uci}: {nodes}");
+        total += nodes;
+    }
+    println!(
+        "\nNodes searched: {total} ({:.2}s)",
+        start.elapsed().as_secs_f64()
+    );
+}
+
+fn check(max_depth: u8) -> bool {
+    let mut all_match = true;
+    for (variant, fen, counts) in PUBLISHED {
+        let position =
+            VariantPosition::from_fen(variant, fen).expect("published positions are valid");
+        all_match &= check_counts(variant.name(), fen, counts, max_depth, |depth| {
+            position.perft(depth)
+        });
+    }
+    for (variant, fen, counts) in SHAPED_PUBLISHED {
+        let position =
+            ShapedPosition::from_fen(variant, fen).expect("published positions are valid");
+        all_match &= check_counts(variant.name(), fen, counts, max_depth, |depth| {
+            position.perft(depth)
+        });
+    }
+    all_match
+}
+
+fn check_counts(
+    name: &str,
+    fen: &str,
+    counts: &[u64],
+    max_depth: u8,
+    perft: impl Fn(u8) -> u64,
+) -> bool {
+    let mut all_match = true;
+    for (depth, expected) in (1..=max_depth).zip(counts.iter()) {
+        let nodes = perft(depth);
+        let verdict = if nodes == *expected { "ok" } else { "WRONG" };
+        println!("{name:<14} {fen} depth {depth}: {nodes} (published {expected}) {verdict}");
+        all_match &= nodes == *expected;
+    }
+    all_match
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}");
+    process::exit(1);
+}
+
+fn number(text: &str) -> u8 {
+    text.parse()
+        .unwrap_or_else(|_| fail(&format!("not a depth: {text}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Deep enough to go through every kind of move, shallow enough for unoptimised builds.
+    const MAX_NODES: u64 = 300_000;
+
+    fn assert_counts(name: &str, fen: &str, counts: &[u64], perft: impl Fn(u8) -> u64) {
+        for (depth, expected) in (1..).zip(counts) {
+            if *expected > MAX_NODES {
+                break;
+            }
+            assert_eq!(perft(depth), *expected, "{name} {fen} depth {depth}");
+        }
+    }
+
+    #[test]
+    fn published_counts() {
+        for (variant, fen, counts) in PUBLISHED {
+            let position = VariantPosition::from_fen(variant, fen).unwrap();
+            assert_counts(variant.name(), fen, counts, |depth| position.perft(depth));
+        }
+    }
+
+    #[test]
+    fn shaped_published_counts() {
+        for (variant, fen, counts) in SHAPED_PUBLISHED {
+            let position = ShapedPosition::from_fen(variant, fen).unwrap();
+            assert_counts(variant.name(), fen, counts, |depth| position.perft(depth));
+        }
+    }
+}
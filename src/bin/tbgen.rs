@@ -0,0 +1,35 @@
+use chess::tbgen;
+use std::path::Path;
+use std::{env, process};
+
+// tbgen <directory> <table>...
+// Tables are generated in the order given, and the ones reached by captures and promotions
+// are read from the directory, so list them from fewer pieces to more:
+// tbgen fixtures/syzygy KNvK KBvK KRvK KQvK KPvK KBNvK
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: tbgen <directory> <table>...");
+        process::exit(1);
+    }
+    let directory = Path::new(&args[1]);
+    for name in &args[2..] {
+        match tbgen::generate(name, directory) {
+            Ok(stats) => println!(
+                "{name}: {} positions ({} wins, {} draws, {} losses), longest dtz {}, \
+                 {} + {} bytes",
+                stats.positions,
+                stats.wins,
+                stats.draws,
+                stats.losses,
+                stats.max_dtz,
+                stats.wdl_bytes,
+                stats.dtz_bytes
+            ),
+            Err(error) => {
+                eprintln!("can't generate {name}: {error}");
+                process::exit(1);
+            }
+        }
+    }
+}
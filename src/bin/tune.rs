@@ -0,0 +1,163 @@
+use chess::eval;
+use chess::params::EvalParams;
+use chess::{Board, Color};
+use std::{env, fs, process, thread};
+
+// tune <positions> <output params> [input params] [max passes]
+// Texel tuning: finds the evaluation weights that best predict the results of the games the
+// positions come from. Each line of the positions file is a quiet position in FEN followed by
+// the result for white, as "1-0", "0-1" or "1/2-1/2" (maybe quoted, as in EPD files) or as
+// 1.0, 0.5 or 0.0 (maybe in brackets). The weights are written out after every pass.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 3 {
+        eprintln!("usage: tune <positions> <output params> [input params] [max passes]");
+        process::exit(1);
+    }
+    let text = fs::read_to_string(&args[1]).unwrap_or_else(|error| {
+        eprintln!("can't read {}: {error}", args[1]);
+        process::exit(1);
+    });
+    let positions: Vec<(Board, f64)> = text.lines().filter_map(parse_line).collect();
+    if positions.is_empty() {
+        eprintln!("no positions in {}", args[1]);
+        process::exit(1);
+    }
+    let mut params = match args.get(3) {
+        Some(path) => EvalParams::load(path).unwrap_or_else(|error| {
+            eprintln!("can't load {path}: {error}");
+            process::exit(1);
+        }),
+        None => EvalParams::default(),
+    };
+    let max_passes = args
+        .get(4)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(usize::MAX);
+
+    let k = find_k(&positions, &params);
+    let mut best = error(&positions, &params, k);
+    println!("{} positions, K = {k:.3}, error {best:.6}", positions.len());
+
+    // Local search: nudge each weight by one either way, and keep the change if the error
+    // goes down. Stop when a whole pass changes nothing.
+    let mut values = params.values();
+    for pass in 1..=max_passes {
+        let mut improved = false;
+        for index in 0..values.len() {
+            for delta in [1, -1] {
+                values[index] += delta;
+                params.set_values(&values);
+                let new_error = error(&positions, &params, k);
+                if new_error < best {
+                    best = new_error;
+                    improved = true;
+                    break;
+                }
+                values[index] -= delta;
+            }
+        }
+        params.set_values(&values);
+        if let Err(error) = params.save(&args[2]) {
+            eprintln!("can't write {}: {error}", args[2]);
+            process::exit(1);
+        }
+        println!("pass {pass}: error {best:.6}");
+        if !improved {
+            break;
+        }
+    }
+}
+
+// A position and the result of its game for white, or None if the line isn't one.
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let result = if line.contains("1/2-1/2") {
+        0.5
+    } else if line.contains("1-0") {
+        1.0
+    } else if line.contains("0-1") {
+        0.0
+    } else {
+        let last = line.split_whitespace().last()?;
+        last.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';'))
+            .parse()
+            .ok()?
+    };
+    // The placement, the side to move, castling and en passant are all that matter here.
+    let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    let board = Board::from_fen(&format!("{} 0 1", fields.join(" ")));
+    Some((board, result))
+}
+
+// Expected result for white of a position with this evaluation.
+fn sigmoid(score: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score as f64 / 400.0))
+}
+
+// Mean squared difference between the results and what the evaluation predicts, worked out on
+// every core.
+fn error(positions: &[(Board, f64)], params: &EvalParams, k: f64) -> f64 {
+    let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+    let chunk_size = positions.len().div_ceil(threads);
+    let total: f64 = thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|(board, result)| {
+                            // The evaluation is for the side to move, the results for white.
+                            let score = eval::evaluate_with(board, params);
+                            let score = if let Color::White = board.side_to_move() {
+                                score
+                            } else {
+                                -score
+                            };
+                            (result - sigmoid(score, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+    total / positions.len() as f64
+}
+
+// Upper limit for K: a handful of positions that the evaluation gets all right would push it
+// up forever.
+const MAX_K: f64 = 10.0;
+
+// The scaling constant of the sigmoid that fits the current weights best, so the tuning
+// doesn't just stretch every weight.
+fn find_k(positions: &[(Board, f64)], params: &EvalParams) -> f64 {
+    let mut best_k = 1.0;
+    let mut best = error(positions, params, best_k);
+    let mut step = 0.5;
+    while step > 0.001 {
+        let mut improved = true;
+        while improved {
+            improved = false;
+            for candidate in [best_k - step, best_k + step] {
+                if candidate <= 0.0 || candidate > MAX_K {
+                    continue;
+                }
+                let candidate_error = error(positions, params, candidate);
+                if candidate_error < best {
+                    best = candidate_error;
+                    best_k = candidate;
+                    improved = true;
+                }
+            }
+        }
+        step /= 2.0;
+    }
+    best_k
+}
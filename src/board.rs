@@ -1,23 +1,35 @@
-use crate::{down, up, Move, OnePieceMoves};
+use crate::fairy::{self, PieceSet};
+use crate::{BoardMove, ChessError, Move, OnePieceMoves, PositionMoves, Square, MAX_PIECE_MOVES};
 
-use super::{Bishop, Color, King, Knight, Pawn, Piece, Queen, Rook};
+use super::{Color, King, Piece, PieceKind};
 use std::fmt;
 use std::hash::Hash;
+use std::sync::Arc;
 
 // The board. Is wrapped in a struct in order to implement methods.
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
+// The fields stay inside the crate, so they can't go out of step: read them through the
+// accessors and set positions up with a BoardBuilder.
 pub struct Board {
-    pub board: [Option<Piece>; 64],
-    pub can_en_passant: CanEnPassant,
-    pub can_castle: CanCastle,
-    pub white_king_pos: u8,
-    pub black_king_pos: u8,
-    pub is_check: bool,
+    pub(crate) board: [Option<Piece>; 64],
+    pub(crate) can_en_passant: CanEnPassant,
+    pub(crate) can_castle: CanCastle,
+    pub(crate) white_king_pos: Square,
+    pub(crate) black_king_pos: Square,
+    pub(crate) is_check: bool,
+    // Whose turn it is. make_move hands it to the other side.
+    pub(crate) side_to_move: Color,
+    // Plies since the last capture or pawn move, for the fifty-move rule.
+    pub(crate) halfmove_clock: u8,
+    // Pieces in hand, for drop variants like crazyhouse. None in standard chess.
+    pub(crate) pockets: Option<Pockets>,
+    // The fairy pieces the position plays with, None for standard chess.
+    pub(crate) fairy: Option<Arc<PieceSet>>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub enum CanEnPassant {
-    Yes(u8),
+    Yes(Square),
     No,
 }
 
@@ -54,135 +66,179 @@ impl CanCastle {
     }
 }
 
+// Pieces in hand of each side, and which pieces on the board were promoted: those go back to
+// the hand of whoever takes them as pawns.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Hash, Eq)]
+pub struct Pockets {
+    // Count by color (Color::index) and kind, in the order of Pockets::KINDS.
+    pieces: [[u8; 5]; 2],
+    // One bit per square holding a promoted piece.
+    pub promoted: u64,
+}
+
+impl Pockets {
+    // The kinds a hand can hold. Kings and fairy pieces never go to the hand.
+    pub const KINDS: [PieceKind; 5] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+    ];
+
+    // Where the kind is counted, if a hand can hold it.
+    fn slot(kind: PieceKind) -> Option<usize> {
+        Self::KINDS.iter().position(|held| *held == kind)
+    }
+
+    pub fn can_hold(kind: PieceKind) -> bool {
+        Self::slot(kind).is_some()
+    }
+
+    // How many pieces of the kind the side has in hand, 0 for kinds a hand can't hold.
+    pub fn count(&self, color: Color, kind: PieceKind) -> u8 {
+        Self::slot(kind).map_or(0, |slot| self.pieces[color.index()][slot])
+    }
+
+    // Put a piece in the hand. A hand can't hold more than 255 pieces of a kind, any more are
+    // lost, and kinds a hand can't hold are left out.
+    pub fn add(&mut self, color: Color, kind: PieceKind) {
+        if let Some(slot) = Self::slot(kind) {
+            let count = &mut self.pieces[color.index()][slot];
+            *count = count.saturating_add(1);
+        }
+    }
+
+    // Take a piece out of the hand, false if there's none of the kind.
+    pub fn remove(&mut self, color: Color, kind: PieceKind) -> bool {
+        let Some(slot) = Self::slot(kind) else {
+            return false;
+        };
+        let count = &mut self.pieces[color.index()][slot];
+        if *count == 0 {
+            return false;
+        }
+        *count -= 1;
+        true
+    }
+
+    pub fn is_promoted(&self, square: Square) -> bool {
+        self.promoted & 1 << square.index() != 0
+    }
+
+    // The hand as FEN writes it between brackets: white's pieces then black's, queens first.
+    pub fn to_fen(&self) -> String {
+        let mut text = String::new();
+        for color in [Color::White, Color::Black] {
+            for kind in Self::KINDS.into_iter().rev() {
+                let letter = Piece::new(kind, color).to_char();
+                for _ in 0..self.count(color, kind) {
+                    text.push(letter);
+                }
+            }
+        }
+        text
+    }
+}
+
+// The pieces a move takes off the board and puts on it, for evaluations that follow the
+// board incrementally. Castling moves two pieces, a capture takes one more off.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PieceChanges {
+    pub removed: [Option<(Piece, Square)>; 2],
+    pub added: [Option<(Piece, Square)>; 2],
+}
+
+// Something that doesn't add up in a board, as found by Board::validate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoardIssue {
+    // The side doesn't have exactly one king.
+    KingCount { color: Color, count: u8 },
+    // white_king_pos or black_king_pos isn't where the side's king stands.
+    KingPosition { color: Color, recorded: Square },
+    // A pawn on the first or last rank.
+    PawnOnBackRank(Square),
+    // A castling right without the king and the rook on their starting squares.
+    CastlingRight { color: Color, kingside: bool },
+    // The en passant pawn can't have just advanced two squares.
+    EnPassant(Square),
+    // The side that just moved left its king attacked.
+    OpponentInCheck(Color),
+    // More pieces than the side can have, even promoting every missing pawn.
+    PieceCount(Color),
+}
+
+impl fmt::Display for BoardIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |color: &Color| if color.is_white() { "white" } else { "black" };
+        match self {
+            BoardIssue::KingCount { color, count } => {
+                write!(f, "{} has {count} kings", name(color))
+            }
+            BoardIssue::KingPosition { color, recorded } => {
+                write!(f, "the {} king isn't on {recorded}", name(color))
+            }
+            BoardIssue::PawnOnBackRank(square) => write!(f, "pawn on {square}"),
+            BoardIssue::CastlingRight { color, kingside } => write!(
+                f,
+                "{} can't castle {}",
+                name(color),
+                if *kingside { "kingside" } else { "queenside" }
+            ),
+            BoardIssue::EnPassant(square) => {
+                write!(f, "the pawn on {square} can't be taken en passant")
+            }
+            BoardIssue::OpponentInCheck(color) => {
+                write!(f, "{} is in check but isn't to move", name(color))
+            }
+            BoardIssue::PieceCount(color) => write!(f, "{} has too many pieces", name(color)),
+        }
+    }
+}
+
 // functions that affect the board
 impl Board {
     // return an empty board
-    fn empty() -> Self {
+    pub(crate) fn empty() -> Self {
         Board {
             board: [None; 64],
             can_en_passant: CanEnPassant::No,
             can_castle: CanCastle::new(),
-            white_king_pos: 4,
-            black_king_pos: 60,
+            white_king_pos: Square::from_index(4),
+            black_king_pos: Square::from_index(60),
             is_check: false,
+            side_to_move: Color::White,
+            halfmove_clock: 0,
+            pockets: None,
+            fairy: None,
         }
     }
 
     // return a board in the starting chess position.
     pub fn new() -> Self {
         let mut board = Self::empty();
-        board.add_piece(
-            Piece::Rook(Rook {
-                color: Color::White,
-            }),
-            0,
-        );
-        board.add_piece(
-            Piece::Knight(Knight {
-                color: Color::White,
-            }),
-            1,
-        );
-        board.add_piece(
-            Piece::Bishop(Bishop {
-                color: Color::White,
-            }),
-            2,
-        );
-        board.add_piece(
-            Piece::Queen(Queen {
-                color: Color::White,
-            }),
-            3,
-        );
-        board.add_piece(
-            Piece::King(King {
-                color: Color::White,
-            }),
-            4,
-        );
-        board.add_piece(
-            Piece::Bishop(Bishop {
-                color: Color::White,
-            }),
-            5,
-        );
-        board.add_piece(
-            Piece::Knight(Knight {
-                color: Color::White,
-            }),
-            6,
-        );
-        board.add_piece(
-            Piece::Rook(Rook {
-                color: Color::White,
-            }),
-            7,
-        );
+        board.add_piece(Piece::new(PieceKind::Rook, Color::White), 0);
+        board.add_piece(Piece::new(PieceKind::Knight, Color::White), 1);
+        board.add_piece(Piece::new(PieceKind::Bishop, Color::White), 2);
+        board.add_piece(Piece::new(PieceKind::Queen, Color::White), 3);
+        board.add_piece(Piece::new(PieceKind::King, Color::White), 4);
+        board.add_piece(Piece::new(PieceKind::Bishop, Color::White), 5);
+        board.add_piece(Piece::new(PieceKind::Knight, Color::White), 6);
+        board.add_piece(Piece::new(PieceKind::Rook, Color::White), 7);
         for i in 8..=15 {
-            board.add_piece(
-                Piece::Pawn(Pawn {
-                    color: Color::White,
-                }),
-                i,
-            );
+            board.add_piece(Piece::new(PieceKind::Pawn, Color::White), i);
         }
 
-        board.add_piece(
-            Piece::Rook(Rook {
-                color: Color::Black,
-            }),
-            63,
-        );
-        board.add_piece(
-            Piece::Knight(Knight {
-                color: Color::Black,
-            }),
-            62,
-        );
-        board.add_piece(
-            Piece::Bishop(Bishop {
-                color: Color::Black,
-            }),
-            61,
-        );
-        board.add_piece(
-            Piece::Queen(Queen {
-                color: Color::Black,
-            }),
-            59,
-        );
-        board.add_piece(
-            Piece::King(King {
-                color: Color::Black,
-            }),
-            60,
-        );
-        board.add_piece(
-            Piece::Bishop(Bishop {
-                color: Color::Black,
-            }),
-            58,
-        );
-        board.add_piece(
-            Piece::Knight(Knight {
-                color: Color::Black,
-            }),
-            57,
-        );
-        board.add_piece(
-            Piece::Rook(Rook {
-                color: Color::Black,
-            }),
-            56,
-        );
+        board.add_piece(Piece::new(PieceKind::Rook, Color::Black), 63);
+        board.add_piece(Piece::new(PieceKind::Knight, Color::Black), 62);
+        board.add_piece(Piece::new(PieceKind::Bishop, Color::Black), 61);
+        board.add_piece(Piece::new(PieceKind::Queen, Color::Black), 59);
+        board.add_piece(Piece::new(PieceKind::King, Color::Black), 60);
+        board.add_piece(Piece::new(PieceKind::Bishop, Color::Black), 58);
+        board.add_piece(Piece::new(PieceKind::Knight, Color::Black), 57);
+        board.add_piece(Piece::new(PieceKind::Rook, Color::Black), 56);
         for i in 48..=55 {
-            board.add_piece(
-                Piece::Pawn(Pawn {
-                    color: Color::Black,
-                }),
-                i,
-            );
+            board.add_piece(Piece::new(PieceKind::Pawn, Color::Black), i);
         }
 
         board
@@ -191,219 +247,456 @@ impl Board {
     // example board with all piece types
     pub fn example() -> Self {
         let mut board = Self::empty();
-        board.black_king_pos = 17;
-        board.white_king_pos = 63;
+        board.black_king_pos = Square::from_index(17);
+        board.white_king_pos = Square::from_index(63);
 
-        board.add_piece(
-            Piece::Pawn(Pawn {
-                color: Color::White,
-            }),
-            9,
-        );
-        board.add_piece(
-            Piece::Rook(Rook {
-                color: Color::Black,
-            }),
-            18,
-        );
-        board.add_piece(
-            Piece::King(King {
-                color: Color::Black,
-            }),
-            17,
-        );
-        board.add_piece(
-            Piece::Knight(Knight {
-                color: Color::White,
-            }),
-            19,
-        );
-        board.add_piece(
-            Piece::King(King {
-                color: Color::White,
-            }),
-            63,
-        );
-        board.add_piece(
-            Piece::Queen(Queen {
-                color: Color::White,
-            }),
-            11,
-        );
+        board.add_piece(Piece::new(PieceKind::Pawn, Color::White), 9);
+        board.add_piece(Piece::new(PieceKind::Rook, Color::Black), 18);
+        board.add_piece(Piece::new(PieceKind::King, Color::Black), 17);
+        board.add_piece(Piece::new(PieceKind::Knight, Color::White), 19);
+        board.add_piece(Piece::new(PieceKind::King, Color::White), 63);
+        board.add_piece(Piece::new(PieceKind::Queen, Color::White), 11);
 
-        board.add_piece(
-            Piece::Bishop(Bishop {
-                color: Color::White,
-            }),
-            36,
-        );
+        board.add_piece(Piece::new(PieceKind::Bishop, Color::White), 36);
         board
     }
 
-    // Get a FEN string and return a Board struct.
+    pub fn piece_at(&self, square: Square) -> Option<Piece> {
+        self.board[square.index()]
+    }
+
+    // Every piece on the board with its square, from a1 to h8.
+    pub fn pieces(&self) -> impl Iterator<Item = (Square, Piece)> + '_ {
+        Square::all().filter_map(|square| Some((square, self.piece_at(square)?)))
+    }
+
+    // Where the king of the color is, or None on a board without one (in antichess, say).
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        let square = if color.is_white() {
+            self.white_king_pos
+        } else {
+            self.black_king_pos
+        };
+        Some(square)
+            .filter(|square| self.board[square.index()] == Some(Piece::new(PieceKind::King, color)))
+    }
+
+    pub fn castling(&self) -> CanCastle {
+        self.can_castle
+    }
+
+    // The pawn that can be taken en passant, if any.
+    pub fn en_passant(&self) -> CanEnPassant {
+        self.can_en_passant
+    }
+
+    pub fn halfmove_clock(&self) -> u8 {
+        self.halfmove_clock
+    }
+
+    pub fn pockets(&self) -> Option<Pockets> {
+        self.pockets
+    }
+
+    // The fairy pieces the position plays with, if any.
+    pub fn fairy_pieces(&self) -> Option<&PieceSet> {
+        self.fairy.as_deref()
+    }
+
+    // The piece for the FEN letter, standard or one of the board's fairy pieces.
+    pub fn piece_from_char(&self, letter: char) -> Option<Piece> {
+        Piece::from_char(letter).or_else(|| self.fairy_pieces()?.from_fen_char(letter))
+    }
+
+    // The piece's FEN letter, uppercase for white, fairy pieces' from the board's set.
+    pub fn piece_letter(&self, piece: Piece) -> char {
+        self.fairy_pieces()
+            .map_or(piece.to_char(), |pieces| pieces.letter(piece))
+    }
+
+    // Material value of the piece in centipawns, fairy pieces' from the board's set.
+    pub fn piece_value(&self, piece: Piece) -> i32 {
+        match (piece.kind, self.fairy_pieces()) {
+            (PieceKind::Fairy(_), Some(pieces)) => pieces.value(piece.kind),
+            _ => piece.value(),
+        }
+    }
+
+    // What the piece is shown as on a printed board, fairy pieces' from the board's set.
+    pub fn piece_glyph(&self, piece: Piece) -> char {
+        self.fairy_pieces()
+            .map_or(piece.glyph(), |pieces| pieces.glyph(piece))
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.side_to_move
+    }
+
+    // Like from_fen, but a FEN that can't be read is an error instead of a best guess. The
+    // fields after the piece placement can still be left out.
+    pub fn try_from_fen(fen: &str) -> Result<Self, ChessError> {
+        check_fen(fen, None).map_err(ChessError::InvalidFen)?;
+        Ok(Self::from_fen(fen))
+    }
+
+    // Like try_from_fen, for a position that plays with the fairy pieces of the set.
+    pub fn try_from_fen_with_pieces(fen: &str, pieces: PieceSet) -> Result<Self, ChessError> {
+        check_fen(fen, Some(&pieces)).map_err(ChessError::InvalidFen)?;
+        Ok(Self::read_fen(fen, Some(Arc::new(pieces))))
+    }
+
+    // Get a FEN string and return a Board struct. White moves if the FEN doesn't say.
     pub fn from_fen(fen: &str) -> Self {
+        Self::read_fen(fen, None)
+    }
+
+    fn read_fen(fen: &str, pieces: Option<Arc<PieceSet>>) -> Self {
+        let mut fields = fen.split_whitespace();
         // Create an empty Board to later mutate it.
         let mut board = Self::empty();
-        // Index at which it will change the board (i. e. add the piece).
+        board.fairy = pieces;
+        // Index at which it will change the board (i. e. add the piece), counted in FEN order:
+        // a8 to h8, then down the ranks.
         let mut square: usize = 0;
+        let board_square =
+            |index: usize| Square::from_index((8 * (7 - index / 8) + index % 8) as u8);
 
-        // For each character in the string, convert it to a piece in the board, and add it.
-        for ch in fen.chars() {
+        // Drop variants add the pieces in hand, between brackets or as a ninth rank:
+        // ".../RNBQKBNR[Qp]" or ".../RNBQKBNR/Qp".
+        let placement = fields.next().unwrap_or("");
+        let (placement, hand) = match placement.split_once('[') {
+            Some((placement, hand)) => (placement, Some(hand.trim_end_matches(']'))),
+            None if placement.matches('/').count() == 8 => {
+                let (placement, hand) = placement.rsplit_once('/').unwrap();
+                (placement, Some(hand))
+            }
+            None => (placement, None),
+        };
+        let mut promoted = 0;
+
+        // For each character in the piece placement, convert it to a piece in the board, and add it.
+        for ch in placement.chars() {
+            // Whatever comes after the last square is left out.
+            if square > 64 || (square == 64 && ch != '~') {
+                break;
+            }
             match ch {
+                // The piece before was promoted, which only drop variants keep track of.
+                '~' if square > 0 => {
+                    promoted |= 1 << board_square(square - 1).index();
+                }
                 // Empty squares
                 '1'..='8' => square += ch.to_digit(10).unwrap() as usize,
-                // White pawn
-                'p' => {
-                    board.add_piece(
-                        Piece::Pawn(Pawn {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
+                // A piece, standard or one of the board's fairy pieces. Unknown letters leave
+                // the square empty.
+                ch if ch.is_ascii_alphabetic() => {
+                    if let Some(piece) = board.piece_from_char(ch) {
+                        let target = board_square(square);
+                        board.add_piece(piece, target.index());
+                        if piece.kind == PieceKind::King {
+                            if piece.color.is_white() {
+                                board.white_king_pos = target;
+                            } else {
+                                board.black_king_pos = target;
+                            }
+                        }
+                    }
                     square += 1;
                 }
 
-                // White knight
-                'n' => {
-                    board.add_piece(
-                        Piece::Knight(Knight {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+                _ => (),
+            }
+        }
 
-                // White bishop
-                'b' => {
-                    board.add_piece(
-                        Piece::Bishop(Bishop {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+        if let Some(hand) = hand {
+            let mut pockets = Pockets {
+                promoted,
+                ..Pockets::default()
+            };
+            for piece in hand.chars().filter_map(Piece::from_char) {
+                pockets.add(piece.color, piece.kind);
+            }
+            board.pockets = Some(pockets);
+        }
 
-                // White rook
-                'r' => {
-                    board.add_piece(
-                        Piece::Rook(Rook {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
+        let color = match fields.next() {
+            Some("b") => Color::Black,
+            _ => Color::White,
+        };
+        board.side_to_move = color;
+        // Without a castling field every right is kept, as boards made from just the piece
+        // placement always did.
+        if let Some(castling) = fields.next() {
+            board.can_castle = CanCastle {
+                white_kingside: castling.contains('K'),
+                white_queenside: castling.contains('Q'),
+                black_kingside: castling.contains('k'),
+                black_queenside: castling.contains('q'),
+            };
+        }
+        // The FEN gives the square behind the pawn, the board keeps the pawn's own square.
+        if let Some(en_passant) = fields.next() {
+            let bytes = en_passant.as_bytes();
+            if bytes.len() == 2 && (b'a'..=b'h').contains(&bytes[0]) {
+                let column = bytes[0] - b'a';
+                match bytes[1] {
+                    b'6' if color.is_white() => {
+                        board.can_en_passant = CanEnPassant::Yes(Square::from_index(32 + column))
+                    }
+                    b'3' if !color.is_white() => {
+                        board.can_en_passant = CanEnPassant::Yes(Square::from_index(24 + column))
+                    }
+                    _ => (),
                 }
+            }
+        }
+        if let Some(halfmove_clock) = fields.next().and_then(|clock| clock.parse().ok()) {
+            board.halfmove_clock = halfmove_clock;
+        }
+        board
+    }
 
-                // White queen
-                'q' => {
-                    board.add_piece(
-                        Piece::Queen(Queen {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
+    // The position as a FEN string. The board doesn't keep the move number, so it's always 1.
+    pub fn to_fen(&self) -> String {
+        let mut fen = String::new();
+        for row in (0..8).rev() {
+            let mut empty_squares = 0;
+            for column in 0..8 {
+                match self.board[row * 8 + column] {
+                    Some(piece) => {
+                        if empty_squares > 0 {
+                            fen.push_str(&empty_squares.to_string());
+                            empty_squares = 0;
+                        }
+                        fen.push(self.piece_letter(piece));
+                        if self.pockets.is_some_and(|pockets| {
+                            pockets.is_promoted(Square::from_index((row * 8 + column) as u8))
+                        }) {
+                            fen.push('~');
+                        }
+                    }
+                    None => empty_squares += 1,
                 }
+            }
+            if empty_squares > 0 {
+                fen.push_str(&empty_squares.to_string());
+            }
+            if row > 0 {
+                fen.push('/');
+            }
+        }
+        if let Some(pockets) = self.pockets {
+            fen.push_str(&format!("[{}]", pockets.to_fen()));
+        }
+        fen.push_str(if self.side_to_move.is_white() {
+            " w "
+        } else {
+            " b "
+        });
+        let can_castle = self.can_castle;
+        let mut castling = String::new();
+        for (right, letter) in [
+            (can_castle.white_kingside, 'K'),
+            (can_castle.white_queenside, 'Q'),
+            (can_castle.black_kingside, 'k'),
+            (can_castle.black_queenside, 'q'),
+        ] {
+            if right {
+                castling.push(letter);
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        fen.push_str(&castling);
+        fen.push(' ');
+        match self.can_en_passant {
+            CanEnPassant::Yes(square) => {
+                let target = if square.rank().index() == 3 {
+                    square.down()
+                } else {
+                    square.up()
+                };
+                fen.push_str(&target.expect("en passant pawn on an edge rank").to_string());
+            }
+            CanEnPassant::No => fen.push('-'),
+        }
+        fen.push_str(&format!(" {} 1", self.halfmove_clock));
+        fen
+    }
 
-                // White king
-                'k' => {
-                    board.add_piece(
-                        Piece::King(King {
-                            color: Color::Black,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    board.black_king_pos =
-                        (8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8);
-                    square += 1;
-                }
+    // Find the legal move written in UCI notation ("e2e4", "e7e8q", "e1g1").
+    pub fn move_from_uci(&self, uci: &str) -> Option<BoardMove> {
+        self.legal_moves()
+            .into_iter()
+            .find(|(start_square, a_move)| a_move.to_uci(*start_square, self.side_to_move) == uci)
+    }
 
-                // Black pawn
-                'P' => {
-                    board.add_piece(
-                        Piece::Pawn(Pawn {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+    // Like move_from_uci, but a move that isn't legal is an error.
+    pub fn try_move_from_uci(&self, uci: &str) -> Result<BoardMove, ChessError> {
+        self.move_from_uci(uci)
+            .ok_or_else(|| ChessError::IllegalMove(uci.to_string()))
+    }
 
-                // Black knight
-                'N' => {
-                    board.add_piece(
-                        Piece::Knight(Knight {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+    // Like move_from_san, but a move that isn't legal is an error.
+    pub fn try_move_from_san(&self, san: &str) -> Result<BoardMove, ChessError> {
+        self.move_from_san(san)
+            .ok_or_else(|| ChessError::IllegalMove(san.to_string()))
+    }
 
-                // Black bishop
-                'B' => {
-                    board.add_piece(
-                        Piece::Bishop(Bishop {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+    // Find the legal move written in standard algebraic notation ("Nf3", "exd5", "O-O", "e8=Q+").
+    pub fn move_from_san(&self, san: &str) -> Option<BoardMove> {
+        let color = self.side_to_move;
+        let san = san.trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.legal_moves();
+        match san {
+            "O-O" | "0-0" => {
+                return legal_moves
+                    .into_iter()
+                    .find(|(_, a_move)| *a_move == Move::CastleKingside)
+            }
+            "O-O-O" | "0-0-0" => {
+                return legal_moves
+                    .into_iter()
+                    .find(|(_, a_move)| *a_move == Move::CastleQueenside)
+            }
+            _ => (),
+        }
+        // Drops: "N@f3", or "@e4" for a pawn.
+        if let Some((letter, destination)) = san.split_once('@') {
+            let letter = if letter.is_empty() { "P" } else { letter };
+            return legal_moves.into_iter().find(|(start_square, a_move)| {
+                matches!(a_move, Move::Drop(_, _))
+                    && a_move.to_uci(*start_square, color) == format!("{letter}@{destination}")
+            });
+        }
 
-                // Black rook
-                'R' => {
-                    board.add_piece(
-                        Piece::Rook(Rook {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+        let mut chars: Vec<char> = san.chars().filter(|ch| *ch != 'x' && *ch != '=').collect();
+        let kind = match chars.first() {
+            Some('N') => PieceKind::Knight,
+            Some('B') => PieceKind::Bishop,
+            Some('R') => PieceKind::Rook,
+            Some('Q') => PieceKind::Queen,
+            Some('K') => PieceKind::King,
+            Some(letter) if letter.is_ascii_uppercase() => self
+                .piece_from_char(*letter)
+                .map_or(PieceKind::Pawn, |piece| piece.kind),
+            _ => PieceKind::Pawn,
+        };
+        if kind != PieceKind::Pawn {
+            chars.remove(0);
+        }
+        // A pawn move may end with the piece it promotes to.
+        let promotion = match chars.last() {
+            Some(letter) if kind == PieceKind::Pawn && "NBRQ".contains(*letter) => chars.pop(),
+            _ => None,
+        };
+        if chars.len() < 2 {
+            return None;
+        }
+        let destination: String = chars.split_off(chars.len() - 2).into_iter().collect();
+        let end_square: Square = destination.parse().ok()?;
+        // Whatever is left tells apart two pieces that can go to the same square.
+        let (file, rank) = chars
+            .iter()
+            .fold((None, None), |(file, rank), ch| match ch {
+                'a'..='h' => (Some(*ch as u8 - b'a'), rank),
+                '1'..='8' => (file, Some(*ch as u8 - b'1')),
+                _ => (file, rank),
+            });
 
-                // Black queen
-                'Q' => {
-                    board.add_piece(
-                        Piece::Queen(Queen {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    square += 1;
-                }
+        legal_moves.into_iter().find(|(start_square, a_move)| {
+            let Some(piece) = self.board[start_square.index()] else {
+                return false;
+            };
+            let promotes_to = match a_move {
+                Move::PawnPromotion(_, new_piece) => Some(new_piece.to_char().to_ascii_uppercase()),
+                _ => None,
+            };
+            !matches!(a_move, Move::CastleKingside | Move::CastleQueenside)
+                && piece.kind == kind
+                && a_move.end_square(color) == end_square
+                && file.is_none_or(|file| Self::get_column(*start_square) == file)
+                && rank.is_none_or(|rank| Self::get_row(*start_square) == rank)
+                && promotes_to == promotion
+        })
+    }
 
-                //Black king
-                'K' => {
-                    board.add_piece(
-                        Piece::King(King {
-                            color: Color::White,
-                        }),
-                        ((8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8))
-                            as usize,
-                    );
-                    board.white_king_pos =
-                        (8 * (7 - Self::get_row(square as u8))) + Self::get_column(square as u8);
-                    square += 1;
+    // Write a legal move in standard algebraic notation, with "+" or "#" when it gives check.
+    pub fn move_to_san(&self, start_square: Square, a_move: Move) -> String {
+        let color = self.side_to_move;
+        let mut san = match a_move {
+            Move::CastleKingside => "O-O".to_string(),
+            Move::CastleQueenside => "O-O-O".to_string(),
+            Move::Drop(_, _) => a_move.to_uci(start_square, color),
+            _ => {
+                let piece = self.board[start_square.index()].unwrap();
+                let end_square = a_move.end_square(color);
+                let capture = if self.is_capture(a_move) { "x" } else { "" };
+                let destination = end_square.to_string();
+                if piece.kind == PieceKind::Pawn {
+                    let file = if capture.is_empty() {
+                        String::new()
+                    } else {
+                        start_square.file().to_string()
+                    };
+                    let promotion = match a_move {
+                        Move::PawnPromotion(_, new_piece) => {
+                            format!("={}", new_piece.to_char().to_ascii_uppercase())
+                        }
+                        _ => String::new(),
+                    };
+                    format!("{file}{capture}{destination}{promotion}")
+                } else {
+                    // Name the file, the rank or both, whatever it takes to tell the piece
+                    // apart from the others of its kind that can go to the same square.
+                    let rivals: Vec<Square> = self
+                        .legal_moves()
+                        .into_iter()
+                        .filter(|(other_start, other_move)| {
+                            *other_start != start_square
+                                && !matches!(
+                                    other_move,
+                                    Move::CastleKingside | Move::CastleQueenside
+                                )
+                                && other_move.end_square(color) == end_square
+                                && self.board[other_start.index()]
+                                    .is_some_and(|other| other.kind == piece.kind)
+                        })
+                        .map(|(other_start, _)| other_start)
+                        .collect();
+                    let start = start_square.to_string();
+                    let same_file = rivals
+                        .iter()
+                        .any(|other| Self::get_column(*other) == Self::get_column(start_square));
+                    let same_rank = rivals
+                        .iter()
+                        .any(|other| Self::get_row(*other) == Self::get_row(start_square));
+                    let disambiguation = if rivals.is_empty() {
+                        ""
+                    } else if !same_file {
+                        &start[..1]
+                    } else if !same_rank {
+                        &start[1..]
+                    } else {
+                        &start[..]
+                    };
+                    format!(
+                        "{}{disambiguation}{capture}{destination}",
+                        self.piece_letter(piece).to_ascii_uppercase()
+                    )
                 }
-
-                _ => (),
             }
+        };
+        let new_board = self.make_move(start_square, a_move);
+        if new_board.is_in_check(color.reverse()) {
+            san.push(if new_board.legal_moves().is_empty() {
+                '#'
+            } else {
+                '+'
+            });
         }
-        board
+        san
     }
 
     // add a piece to a specific board location
@@ -411,41 +704,108 @@ impl Board {
         self.board[square_to_add_piece] = Some(piece);
     }
 
-    pub fn get_row(square: u8) -> u8 {
-        square / 8
+    pub fn get_row(square: Square) -> u8 {
+        square.rank().index()
     }
-    pub fn get_column(square: u8) -> u8 {
-        square % 8
+    pub fn get_column(square: Square) -> u8 {
+        square.file().index()
     }
 
+    // The moves of each piece of the side to move, as generate_moves_for gives them.
     pub fn generate_moves(
         &self,
-        color: Color,
         moves_list: &mut OnePieceMoves,
-        all_piece_moves_list: &mut [Option<(u8, OnePieceMoves)>; 16],
+        all_piece_moves_list: &mut PositionMoves,
     ) {
-        let mut moves_index = 0;
+        self.generate_moves_for(self.side_to_move, moves_list, all_piece_moves_list);
+    }
 
+    // The moves of each piece of the given color, whether or not it's its turn, to see what
+    // the other side attacks. The list is cleared first and gets one entry per piece.
+    pub fn generate_moves_for(
+        &self,
+        color: Color,
+        moves_list: &mut OnePieceMoves,
+        all_piece_moves_list: &mut PositionMoves,
+    ) {
+        all_piece_moves_list.clear();
         for (index, item) in self
             .board
             .into_iter()
             .enumerate()
             .filter(|tuple| is_some_and_same_color(tuple.1, color))
         {
-            item.unwrap().get_moves(self, index as u8, moves_list);
-            all_piece_moves_list[moves_index] = Some((index as u8, *moves_list));
-            moves_index += 1;
-        }
-        if moves_index != 16 {
-            all_piece_moves_list[moves_index] = None;
+            let square = Square::from_index(index as u8);
+            item.unwrap().get_moves(self, square, moves_list);
+            all_piece_moves_list.push((square, *moves_list));
         }
     }
 
-    pub fn make_move(&self, start_square: usize, end_square: Move, color: Color) -> Self {
+    // Play the move for the side to move and hand the turn over, without checking the move is
+    // legal, or even that the piece is the mover's. See try_make_move for that.
+    pub fn make_move(&self, start_square: Square, end_square: Move) -> Self {
+        let color = self.side_to_move;
         let mut clone: Board = self.clone();
+        clone.side_to_move = color.reverse();
+        clone.halfmove_clock = if self.is_capture(end_square)
+            || matches!(
+                self.board[start_square.index()],
+                Some(Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                })
+            ) {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+        if let Some(pockets) = &mut clone.pockets {
+            // What's taken goes to the taker's hand, as a pawn if it had been promoted.
+            let captured_square = match end_square {
+                Move::RegularMove(sqr) | Move::PawnPromotion(sqr, _)
+                    if self.board[sqr.index()].is_some() =>
+                {
+                    Some(sqr)
+                }
+                Move::EnPassant(sqr) => {
+                    if color.is_white() {
+                        sqr.down()
+                    } else {
+                        sqr.up()
+                    }
+                }
+                _ => None,
+            };
+            if let Some(sqr) = captured_square {
+                let kind = if pockets.is_promoted(sqr) {
+                    Some(PieceKind::Pawn)
+                } else {
+                    self.board[sqr.index()].map(|piece| piece.kind)
+                };
+                if let Some(kind) = kind {
+                    pockets.add(color, kind);
+                }
+                pockets.promoted &= !(1 << sqr.index());
+            }
+            if pockets.is_promoted(start_square) {
+                pockets.promoted &= !(1 << start_square.index());
+                pockets.promoted |= 1 << end_square.end_square(color).index();
+            }
+            match end_square {
+                Move::PawnPromotion(sqr, _) => pockets.promoted |= 1 << sqr.index(),
+                Move::Drop(_, piece) => {
+                    pockets.remove(color, piece.kind);
+                }
+                _ => (),
+            }
+        }
         match end_square {
             Move::RegularMove(sqr) => {
-                if let Some(Piece::King(_)) = self.board[start_square] {
+                if let Some(Piece {
+                    kind: PieceKind::King,
+                    ..
+                }) = self.board[start_square.index()]
+                {
                     match color {
                         Color::White => {
                             clone.white_king_pos = sqr;
@@ -458,21 +818,25 @@ impl Board {
                             clone.can_castle.black_queenside = false;
                         }
                     }
-                } else if let Some(Piece::Rook(_)) = self.board[start_square] {
+                } else if let Some(Piece {
+                    kind: PieceKind::Rook,
+                    ..
+                }) = self.board[start_square.index()]
+                {
                     match color {
-                        Color::White => match start_square {
+                        Color::White => match start_square.index() {
                             0 => clone.can_castle.white_queenside = false,
                             7 => clone.can_castle.white_kingside = false,
                             _ => (),
                         },
-                        Color::Black => match start_square {
+                        Color::Black => match start_square.index() {
                             63 => clone.can_castle.black_kingside = false,
                             56 => clone.can_castle.black_queenside = false,
                             _ => (),
                         },
                     }
                 } else {
-                    match sqr {
+                    match sqr.index() {
                         0 => clone.can_castle.white_queenside = false,
                         7 => clone.can_castle.white_kingside = false,
                         63 => clone.can_castle.black_kingside = false,
@@ -480,35 +844,35 @@ impl Board {
                         _ => (),
                     }
                 }
-                clone.board[sqr as usize] = clone.board[start_square];
-                clone.board[start_square] = None;
+                clone.board[sqr.index()] = clone.board[start_square.index()];
+                clone.board[start_square.index()] = None;
                 clone.can_en_passant = CanEnPassant::No;
             }
             Move::PawnAdvanceTwoSquares(sqr) => {
-                clone.board[sqr as usize] = clone.board[start_square];
-                clone.board[start_square] = None;
+                clone.board[sqr.index()] = clone.board[start_square.index()];
+                clone.board[start_square.index()] = None;
                 clone.can_en_passant = CanEnPassant::Yes(sqr);
             }
             Move::CastleKingside => {
                 if color.is_white() {
                     clone.board.swap(4, 6);
                     clone.board.swap(5, 7);
-                    clone.white_king_pos = 6;
+                    clone.white_king_pos = Square::from_index(6);
                 } else {
                     clone.board.swap(60, 62);
                     clone.board.swap(63, 61);
-                    clone.black_king_pos = 62;
+                    clone.black_king_pos = Square::from_index(62);
                 }
                 clone.can_castle.all_to_false_with_color(color);
                 clone.can_en_passant = CanEnPassant::No;
             }
             Move::CastleQueenside => {
                 if color.is_white() {
-                    clone.white_king_pos = 2;
+                    clone.white_king_pos = Square::from_index(2);
                     clone.board.swap(4, 2);
                     clone.board.swap(0, 3);
                 } else {
-                    clone.black_king_pos = 58;
+                    clone.black_king_pos = Square::from_index(58);
                     clone.board.swap(60, 58);
                     clone.board.swap(56, 59);
                 }
@@ -517,32 +881,565 @@ impl Board {
             }
 
             Move::PawnPromotion(sqr, new_piece) => {
-                clone.board[start_square] = None;
-                clone.board[sqr as usize] = Some(new_piece);
+                clone.board[start_square.index()] = None;
+                clone.board[sqr.index()] = Some(new_piece);
                 clone.can_en_passant = CanEnPassant::No;
             }
 
             Move::EnPassant(sqr) => {
-                clone.board[sqr as usize] = clone.board[start_square];
-                clone.board[if let Color::White = color {
-                    down(sqr as usize).unwrap() as usize
+                clone.board[sqr.index()] = clone.board[start_square.index()];
+                let captured = if let Color::White = color {
+                    sqr.down()
                 } else {
-                    up(sqr as usize).unwrap() as usize
-                }] = None;
-                clone.board[start_square] = None;
+                    sqr.up()
+                };
+                if let Some(captured) = captured {
+                    clone.board[captured.index()] = None;
+                }
+                clone.board[start_square.index()] = None;
+                clone.can_en_passant = CanEnPassant::No;
+            }
+
+            Move::Drop(sqr, piece) => {
+                clone.board[sqr.index()] = Some(piece);
                 clone.can_en_passant = CanEnPassant::No;
             }
         }
 
         clone
     }
+
+    // What make_move takes off the board and puts on it, worked out without making it.
+    pub fn piece_changes(&self, start_square: Square, a_move: Move) -> PieceChanges {
+        let color = self.side_to_move;
+        let piece_on = |square: Square| self.board[square.index()].map(|piece| (piece, square));
+        let mut changes = PieceChanges::default();
+        match a_move {
+            Move::RegularMove(sqr) | Move::PawnAdvanceTwoSquares(sqr) => {
+                changes.removed = [piece_on(start_square), piece_on(sqr)];
+                changes.added[0] = self.board[start_square.index()].map(|piece| (piece, sqr));
+            }
+            Move::PawnPromotion(sqr, new_piece) => {
+                changes.removed = [piece_on(start_square), piece_on(sqr)];
+                changes.added[0] = Some((new_piece, sqr));
+            }
+            Move::EnPassant(sqr) => {
+                let captured = if color.is_white() {
+                    sqr.down()
+                } else {
+                    sqr.up()
+                };
+                changes.removed = [piece_on(start_square), captured.and_then(piece_on)];
+                changes.added[0] = self.board[start_square.index()].map(|piece| (piece, sqr));
+            }
+            Move::CastleKingside | Move::CastleQueenside => {
+                // King and rook squares, before and after, on white's side.
+                let (king, rook, king_to, rook_to) = if a_move == Move::CastleKingside {
+                    (4, 7, 6, 5)
+                } else {
+                    (4, 0, 2, 3)
+                };
+                let offset = if color.is_white() { 0 } else { 56 };
+                let square = |index: u8| Square::from_index(index + offset);
+                changes.removed = [piece_on(square(king)), piece_on(square(rook))];
+                changes.added = [
+                    self.board[square(king).index()].map(|piece| (piece, square(king_to))),
+                    self.board[square(rook).index()].map(|piece| (piece, square(rook_to))),
+                ];
+            }
+            Move::Drop(sqr, piece) => changes.added[0] = Some((piece, sqr)),
+        }
+        changes
+    }
+
+    // Make the move only if it doesn't leave the mover's king attacked. Castling is also refused
+    // when the king is in check or passes over an attacked square.
+    pub fn make_legal_move(&self, start_square: Square, a_move: Move) -> Option<Self> {
+        let color = self.side_to_move;
+        let passing_square = match (a_move, color) {
+            (Move::CastleKingside, Color::White) => Some(Square::from_index(5)),
+            (Move::CastleKingside, Color::Black) => Some(Square::from_index(61)),
+            (Move::CastleQueenside, Color::White) => Some(Square::from_index(3)),
+            (Move::CastleQueenside, Color::Black) => Some(Square::from_index(59)),
+            _ => None,
+        };
+        if let Some(passing_square) = passing_square {
+            if self.is_in_check(color) || self.is_attacked(passing_square, color.reverse()) {
+                return None;
+            }
+        }
+        let new_board = self.make_move(start_square, a_move);
+        if new_board.is_in_check(color) {
+            None
+        } else {
+            Some(new_board)
+        }
+    }
+
+    // Play the move if it's legal for the piece on the start square, and an error otherwise,
+    // where make_move would play along with whatever it's given. Moving a piece of the side
+    // not to move is an error too, so the two sides can't play out of turn.
+    pub fn try_make_move(&self, start_square: Square, a_move: Move) -> Result<Self, ChessError> {
+        let illegal = |reason: &str| {
+            ChessError::IllegalMove(format!("{a_move} from square {start_square}{reason}"))
+        };
+        let mover = match a_move {
+            Move::Drop(_, piece) => Some(piece),
+            _ => self.board[start_square.index()],
+        };
+        if mover.is_some_and(|piece| piece.color != self.side_to_move) {
+            let to_move = if self.side_to_move.is_white() {
+                "white"
+            } else {
+                "black"
+            };
+            return Err(illegal(&format!(", {to_move} to move")));
+        }
+        if !self.is_pseudo_legal(start_square, a_move) {
+            return Err(illegal(""));
+        }
+        self.make_legal_move(start_square, a_move)
+            .ok_or_else(|| illegal(""))
+    }
+
+    // Pass the turn without moving, for null move pruning. Any en passant capture is lost.
+    pub fn make_null_move(&self) -> Self {
+        let mut clone = self.clone();
+        clone.side_to_move = self.side_to_move.reverse();
+        clone.can_en_passant = CanEnPassant::No;
+        clone
+    }
+
+    // Every legal move for the side to move, as (start square, move) pairs.
+    // Goes through the board itself rather than generate_moves, since with drops a side can
+    // have more than 16 pieces.
+    pub fn legal_moves(&self) -> Vec<BoardMove> {
+        let color = self.side_to_move;
+        let mut moves_list = [None; MAX_PIECE_MOVES];
+        let mut pseudo_legal = Vec::with_capacity(48);
+        for (index, item) in self
+            .board
+            .into_iter()
+            .enumerate()
+            .filter(|tuple| is_some_and_same_color(tuple.1, color))
+        {
+            let square = Square::from_index(index as u8);
+            item.unwrap().get_moves(self, square, &mut moves_list);
+            pseudo_legal.extend(
+                moves_list
+                    .into_iter()
+                    .map_while(|item| item)
+                    .map(|each_move| (square, each_move)),
+            );
+        }
+        self.generate_drops(&mut pseudo_legal);
+        pseudo_legal
+            .into_iter()
+            .filter(|(start_square, each_move)| {
+                self.make_legal_move(*start_square, *each_move).is_some()
+            })
+            .collect()
+    }
+
+    // Every drop of a piece in the hand of the side to move on an empty square. Pawns can't go
+    // on the first or last rank.
+    pub fn generate_drops(&self, drops: &mut Vec<BoardMove>) {
+        let color = self.side_to_move;
+        let Some(pockets) = self.pockets else {
+            return;
+        };
+        for kind in Pockets::KINDS
+            .into_iter()
+            .filter(|kind| pockets.count(color, *kind) > 0)
+        {
+            let piece = Piece::new(kind, color);
+            for square in Square::all() {
+                if self.board[square.index()].is_none()
+                    && (kind != PieceKind::Pawn || !matches!(square.rank().index(), 0 | 7))
+                {
+                    drops.push((square, Move::Drop(square, piece)));
+                }
+            }
+        }
+    }
+
+    // Everything that doesn't add up in the position by the rules of standard chess.
+    pub fn validate(&self) -> Result<(), Vec<BoardIssue>> {
+        let color = self.side_to_move;
+        let mut issues = Vec::new();
+        let piece_on = |index: usize| self.board[index];
+        let is_piece = |index: usize, kind: PieceKind, color: Color| {
+            piece_on(index) == Some(Piece::new(kind, color))
+        };
+
+        for side in [Color::White, Color::Black] {
+            let kings: Vec<Square> = Square::all()
+                .filter(|square| is_piece(square.index(), PieceKind::King, side))
+                .collect();
+            if kings.len() != 1 {
+                issues.push(BoardIssue::KingCount {
+                    color: side,
+                    count: kings.len() as u8,
+                });
+            }
+            let recorded = if side.is_white() {
+                self.white_king_pos
+            } else {
+                self.black_king_pos
+            };
+            if !is_piece(recorded.index(), PieceKind::King, side) {
+                issues.push(BoardIssue::KingPosition {
+                    color: side,
+                    recorded,
+                });
+            }
+            // The side that isn't to move can't be in check.
+            if side != color
+                && kings
+                    .iter()
+                    .any(|king| self.is_attacked(*king, side.reverse()))
+            {
+                issues.push(BoardIssue::OpponentInCheck(side));
+            }
+        }
+
+        for square in Square::all().filter(|square| matches!(square.rank().index(), 0 | 7)) {
+            if let Some(Piece {
+                kind: PieceKind::Pawn,
+                ..
+            }) = piece_on(square.index())
+            {
+                issues.push(BoardIssue::PawnOnBackRank(square));
+            }
+        }
+
+        let rights = [
+            (Color::White, true, self.can_castle.white_kingside, 4, 7),
+            (Color::White, false, self.can_castle.white_queenside, 4, 0),
+            (Color::Black, true, self.can_castle.black_kingside, 60, 63),
+            (Color::Black, false, self.can_castle.black_queenside, 60, 56),
+        ];
+        for (side, kingside, has_right, king, rook) in rights {
+            if has_right
+                && !(is_piece(king, PieceKind::King, side) && is_piece(rook, PieceKind::Rook, side))
+            {
+                issues.push(BoardIssue::CastlingRight {
+                    color: side,
+                    kingside,
+                });
+            }
+        }
+
+        // The pawn that just advanced two squares belongs to the side not to move, and the
+        // squares it went over are empty.
+        if let CanEnPassant::Yes(square) = self.can_en_passant {
+            let mover = color.reverse();
+            let (rank, behind) = if mover.is_white() {
+                (3, [square.down(), square.down().and_then(Square::down)])
+            } else {
+                (4, [square.up(), square.up().and_then(Square::up)])
+            };
+            let is_consistent = square.rank().index() == rank
+                && is_piece(square.index(), PieceKind::Pawn, mover)
+                && behind
+                    .iter()
+                    .all(|sqr| sqr.is_some_and(|sqr| piece_on(sqr.index()).is_none()));
+            if !is_consistent {
+                issues.push(BoardIssue::EnPassant(square));
+            }
+        }
+
+        // Pieces come back in drop variants, and fairy pieces don't start anywhere in
+        // particular, so only count standard chess.
+        if self.pockets.is_none() && !fairy::has_fairy_pieces(self) {
+            for side in [Color::White, Color::Black] {
+                let count = |kind| {
+                    self.board
+                        .iter()
+                        .filter(|piece| **piece == Some(Piece::new(kind, side)))
+                        .count()
+                };
+                // Every piece beyond the starting ones took a promotion, so a pawn.
+                let promoted: usize = [
+                    (PieceKind::Knight, 2),
+                    (PieceKind::Bishop, 2),
+                    (PieceKind::Rook, 2),
+                    (PieceKind::Queen, 1),
+                ]
+                .into_iter()
+                .map(|(kind, start)| count(kind).saturating_sub(start))
+                .sum();
+                if count(PieceKind::Pawn) + promoted > 8 {
+                    issues.push(BoardIssue::PieceCount(side));
+                }
+            }
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+
+    // Is the king of the given color attacked?
+    pub fn is_in_check(&self, color: Color) -> bool {
+        let king_square = if let Color::White = color {
+            self.white_king_pos
+        } else {
+            self.black_king_pos
+        };
+        self.is_attacked(king_square, color.reverse())
+    }
+
+    // Is the square attacked by any piece of the given color? Looks outwards from the square
+    // instead of generating the attacker's moves.
+    pub fn is_attacked(&self, square: Square, by: Color) -> bool {
+        // Pawns attack diagonally forwards, so look diagonally backwards from the square.
+        let pawn_squares = if by.is_white() {
+            [square.down_left(), square.down_right()]
+        } else {
+            [square.up_left(), square.up_right()]
+        };
+        for sqr in pawn_squares.into_iter().flatten() {
+            if self.board[sqr.index()] == Some(Piece::new(PieceKind::Pawn, by)) {
+                return true;
+            }
+        }
+        for sqr in knight_squares(square).into_iter().flatten() {
+            if self.board[sqr.index()] == Some(Piece::new(PieceKind::Knight, by)) {
+                return true;
+            }
+        }
+        for sqr in King::get_adjacent_squares(square).into_iter().flatten() {
+            if self.board[sqr.index()] == Some(Piece::new(PieceKind::King, by)) {
+                return true;
+            }
+        }
+        let diagonals: [fn(Square) -> Option<Square>; 4] = [
+            Square::up_left,
+            Square::up_right,
+            Square::down_left,
+            Square::down_right,
+        ];
+        for direction in diagonals {
+            if let Some((_, piece)) = self.first_piece_in_line(square, direction) {
+                if piece.color == by && matches!(piece.kind, PieceKind::Bishop | PieceKind::Queen) {
+                    return true;
+                }
+            }
+        }
+        let lines: [fn(Square) -> Option<Square>; 4] =
+            [Square::up, Square::down, Square::left, Square::right];
+        for direction in lines {
+            if let Some((_, piece)) = self.first_piece_in_line(square, direction) {
+                if piece.color == by && matches!(piece.kind, PieceKind::Rook | PieceKind::Queen) {
+                    return true;
+                }
+            }
+        }
+        self.fairy.is_some()
+            && self
+                .board
+                .iter()
+                .enumerate()
+                .any(|(sqr, piece)| match piece {
+                    Some(Piece {
+                        kind: PieceKind::Fairy(id),
+                        color,
+                    }) if *color == by => fairy::attacks(self, sqr as u8, by, *id, square.into()),
+                    _ => false,
+                })
+    }
+
+    // The first piece (and its square) found walking from the square in a direction, if any.
+    pub(crate) fn first_piece_in_line(
+        &self,
+        square: Square,
+        direction: fn(Square) -> Option<Square>,
+    ) -> Option<(Square, Piece)> {
+        let mut next_square = direction(square);
+        while let Some(sqr) = next_square {
+            if let Some(piece) = self.board[sqr.index()] {
+                return Some((sqr, piece));
+            }
+            next_square = direction(sqr);
+        }
+        None
+    }
+
+    // Is the move a capture (en passant included)?
+    pub fn is_capture(&self, a_move: Move) -> bool {
+        match a_move {
+            Move::RegularMove(sqr) | Move::PawnPromotion(sqr, _) => {
+                self.board[sqr.index()].is_some()
+            }
+            Move::EnPassant(_) => true,
+            _ => false,
+        }
+    }
+
+    // Captures and promotions change the material on the board, everything else is quiet.
+    pub fn is_noisy(&self, a_move: Move) -> bool {
+        matches!(a_move, Move::PawnPromotion(_, _)) || self.is_capture(a_move)
+    }
+
+    // Pseudo-legal captures and promotions for the side to move. Only pawns go through the
+    // full move generator, the other pieces look straight for something to take.
+    pub fn generate_noisy_moves(&self, noisy_moves: &mut Vec<BoardMove>) {
+        let color = self.side_to_move;
+        let mut moves_list = [None; MAX_PIECE_MOVES];
+        for (index, item) in self
+            .board
+            .into_iter()
+            .enumerate()
+            .filter(|tuple| is_some_and_same_color(tuple.1, color))
+        {
+            let square = Square::from_index(index as u8);
+            let mut add_if_enemy = |sqr: Square| {
+                if let Some(piece) = self.board[sqr.index()] {
+                    if piece.color != color {
+                        noisy_moves.push((square, Move::RegularMove(sqr)));
+                    }
+                }
+            };
+            match item.unwrap() {
+                piece @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                } => {
+                    piece.get_moves(self, square, &mut moves_list);
+                    noisy_moves.extend(
+                        moves_list
+                            .into_iter()
+                            .map_while(|item| item)
+                            .filter(|each_move| self.is_noisy(*each_move))
+                            .map(|each_move| (square, each_move)),
+                    );
+                }
+                Piece {
+                    kind: PieceKind::Knight,
+                    ..
+                } => knight_squares(square)
+                    .into_iter()
+                    .flatten()
+                    .for_each(add_if_enemy),
+                Piece {
+                    kind: PieceKind::King,
+                    ..
+                } => King::get_adjacent_squares(square)
+                    .into_iter()
+                    .flatten()
+                    .for_each(add_if_enemy),
+                piece => {
+                    let directions: &[fn(Square) -> Option<Square>] = match piece {
+                        Piece {
+                            kind: PieceKind::Bishop,
+                            ..
+                        } => &[
+                            Square::up_left,
+                            Square::up_right,
+                            Square::down_left,
+                            Square::down_right,
+                        ],
+                        Piece {
+                            kind: PieceKind::Rook,
+                            ..
+                        } => &[Square::up, Square::down, Square::left, Square::right],
+                        _ => &[
+                            Square::up_left,
+                            Square::up_right,
+                            Square::down_left,
+                            Square::down_right,
+                            Square::up,
+                            Square::down,
+                            Square::left,
+                            Square::right,
+                        ],
+                    };
+                    for direction in directions {
+                        if let Some((sqr, _)) = self.first_piece_in_line(square, *direction) {
+                            add_if_enemy(sqr);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Pseudo-legal moves for the side to move that neither capture nor promote.
+    pub fn generate_quiet_moves(&self, quiet_moves: &mut Vec<BoardMove>) {
+        let color = self.side_to_move;
+        let mut moves_list = [None; MAX_PIECE_MOVES];
+        for (index, item) in self
+            .board
+            .into_iter()
+            .enumerate()
+            .filter(|tuple| is_some_and_same_color(tuple.1, color))
+        {
+            let square = Square::from_index(index as u8);
+            item.unwrap().get_moves(self, square, &mut moves_list);
+            quiet_moves.extend(
+                moves_list
+                    .into_iter()
+                    .map_while(|item| item)
+                    .filter(|each_move| !self.is_noisy(*each_move))
+                    .map(|each_move| (square, each_move)),
+            );
+        }
+        self.generate_drops(quiet_moves);
+    }
+
+    // Could the piece on the start square play this move right now? Used to check moves
+    // remembered from other positions (hash moves, killers) before trusting them.
+    pub fn is_pseudo_legal(&self, start_square: Square, a_move: Move) -> bool {
+        let color = self.side_to_move;
+        if let Move::Drop(sqr, piece) = a_move {
+            // Kings and fairy pieces are never in hand.
+            if !Pockets::can_hold(piece.kind) {
+                return false;
+            }
+            return sqr == start_square
+                && piece.color == color
+                && self.board[sqr.index()].is_none()
+                && (piece.kind != PieceKind::Pawn || !matches!(sqr.rank().index(), 0 | 7))
+                && self
+                    .pockets
+                    .is_some_and(|pockets| pockets.count(color, piece.kind) > 0);
+        }
+        match self.board[start_square.index()] {
+            Some(piece) if piece.color == color => {
+                let mut moves_list = [None; MAX_PIECE_MOVES];
+                piece.get_moves(self, start_square, &mut moves_list);
+                moves_list
+                    .into_iter()
+                    .map_while(|item| item)
+                    .any(|each_move| each_move == a_move)
+            }
+            _ => false,
+        }
+    }
+}
+
+// The (up to) 8 squares a knight on the given square jumps to.
+pub(crate) fn knight_squares(square: Square) -> [Option<Square>; 8] {
+    [
+        square.up().and_then(Square::up_left),
+        square.up().and_then(Square::up_right),
+        square.down().and_then(Square::down_left),
+        square.down().and_then(Square::down_right),
+        square.right().and_then(Square::up_right),
+        square.right().and_then(Square::down_right),
+        square.left().and_then(Square::up_left),
+        square.left().and_then(Square::down_left),
+    ]
 }
 
 fn is_some_and_same_color(possible_piece: Option<Piece>, color: Color) -> bool {
     if possible_piece.is_none() {
         return false;
     }
-    if possible_piece.unwrap().get_color() == color {
+    if possible_piece.unwrap().color == color {
         return true;
     }
     false
@@ -554,26 +1451,7 @@ impl fmt::Display for Board {
         let mut board = String::new();
         for (square_counter, square) in self.board.into_iter().enumerate() {
             if let Some(piece) = square {
-                match piece {
-                    Piece::Pawn(piece) => {
-                        board.push(if piece.color.is_white() { '♙' } else { '♟' })
-                    }
-                    Piece::Knight(piece) => {
-                        board.push(if piece.color.is_white() { '♘' } else { '♞' })
-                    }
-                    Piece::Bishop(piece) => {
-                        board.push(if piece.color.is_white() { '♗' } else { '♝' })
-                    }
-                    Piece::Rook(piece) => {
-                        board.push(if piece.color.is_white() { '♖' } else { '♜' })
-                    }
-                    Piece::Queen(piece) => {
-                        board.push(if piece.color.is_white() { '♕' } else { '♛' })
-                    }
-                    Piece::King(piece) => {
-                        board.push(if piece.color.is_white() { '♔' } else { '♚' })
-                    }
-                }
+                board.push(self.piece_glyph(piece));
             } else {
                 board.push('.');
             }
@@ -590,3 +1468,239 @@ impl Default for Board {
         Self::new()
     }
 }
+
+// Why the FEN can't be read, if it can't. Looks at the fields one by one, not at whether the
+// position makes sense.
+fn check_fen(fen: &str, pieces: Option<&PieceSet>) -> Result<(), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let Some(placement) = fields.first() else {
+        return Err("empty FEN".to_string());
+    };
+    if fields.len() > 6 {
+        return Err(format!("{} fields, not 6", fields.len()));
+    }
+    let (placement, hand) = match placement.split_once('[') {
+        Some((placement, hand)) => match hand.strip_suffix(']') {
+            Some(hand) => (placement, Some(hand)),
+            None => return Err(format!("unclosed pieces in hand \"[{hand}\"")),
+        },
+        None if placement.matches('/').count() == 8 => {
+            let (placement, hand) = placement.rsplit_once('/').unwrap();
+            (placement, Some(hand))
+        }
+        None => (*placement, None),
+    };
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("{} ranks, not 8", ranks.len()));
+    }
+    for rank in ranks {
+        let mut files = 0;
+        for ch in rank.chars() {
+            match ch {
+                '1'..='8' => files += ch.to_digit(10).unwrap(),
+                '~' if files > 0 => (),
+                ch if "pnbrqk".contains(ch.to_ascii_lowercase())
+                    || pieces.is_some_and(|pieces| pieces.from_fen_char(ch).is_some()) =>
+                {
+                    files += 1
+                }
+                _ => return Err(format!("unknown piece '{ch}' in rank \"{rank}\"")),
+            }
+        }
+        if files != 8 {
+            return Err(format!("rank \"{rank}\" has {files} files, not 8"));
+        }
+    }
+    if let Some(ch) = hand.and_then(|hand| {
+        hand.chars()
+            .find(|ch| !"pnbrq".contains(ch.to_ascii_lowercase()))
+    }) {
+        return Err(format!("unknown piece '{ch}' in hand"));
+    }
+    if let Some(ch) = hand.and_then(|hand| {
+        "PNBRQpnbrq"
+            .chars()
+            .find(|ch| hand.matches(*ch).count() > u8::MAX as usize)
+    }) {
+        return Err(format!("more than {} '{ch}' in hand", u8::MAX));
+    }
+    if let Some(color) = fields.get(1) {
+        if !matches!(*color, "w" | "b") {
+            return Err(format!("bad side to move \"{color}\""));
+        }
+    }
+    if let Some(castling) = fields.get(2) {
+        if *castling != "-" && !castling.chars().all(|ch| "KQkq".contains(ch)) {
+            return Err(format!("bad castling rights \"{castling}\""));
+        }
+    }
+    if let Some(en_passant) = fields.get(3) {
+        let is_square = en_passant
+            .parse::<Square>()
+            .is_ok_and(|square| matches!(square.rank().index(), 2 | 5));
+        if *en_passant != "-" && !is_square {
+            return Err(format!("bad en passant square \"{en_passant}\""));
+        }
+    }
+    if let Some(clock) = fields.get(4) {
+        if clock.parse::<u8>().is_err() {
+            return Err(format!("bad halfmove clock \"{clock}\""));
+        }
+    }
+    if let Some(moves) = fields.get(5) {
+        if moves.parse::<u32>().is_err() {
+            return Err(format!("bad move number \"{moves}\""));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_hold_at_most_255_pieces_of_a_kind() {
+        let fen = |pawns: usize| format!("4k3/8/8/8/8/8/8/4K3[{}] w - - 0 1", "P".repeat(pawns));
+        let board = Board::try_from_fen(&fen(255)).unwrap();
+        assert_eq!(
+            board.pockets.unwrap().count(Color::White, PieceKind::Pawn),
+            255
+        );
+        assert!(matches!(
+            Board::try_from_fen(&fen(256)),
+            Err(ChessError::InvalidFen(_))
+        ));
+    }
+
+    #[test]
+    fn drops_of_pieces_not_in_hand_are_errors() {
+        let board = Board::try_from_fen("4k3/8/8/8/8/8/8/4K3[Pn] w - - 0 1").unwrap();
+        let e4: Square = "e4".parse().unwrap();
+        let drop = |kind| Move::Drop(e4, Piece::new(kind, Color::White));
+        assert!(board.try_make_move(e4, drop(PieceKind::Pawn)).is_ok());
+        for kind in [PieceKind::King, PieceKind::Fairy(0), PieceKind::Knight] {
+            assert!(matches!(
+                board.try_make_move(e4, drop(kind)),
+                Err(ChessError::IllegalMove(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn sides_with_more_than_sixteen_pieces_get_moves() {
+        let board = Board::try_from_fen("4k3/8/PPPPPPPP/8/PPPPPPPP/8/8/Q3K3 w - - 0 1").unwrap();
+        let mut moves = PositionMoves::new();
+        board.generate_moves(&mut [None; MAX_PIECE_MOVES], &mut moves);
+        assert_eq!(moves.len(), 18);
+        let a1: Square = "a1".parse().unwrap();
+        let a2: Square = "a2".parse().unwrap();
+        assert!(board.legal_moves().contains(&(a1, Move::RegularMove(a2))));
+        assert!(board.try_make_move(a1, Move::RegularMove(a2)).is_ok());
+    }
+
+    #[test]
+    fn moving_the_opponents_piece_is_an_error() {
+        let board = Board::new();
+        let e7: Square = "e7".parse().unwrap();
+        let e6: Square = "e6".parse().unwrap();
+        assert!(matches!(
+            board.try_make_move(e7, Move::RegularMove(e6)),
+            Err(ChessError::IllegalMove(_))
+        ));
+        let board = board.make_move(
+            "e2".parse().unwrap(),
+            Move::RegularMove("e3".parse().unwrap()),
+        );
+        assert!(board.try_make_move(e7, Move::RegularMove(e6)).is_ok());
+    }
+
+    fn issues(fen: &str) -> Vec<BoardIssue> {
+        Board::from_fen(fen).validate().err().unwrap_or_default()
+    }
+
+    #[test]
+    fn the_start_position_is_valid() {
+        assert_eq!(Board::new().validate(), Ok(()));
+    }
+
+    #[test]
+    fn each_side_needs_one_king() {
+        assert!(
+            issues("8/8/8/8/8/8/8/4K3 w - - 0 1").contains(&BoardIssue::KingCount {
+                color: Color::Black,
+                count: 0
+            })
+        );
+        assert_eq!(
+            issues("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"),
+            vec![BoardIssue::KingCount {
+                color: Color::White,
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn the_recorded_king_square_has_the_king() {
+        let mut board = Board::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        board.board.swap(4, 3);
+        assert_eq!(
+            board.validate(),
+            Err(vec![BoardIssue::KingPosition {
+                color: Color::White,
+                recorded: "e1".parse().unwrap()
+            }])
+        );
+    }
+
+    #[test]
+    fn castling_rights_need_the_king_and_rook_at_home() {
+        assert_eq!(
+            issues("4k3/8/8/8/8/8/8/4K3 w K - 0 1"),
+            vec![BoardIssue::CastlingRight {
+                color: Color::White,
+                kingside: true
+            }]
+        );
+        assert_eq!(issues("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), vec![]);
+    }
+
+    #[test]
+    fn en_passant_needs_a_pawn_that_just_advanced_two_squares() {
+        let e4: Square = "e4".parse().unwrap();
+        assert_eq!(
+            issues("4k3/8/8/8/8/8/8/4K3 b - e3 0 1"),
+            vec![BoardIssue::EnPassant(e4)]
+        );
+        assert_eq!(issues("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1"), vec![]);
+    }
+
+    #[test]
+    fn pawns_cannot_stand_on_the_back_ranks() {
+        let a1: Square = "a1".parse().unwrap();
+        assert_eq!(
+            issues("4k3/8/8/8/8/8/8/P3K3 w - - 0 1"),
+            vec![BoardIssue::PawnOnBackRank(a1)]
+        );
+    }
+
+    #[test]
+    fn the_side_that_just_moved_cannot_be_in_check() {
+        assert_eq!(
+            issues("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"),
+            vec![BoardIssue::OpponentInCheck(Color::Black)]
+        );
+        assert_eq!(issues("4k3/8/8/8/8/8/8/4R1K1 b - - 0 1"), vec![]);
+    }
+
+    #[test]
+    fn pieces_beyond_what_promotions_give_are_too_many() {
+        assert_eq!(
+            issues("4k3/8/8/8/P7/PPPPPPPP/8/4K3 w - - 0 1"),
+            vec![BoardIssue::PieceCount(Color::White)]
+        );
+        assert_eq!(issues("4k3/8/8/8/8/8/NNNNNNNN/4K3 w - - 0 1"), vec![]);
+    }
+}
@@ -0,0 +1,210 @@
+use crate::board::{BoardIssue, CanCastle, CanEnPassant, Pockets};
+use crate::fairy::PieceSet;
+use crate::{Board, ChessError, Color, Piece, PieceKind, Square};
+use std::sync::Arc;
+
+// Sets a position up piece by piece, for position editors and the like. Nothing is checked
+// until build(), so the position can go through any state on the way.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoardBuilder {
+    board: Board,
+    // The square behind the pawn that just advanced two squares, as FEN gives it.
+    en_passant: Option<Square>,
+}
+
+impl BoardBuilder {
+    // An empty board, white to move, without castling rights.
+    pub fn new() -> Self {
+        let mut board = Board::empty();
+        board.can_castle = CanCastle {
+            white_kingside: false,
+            white_queenside: false,
+            black_kingside: false,
+            black_queenside: false,
+        };
+        BoardBuilder {
+            board,
+            en_passant: None,
+        }
+    }
+
+    // Start from a position, to edit it.
+    pub fn from_board(board: &Board) -> Self {
+        let en_passant = match board.can_en_passant {
+            CanEnPassant::Yes(square) if board.side_to_move.is_white() => square.up(),
+            CanEnPassant::Yes(square) => square.down(),
+            CanEnPassant::No => None,
+        };
+        BoardBuilder {
+            board: board.clone(),
+            en_passant,
+        }
+    }
+
+    // Put the piece on the square, replacing whatever was there.
+    pub fn put(mut self, square: Square, piece: Piece) -> Self {
+        self.board.board[square.index()] = Some(piece);
+        self.forget_promotion(square);
+        self
+    }
+
+    pub fn remove(mut self, square: Square) -> Self {
+        self.board.board[square.index()] = None;
+        self.forget_promotion(square);
+        self
+    }
+
+    // Whatever is put on the square now wasn't promoted, in drop variants.
+    fn forget_promotion(&mut self, square: Square) {
+        if let Some(pockets) = &mut self.board.pockets {
+            pockets.promoted &= !(1 << square.index());
+        }
+    }
+
+    // Take every piece off the board, and out of the hands in drop variants.
+    pub fn clear(mut self) -> Self {
+        self.board.board = [None; 64];
+        if let Some(pockets) = &mut self.board.pockets {
+            *pockets = Pockets::default();
+        }
+        self
+    }
+
+    pub fn set_side_to_move(mut self, color: Color) -> Self {
+        self.board.side_to_move = color;
+        self
+    }
+
+    pub fn set_castling(mut self, can_castle: CanCastle) -> Self {
+        self.board.can_castle = can_castle;
+        self
+    }
+
+    // The square a pawn can take en passant on (e6 after e7e5), or None.
+    pub fn set_ep(mut self, square: Option<Square>) -> Self {
+        self.en_passant = square;
+        self
+    }
+
+    pub fn set_halfmove_clock(mut self, halfmove_clock: u8) -> Self {
+        self.board.halfmove_clock = halfmove_clock;
+        self
+    }
+
+    // Pieces in hand, making it a board for drop variants. None for standard chess.
+    pub fn set_pockets(mut self, pockets: Option<Pockets>) -> Self {
+        self.board.pockets = pockets;
+        self
+    }
+
+    // The fairy pieces the position plays with, so PieceKind::Fairy pieces can be put on it.
+    pub fn set_fairy_pieces(mut self, pieces: PieceSet) -> Self {
+        self.board.fairy = Some(Arc::new(pieces));
+        self
+    }
+
+    // The board, if Board::validate finds nothing wrong with it.
+    pub fn build(self) -> Result<Board, ChessError> {
+        let mut board = self.board;
+        let color = board.side_to_move;
+        for side in [Color::White, Color::Black] {
+            let king = Square::all().find(|square| {
+                board.board[square.index()] == Some(Piece::new(PieceKind::King, side))
+            });
+            if let Some(king) = king {
+                if side.is_white() {
+                    board.white_king_pos = king;
+                } else {
+                    board.black_king_pos = king;
+                }
+            }
+        }
+        board.can_en_passant = match self.en_passant {
+            // The pawn that advanced is one square further from its own side.
+            Some(square) => {
+                let pawn = if color.is_white() {
+                    square.down()
+                } else {
+                    square.up()
+                };
+                match pawn {
+                    Some(pawn) => CanEnPassant::Yes(pawn),
+                    None => {
+                        return Err(ChessError::InvalidPosition(vec![BoardIssue::EnPassant(
+                            square,
+                        )]))
+                    }
+                }
+            }
+            None => CanEnPassant::No,
+        };
+        board.validate().map_err(ChessError::InvalidPosition)?;
+        Ok(board)
+    }
+}
+
+impl Default for BoardBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn boards_without_kings_are_rejected() {
+        let built = BoardBuilder::new()
+            .put(square("e1"), Piece::new(PieceKind::King, Color::White))
+            .build();
+        match built {
+            Err(ChessError::InvalidPosition(issues)) => assert_eq!(
+                issues,
+                vec![
+                    BoardIssue::KingCount {
+                        color: Color::Black,
+                        count: 0
+                    },
+                    BoardIssue::KingPosition {
+                        color: Color::Black,
+                        recorded: square("e8")
+                    }
+                ]
+            ),
+            other => panic!("expected an invalid position, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn built_boards_match_their_fen() {
+        let board = BoardBuilder::new()
+            .put(square("e1"), Piece::new(PieceKind::King, Color::White))
+            .put(square("e8"), Piece::new(PieceKind::King, Color::Black))
+            .put(square("d4"), Piece::new(PieceKind::Pawn, Color::Black))
+            .put(square("e4"), Piece::new(PieceKind::Pawn, Color::White))
+            .set_side_to_move(Color::Black)
+            .set_ep(Some(square("e3")))
+            .build()
+            .unwrap();
+        assert_eq!(board.to_fen(), "4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1");
+        assert_eq!(board.king_square(Color::White), Some(square("e1")));
+        assert_eq!(
+            BoardBuilder::from_board(&board).build().unwrap(),
+            Board::try_from_fen(&board.to_fen()).unwrap()
+        );
+    }
+
+    #[test]
+    fn kingless_boards_have_no_king_square() {
+        let board = BoardBuilder::from_board(&Board::new())
+            .remove(square("e1"))
+            .board;
+        assert_eq!(board.king_square(Color::White), None);
+        assert_eq!(board.king_square(Color::Black), Some(square("e8")));
+    }
+}
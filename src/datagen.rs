@@ -0,0 +1,467 @@
+use crate::board::{CanCastle, CanEnPassant};
+use crate::book::{BookSelection, OpeningBook};
+use crate::pgn::GameResult;
+use crate::search::{SearchConfig, SearchLimits, Searcher};
+use crate::tournament::game_over;
+use crate::tt::TB_WIN_BOUND;
+use crate::{zobrist, Board, BoardMove, Color, Square};
+use std::io;
+
+// Training data from engine self-play: games at a fixed number of nodes per move, starting
+// from book or random openings, and every quiet position of them with its search score and
+// the result of the game. A game only depends on its seed, so a run can be reproduced.
+
+// Positions the search scores at least this high (for one side, several plies running) are
+// adjudicated as wins.
+const WIN_ADJUDICATION_SCORE: i32 = 2000;
+const WIN_ADJUDICATION_PLIES: usize = 4;
+// Games that go on this long are called draws.
+const MAX_GAME_PLIES: usize = 400;
+// Openings that come out more unbalanced than this are thrown away and tried again.
+const MAX_OPENING_SCORE: i32 = 400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DatagenConfig {
+    pub nodes: u64,
+    // Random moves played after the book runs out (or from the start without a book).
+    pub random_plies: usize,
+    pub hash_megabytes: usize,
+}
+
+impl Default for DatagenConfig {
+    fn default() -> Self {
+        DatagenConfig {
+            nodes: 5000,
+            random_plies: 8,
+            hash_megabytes: 16,
+        }
+    }
+}
+
+// A position worth training on, with its score from white's point of view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataPosition {
+    pub board: Board,
+    pub score: i32,
+}
+
+// Xorshift64*, seeded through splitmix64 so nearby seeds give unrelated games.
+#[derive(Clone, Copy, Debug)]
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Random((z ^ (z >> 31)).max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+// The seed of one game of a run, so games can be handed out to threads in any order.
+pub fn game_seed(run_seed: u64, game: u64) -> u64 {
+    Random::new(run_seed ^ game.wrapping_mul(0x9E37_79B9_7F4A_7C15)).next_u64()
+}
+
+// Plays games from one thread, with a search of its own.
+pub struct GameGenerator {
+    config: DatagenConfig,
+    searcher: Searcher,
+    book: Option<OpeningBook>,
+}
+
+impl GameGenerator {
+    pub fn new(config: DatagenConfig, book: Option<OpeningBook>) -> Self {
+        GameGenerator {
+            config,
+            searcher: Searcher::new(SearchConfig::default(), config.hash_megabytes),
+            book,
+        }
+    }
+
+    // Plays one game and returns its quiet positions and its result.
+    pub fn play(&mut self, seed: u64) -> (Vec<DataPosition>, GameResult) {
+        let mut random = Random::new(seed);
+        if let Some(book) = &mut self.book {
+            book.set_seed(random.next_u64());
+        }
+        let (mut board, mut history) = self.opening(&mut random);
+        self.searcher.new_game();
+
+        let limits = SearchLimits {
+            nodes: Some(self.config.nodes),
+            ..Default::default()
+        };
+        let mut positions = Vec::new();
+        let mut winning_plies = 0;
+        let mut last_winner = None;
+        let result = loop {
+            let color = board.side_to_move();
+            if let Some((result, _)) = game_over(&board, color, &history) {
+                break result;
+            }
+            if history.len() >= MAX_GAME_PLIES {
+                break GameResult::Draw;
+            }
+            self.searcher.set_game_history(history.clone());
+            let search = self.searcher.search_with_limits(&board, &limits, |_| ());
+            let Some((start, a_move)) = search.best_move else {
+                break GameResult::Draw;
+            };
+            let white_score = if color.is_white() {
+                search.score
+            } else {
+                -search.score
+            };
+
+            // Adjudicate clear wins instead of playing them out.
+            let winner =
+                (white_score.abs() >= WIN_ADJUDICATION_SCORE).then_some(if white_score > 0 {
+                    GameResult::WhiteWins
+                } else {
+                    GameResult::BlackWins
+                });
+            winning_plies = if winner.is_some() && winner == last_winner {
+                winning_plies + 1
+            } else {
+                usize::from(winner.is_some())
+            };
+            last_winner = winner;
+            if let (Some(winner), true) = (winner, winning_plies >= WIN_ADJUDICATION_PLIES) {
+                break winner;
+            }
+
+            // Only quiet positions are kept: the score of a position in check or in the middle
+            // of an exchange says little about what the evaluation should give it.
+            if !board.is_in_check(color)
+                && !board.is_noisy(a_move)
+                && search.score.abs() < TB_WIN_BOUND
+            {
+                positions.push(DataPosition {
+                    board: board.clone(),
+                    score: white_score,
+                });
+            }
+            history.push(zobrist::hash(&board));
+            board = board.make_move(start, a_move);
+        };
+        (positions, result)
+    }
+
+    // Book moves while the book has any, then random ones. Openings that end the game or that
+    // leave one side far ahead are played again with the next random numbers.
+    fn opening(&mut self, random: &mut Random) -> (Board, Vec<u64>) {
+        loop {
+            let mut board = Board::new();
+            let mut history = Vec::new();
+            let mut in_book = self.book.is_some();
+            let mut random_plies = 0;
+            while random_plies < self.config.random_plies {
+                let book_move = match &mut self.book {
+                    Some(book) if in_book => {
+                        book.choose_move(&board, BookSelection::WeightedRandom)
+                    }
+                    _ => None,
+                };
+                in_book = book_move.is_some();
+                let (start, a_move): BoardMove = match book_move {
+                    Some(book_move) => book_move,
+                    None => {
+                        let moves = board.legal_moves();
+                        if moves.is_empty() {
+                            break;
+                        }
+                        random_plies += 1;
+                        moves[random.next_u64() as usize % moves.len()]
+                    }
+                };
+                history.push(zobrist::hash(&board));
+                board = board.make_move(start, a_move);
+            }
+            if game_over(&board, board.side_to_move(), &history).is_some() {
+                continue;
+            }
+            self.searcher.new_game();
+            let limits = SearchLimits {
+                nodes: Some(self.config.nodes),
+                ..Default::default()
+            };
+            let search = self.searcher.search_with_limits(&board, &limits, |_| ());
+            if search.score.abs() <= MAX_OPENING_SCORE {
+                return (board, history);
+            }
+        }
+    }
+}
+
+// One line of the text format: the FEN, the score for white and the result for white as
+// 1.0, 0.5 or 0.0, separated by " | ". The tune binary reads these as they are.
+pub fn text_line(position: &DataPosition, result: GameResult) -> String {
+    format!(
+        "{} | {} | {:.1}",
+        position.board.to_fen(),
+        position.score,
+        result.points_for(Color::White) as f64 / 2.0
+    )
+}
+
+// Size of a position in the packed format.
+pub const PACKED_SIZE: usize = 32;
+
+// The packed format, 32 bytes a position, numbers little-endian:
+//   0..8   occupied squares, bit 0 = a1
+//   8..24  the pieces of the occupied squares from a1 up, 4 bits each (low half of the byte
+//          first): 1 pawn, 2 knight, 3 bishop, 4 rook, 5 queen, 6 king, plus 8 for black
+//   24     side to move, 0 white and 1 black
+//   25     castling rights: 1 white kingside, 2 white queenside, 4 black kingside,
+//          8 black queenside
+//   26     en passant square, or 64 for none
+//   27     halfmove clock
+//   28..30 score for white, as an i16
+//   30     result for white: 0 loss, 1 draw, 2 win
+//   31     unused, 0
+pub fn pack(position: &DataPosition, result: GameResult) -> [u8; PACKED_SIZE] {
+    let board = &position.board;
+    let mut bytes = [0; PACKED_SIZE];
+    let mut occupied: u64 = 0;
+    let mut index = 0;
+    for (square, piece) in board.board.iter().enumerate() {
+        if let Some(piece) = piece {
+            occupied |= 1 << square;
+            let code = piece.kind.index() as u8 + 1 + if piece.color.is_white() { 0 } else { 8 };
+            bytes[8 + index / 2] |= code << (4 * (index % 2));
+            index += 1;
+        }
+    }
+    bytes[..8].copy_from_slice(&occupied.to_le_bytes());
+    bytes[24] = position.board.side_to_move().index() as u8;
+    let castle = board.can_castle;
+    bytes[25] = [
+        castle.white_kingside,
+        castle.white_queenside,
+        castle.black_kingside,
+        castle.black_queenside,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |rights, (bit, right)| rights | (u8::from(*right) << bit));
+    bytes[26] = match board.can_en_passant {
+        CanEnPassant::Yes(square) => square.into(),
+        CanEnPassant::No => 64,
+    };
+    bytes[27] = board.halfmove_clock;
+    let score = position.score.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+    bytes[28..30].copy_from_slice(&score.to_le_bytes());
+    bytes[30] = result.points_for(Color::White) as u8;
+    bytes
+}
+
+// Reads a position back from the packed format.
+pub fn unpack(bytes: &[u8; PACKED_SIZE]) -> io::Result<(DataPosition, GameResult)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let occupied = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+    if occupied.count_ones() > 32 {
+        return Err(invalid("more than 32 pieces"));
+    }
+    // Rebuild the placement as FEN and let the FEN parser set up the rest.
+    let mut letters = ['.'; 64];
+    let mut index = 0;
+    for (square, letter) in letters.iter_mut().enumerate() {
+        if occupied & (1 << square) == 0 {
+            continue;
+        }
+        let code = (bytes[8 + index / 2] >> (4 * (index % 2))) & 0xF;
+        let kind = (code & 7) as usize;
+        if !(1..=6).contains(&kind) {
+            return Err(invalid("bad piece code"));
+        }
+        let black = code & 8 != 0;
+        let piece = b"PNBRQK"[kind - 1] as char;
+        *letter = if black {
+            piece.to_ascii_lowercase()
+        } else {
+            piece
+        };
+        index += 1;
+    }
+    let mut placement = String::new();
+    for rank in (0..8).rev() {
+        let mut empty = 0;
+        for file in 0..8 {
+            match letters[rank * 8 + file] {
+                '.' => empty += 1,
+                letter => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(letter);
+                }
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if rank > 0 {
+            placement.push('/');
+        }
+    }
+    let color = match bytes[24] {
+        0 => Color::White,
+        1 => Color::Black,
+        _ => return Err(invalid("bad side to move")),
+    };
+    let mut board = Board::from_fen(&format!(
+        "{placement} {} - - 0 1",
+        if color.is_white() { 'w' } else { 'b' }
+    ));
+    let rights = bytes[25];
+    board.can_castle = CanCastle {
+        white_kingside: rights & 1 != 0,
+        white_queenside: rights & 2 != 0,
+        black_kingside: rights & 4 != 0,
+        black_queenside: rights & 8 != 0,
+    };
+    board.can_en_passant = match bytes[26] {
+        64 => CanEnPassant::No,
+        square => match Square::new(square) {
+            Some(square) => CanEnPassant::Yes(square),
+            None => return Err(invalid("bad en passant square")),
+        },
+    };
+    board.halfmove_clock = bytes[27];
+    let score = i16::from_le_bytes([bytes[28], bytes[29]]) as i32;
+    let result = match bytes[30] {
+        0 => GameResult::BlackWins,
+        1 => GameResult::Draw,
+        2 => GameResult::WhiteWins,
+        _ => return Err(invalid("bad result")),
+    };
+    Ok((DataPosition { board, score }, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn position(fen: &str, score: i32) -> DataPosition {
+        DataPosition {
+            board: Board::from_fen(fen),
+            score,
+        }
+    }
+
+    #[test]
+    fn positions_pack_and_unpack() {
+        let positions = [
+            (
+                position(
+                    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+                    35,
+                ),
+                GameResult::WhiteWins,
+            ),
+            (
+                position(
+                    "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b Kq e3 0 1",
+                    -120,
+                ),
+                GameResult::Draw,
+            ),
+            (
+                position("8/8/4k3/8/8/2K5/8/7q w - - 37 1", -40000),
+                GameResult::BlackWins,
+            ),
+        ];
+        for (position, result) in positions {
+            let (unpacked, unpacked_result) = unpack(&pack(&position, result)).unwrap();
+            assert_eq!(unpacked.board.to_fen(), position.board.to_fen());
+            assert_eq!(unpacked.score, position.score.max(i16::MIN as i32));
+            assert_eq!(unpacked_result, result);
+        }
+    }
+
+    #[test]
+    fn bad_packed_positions_are_errors() {
+        let good = pack(&position(START, 0), GameResult::Draw);
+        let mut too_many = good;
+        too_many[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let mut bad_piece = good;
+        bad_piece[8] = 0x07;
+        let mut bad_side = good;
+        bad_side[24] = 2;
+        let mut bad_result = good;
+        bad_result[30] = 3;
+        for bytes in [too_many, bad_piece, bad_side, bad_result] {
+            assert_eq!(
+                unpack(&bytes).unwrap_err().kind(),
+                io::ErrorKind::InvalidData
+            );
+        }
+    }
+
+    #[test]
+    fn text_lines_give_the_score_and_result_for_white() {
+        let start = position(START, 15);
+        assert_eq!(
+            text_line(&start, GameResult::BlackWins),
+            format!("{} | 15 | 0.0", START)
+        );
+        assert!(text_line(&start, GameResult::Draw).ends_with(" | 0.5"));
+        assert!(text_line(&start, GameResult::WhiteWins).ends_with(" | 1.0"));
+    }
+
+    #[test]
+    fn a_seed_always_plays_the_same_game() {
+        assert_eq!(game_seed(7, 3), game_seed(7, 3));
+        assert_ne!(game_seed(7, 3), game_seed(7, 4));
+        assert_ne!(game_seed(7, 3), game_seed(8, 3));
+
+        let config = DatagenConfig {
+            nodes: 300,
+            random_plies: 6,
+            hash_megabytes: 1,
+        };
+        let mut generator = GameGenerator::new(config, None);
+        let (positions, result) = generator.play(game_seed(1, 0));
+        let (again, again_result) = GameGenerator::new(config, None).play(game_seed(1, 0));
+        assert_eq!(positions, again);
+        assert_eq!(result, again_result);
+        assert_ne!(result, GameResult::Unknown);
+        assert!(!positions.is_empty());
+
+        let (other, _) = generator.play(game_seed(1, 1));
+        assert_ne!(positions[0].board, other[0].board);
+    }
+
+    #[test]
+    fn only_quiet_positions_are_kept() {
+        let config = DatagenConfig {
+            nodes: 300,
+            random_plies: 6,
+            hash_megabytes: 1,
+        };
+        let mut generator = GameGenerator::new(config, None);
+        let (positions, _) = generator.play(game_seed(2, 0));
+        for position in positions {
+            let board = &position.board;
+            assert!(
+                !board.is_in_check(board.side_to_move()),
+                "{}",
+                board.to_fen()
+            );
+            assert!(position.score.abs() < TB_WIN_BOUND);
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use crate::board::{CanCastle, CanEnPassant};
+use crate::{Board, Color, Move, Piece, PieceKind, Square};
+use std::sync::OnceLock;
+
+// Endgame knowledge that the general evaluation gets wrong, picked by the material on the
+// board. Some endgames get a score of their own (KPK from a bitbase, and the basic mates),
+// others keep the general score but scale its endgame part down towards a draw.
+
+// Score for a position that's won but not yet a mate the search can see. Well below the
+// tablebase and mate scores, well above anything the material count reaches.
+pub const KNOWN_WIN: i32 = 10000;
+
+// Scale factors are out of SCALE_NORMAL; 0 is a dead draw.
+pub const SCALE_NORMAL: i32 = 64;
+const SCALE_OPPOSITE_BISHOPS: i32 = 16;
+const SCALE_OPPOSITE_BISHOPS_WITH_PIECES: i32 = 44;
+
+// Endgame values of the pieces, used for the scores of the specialised evaluators.
+const PIECE_VALUES: [i32; 6] = [94, 281, 297, 512, 936, 0];
+
+// The kinds that count as pieces rather than pawns.
+const PIECES: [PieceKind; 4] = [
+    PieceKind::Knight,
+    PieceKind::Bishop,
+    PieceKind::Rook,
+    PieceKind::Queen,
+];
+
+fn piece_value(kind: PieceKind) -> i32 {
+    PIECE_VALUES.get(kind.index()).copied().unwrap_or(0)
+}
+
+// How many pieces of each kind each side has, indexed by color (white first) and kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialSignature {
+    counts: [[u8; 6]; 2],
+}
+
+impl MaterialSignature {
+    pub fn of(board: &Board) -> Self {
+        let mut counts = [[0; 6]; 2];
+        // Fairy pieces aren't counted, nothing here knows about them.
+        for piece in board.board.iter().flatten() {
+            if let Some(count) = counts[piece.color.index()].get_mut(piece.kind.index()) {
+                *count += 1;
+            }
+        }
+        MaterialSignature { counts }
+    }
+
+    // Number of pieces of a kind the color has. Always 0 for fairy pieces.
+    pub fn count(&self, color: Color, kind: PieceKind) -> u8 {
+        self.counts[color.index()]
+            .get(kind.index())
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Number of knights, bishops, rooks and queens the color has.
+    pub fn piece_count(&self, color: Color) -> u8 {
+        PIECES.iter().map(|kind| self.count(color, *kind)).sum()
+    }
+
+    // Is the color down to its bare king?
+    pub fn is_lone_king(&self, color: Color) -> bool {
+        self.count(color, PieceKind::Pawn) == 0 && self.piece_count(color) == 0
+    }
+
+    // Does the color have exactly these knights, bishops, rooks and queens (and no pawns)?
+    fn has_exactly(&self, color: Color, pieces: [u8; 4]) -> bool {
+        self.count(color, PieceKind::Pawn) == 0
+            && PIECES.map(|kind| self.count(color, kind)) == pieces
+    }
+}
+
+fn squares_of(board: &Board, color: Color, kind: PieceKind) -> impl Iterator<Item = Square> + '_ {
+    board
+        .pieces()
+        .filter(move |(_, piece)| *piece == Piece::new(kind, color))
+        .map(|(square, _)| square)
+}
+
+// Bonus for the losing king being near the edge of the board, where it can be mated.
+fn push_to_edge(square: Square) -> i32 {
+    let from_edge = |index: u8| index.min(7 - index) as i32;
+    20 * (6 - from_edge(square.file().index()) - from_edge(square.rank().index()))
+}
+
+// Bonus for the kings being close together, since the winning king has to help with the mate.
+fn push_close(a: Square, b: Square) -> i32 {
+    20 * (7 - a.distance(b) as i32)
+}
+
+// Specialised score for the position, from the point of view of the side to move, if the
+// material is one of the endgames that has one.
+pub fn evaluate(board: &Board, material: &MaterialSignature) -> Option<i32> {
+    let color = board.side_to_move();
+    for strong in [Color::White, Color::Black] {
+        let weak = strong.reverse();
+        if !material.is_lone_king(weak) {
+            continue;
+        }
+        let kings = (board.king_square(strong)?, board.king_square(weak)?);
+        let score = if material.piece_count(strong) == 0
+            && material.count(strong, PieceKind::Pawn) == 1
+        {
+            evaluate_kpk(board, strong, kings, color)
+        } else if material.has_exactly(strong, [1, 1, 0, 0]) {
+            evaluate_kbnk(board, strong, kings)
+        } else if material.count(strong, PieceKind::Queen) + material.count(strong, PieceKind::Rook)
+            > 0
+        {
+            Some(evaluate_kxk(strong, kings, material))
+        } else {
+            None
+        };
+        if let Some(score) = score {
+            return Some(if strong == color { score } else { -score });
+        }
+    }
+    None
+}
+
+// King and pawn against king: won or drawn according to the bitbase. None for a pawn on the
+// first or last rank, which the bitbase has no entry for. `kings` are the strong side's king
+// and then the weak side's.
+fn evaluate_kpk(
+    board: &Board,
+    strong: Color,
+    (strong_king, weak_king): (Square, Square),
+    to_move: Color,
+) -> Option<i32> {
+    let pawn = squares_of(board, strong, PieceKind::Pawn).next()?;
+    if !kpk_is_win(strong, strong_king, pawn, weak_king, strong == to_move)? {
+        return Some(0);
+    }
+    let relative_rank = if strong.is_white() {
+        pawn.rank()
+    } else {
+        pawn.rank().mirror()
+    };
+    Some(KNOWN_WIN + piece_value(PieceKind::Pawn) + 10 * relative_rank.index() as i32)
+}
+
+// A queen or a rook (and maybe more) against a bare king: drive the king to the edge.
+fn evaluate_kxk(
+    strong: Color,
+    (strong_king, weak_king): (Square, Square),
+    material: &MaterialSignature,
+) -> i32 {
+    let material_score: i32 = PieceKind::all()
+        .map(|kind| material.count(strong, kind) as i32 * piece_value(kind))
+        .sum();
+    KNOWN_WIN + material_score + push_to_edge(weak_king) + push_close(strong_king, weak_king)
+}
+
+// Bishop and knight against a bare king: the mate only works in a corner the bishop covers,
+// so drive the king towards one of those.
+fn evaluate_kbnk(
+    board: &Board,
+    strong: Color,
+    (strong_king, weak_king): (Square, Square),
+) -> Option<i32> {
+    let bishop = squares_of(board, strong, PieceKind::Bishop).next()?;
+    // Measured for the a1 and h8 corners, so mirror the king for a light-squared bishop.
+    let corner_king = if bishop.is_dark() {
+        weak_king
+    } else {
+        weak_king.mirror_file()
+    };
+    let to_corner = 50 * (7 - corner_king.anti_diagonal() as i32).abs();
+    Some(
+        KNOWN_WIN
+            + piece_value(PieceKind::Knight)
+            + piece_value(PieceKind::Bishop)
+            + to_corner
+            + push_close(strong_king, weak_king),
+    )
+}
+
+// Factor to scale the endgame part of the score by, out of SCALE_NORMAL, for endgames that
+// are more drawish than the material says. `strong` is the side the score favours.
+pub fn scale_factor(board: &Board, material: &MaterialSignature, strong: Color) -> i32 {
+    let weak = strong.reverse();
+    if is_wrong_bishop_draw(board, material, strong) {
+        return 0;
+    }
+    if material.count(strong, PieceKind::Bishop) == 1
+        && material.count(weak, PieceKind::Bishop) == 1
+    {
+        let strong_bishop = squares_of(board, strong, PieceKind::Bishop).next();
+        let weak_bishop = squares_of(board, weak, PieceKind::Bishop).next();
+        if let (Some(strong_bishop), Some(weak_bishop)) = (strong_bishop, weak_bishop) {
+            if strong_bishop.is_dark() != weak_bishop.is_dark() {
+                let only_bishops =
+                    material.piece_count(strong) == 1 && material.piece_count(weak) == 1;
+                return if only_bishops {
+                    SCALE_OPPOSITE_BISHOPS
+                } else {
+                    SCALE_OPPOSITE_BISHOPS_WITH_PIECES
+                };
+            }
+        }
+    }
+    SCALE_NORMAL
+}
+
+// Bishop and rook pawns against a bare king, where the bishop can't cover the promotion
+// square and the defending king has got to it: a draw however many pawns there are.
+fn is_wrong_bishop_draw(board: &Board, material: &MaterialSignature, strong: Color) -> bool {
+    let weak = strong.reverse();
+    if !material.is_lone_king(weak)
+        || material.count(strong, PieceKind::Pawn) == 0
+        || material.piece_count(strong) != 1
+        || material.count(strong, PieceKind::Bishop) != 1
+    {
+        return false;
+    }
+    let mut pawns = squares_of(board, strong, PieceKind::Pawn);
+    let Some(pawn_file) = pawns.next().map(Square::file) else {
+        return false;
+    };
+    if !matches!(pawn_file.index(), 0 | 7) || pawns.any(|pawn| pawn.file() != pawn_file) {
+        return false;
+    }
+    let first_rank_square = Square::from_index(pawn_file.index());
+    let promotion_square = if strong.is_white() {
+        first_rank_square.mirror()
+    } else {
+        first_rank_square
+    };
+    let (Some(bishop), Some(weak_king)) = (
+        squares_of(board, strong, PieceKind::Bishop).next(),
+        board.king_square(weak),
+    ) else {
+        return false;
+    };
+    bishop.is_dark() != promotion_square.is_dark() && weak_king.distance(promotion_square) <= 1
+}
+
+// The KPK bitbase: for every position with the pawn on files a to d and white as the side with
+// the pawn, whether white wins. Generated by retrograde analysis the first time it's needed.
+const KPK_SIZE: usize = 2 * 24 * 64 * 64;
+
+static KPK: OnceLock<Vec<u64>> = OnceLock::new();
+
+// The pawn has to be on files a to d and ranks 2 to 7.
+fn kpk_index(white_king: Square, pawn: Square, black_king: Square, white_to_move: bool) -> usize {
+    let pawn_index = pawn.file().index() as usize * 6 + pawn.rank().index() as usize - 1;
+    ((pawn_index * 64 + white_king.index()) * 64 + black_king.index()) * 2 + white_to_move as usize
+}
+
+// Does the side with the pawn win? Squares are as on the board; the position is flipped so the
+// pawn is white's and on the queenside. None if the pawn is on the first or last rank.
+pub fn kpk_is_win(
+    strong: Color,
+    mut strong_king: Square,
+    mut pawn: Square,
+    mut weak_king: Square,
+    strong_to_move: bool,
+) -> Option<bool> {
+    if !(1..7).contains(&pawn.rank().index()) {
+        return None;
+    }
+    if !strong.is_white() {
+        strong_king = strong_king.mirror();
+        pawn = pawn.mirror();
+        weak_king = weak_king.mirror();
+    }
+    if pawn.file().index() > 3 {
+        strong_king = strong_king.mirror_file();
+        pawn = pawn.mirror_file();
+        weak_king = weak_king.mirror_file();
+    }
+    let index = kpk_index(strong_king, pawn, weak_king, strong_to_move);
+    let bits = KPK.get_or_init(generate_kpk);
+    Some(bits[index / 64] & (1 << (index % 64)) != 0)
+}
+
+// Build the bitbase now rather than in the middle of a search, where it takes long enough to
+// lose on time in fast games.
+pub fn init() {
+    KPK.get_or_init(generate_kpk);
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum KpkResult {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+// Where a move in the bitbase leads: another position in it, or straight to a known result.
+#[derive(Clone, Copy)]
+enum KpkEdge {
+    Position(u32),
+    Result(KpkResult),
+}
+
+fn kpk_board(white_king: Square, pawn: Square, black_king: Square, color: Color) -> Board {
+    let mut board = [None; 64];
+    board[white_king.index()] = Some(Piece::new(PieceKind::King, Color::White));
+    board[black_king.index()] = Some(Piece::new(PieceKind::King, Color::Black));
+    board[pawn.index()] = Some(Piece::new(PieceKind::Pawn, Color::White));
+    Board {
+        board,
+        can_en_passant: CanEnPassant::No,
+        can_castle: CanCastle {
+            white_kingside: false,
+            white_queenside: false,
+            black_kingside: false,
+            black_queenside: false,
+        },
+        white_king_pos: white_king,
+        black_king_pos: black_king,
+        is_check: false,
+        side_to_move: color,
+        halfmove_clock: 0,
+        pockets: None,
+        fairy: None,
+    }
+}
+
+// Result of a promotion: a win unless black, to move, takes the new piece or is stalemated.
+fn promotion_result(board: &Board, promotion_square: Square) -> KpkResult {
+    let replies = board.legal_moves();
+    if replies.is_empty() {
+        return if board.is_in_check(Color::Black) {
+            KpkResult::Win
+        } else {
+            KpkResult::Draw
+        };
+    }
+    let takes = replies
+        .iter()
+        .any(|(_, reply)| reply.end_square(Color::Black) == promotion_square);
+    if takes {
+        KpkResult::Draw
+    } else {
+        KpkResult::Win
+    }
+}
+
+// The moves out of every position, in index order, as lists of edges.
+fn kpk_moves(results: &mut [KpkResult]) -> (Vec<usize>, Vec<KpkEdge>) {
+    let mut starts = Vec::with_capacity(KPK_SIZE + 1);
+    let mut edges = Vec::new();
+    for pawn_index in 0..24 {
+        let pawn = Square::from_index((pawn_index % 6 + 1) * 8 + pawn_index / 6);
+        for white_king in Square::all() {
+            for black_king in Square::all() {
+                for white_to_move in [false, true] {
+                    starts.push(edges.len());
+                    let index = kpk_index(white_king, pawn, black_king, white_to_move);
+                    if white_king == pawn
+                        || black_king == pawn
+                        || white_king.distance(black_king) <= 1
+                    {
+                        continue;
+                    }
+                    let (color, other) = if white_to_move {
+                        (Color::White, Color::Black)
+                    } else {
+                        (Color::Black, Color::White)
+                    };
+                    let board = kpk_board(white_king, pawn, black_king, color);
+                    if board.is_in_check(other) {
+                        continue;
+                    }
+                    results[index] = KpkResult::Unknown;
+                    for (start, a_move) in board.legal_moves() {
+                        let end = a_move.end_square(color);
+                        let next = board.make_move(start, a_move);
+                        let edge = match a_move {
+                            Move::PawnPromotion(
+                                _,
+                                Piece {
+                                    kind: PieceKind::Queen,
+                                    ..
+                                }
+                                | Piece {
+                                    kind: PieceKind::Rook,
+                                    ..
+                                },
+                            ) => KpkEdge::Result(promotion_result(&next, end)),
+                            // A minor piece never wins on its own.
+                            Move::PawnPromotion(..) => KpkEdge::Result(KpkResult::Draw),
+                            _ if end == pawn && !white_to_move => KpkEdge::Result(KpkResult::Draw),
+                            _ => {
+                                let pawn = if start == pawn { end } else { pawn };
+                                KpkEdge::Position(kpk_index(
+                                    next.white_king_pos,
+                                    pawn,
+                                    next.black_king_pos,
+                                    !white_to_move,
+                                ) as u32)
+                            }
+                        };
+                        edges.push(edge);
+                    }
+                    if edges.len() == starts[starts.len() - 1] {
+                        let mated = !white_to_move && board.is_in_check(Color::Black);
+                        results[index] = if mated {
+                            KpkResult::Win
+                        } else {
+                            KpkResult::Draw
+                        };
+                    }
+                }
+            }
+        }
+    }
+    starts.push(edges.len());
+    (starts, edges)
+}
+
+fn generate_kpk() -> Vec<u64> {
+    let mut results = vec![KpkResult::Invalid; KPK_SIZE];
+    let (starts, edges) = kpk_moves(&mut results);
+    // White to move wins if some move wins, black to move loses if every move loses. Whatever
+    // is still unknown when nothing changes any more is a draw.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for index in 0..KPK_SIZE {
+            if results[index] != KpkResult::Unknown {
+                continue;
+            }
+            let white_to_move = index % 2 == 1;
+            let mut any_win = false;
+            let mut any_draw = false;
+            let mut all_known = true;
+            for edge in &edges[starts[index]..starts[index + 1]] {
+                let result = match *edge {
+                    KpkEdge::Position(next) => results[next as usize],
+                    KpkEdge::Result(result) => result,
+                };
+                match result {
+                    KpkResult::Win => any_win = true,
+                    KpkResult::Draw => any_draw = true,
+                    _ => all_known = false,
+                }
+            }
+            let result = if white_to_move {
+                if any_win {
+                    KpkResult::Win
+                } else if all_known {
+                    KpkResult::Draw
+                } else {
+                    continue;
+                }
+            } else if any_draw {
+                KpkResult::Draw
+            } else if all_known {
+                KpkResult::Win
+            } else {
+                continue;
+            };
+            results[index] = result;
+            changed = true;
+        }
+    }
+
+    let mut bits = vec![0u64; KPK_SIZE / 64];
+    for (index, result) in results.iter().enumerate() {
+        if *result == KpkResult::Win {
+            bits[index / 64] |= 1 << (index % 64);
+        }
+    }
+    bits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(name: &str) -> Square {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn kpk_wins_with_the_king_in_front_of_the_pawn() {
+        // White Ke6 and Pe5 against Ke8 wins whoever is to move.
+        assert_eq!(
+            kpk_is_win(Color::White, square("e6"), square("e5"), square("e8"), true),
+            Some(true)
+        );
+        assert_eq!(
+            kpk_is_win(
+                Color::White,
+                square("e6"),
+                square("e5"),
+                square("e8"),
+                false
+            ),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn kpk_draws_when_the_king_blocks_the_pawn() {
+        // White Ke5 and Pe6 against Ke7, white to move.
+        assert_eq!(
+            kpk_is_win(Color::White, square("e5"), square("e6"), square("e7"), true),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn kpk_rook_pawn_draws_against_the_king_in_the_corner() {
+        // Black Kf4 and Ph4 against Kh1, black to move.
+        assert_eq!(
+            kpk_is_win(Color::Black, square("f4"), square("h4"), square("h1"), true),
+            Some(false)
+        );
+    }
+
+    #[test]
+    fn kpk_has_no_entry_for_pawns_on_the_back_ranks() {
+        assert_eq!(
+            kpk_is_win(Color::White, square("e1"), square("a1"), square("e8"), true),
+            None
+        );
+        assert_eq!(
+            kpk_is_win(Color::White, square("e1"), square("a8"), square("e8"), true),
+            None
+        );
+        let board = Board::from_fen("4k3/8/8/8/8/8/8/P3K3 w - - 0 1");
+        let material = MaterialSignature::of(&board);
+        assert_eq!(evaluate(&board, &material), None);
+        crate::eval::evaluate(&board);
+    }
+}
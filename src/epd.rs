@@ -0,0 +1,466 @@
+use crate::search::{mate_in, SearchLimits, SearchResult, Searcher};
+use crate::{Board, BoardMove};
+use std::fmt;
+
+// Extended Position Description: the first four fields of a FEN (placement, side to move,
+// castling and en passant) followed by operations, each an opcode and its operands ending in
+// a semicolon: `bm Qg6; id "WAC.001";`. Operands with spaces or semicolons in them are quoted.
+// The opcodes used here:
+//   bm   best moves, in SAN             am   moves to avoid, in SAN
+//   dm   mate in this many moves        id   name of the position
+//   ce   centipawn evaluation           acd  depth searched
+//   acn  nodes searched                 pv   principal variation, in SAN
+//   sm   the move that was played       hmvc halfmove clock
+//   c0 to c9 comments
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Epd {
+    pub board: Board,
+    // Operations in the order they were read or set, opcodes are unique.
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn new(board: Board) -> Self {
+        Epd {
+            board,
+            operations: Vec::new(),
+        }
+    }
+
+    // Read one line. Six field FENs are accepted too, the last two fields are taken as the
+    // halfmove clock and the move number.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut rest = line.trim();
+        let mut fields = Vec::new();
+        while fields.len() < 4 {
+            let (field, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            if field.is_empty() {
+                return Err(format!("not enough fields in \"{line}\""));
+            }
+            fields.push(field);
+            rest = after.trim_start();
+        }
+        if !matches!(fields[1], "w" | "b") {
+            return Err(format!("bad side to move \"{}\"", fields[1]));
+        }
+        let mut halfmove_clock = 0;
+        let mut words = rest.splitn(3, char::is_whitespace);
+        if let (Some(halfmoves), Some(moves)) = (words.next(), words.next()) {
+            if let (Ok(halfmoves), Ok(_)) = (halfmoves.parse(), moves.parse::<u32>()) {
+                halfmove_clock = halfmoves;
+                rest = words.next().unwrap_or("");
+            }
+        }
+        let board = Board::try_from_fen(&format!("{} 0 1", fields.join(" ")))
+            .map_err(|error| error.to_string())?;
+        let mut epd = Epd::new(board);
+        for operation in split_operations(rest)? {
+            if let Some((opcode, operands)) = operation.split_first() {
+                epd.set(opcode, operands.to_vec());
+            }
+        }
+        epd.board.halfmove_clock = epd
+            .number("hmvc")
+            .map_or(halfmove_clock, |clock| clock.clamp(0, u8::MAX as i64) as u8);
+        Ok(epd)
+    }
+
+    // Operands of the opcode, if the position has it.
+    pub fn get(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .map(|(_, operands)| operands.as_slice())
+    }
+
+    // Add the operation, or replace its operands if the position already has it.
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|(name, _)| name == opcode) {
+            Some((_, old)) => *old = operands,
+            None => self.operations.push((opcode.to_string(), operands)),
+        }
+    }
+
+    pub fn remove(&mut self, opcode: &str) {
+        self.operations.retain(|(name, _)| name != opcode);
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.get("id")?.first().map(|id| id.as_str())
+    }
+
+    // First operand of the opcode, as a number.
+    pub fn number(&self, opcode: &str) -> Option<i64> {
+        self.get(opcode)?.first()?.parse().ok()
+    }
+
+    // The moves of an operation written in SAN (UCI notation is accepted too). Moves that
+    // aren't legal in the position are left out.
+    pub fn moves(&self, opcode: &str) -> Vec<BoardMove> {
+        self.get(opcode)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|text| {
+                self.board
+                    .move_from_san(text)
+                    .or_else(|| self.board.move_from_uci(text))
+            })
+            .collect()
+    }
+
+    pub fn best_moves(&self) -> Vec<BoardMove> {
+        self.moves("bm")
+    }
+
+    pub fn avoid_moves(&self) -> Vec<BoardMove> {
+        self.moves("am")
+    }
+
+    // Moves to the mate the position asks for.
+    pub fn direct_mate(&self) -> Option<u32> {
+        self.number("dm")
+            .and_then(|moves| u32::try_from(moves).ok())
+    }
+
+    // Does the position ask for anything a search can be checked against?
+    pub fn has_test(&self) -> bool {
+        ["bm", "am", "dm"]
+            .iter()
+            .any(|opcode| self.get(opcode).is_some())
+    }
+
+    // Whether the search found what the position asks for: one of the best moves, none of
+    // the moves to avoid, and a mate in at most the given moves. None without anything to check.
+    pub fn check(&self, result: &SearchResult) -> Option<bool> {
+        if !self.has_test() {
+            return None;
+        }
+        let best_moves = self.best_moves();
+        let avoid_moves = self.avoid_moves();
+        let Some(best_move) = result.best_move else {
+            return Some(false);
+        };
+        let mate_found = match self.direct_mate() {
+            Some(moves) => {
+                mate_in(result.score).is_some_and(|found| found > 0 && found as u32 <= moves)
+            }
+            None => true,
+        };
+        Some(
+            (self.get("bm").is_none() || best_moves.contains(&best_move))
+                && !avoid_moves.contains(&best_move)
+                && mate_found,
+        )
+    }
+
+    // Search limits for the position: the given ones, looking for the mate when it asks for one.
+    pub fn limits(&self, limits: &SearchLimits) -> SearchLimits {
+        let mut limits = *limits;
+        if limits.mate.is_none() {
+            limits.mate = self.direct_mate();
+        }
+        limits
+    }
+
+    // A copy annotated with what the search found: the move played, its score (and the mate
+    // when there is one), the depth, the nodes and the principal variation.
+    pub fn annotated(&self, result: &SearchResult) -> Epd {
+        let mut epd = self.clone();
+        if let Some((start_square, a_move)) = result.best_move {
+            epd.set("sm", vec![self.board.move_to_san(start_square, a_move)]);
+        }
+        epd.set("ce", vec![result.score.to_string()]);
+        match mate_in(result.score) {
+            Some(moves) if moves > 0 && self.get("dm").is_none() => {
+                epd.set("dm", vec![moves.to_string()])
+            }
+            _ => (),
+        }
+        epd.set("acd", vec![result.depth.to_string()]);
+        epd.set("acn", vec![result.nodes.to_string()]);
+        epd.set("pv", pv_to_san(&self.board, &result.pv));
+        epd
+    }
+}
+
+// The moves one after the other, each in SAN.
+pub fn pv_to_san(board: &Board, pv: &[BoardMove]) -> Vec<String> {
+    let mut board = board.clone();
+    pv.iter()
+        .map(|(start_square, a_move)| {
+            let san = board.move_to_san(*start_square, *a_move);
+            board = board.make_move(*start_square, *a_move);
+            san
+        })
+        .collect()
+}
+
+// The operations of an EPD line, each split into its opcode and operands, quotes taken off.
+fn split_operations(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut operations = Vec::new();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(ch) => quoted.push(ch),
+                        None => return Err(format!("unterminated string in \"{text}\"")),
+                    }
+                }
+                words.push(quoted);
+            }
+            ';' => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                if !words.is_empty() {
+                    operations.push(std::mem::take(&mut words));
+                }
+            }
+            ch if ch.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            ch => word.push(ch),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        operations.push(words);
+    }
+    Ok(operations)
+}
+
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fen = self.board.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().take(4).collect();
+        write!(f, "{}", fields.join(" "))?;
+        for (opcode, operands) in &self.operations {
+            write!(f, " {opcode}")?;
+            // Names and comments are strings, and so is anything with a space or a semicolon.
+            let is_string = opcode == "id"
+                || (opcode.len() == 2
+                    && opcode.starts_with('c')
+                    && opcode.as_bytes()[1].is_ascii_digit());
+            for operand in operands {
+                if is_string || operand.is_empty() || operand.contains([' ', ';']) {
+                    write!(f, " \"{operand}\"")?;
+                } else {
+                    write!(f, " {operand}")?;
+                }
+            }
+            write!(f, ";")?;
+        }
+        Ok(())
+    }
+}
+
+// How a suite went.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SuiteScore {
+    pub solved: u32,
+    pub failed: u32,
+    // Positions without bm, am or dm.
+    pub untested: u32,
+}
+
+impl fmt::Display for SuiteScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tested = self.solved + self.failed;
+        write!(
+            f,
+            "Solved {} of {tested} ({:.1}%)",
+            self.solved,
+            self.solved as f64 * 100.0 / tested.max(1) as f64
+        )?;
+        if self.untested > 0 {
+            write!(f, ", {} without a test", self.untested)?;
+        }
+        Ok(())
+    }
+}
+
+// Search every position with a cleared hash table and check the result. `on_result` gets each
+// position with its search result and whether it was solved.
+pub fn run_suite(
+    searcher: &mut Searcher,
+    positions: &[Epd],
+    limits: &SearchLimits,
+    mut on_result: impl FnMut(&Epd, &SearchResult, Option<bool>),
+) -> SuiteScore {
+    let mut score = SuiteScore::default();
+    for epd in positions {
+        searcher.new_game();
+        searcher.set_game_history(Vec::new());
+        let result = searcher.search_with_limits(&epd.board, &epd.limits(limits), |_| ());
+        let solved = epd.check(&result);
+        match solved {
+            Some(true) => score.solved += 1,
+            Some(false) => score.failed += 1,
+            None => score.untested += 1,
+        }
+        on_result(epd, &result, solved);
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchConfig;
+    use crate::tt::MATE_SCORE;
+
+    const WAC_001: &str =
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4bK1 w - - bm Qg6; id \"WAC.001\";";
+    const MATE_IN_TWO: &str = "k7/8/2K5/8/8/8/8/7R w - - dm 2; id \"rook mate\";";
+
+    fn epd(line: &str) -> Epd {
+        Epd::parse(line).unwrap()
+    }
+
+    fn result(epd: &Epd, best_move: &str, score: i32) -> SearchResult {
+        SearchResult {
+            best_move: epd.board.move_from_uci(best_move),
+            score,
+            depth: 7,
+            nodes: 1234,
+            pv: epd.board.move_from_uci(best_move).into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn operations_are_read() {
+        let wac = epd(WAC_001);
+        assert_eq!(wac.id(), Some("WAC.001"));
+        assert_eq!(
+            wac.best_moves(),
+            wac.board
+                .move_from_uci("g3g6")
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+        assert!(wac.has_test());
+
+        let line = "4k3/8/8/8/8/8/4P3/4K3 b - - am Kd7 e8f8; ce -35; acd 12; c0 \"a; b\"; hmvc 7;";
+        let position = epd(line);
+        assert_eq!(position.avoid_moves().len(), 2);
+        assert_eq!(position.number("ce"), Some(-35));
+        assert_eq!(position.number("acd"), Some(12));
+        assert_eq!(position.get("c0"), Some(&["a; b".to_string()][..]));
+        assert_eq!(position.board.halfmove_clock, 7);
+        assert_eq!(epd(MATE_IN_TWO).direct_mate(), Some(2));
+
+        // The two FEN counters may come before the operations.
+        let fen = epd("4k3/8/8/8/8/8/4P3/4K3 w - - 15 40 bm e4;");
+        assert_eq!(fen.board.halfmove_clock, 15);
+        assert_eq!(fen.best_moves().len(), 1);
+        assert!(!epd("4k3/8/8/8/8/8/4P3/4K3 w - -").has_test());
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w -").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 x - -").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - id \"open").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/9 w - - bm e4;").is_err());
+    }
+
+    #[test]
+    fn lines_are_written_back() {
+        let mut wac = epd(WAC_001);
+        assert_eq!(wac.to_string(), WAC_001);
+        wac.set("c0", vec!["solved".to_string()]);
+        wac.set("bm", vec!["Qg6".to_string(), "Rxc4".to_string()]);
+        wac.remove("id");
+        assert_eq!(
+            wac.to_string(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4bK1 w - - bm Qg6 Rxc4; c0 \"solved\";"
+        );
+        assert_eq!(epd(&wac.to_string()), wac);
+    }
+
+    #[test]
+    fn search_results_are_checked() {
+        let wac = epd(WAC_001);
+        assert_eq!(wac.check(&result(&wac, "g3g6", 500)), Some(true));
+        assert_eq!(wac.check(&result(&wac, "c2b3", 500)), Some(false));
+
+        let avoid = epd("4k3/8/8/8/8/8/4P3/4K3 w - - am e4;");
+        assert_eq!(avoid.check(&result(&avoid, "e2e4", 0)), Some(false));
+        assert_eq!(avoid.check(&result(&avoid, "e2e3", 0)), Some(true));
+
+        let mate = epd(MATE_IN_TWO);
+        assert_eq!(
+            mate.check(&result(&mate, "h1h7", MATE_SCORE - 3)),
+            Some(true)
+        );
+        assert_eq!(
+            mate.check(&result(&mate, "h1h7", MATE_SCORE - 5)),
+            Some(false)
+        );
+        assert_eq!(mate.check(&result(&mate, "h1h7", 900)), Some(false));
+        let untested = epd("4k3/8/8/8/8/8/4P3/4K3 w - -");
+        assert_eq!(untested.check(&result(&untested, "e2e4", 0)), None);
+
+        assert_eq!(mate.limits(&SearchLimits::default()).mate, Some(2));
+        let asked = SearchLimits {
+            mate: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(mate.limits(&asked).mate, Some(5));
+    }
+
+    #[test]
+    fn results_are_written_as_operations() {
+        let wac = epd(WAC_001);
+        let annotated = wac.annotated(&result(&wac, "g3g6", 550));
+        assert_eq!(
+            annotated.to_string(),
+            format!("{} sm Qg6; ce 550; acd 7; acn 1234; pv Qg6;", WAC_001)
+        );
+        let mate = epd("k7/8/2K5/8/8/8/8/7R w - -");
+        let annotated = mate.annotated(&result(&mate, "h1h7", MATE_SCORE - 3));
+        assert_eq!(annotated.direct_mate(), Some(2));
+        assert_eq!(
+            pv_to_san(&wac.board, &[wac.board.move_from_uci("g3g6").unwrap()]),
+            ["Qg6"]
+        );
+    }
+
+    #[test]
+    fn suites_count_solved_positions() {
+        let positions = [
+            epd(MATE_IN_TWO),
+            epd("k7/8/2K5/8/8/8/8/7R w - - bm Rh2;"),
+            epd("k7/8/2K5/8/8/8/8/7R w - -"),
+        ];
+        let limits = SearchLimits {
+            depth: Some(5),
+            ..Default::default()
+        };
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let mut seen = Vec::new();
+        let score = run_suite(&mut searcher, &positions, &limits, |epd, _, solved| {
+            seen.push((epd.id().map(str::to_string), solved))
+        });
+        assert_eq!(
+            score,
+            SuiteScore {
+                solved: 1,
+                failed: 1,
+                untested: 1,
+            }
+        );
+        assert_eq!(seen[0], (Some("rook mate".to_string()), Some(true)));
+        assert_eq!(score.to_string(), "Solved 1 of 2 (50.0%), 1 without a test");
+    }
+}
@@ -0,0 +1,46 @@
+use crate::board::BoardIssue;
+use crate::square::ParseSquareError;
+use std::error::Error;
+use std::fmt;
+
+// What can go wrong handing the library bad input: the Result returning functions give one of
+// these where the older ones panic or quietly make do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChessError {
+    // A square name like "z9", or an index off the board.
+    InvalidSquare(String),
+    // A FEN that can't be read, and why.
+    InvalidFen(String),
+    // A move that can't be read, or can't be played in the position.
+    IllegalMove(String),
+    // A position set up with a BoardBuilder that doesn't add up.
+    InvalidPosition(Vec<BoardIssue>),
+    // A fairy piece that can't be defined or registered, and why.
+    InvalidPiece(String),
+    // A board size that isn't supported.
+    InvalidGeometry(String),
+}
+
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChessError::InvalidSquare(square) => write!(f, "not a square: \"{square}\""),
+            ChessError::InvalidFen(reason) => write!(f, "invalid FEN: {reason}"),
+            ChessError::IllegalMove(a_move) => write!(f, "illegal move: \"{a_move}\""),
+            ChessError::InvalidPosition(issues) => {
+                let issues: Vec<String> = issues.iter().map(|issue| issue.to_string()).collect();
+                write!(f, "invalid position: {}", issues.join(", "))
+            }
+            ChessError::InvalidPiece(reason) => write!(f, "invalid fairy piece: {reason}"),
+            ChessError::InvalidGeometry(reason) => write!(f, "invalid board: {reason}"),
+        }
+    }
+}
+
+impl Error for ChessError {}
+
+impl From<ParseSquareError> for ChessError {
+    fn from(error: ParseSquareError) -> Self {
+        ChessError::InvalidSquare(error.input)
+    }
+}
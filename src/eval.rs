@@ -0,0 +1,217 @@
+use crate::board::Pockets;
+use crate::endgame::{self, MaterialSignature, SCALE_NORMAL};
+use crate::fairy;
+use crate::params::{EvalParams, DEFAULT_PARAMS};
+use crate::pawns::{self, PawnTable};
+use crate::pieces;
+use crate::{Board, Color, Piece, PieceKind};
+use std::fmt;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+
+// A middlegame and an endgame value, blended by the game phase with `taper`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Score {
+    pub mg: i32,
+    pub eg: i32,
+}
+
+impl Score {
+    pub const fn new(mg: i32, eg: i32) -> Self {
+        Score { mg, eg }
+    }
+}
+
+impl Add for Score {
+    type Output = Score;
+    fn add(self, other: Score) -> Score {
+        Score::new(self.mg + other.mg, self.eg + other.eg)
+    }
+}
+
+impl Sub for Score {
+    type Output = Score;
+    fn sub(self, other: Score) -> Score {
+        Score::new(self.mg - other.mg, self.eg - other.eg)
+    }
+}
+
+impl Neg for Score {
+    type Output = Score;
+    fn neg(self) -> Score {
+        Score::new(-self.mg, -self.eg)
+    }
+}
+
+impl Mul<i32> for Score {
+    type Output = Score;
+    fn mul(self, factor: i32) -> Score {
+        Score::new(self.mg * factor, self.eg * factor)
+    }
+}
+
+impl AddAssign for Score {
+    fn add_assign(&mut self, other: Score) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Score {
+    fn sub_assign(&mut self, other: Score) {
+        *self = *self - other;
+    }
+}
+
+// How much each piece type counts towards the game phase. The starting position has 24.
+const PHASE_WEIGHTS: [i32; 6] = [0, 1, 1, 2, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// Table of evaluation terms for debugging: one row per term with the white and the black
+// score (each from its own side's point of view), the difference, and a total row.
+pub(crate) fn write_breakdown(
+    f: &mut fmt::Formatter,
+    rows: &[(String, Score, Score)],
+) -> fmt::Result {
+    fn write_row(f: &mut fmt::Formatter, name: &str, white: Score, black: Score) -> fmt::Result {
+        let total = white - black;
+        writeln!(
+            f,
+            "{name:>12} | {:>5} {:>5} | {:>5} {:>5} | {:>5} {:>5}",
+            white.mg, white.eg, black.mg, black.eg, total.mg, total.eg
+        )
+    }
+    writeln!(
+        f,
+        "{:>12} | {:>11} | {:>11} | {:>11}",
+        "term", "white", "black", "total"
+    )?;
+    let mut white_total = Score::default();
+    let mut black_total = Score::default();
+    for (name, white, black) in rows {
+        write_row(f, name, *white, *black)?;
+        white_total += *white;
+        black_total += *black;
+    }
+    write_row(f, "Total", white_total, black_total)
+}
+
+// The tables are written rank 8 first, so white's squares have to be flipped vertically.
+// Black's squares are already mirrored by that layout.
+fn table_index(square: usize, color: Color) -> usize {
+    if color.is_white() {
+        square ^ 56
+    } else {
+        square
+    }
+}
+
+// Game phase, from MAX_PHASE (all pieces on the board) down to 0 (only kings and pawns).
+// Fairy pieces weigh by their value, a queen's worth as much as a queen.
+pub fn game_phase(board: &Board) -> i32 {
+    board
+        .board
+        .iter()
+        .flatten()
+        .map(|piece| {
+            PHASE_WEIGHTS
+                .get(piece.kind.index())
+                .copied()
+                .unwrap_or_else(|| {
+                    (board.piece_value(*piece) * PHASE_WEIGHTS[4] / 900).clamp(1, MAX_PHASE)
+                })
+        })
+        .sum::<i32>()
+        .min(MAX_PHASE)
+}
+
+// Blend a middlegame and an endgame score by the game phase.
+pub fn taper(mg: i32, eg: i32, phase: i32) -> i32 {
+    (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE
+}
+
+// Static evaluation of the position, in centipawns, from the point of view of the side to move.
+// Endgames with specialised knowledge are handed to `endgame`.
+pub fn evaluate(board: &Board) -> i32 {
+    evaluate_with(board, &DEFAULT_PARAMS)
+}
+
+// Same as `evaluate`, with other weights.
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> i32 {
+    evaluate_with_pawns(board, params, pawns::evaluate(board, params))
+}
+
+// Same as `evaluate_with`, with the pawn structure looked up in (or added to) the pawn hash
+// table. The table has to be cleared when the weights change.
+pub fn evaluate_cached(board: &Board, params: &EvalParams, pawn_table: &mut PawnTable) -> i32 {
+    evaluate_with_pawns(board, params, pawn_table.probe(board, params))
+}
+
+// `pawns` is the pawn structure score from white's point of view. The piece terms aren't
+// cached, they depend on everything on the board.
+fn evaluate_with_pawns(board: &Board, params: &EvalParams, pawns: Score) -> i32 {
+    let color = board.side_to_move();
+    let material = MaterialSignature::of(board);
+    // Endgame knowledge doesn't hold while there are pieces to drop, or fairy pieces.
+    let knows_endgames = board.pockets.is_none() && !fairy::has_fairy_pieces(board);
+    if knows_endgames {
+        if let Some(score) = endgame::evaluate(board, &material) {
+            return score;
+        }
+    }
+    let terms = pawns + pieces::evaluate(board, params);
+    let mut score = if color.is_white() { terms } else { -terms };
+    for (square, piece) in board.board.iter().enumerate() {
+        if let Some(piece) = piece {
+            let kind = piece.kind.index();
+            let piece_color = piece.color;
+            // Fairy pieces are worth what their definition says, wherever they stand.
+            let value = if let PieceKind::Fairy(_) = piece.kind {
+                let worth = board.piece_value(*piece);
+                Score::new(worth, worth)
+            } else {
+                params.material[kind] + params.pst[kind][table_index(square, piece_color)]
+            };
+            if piece_color == color {
+                score += value;
+            } else {
+                score -= value;
+            }
+        }
+    }
+    // Pieces in hand are worth their material.
+    if let Some(pockets) = board.pockets {
+        for kind in Pockets::KINDS {
+            let count =
+                pockets.count(color, kind) as i32 - pockets.count(color.reverse(), kind) as i32;
+            score += params.material[kind.index()] * count;
+        }
+    }
+    let strong = if score.eg >= 0 {
+        color
+    } else {
+        color.reverse()
+    };
+    let scale = if knows_endgames {
+        endgame::scale_factor(board, &material, strong)
+    } else {
+        SCALE_NORMAL
+    };
+    let eg = score.eg * scale / SCALE_NORMAL;
+    taper(score.mg, eg, game_phase(board))
+}
+
+// Does the color have anything besides pawns and the king? Without it, zugzwang is likely.
+pub fn has_non_pawn_material(board: &Board, color: Color) -> bool {
+    board.board.iter().flatten().any(|piece| {
+        piece.color == color
+            && !matches!(
+                piece,
+                Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                } | Piece {
+                    kind: PieceKind::King,
+                    ..
+                }
+            )
+    })
+}
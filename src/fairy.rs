@@ -0,0 +1,628 @@
+use crate::geometry::Geometry;
+use crate::{Board, ChessError, Color, Move, OnePieceMoves, Piece, PieceKind, Square};
+
+// Fairy pieces: pieces defined at run time by how they move, written in Betza's notation.
+// Definitions are added to a PieceSet, and a position set up with the set can use their
+// letters in its FEN, generate their moves, see their attacks and show their glyphs like any
+// other piece's. Each position carries its own set, so two games never share pieces.
+//
+// The notation is a list of atoms, each a capital letter for a basic move, optionally
+// preceded by modifiers and followed by a range:
+//   W (1,0) wazir   F (1,1) ferz     D (2,0) dabbaba  N (2,1) knight  A (2,2) alfil
+//   H (3,0) threeleaper  C (3,1) camel  Z (3,2) zebra  G (3,3) tripper
+//   K king (WF)  R rook (WW)  B bishop (FF)  Q queen (RB)
+// An atom written twice rides, repeating its step until it's blocked (NN is the nightrider),
+// and a number after it is the most steps it takes (W3, 0 meaning no limit). Modifiers:
+//   m   only moves, doesn't capture        c   only captures
+//   p   hops over a piece on its line, like the xiangqi cannon
+//   g   hops over the first piece and lands right behind it, like the grasshopper
+//   f b l r v s  forwards, backwards, left, right, vertical, sideways: only those
+//                directions. f or b followed by l or r is one diagonal direction
+// So the archbishop is BN, the chancellor RN and the amazon QN. Forward is up for white.
+
+// kind_index of the first fairy piece, the others follow in the order they were added.
+pub const FAIRY_KIND: usize = 6;
+// Fairy pieces there can be in a set.
+pub const MAX_FAIRY_PIECES: usize = 32;
+// Range of a rider without a limit, longer than any board.
+pub const UNLIMITED: u8 = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PieceDefinition {
+    pub name: String,
+    // Letter in FENs and SAN, lowercase. White's piece is the uppercase one.
+    pub letter: char,
+    pub betza: String,
+    // What Board's Display shows for the white and the black piece.
+    pub glyphs: [char; 2],
+    // Material value, in centipawns.
+    pub value: i32,
+}
+
+impl PieceDefinition {
+    // A definition showing the piece by its letter.
+    pub fn new(name: &str, letter: char, betza: &str, value: i32) -> Self {
+        let letter = letter.to_ascii_lowercase();
+        PieceDefinition {
+            name: name.to_string(),
+            letter,
+            betza: betza.to_string(),
+            glyphs: [letter.to_ascii_uppercase(), letter],
+            value,
+        }
+    }
+
+    pub fn with_glyphs(mut self, white: char, black: char) -> Self {
+        self.glyphs = [white, black];
+        self
+    }
+}
+
+// The pieces that come with the crate, to add to a PieceSet.
+pub fn builtin_pieces() -> Vec<PieceDefinition> {
+    vec![
+        PieceDefinition::new("archbishop", 'a', "BN", 800),
+        PieceDefinition::new("chancellor", 'c', "RN", 900),
+        PieceDefinition::new("amazon", 'm', "QN", 1200),
+        PieceDefinition::new("camel", 'l', "C", 250),
+        PieceDefinition::new("zebra", 'z', "Z", 250),
+        PieceDefinition::new("nightrider", 'i', "NN", 550),
+        PieceDefinition::new("grasshopper", 'g', "gQ", 200),
+    ]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Hop {
+    // Moves along its line until it's blocked.
+    None,
+    // Needs exactly one piece between it and where it lands (p).
+    Screen,
+    // Lands right behind the first piece on its line (g).
+    Grasshopper,
+}
+
+// One direction a piece moves in, from white's side of the board: `rank` is forwards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Step {
+    pub file: i8,
+    pub rank: i8,
+    // Most times the step is repeated, 1 for leapers and UNLIMITED for riders.
+    pub range: u8,
+    pub moves: bool,
+    pub captures: bool,
+    pub hop: Hop,
+}
+
+// The steps of a piece written in Betza's notation, see the top of this module.
+pub fn parse_betza(text: &str) -> Result<Vec<Step>, ChessError> {
+    let chars: Vec<char> = text.chars().filter(|ch| !ch.is_whitespace()).collect();
+    let mut steps: Vec<Step> = Vec::new();
+    let mut modifiers = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let ch = chars[index];
+        index += 1;
+        if ch.is_ascii_lowercase() {
+            if !"mcpgfblrvs".contains(ch) {
+                return Err(ChessError::InvalidPiece(format!(
+                    "unsupported modifier '{ch}' in \"{text}\""
+                )));
+            }
+            modifiers.push(ch);
+            continue;
+        }
+        let (atoms, rides): (&[(i8, i8)], bool) = match ch {
+            'W' => (&[(1, 0)], false),
+            'F' => (&[(1, 1)], false),
+            'D' => (&[(2, 0)], false),
+            'N' => (&[(2, 1)], false),
+            'A' => (&[(2, 2)], false),
+            'H' => (&[(3, 0)], false),
+            'C' => (&[(3, 1)], false),
+            'Z' => (&[(3, 2)], false),
+            'G' => (&[(3, 3)], false),
+            'K' => (&[(1, 0), (1, 1)], false),
+            'R' => (&[(1, 0)], true),
+            'B' => (&[(1, 1)], true),
+            'Q' => (&[(1, 0), (1, 1)], true),
+            _ => {
+                return Err(ChessError::InvalidPiece(format!(
+                    "unknown atom '{ch}' in \"{text}\""
+                )))
+            }
+        };
+        let mut range = if rides { UNLIMITED } else { 1 };
+        if !rides && chars.get(index) == Some(&ch) {
+            range = UNLIMITED;
+            index += 1;
+        } else if chars.get(index).is_some_and(|next| next.is_ascii_digit()) {
+            let digits: String = chars[index..]
+                .iter()
+                .take_while(|next| next.is_ascii_digit())
+                .collect();
+            index += digits.len();
+            range = match digits.parse::<u8>() {
+                Ok(0) => UNLIMITED,
+                Ok(number) => number.min(UNLIMITED),
+                Err(_) => {
+                    return Err(ChessError::InvalidPiece(format!(
+                        "bad range {digits} in \"{text}\""
+                    )))
+                }
+            };
+        }
+        let has_mode = modifiers.contains(['m', 'c']);
+        let hop = if modifiers.contains('g') {
+            Hop::Grasshopper
+        } else if modifiers.contains('p') {
+            Hop::Screen
+        } else {
+            Hop::None
+        };
+        if hop != Hop::None && range == 1 {
+            return Err(ChessError::InvalidPiece(format!(
+                "hoppers have to ride, in \"{text}\""
+            )));
+        }
+        let directions = direction_groups(&modifiers);
+        for &(long, short) in atoms {
+            for (file, rank) in symmetries(long, short) {
+                let allowed = directions.is_empty()
+                    || directions
+                        .iter()
+                        .any(|group| group.chars().all(|dir| is_towards(dir, file, rank)));
+                let step = Step {
+                    file,
+                    rank,
+                    range,
+                    moves: !has_mode || modifiers.contains('m'),
+                    captures: !has_mode || modifiers.contains('c'),
+                    hop,
+                };
+                if allowed && !steps.contains(&step) {
+                    steps.push(step);
+                }
+            }
+        }
+        modifiers.clear();
+    }
+    if !modifiers.is_empty() {
+        return Err(ChessError::InvalidPiece(format!(
+            "modifiers without an atom in \"{text}\""
+        )));
+    }
+    if steps.is_empty() {
+        return Err(ChessError::InvalidPiece(format!("\"{text}\" doesn't move")));
+    }
+    Ok(steps)
+}
+
+// The direction modifiers, f or b followed by l or r making one group.
+fn direction_groups(modifiers: &str) -> Vec<String> {
+    let directions: Vec<char> = modifiers
+        .chars()
+        .filter(|ch| "fblrvs".contains(*ch))
+        .collect();
+    let mut groups = Vec::new();
+    let mut index = 0;
+    while index < directions.len() {
+        let ch = directions[index];
+        match directions.get(index + 1) {
+            Some(&next) if "fb".contains(ch) && "lr".contains(next) => {
+                groups.push(format!("{ch}{next}"));
+                index += 2;
+            }
+            _ => {
+                groups.push(ch.to_string());
+                index += 1;
+            }
+        }
+    }
+    groups
+}
+
+fn is_towards(direction: char, file: i8, rank: i8) -> bool {
+    match direction {
+        'f' => rank > 0,
+        'b' => rank < 0,
+        'l' => file < 0,
+        'r' => file > 0,
+        'v' => rank.abs() > file.abs(),
+        _ => file.abs() > rank.abs(),
+    }
+}
+
+// The up to eight ways of making a (long, short) step.
+fn symmetries(long: i8, short: i8) -> Vec<(i8, i8)> {
+    let mut offsets = Vec::new();
+    for (file, rank) in [(long, short), (short, long)] {
+        for (file_sign, rank_sign) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let offset = (file * file_sign, rank * rank_sign);
+            if !offsets.contains(&offset) {
+                offsets.push(offset);
+            }
+        }
+    }
+    offsets
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Registered {
+    definition: PieceDefinition,
+    steps: Vec<Step>,
+}
+
+// The fairy pieces a position plays with. A piece's id is its place in the set.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct PieceSet {
+    pieces: Vec<Registered>,
+}
+
+impl PieceSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A set of every builtin piece.
+    pub fn builtin() -> Self {
+        let mut pieces = Self::new();
+        for definition in builtin_pieces() {
+            pieces
+                .add(definition)
+                .expect("the builtin pieces are valid and have letters of their own");
+        }
+        pieces
+    }
+
+    // Add a piece, returning its id. Adding the same definition again gives the same id, a
+    // different one with a letter that's taken is an error, and so are the standard letters.
+    pub fn add(&mut self, definition: PieceDefinition) -> Result<u8, ChessError> {
+        let letter = definition.letter.to_ascii_lowercase();
+        if !letter.is_ascii_lowercase() || "pnbrqk".contains(letter) {
+            return Err(ChessError::InvalidPiece(format!(
+                "'{letter}' can't be used for a fairy piece"
+            )));
+        }
+        let steps = parse_betza(&definition.betza)?;
+        if let Some(id) = self
+            .pieces
+            .iter()
+            .position(|piece| piece.definition.letter == letter)
+        {
+            return if self.pieces[id].definition == definition {
+                Ok(id as u8)
+            } else {
+                Err(ChessError::InvalidPiece(format!(
+                    "'{letter}' is already the {}",
+                    self.pieces[id].definition.name
+                )))
+            };
+        }
+        if self.pieces.len() >= MAX_FAIRY_PIECES {
+            return Err(ChessError::InvalidPiece(format!(
+                "no room for more than {MAX_FAIRY_PIECES} fairy pieces"
+            )));
+        }
+        self.pieces.push(Registered { definition, steps });
+        Ok(self.pieces.len() as u8 - 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pieces.is_empty()
+    }
+
+    pub fn definition(&self, id: u8) -> Option<&PieceDefinition> {
+        self.pieces.get(id as usize).map(|piece| &piece.definition)
+    }
+
+    // The piece of the set with the name, of the color.
+    pub fn piece(&self, name: &str, color: Color) -> Option<Piece> {
+        let id = self
+            .pieces
+            .iter()
+            .position(|piece| piece.definition.name.eq_ignore_ascii_case(name))?;
+        Some(Piece::new(PieceKind::Fairy(id as u8), color))
+    }
+
+    // The piece of the set with the FEN letter, white for an uppercase one.
+    pub fn from_fen_char(&self, ch: char) -> Option<Piece> {
+        let id = self
+            .pieces
+            .iter()
+            .position(|piece| piece.definition.letter == ch.to_ascii_lowercase())?;
+        let color = if ch.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        Some(Piece::new(PieceKind::Fairy(id as u8), color))
+    }
+
+    // The FEN letter of the piece, uppercase for white, standard pieces included.
+    pub fn letter(&self, piece: Piece) -> char {
+        match piece.kind {
+            PieceKind::Fairy(id) => {
+                let letter = self
+                    .definition(id)
+                    .map_or('?', |definition| definition.letter);
+                if piece.color.is_white() {
+                    letter.to_ascii_uppercase()
+                } else {
+                    letter
+                }
+            }
+            _ => piece.to_char(),
+        }
+    }
+
+    // Material value of the kind, in centipawns, standard kinds included.
+    pub fn value(&self, kind: PieceKind) -> i32 {
+        match kind {
+            PieceKind::Fairy(id) => self.definition(id).map_or(0, |definition| definition.value),
+            _ => kind.value(),
+        }
+    }
+
+    // What the piece is shown as on a printed board, standard pieces included.
+    pub fn glyph(&self, piece: Piece) -> char {
+        match piece.kind {
+            PieceKind::Fairy(id) => self
+                .definition(id)
+                .map_or('?', |definition| definition.glyphs[piece.color.index()]),
+            _ => piece.glyph(),
+        }
+    }
+
+    pub(crate) fn steps(&self, id: u8) -> &[Step] {
+        self.pieces
+            .get(id as usize)
+            .map_or(&[], |piece| piece.steps.as_slice())
+    }
+}
+
+// Does the board have a fairy piece on it?
+pub fn has_fairy_pieces(board: &Board) -> bool {
+    board.fairy.is_some()
+        && board.board.iter().flatten().any(|piece| {
+            matches!(
+                piece,
+                Piece {
+                    kind: PieceKind::Fairy(_),
+                    ..
+                }
+            )
+        })
+}
+
+// Calls `land` with every square the step reaches on a board of the given shape, `occupant`
+// telling the color of the piece on a square. Hoppers only reach squares past their screen.
+pub(crate) fn walk(
+    geometry: Geometry,
+    occupant: impl Fn(u8) -> Option<Color>,
+    square: u8,
+    color: Color,
+    step: &Step,
+    mut land: impl FnMut(u8),
+) {
+    let rank = if color == Color::White {
+        step.rank
+    } else {
+        -step.rank
+    };
+    let mut screened = false;
+    for steps in 1..=step.range as i8 {
+        let Some(to) = geometry.offset(square, step.file * steps, rank * steps) else {
+            return;
+        };
+        let occupant = occupant(to);
+        if step.hop != Hop::None && !screened {
+            screened = occupant.is_some();
+            continue;
+        }
+        match occupant {
+            None if step.moves => land(to),
+            Some(other) if other != color && step.captures => land(to),
+            _ => (),
+        }
+        if occupant.is_some() || step.hop == Hop::Grasshopper {
+            return;
+        }
+    }
+}
+
+pub(crate) fn generate_moves(
+    board: &Board,
+    square: Square,
+    color: Color,
+    id: u8,
+    moves: &mut OnePieceMoves,
+) {
+    let mut moves_index = 0;
+    if let Some(pieces) = &board.fairy {
+        // A square can be reached by more than one step, e.g. a queen that also rides.
+        let mut reached = 0u64;
+        for step in pieces.steps(id) {
+            let occupant = |sqr: u8| board.board[sqr as usize].map(|piece| piece.color);
+            walk(
+                Geometry::STANDARD,
+                occupant,
+                square.into(),
+                color,
+                step,
+                |to| {
+                    if reached & (1 << to) == 0 && moves_index < moves.len() - 1 {
+                        reached |= 1 << to;
+                        moves[moves_index] = Some(Move::RegularMove(Square::from_index(to)));
+                        moves_index += 1;
+                    }
+                },
+            );
+        }
+    }
+    moves[moves_index] = None;
+}
+
+// Does the fairy piece of `color` on `square` attack `target`?
+pub(crate) fn attacks(board: &Board, square: u8, color: Color, id: u8, target: u8) -> bool {
+    let Some(pieces) = &board.fairy else {
+        return false;
+    };
+    let mut found = false;
+    for step in pieces.steps(id).iter().filter(|step| step.captures) {
+        let capture = Step {
+            moves: false,
+            ..*step
+        };
+        // The target counts as an enemy piece, is_attacked asks about empty squares too.
+        let occupant = |sqr: u8| {
+            if sqr == target {
+                Some(color.reverse())
+            } else {
+                board.board[sqr as usize].map(|piece| piece.color)
+            }
+        };
+        walk(
+            Geometry::STANDARD,
+            occupant,
+            square,
+            color,
+            &capture,
+            |to| found |= to == target,
+        );
+        if found {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offsets(steps: &[Step]) -> Vec<(i8, i8)> {
+        steps.iter().map(|step| (step.file, step.rank)).collect()
+    }
+
+    #[test]
+    fn leapers_take_one_step_every_way() {
+        for (betza, count) in [("W", 4), ("F", 4), ("N", 8), ("Z", 8), ("K", 8), ("WF", 8)] {
+            let steps = parse_betza(betza).unwrap();
+            assert_eq!(steps.len(), count, "{betza}");
+            assert!(steps.iter().all(|step| step.range == 1
+                && step.moves
+                && step.captures
+                && step.hop == Hop::None));
+        }
+        assert!(offsets(&parse_betza("C").unwrap()).contains(&(-1, 3)));
+    }
+
+    #[test]
+    fn riders_repeat_their_step() {
+        for betza in ["R", "WW", "W0", "NN", "Q"] {
+            let steps = parse_betza(betza).unwrap();
+            assert!(steps.iter().all(|step| step.range == UNLIMITED), "{betza}");
+        }
+        assert_eq!(parse_betza("R").unwrap(), parse_betza("WW").unwrap());
+        assert!(parse_betza("W3")
+            .unwrap()
+            .iter()
+            .all(|step| step.range == 3));
+        assert_eq!(parse_betza("BN").unwrap().len(), 12);
+    }
+
+    #[test]
+    fn modifiers_limit_what_a_step_does() {
+        let steps = parse_betza("mWcF").unwrap();
+        for step in steps {
+            let orthogonal = step.file == 0 || step.rank == 0;
+            assert_eq!((step.moves, step.captures), (orthogonal, !orthogonal));
+        }
+        assert_eq!(offsets(&parse_betza("fW").unwrap()), [(0, 1)]);
+        assert_eq!(offsets(&parse_betza("flF").unwrap()), [(-1, 1)]);
+        assert_eq!(offsets(&parse_betza("sW").unwrap()).len(), 2);
+        assert!(offsets(&parse_betza("vN").unwrap())
+            .iter()
+            .all(|(file, rank)| rank.abs() == 2 && file.abs() == 1));
+        assert!(parse_betza("pR")
+            .unwrap()
+            .iter()
+            .all(|step| step.hop == Hop::Screen));
+        assert!(parse_betza("gQ")
+            .unwrap()
+            .iter()
+            .all(|step| step.hop == Hop::Grasshopper));
+    }
+
+    #[test]
+    fn invalid_notation_is_an_error() {
+        for betza in ["", "X", "xW", "m", "Wm", "pW", "W999", "w"] {
+            assert!(
+                matches!(parse_betza(betza), Err(ChessError::InvalidPiece(_))),
+                "{betza}"
+            );
+        }
+    }
+
+    #[test]
+    fn sets_keep_their_pieces_to_themselves() {
+        let mut camels = PieceSet::new();
+        let camel = camels
+            .add(PieceDefinition::new("camel", 'l', "C", 250))
+            .unwrap();
+        assert_eq!(
+            camels.add(PieceDefinition::new("camel", 'l', "C", 250)),
+            Ok(camel)
+        );
+        assert!(camels
+            .add(PieceDefinition::new("lion", 'l', "KAD", 700))
+            .is_err());
+        assert!(camels
+            .add(PieceDefinition::new("knight", 'n', "N", 320))
+            .is_err());
+        let mut lions = PieceSet::new();
+        lions
+            .add(PieceDefinition::new("lion", 'l', "KAD", 700))
+            .unwrap();
+
+        let fen = "4k3/8/8/8/3L4/8/8/7K w - - 0 1";
+        assert!(Board::try_from_fen(fen).is_err());
+        let with_camel = Board::try_from_fen_with_pieces(fen, camels).unwrap();
+        let with_lion = Board::try_from_fen_with_pieces(fen, lions).unwrap();
+        let d4: Square = "d4".parse().unwrap();
+        let moves_from_d4 = |board: &Board| {
+            board
+                .legal_moves()
+                .into_iter()
+                .filter(|(start_square, _)| *start_square == d4)
+                .count()
+        };
+        assert_eq!(moves_from_d4(&with_camel), 8);
+        assert_eq!(moves_from_d4(&with_lion), 16);
+        assert_eq!(
+            with_camel.piece_value(with_camel.piece_at(d4).unwrap()),
+            250
+        );
+        assert_eq!(with_lion.piece_value(with_lion.piece_at(d4).unwrap()), 700);
+        assert_eq!(with_camel.to_fen(), fen);
+    }
+
+    #[test]
+    fn builtin_pieces_move_on_the_board() {
+        let fen = "4k3/8/8/8/3A4/8/8/4K3 w - - 0 1";
+        let board = Board::try_from_fen_with_pieces(fen, PieceSet::builtin()).unwrap();
+        let d4: Square = "d4".parse().unwrap();
+        let archbishop = board.piece_at(d4).unwrap();
+        assert_eq!(board.piece_letter(archbishop), 'A');
+        assert_eq!(board.piece_value(archbishop), 800);
+        assert_eq!(
+            board
+                .legal_moves()
+                .into_iter()
+                .filter(|(start_square, _)| *start_square == d4)
+                .count(),
+            21
+        );
+        assert!(board.is_attacked("f5".parse().unwrap(), Color::White));
+    }
+}
@@ -0,0 +1,846 @@
+use crate::board::CanCastle;
+use crate::fairy::{self, PieceSet, Step};
+use crate::pgn::GameResult;
+use crate::{ChessError, Color, Piece, PieceKind};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+// Boards of other shapes than 8x8. The orthodox Board keeps its precomputed 64 square tables,
+// positions on other boards are ShapedPositions, which work every step out from a Geometry.
+// Squares are numbered from a1 along the ranks as on the 8x8 board: a1 is 0, a2 is `files`.
+//
+// ShapedPosition has its own move generation, FEN and castling rules, and games can be played
+// on it move by move with `play` and `outcome`. The engine itself (search, UCI, books, tables)
+// only works on the 8x8 Board, so it can't play these variants against anyone.
+
+// Up to 12 files (a to l) and 10 ranks, so every set of squares fits in a u128.
+pub const MAX_FILES: u8 = 12;
+pub const MAX_RANKS: u8 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Geometry {
+    files: u8,
+    ranks: u8,
+}
+
+impl Geometry {
+    pub const STANDARD: Geometry = Geometry { files: 8, ranks: 8 };
+
+    pub fn new(files: u8, ranks: u8) -> Result<Self, ChessError> {
+        if !(1..=MAX_FILES).contains(&files) || !(1..=MAX_RANKS).contains(&ranks) {
+            return Err(ChessError::InvalidGeometry(format!(
+                "boards have 1 to {MAX_FILES} files and 1 to {MAX_RANKS} ranks, not {files}x{ranks}"
+            )));
+        }
+        Ok(Geometry { files, ranks })
+    }
+
+    pub fn files(&self) -> u8 {
+        self.files
+    }
+
+    pub fn ranks(&self) -> u8 {
+        self.ranks
+    }
+
+    pub fn squares(&self) -> usize {
+        self.files as usize * self.ranks as usize
+    }
+
+    // File of the square, from 0 (a).
+    pub fn file(&self, square: u8) -> u8 {
+        square % self.files
+    }
+
+    // Rank of the square, from 0 (1).
+    pub fn rank(&self, square: u8) -> u8 {
+        square / self.files
+    }
+
+    pub fn square(&self, file: i8, rank: i8) -> Option<u8> {
+        if (0..self.files as i8).contains(&file) && (0..self.ranks as i8).contains(&rank) {
+            Some(rank as u8 * self.files + file as u8)
+        } else {
+            None
+        }
+    }
+
+    // The square `files` files right and `ranks` ranks up, if it's on the board.
+    pub fn offset(&self, square: u8, files: i8, ranks: i8) -> Option<u8> {
+        self.square(
+            self.file(square) as i8 + files,
+            self.rank(square) as i8 + ranks,
+        )
+    }
+
+    // "e4", or "j10" on a board with ten ranks.
+    pub fn square_name(&self, square: u8) -> String {
+        format!(
+            "{}{}",
+            (b'a' + self.file(square)) as char,
+            self.rank(square) + 1
+        )
+    }
+
+    pub fn parse_square(&self, name: &str) -> Option<u8> {
+        let mut chars = name.chars();
+        let file = chars.next().filter(char::is_ascii_lowercase)? as u8 - b'a';
+        let rank: u8 = chars.as_str().parse().ok()?;
+        self.square(file as i8, rank as i8 - 1)
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry::STANDARD
+    }
+}
+
+// The variants played on boards other than 8x8.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShapedVariant {
+    // 10x8 with the archbishop and the chancellor.
+    Capablanca,
+    // Capablanca with the pieces set up differently.
+    Gothic,
+    // 6x6 without bishops, castling or double pawn steps.
+    LosAlamos,
+    // 5x5 minichess, without castling or double pawn steps.
+    Gardner,
+}
+
+pub const SHAPED_VARIANTS: [ShapedVariant; 4] = [
+    ShapedVariant::Capablanca,
+    ShapedVariant::Gothic,
+    ShapedVariant::LosAlamos,
+    ShapedVariant::Gardner,
+];
+
+impl ShapedVariant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ShapedVariant::Capablanca => "capablanca",
+            ShapedVariant::Gothic => "gothic",
+            ShapedVariant::LosAlamos => "losalamos",
+            ShapedVariant::Gardner => "gardner",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SHAPED_VARIANTS
+            .into_iter()
+            .find(|variant| variant.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        match self {
+            ShapedVariant::Capablanca | ShapedVariant::Gothic => Geometry {
+                files: 10,
+                ranks: 8,
+            },
+            ShapedVariant::LosAlamos => Geometry { files: 6, ranks: 6 },
+            ShapedVariant::Gardner => Geometry { files: 5, ranks: 5 },
+        }
+    }
+
+    pub fn start_fen(&self) -> &'static str {
+        match self {
+            ShapedVariant::Capablanca => {
+                "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1"
+            }
+            ShapedVariant::Gothic => {
+                "rnbqckabnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNBQCKABNR w KQkq - 0 1"
+            }
+            ShapedVariant::LosAlamos => "rnqknr/pppppp/6/6/PPPPPP/RNQKNR w - - 0 1",
+            ShapedVariant::Gardner => "rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1",
+        }
+    }
+
+    // Pawns on their starting rank may advance two squares, and be taken en passant.
+    fn has_double_steps(&self) -> bool {
+        matches!(self, ShapedVariant::Capablanca | ShapedVariant::Gothic)
+    }
+
+    fn has_castling(&self) -> bool {
+        matches!(self, ShapedVariant::Capablanca | ShapedVariant::Gothic)
+    }
+
+    // The fairy pieces the variant plays with.
+    pub fn pieces(&self) -> PieceSet {
+        let mut pieces = PieceSet::new();
+        if matches!(self, ShapedVariant::Capablanca | ShapedVariant::Gothic) {
+            for definition in fairy::builtin_pieces()
+                .into_iter()
+                .filter(|piece| matches!(piece.name.as_str(), "archbishop" | "chancellor"))
+            {
+                pieces
+                    .add(definition)
+                    .expect("the builtin pieces are valid");
+            }
+        }
+        pieces
+    }
+
+    // What pawns of the color promote to, fairy pieces taken from the variant's set.
+    fn promotions(&self, pieces: &PieceSet, color: Color) -> Vec<Piece> {
+        let mut promotions: Vec<Piece> = match self {
+            ShapedVariant::LosAlamos => [PieceKind::Queen, PieceKind::Rook, PieceKind::Knight],
+            _ => [PieceKind::Queen, PieceKind::Rook, PieceKind::Bishop],
+        }
+        .into_iter()
+        .map(|kind| Piece::new(kind, color))
+        .collect();
+        match self {
+            ShapedVariant::LosAlamos => (),
+            ShapedVariant::Gardner => promotions.push(Piece::new(PieceKind::Knight, color)),
+            ShapedVariant::Capablanca | ShapedVariant::Gothic => {
+                promotions.push(Piece::new(PieceKind::Knight, color));
+                for name in ["chancellor", "archbishop"] {
+                    promotions.extend(pieces.piece(name, color));
+                }
+            }
+        }
+        promotions
+    }
+}
+
+// Steps of the knight, bishop, rook, queen and king, worked out once. Pawns have none.
+fn standard_steps(kind: PieceKind) -> &'static [Step] {
+    static STEPS: OnceLock<Vec<(PieceKind, Vec<Step>)>> = OnceLock::new();
+    let steps = STEPS.get_or_init(|| {
+        [
+            (PieceKind::Knight, "N"),
+            (PieceKind::Bishop, "B"),
+            (PieceKind::Rook, "R"),
+            (PieceKind::Queen, "Q"),
+            (PieceKind::King, "K"),
+        ]
+        .into_iter()
+        .map(|(kind, betza)| (kind, fairy::parse_betza(betza).unwrap_or_default()))
+        .collect()
+    });
+    steps
+        .iter()
+        .find(|(standard, _)| *standard == kind)
+        .map_or(&[], |(_, steps)| steps)
+}
+
+// A move on a board of any shape. Like Move, but its squares are plain indices, since boards
+// bigger than 8x8 have more than 64 of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShapedMove {
+    Regular(u8),
+    DoubleStep(u8),
+    EnPassant(u8),
+    Promotion(u8, Piece),
+    CastleKingside,
+    CastleQueenside,
+}
+
+// A move and the square it starts from.
+pub type ShapedBoardMove = (u8, ShapedMove);
+
+// A position on a board of any shape, with the side to move.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ShapedPosition {
+    variant: ShapedVariant,
+    geometry: Geometry,
+    // Indexed by square.
+    squares: Vec<Option<Piece>>,
+    color: Color,
+    can_castle: CanCastle,
+    // The square a pawn skipped over advancing two squares on the last move.
+    en_passant: Option<u8>,
+    halfmove_clock: u8,
+    // The variant's fairy pieces.
+    pieces: Arc<PieceSet>,
+}
+
+impl ShapedPosition {
+    pub fn start(variant: ShapedVariant) -> Self {
+        Self::from_fen(variant, variant.start_fen()).expect("start positions are valid")
+    }
+
+    // Reads a FEN for the variant's board: every rank has to add up to its width, and
+    // numbers can have two digits. Fairy pieces are the variant's own, see ShapedVariant::pieces.
+    pub fn from_fen(variant: ShapedVariant, fen: &str) -> Result<Self, ChessError> {
+        Self::read_fen(variant, fen).map_err(ChessError::InvalidFen)
+    }
+
+    pub fn variant(&self) -> ShapedVariant {
+        self.variant
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn piece_at(&self, square: u8) -> Option<Piece> {
+        self.squares.get(square as usize).copied().flatten()
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.color
+    }
+
+    fn read_fen(variant: ShapedVariant, fen: &str) -> Result<Self, String> {
+        let geometry = variant.geometry();
+        let pieces = variant.pieces();
+        let mut fields = fen.split_whitespace();
+        let placement = fields.next().ok_or("empty FEN")?;
+        let rows: Vec<&str> = placement.split('/').collect();
+        if rows.len() != geometry.ranks as usize {
+            return Err(format!(
+                "{} has {} ranks, not {}",
+                variant.name(),
+                geometry.ranks,
+                rows.len()
+            ));
+        }
+        let mut squares = vec![None; geometry.squares()];
+        for (row, text) in rows.iter().enumerate() {
+            let rank = geometry.ranks - 1 - row as u8;
+            let too_long = || format!("rank \"{text}\" is too long");
+            let mut file: u8 = 0;
+            let mut empty: u8 = 0;
+            for ch in text.chars() {
+                if let Some(digit) = ch.to_digit(10) {
+                    empty = empty
+                        .checked_mul(10)
+                        .and_then(|empty| empty.checked_add(digit as u8))
+                        .ok_or_else(too_long)?;
+                    continue;
+                }
+                file = file
+                    .checked_add(std::mem::take(&mut empty))
+                    .ok_or_else(too_long)?;
+                let piece = Piece::from_char(ch)
+                    .or_else(|| pieces.from_fen_char(ch))
+                    .ok_or_else(|| format!("unknown piece '{ch}' in \"{fen}\""))?;
+                let square = geometry
+                    .square(file as i8, rank as i8)
+                    .ok_or_else(too_long)?;
+                squares[square as usize] = Some(piece);
+                file += 1;
+            }
+            file = file.checked_add(empty).ok_or_else(too_long)?;
+            if file != geometry.files {
+                return Err(format!(
+                    "rank \"{text}\" has {file} files, not {}",
+                    geometry.files
+                ));
+            }
+        }
+        let color = match fields.next() {
+            Some("b") => Color::Black,
+            _ => Color::White,
+        };
+        let castling = fields.next().unwrap_or("-");
+        let can_castle = CanCastle {
+            white_kingside: castling.contains('K'),
+            white_queenside: castling.contains('Q'),
+            black_kingside: castling.contains('k'),
+            black_queenside: castling.contains('q'),
+        };
+        let en_passant = fields.next().and_then(|name| geometry.parse_square(name));
+        let halfmove_clock = fields
+            .next()
+            .and_then(|clock| clock.parse().ok())
+            .unwrap_or(0);
+        Ok(ShapedPosition {
+            variant,
+            geometry,
+            squares,
+            color,
+            can_castle,
+            en_passant,
+            halfmove_clock,
+            pieces: Arc::new(pieces),
+        })
+    }
+
+    pub fn to_fen(&self) -> String {
+        let geometry = self.geometry;
+        let mut rows = Vec::new();
+        for rank in (0..geometry.ranks).rev() {
+            let mut row = String::new();
+            let mut empty = 0;
+            for file in 0..geometry.files {
+                match self.squares[(rank * geometry.files + file) as usize] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            row.push_str(&std::mem::take(&mut empty).to_string());
+                        }
+                        row.push(self.pieces.letter(piece));
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                row.push_str(&empty.to_string());
+            }
+            rows.push(row);
+        }
+        let can_castle = self.can_castle;
+        let castling: String = [
+            (can_castle.white_kingside, 'K'),
+            (can_castle.white_queenside, 'Q'),
+            (can_castle.black_kingside, 'k'),
+            (can_castle.black_queenside, 'q'),
+        ]
+        .iter()
+        .filter(|(right, _)| *right)
+        .map(|(_, letter)| *letter)
+        .collect();
+        format!(
+            "{} {} {} {} {} 1",
+            rows.join("/"),
+            if self.color.is_white() { 'w' } else { 'b' },
+            if castling.is_empty() { "-" } else { &castling },
+            self.en_passant
+                .map_or("-".to_string(), |square| geometry.square_name(square)),
+            self.halfmove_clock
+        )
+    }
+
+    fn color_on(&self, square: u8) -> Option<Color> {
+        self.squares[square as usize].map(|piece| piece.color)
+    }
+
+    pub fn king_square(&self, color: Color) -> Option<u8> {
+        self.squares
+            .iter()
+            .position(|piece| *piece == Some(Piece::new(PieceKind::King, color)))
+            .map(|square| square as u8)
+    }
+
+    // Ranks a pawn of the color goes forwards by.
+    fn forward(color: Color) -> i8 {
+        if color.is_white() {
+            1
+        } else {
+            -1
+        }
+    }
+
+    // The steps of a piece that isn't a pawn.
+    fn steps(&self, piece: Piece) -> &[Step] {
+        match piece.kind {
+            PieceKind::Fairy(id) => self.pieces.steps(id),
+            kind => standard_steps(kind),
+        }
+    }
+
+    pub fn is_attacked(&self, target: u8, by: Color) -> bool {
+        let occupant = |square: u8| {
+            if square == target {
+                Some(by.reverse())
+            } else {
+                self.color_on(square)
+            }
+        };
+        let mut found = false;
+        for (square, piece) in self.squares.iter().enumerate() {
+            let Some(piece) = piece.filter(|piece| piece.color == by) else {
+                continue;
+            };
+            let square = square as u8;
+            if let Piece {
+                kind: PieceKind::Pawn,
+                ..
+            } = piece
+            {
+                found = [-1, 1].iter().any(|&file| {
+                    self.geometry.offset(square, file, Self::forward(by)) == Some(target)
+                });
+            } else {
+                for step in self.steps(piece).iter().filter(|step| step.captures) {
+                    let capture = Step {
+                        moves: false,
+                        ..*step
+                    };
+                    fairy::walk(self.geometry, occupant, square, by, &capture, |to| {
+                        found |= to == target
+                    });
+                    if found {
+                        break;
+                    }
+                }
+            }
+            if found {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn is_in_check(&self, color: Color) -> bool {
+        self.king_square(color)
+            .is_some_and(|square| self.is_attacked(square, color.reverse()))
+    }
+
+    // Where the king ends up castling to the side: two files in from the corner, with the
+    // rook next to it on the inside, as on the 8x8 board.
+    fn castling_squares(&self, kingside: bool) -> (u8, u8, u8) {
+        let files = self.geometry.files as i8;
+        let rank = if self.color.is_white() {
+            0
+        } else {
+            self.geometry.ranks as i8 - 1
+        };
+        let (rook_file, king_to, rook_to) = if kingside {
+            (files - 1, files - 2, files - 3)
+        } else {
+            (0, 2, 3)
+        };
+        let square = |file| self.geometry.square(file, rank).unwrap_or(0);
+        (square(rook_file), square(king_to), square(rook_to))
+    }
+
+    // Where the piece on the start square ends up, the king's square for castling.
+    pub fn end_square(&self, a_move: ShapedMove) -> u8 {
+        match a_move {
+            ShapedMove::CastleKingside => self.castling_squares(true).1,
+            ShapedMove::CastleQueenside => self.castling_squares(false).1,
+            ShapedMove::Regular(to)
+            | ShapedMove::DoubleStep(to)
+            | ShapedMove::EnPassant(to)
+            | ShapedMove::Promotion(to, _) => to,
+        }
+    }
+
+    fn castling_moves(&self, king: u8, moves: &mut Vec<ShapedBoardMove>) {
+        let can_castle = self.can_castle;
+        let rights = if self.color.is_white() {
+            [can_castle.white_kingside, can_castle.white_queenside]
+        } else {
+            [can_castle.black_kingside, can_castle.black_queenside]
+        };
+        let rook = Piece::new(PieceKind::Rook, self.color);
+        for (kingside, has_right) in [true, false].into_iter().zip(rights) {
+            let (rook_square, king_to, rook_to) = self.castling_squares(kingside);
+            if !has_right || self.squares[rook_square as usize] != Some(rook) {
+                continue;
+            }
+            // Everything from the king and the rook to where they go has to be empty, but
+            // for the two of them, and the king can't pass through check.
+            let span = |a: u8, b: u8| a.min(b)..=a.max(b);
+            let is_free = span(king, rook_square)
+                .chain(span(king, king_to))
+                .chain(span(rook_square, rook_to))
+                .all(|square| {
+                    square == king
+                        || square == rook_square
+                        || self.squares[square as usize].is_none()
+                });
+            if is_free
+                && span(king, king_to).all(|square| !self.is_attacked(square, self.color.reverse()))
+            {
+                moves.push((
+                    king,
+                    if kingside {
+                        ShapedMove::CastleKingside
+                    } else {
+                        ShapedMove::CastleQueenside
+                    },
+                ));
+            }
+        }
+    }
+
+    fn pawn_moves(&self, square: u8, moves: &mut Vec<ShapedBoardMove>) {
+        let geometry = self.geometry;
+        let color = self.color;
+        let forward = Self::forward(color);
+        let last_rank = if color.is_white() {
+            geometry.ranks - 1
+        } else {
+            0
+        };
+        let add = |moves: &mut Vec<ShapedBoardMove>, to: u8| {
+            if geometry.rank(to) == last_rank {
+                for piece in self.variant.promotions(&self.pieces, color) {
+                    moves.push((square, ShapedMove::Promotion(to, piece)));
+                }
+            } else {
+                moves.push((square, ShapedMove::Regular(to)));
+            }
+        };
+        if let Some(to) = geometry.offset(square, 0, forward) {
+            if self.squares[to as usize].is_none() {
+                add(moves, to);
+                let first_rank = if color.is_white() {
+                    1
+                } else {
+                    geometry.ranks - 2
+                };
+                if self.variant.has_double_steps() && geometry.rank(square) == first_rank {
+                    if let Some(two) = geometry
+                        .offset(to, 0, forward)
+                        .filter(|two| self.squares[*two as usize].is_none())
+                    {
+                        moves.push((square, ShapedMove::DoubleStep(two)));
+                    }
+                }
+            }
+        }
+        for file in [-1, 1] {
+            let Some(to) = geometry.offset(square, file, forward) else {
+                continue;
+            };
+            if self.color_on(to) == Some(color.reverse()) {
+                add(moves, to);
+            } else if self.en_passant == Some(to) {
+                moves.push((square, ShapedMove::EnPassant(to)));
+            }
+        }
+    }
+
+    pub fn pseudo_legal_moves(&self) -> Vec<ShapedBoardMove> {
+        let mut moves = Vec::new();
+        for (square, piece) in self.squares.iter().enumerate() {
+            let Some(piece) = piece.filter(|piece| piece.color == self.color) else {
+                continue;
+            };
+            let square = square as u8;
+            match piece {
+                Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                } => self.pawn_moves(square, &mut moves),
+                _ => {
+                    // A square can be reached by more than one step, e.g. an archbishop's.
+                    let mut reached = 0u128;
+                    let occupant = |square: u8| self.color_on(square);
+                    for step in self.steps(piece) {
+                        fairy::walk(self.geometry, occupant, square, self.color, step, |to| {
+                            if reached & (1 << to) == 0 {
+                                reached |= 1 << to;
+                                moves.push((square, ShapedMove::Regular(to)));
+                            }
+                        });
+                    }
+                    if let Piece {
+                        kind: PieceKind::King,
+                        ..
+                    } = piece
+                    {
+                        if self.variant.has_castling() {
+                            self.castling_moves(square, &mut moves);
+                        }
+                    }
+                }
+            }
+        }
+        moves
+    }
+
+    pub fn legal_moves(&self) -> Vec<ShapedBoardMove> {
+        self.pseudo_legal_moves()
+            .into_iter()
+            .filter(|(start_square, a_move)| {
+                !self
+                    .make_move(*start_square, *a_move)
+                    .is_in_check(self.color)
+            })
+            .collect()
+    }
+
+    pub fn make_move(&self, start_square: u8, a_move: ShapedMove) -> Self {
+        let mut position = self.clone();
+        let geometry = self.geometry;
+        let Some(piece) = self.squares[start_square as usize] else {
+            return position;
+        };
+        let end_square = self.end_square(a_move);
+        let is_capture = self.squares[end_square as usize].is_some();
+        position.squares[start_square as usize] = None;
+        position.en_passant = None;
+        match a_move {
+            ShapedMove::CastleKingside | ShapedMove::CastleQueenside => {
+                let (rook_square, king_to, rook_to) =
+                    self.castling_squares(a_move == ShapedMove::CastleKingside);
+                position.squares[rook_square as usize] = None;
+                position.squares[king_to as usize] = Some(piece);
+                position.squares[rook_to as usize] = Some(Piece::new(PieceKind::Rook, self.color));
+            }
+            ShapedMove::DoubleStep(to) => {
+                position.squares[to as usize] = Some(piece);
+                position.en_passant = geometry.offset(to, 0, -Self::forward(self.color));
+            }
+            ShapedMove::EnPassant(to) => {
+                position.squares[to as usize] = Some(piece);
+                if let Some(taken) = geometry.offset(to, 0, -Self::forward(self.color)) {
+                    position.squares[taken as usize] = None;
+                }
+            }
+            ShapedMove::Promotion(to, new_piece) => {
+                position.squares[to as usize] = Some(new_piece);
+            }
+            ShapedMove::Regular(to) => position.squares[to as usize] = Some(piece),
+        }
+        position.halfmove_clock = if is_capture
+            || matches!(
+                piece,
+                Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                }
+            ) {
+            0
+        } else {
+            self.halfmove_clock.saturating_add(1)
+        };
+        // Rights go when the king moves, or when anything moves from or to a rook's corner.
+        let last_rank = geometry.ranks - 1;
+        let corner = |file: u8, rank: u8| rank * geometry.files + file;
+        let touched = |square: u8| square == start_square || square == end_square;
+        let can_castle = &mut position.can_castle;
+        if let Piece {
+            kind: PieceKind::King,
+            ..
+        } = piece
+        {
+            if self.color.is_white() {
+                can_castle.white_kingside = false;
+                can_castle.white_queenside = false;
+            } else {
+                can_castle.black_kingside = false;
+                can_castle.black_queenside = false;
+            }
+        }
+        can_castle.white_kingside &= !touched(corner(geometry.files - 1, 0));
+        can_castle.white_queenside &= !touched(corner(0, 0));
+        can_castle.black_kingside &= !touched(corner(geometry.files - 1, last_rank));
+        can_castle.black_queenside &= !touched(corner(0, last_rank));
+        position.color = self.color.reverse();
+        position
+    }
+
+    // Long algebraic notation, castling written as the king's move.
+    pub fn move_to_uci(&self, start_square: u8, a_move: ShapedMove) -> String {
+        let mut uci = self.geometry.square_name(start_square)
+            + &self.geometry.square_name(self.end_square(a_move));
+        if let ShapedMove::Promotion(_, piece) = a_move {
+            uci.push(self.pieces.letter(piece).to_ascii_lowercase());
+        }
+        uci
+    }
+
+    pub fn move_from_uci(&self, uci: &str) -> Option<ShapedBoardMove> {
+        self.legal_moves()
+            .into_iter()
+            .find(|(start_square, a_move)| self.move_to_uci(*start_square, *a_move) == uci)
+    }
+
+    // Plays a move given in long algebraic notation, if it is legal.
+    pub fn play(&self, uci: &str) -> Result<Self, ChessError> {
+        let (start_square, a_move) = self
+            .move_from_uci(uci)
+            .ok_or_else(|| ChessError::IllegalMove(uci.to_string()))?;
+        Ok(self.make_move(start_square, a_move))
+    }
+
+    // The result if the game is over: mate, stalemate or the fifty-move rule. Draws by
+    // repetition are left to the caller, which has the game's history.
+    pub fn outcome(&self) -> Option<GameResult> {
+        if self.legal_moves().is_empty() {
+            return Some(if !self.is_in_check(self.color) {
+                GameResult::Draw
+            } else if self.color.is_white() {
+                GameResult::BlackWins
+            } else {
+                GameResult::WhiteWins
+            });
+        }
+        (self.halfmove_clock >= 100).then_some(GameResult::Draw)
+    }
+
+    // Number of leaf nodes of the legal move tree to the depth.
+    pub fn perft(&self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|(start_square, a_move)| self.make_move(start_square, a_move).perft(depth - 1))
+            .sum()
+    }
+}
+
+// Print the board with the top rank first.
+impl fmt::Display for ShapedPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for rank in (0..self.geometry.ranks).rev() {
+            let mut row = String::new();
+            for file in 0..self.geometry.files {
+                match self.squares[(rank * self.geometry.files + file) as usize] {
+                    Some(piece) => row.push(self.pieces.glyph(piece)),
+                    None => row.push('.'),
+                }
+                row.push(' ');
+            }
+            writeln!(f, "{row}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_numbers_in_a_fen_are_an_error() {
+        for placement in ["300", "99p99p99p", "9999999999999999999"] {
+            let fen = format!("{placement}/10/10/10/10/10/10/10 w - - 0 1");
+            assert!(matches!(
+                ShapedPosition::from_fen(ShapedVariant::Capablanca, &fen),
+                Err(ChessError::InvalidFen(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn games_are_played_to_a_result() {
+        let mut position = ShapedPosition::start(ShapedVariant::Gardner);
+        assert_eq!(position.outcome(), None);
+        for uci in ["c2c3", "b4c3"] {
+            position = position.play(uci).unwrap();
+        }
+        assert!(matches!(
+            position.play("c3c4"),
+            Err(ChessError::IllegalMove(_))
+        ));
+        let mate = ShapedPosition::from_fen(ShapedVariant::Gardner, "k4/1Q3/1K3/5/5 b - - 0 1");
+        assert_eq!(mate.unwrap().outcome(), Some(GameResult::WhiteWins));
+        let stalemate =
+            ShapedPosition::from_fen(ShapedVariant::Gardner, "k4/2Q2/1K3/5/5 b - - 0 1");
+        assert_eq!(stalemate.unwrap().outcome(), Some(GameResult::Draw));
+        let fifty = ShapedPosition::from_fen(ShapedVariant::Gardner, "k4/5/1K3/5/4R w - - 100 80");
+        assert_eq!(fifty.unwrap().outcome(), Some(GameResult::Draw));
+    }
+
+    #[test]
+    fn castling_works_on_the_wide_board() {
+        let position = ShapedPosition::from_fen(
+            ShapedVariant::Capablanca,
+            "r3k4r/10/10/10/10/10/10/R3K4R w KQkq - 0 1",
+        )
+        .unwrap();
+        let castled = position.play("e1i1").unwrap();
+        assert_eq!(
+            castled.piece_at(8),
+            Some(Piece::new(PieceKind::King, Color::White))
+        );
+        assert_eq!(
+            castled.piece_at(7),
+            Some(Piece::new(PieceKind::Rook, Color::White))
+        );
+        assert!(castled.to_fen().ends_with(" b kq - 1 1"));
+    }
+}
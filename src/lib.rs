@@ -3,12 +3,46 @@ pub mod board;
 use std::fmt;
 
 pub use board::Board;
+pub use builder::BoardBuilder;
+pub use error::ChessError;
+pub use square::{File, ParseSquareError, Rank, Square};
+pub mod book;
+pub mod builder;
+pub mod datagen;
+pub mod endgame;
+pub mod epd;
+pub mod error;
+pub mod eval;
+pub mod fairy;
+pub mod geometry;
+pub mod mate;
+pub mod nnue;
+pub mod ordering;
+pub mod params;
+pub mod pawns;
+pub mod pgn;
+pub mod pieces;
 pub mod search;
+pub mod sprt;
+pub mod square;
+pub mod syzygy;
+pub mod tbgen;
+pub mod time;
+pub mod tournament;
+pub mod tt;
+pub mod uci;
+pub mod variant;
+pub mod zobrist;
 use board::CanEnPassant;
-pub use search::multi_thread_eval;
+pub use search::{multi_thread_eval, SearchConfig, SearchInfo, SearchLimits, Searcher};
 
-pub type OnePieceMoves = [Option<Move>; 28];
-pub type PositionMoves = [Option<(u8, OnePieceMoves)>; 16];
+// Room for the moves of one piece, the None after the last one included. Fairy pieces can
+// have many more moves than a queen.
+pub const MAX_PIECE_MOVES: usize = 64;
+pub type OnePieceMoves = [Option<Move>; MAX_PIECE_MOVES];
+pub type PositionMoves = Vec<(Square, OnePieceMoves)>;
+// A move together with the square of the piece making it.
+pub type BoardMove = (Square, Move);
 
 // Pre-computed values for relative squares for each square.
 pub static UP: [u8; 64] = [
@@ -51,89 +85,32 @@ pub static UP_LEFT: [u8; 64] = [
     64, 32, 33, 34, 35, 36, 37, 38, 64, 40, 41, 42, 43, 44, 45, 46, 64, 48, 49, 50, 51, 52, 53, 54,
     64, 56, 57, 58, 59, 60, 61, 62, 64, 64, 64, 64, 64, 64, 64, 64,
 ];
-//Functions to get precomputed values of squares relative to any given square
-fn up(square: usize) -> Option<u8> {
-    if UP[square] != 64 {
-        Some(UP[square])
-    } else {
-        None
-    }
-}
-fn down(square: usize) -> Option<u8> {
-    if DOWN[square] != 64 {
-        Some(DOWN[square])
-    } else {
-        None
-    }
-}
-fn left(square: usize) -> Option<u8> {
-    if LEFT[square] != 64 {
-        Some(LEFT[square])
-    } else {
-        None
-    }
-}
-fn right(square: usize) -> Option<u8> {
-    if RIGHT[square] != 64 {
-        Some(RIGHT[square])
-    } else {
-        None
-    }
-}
-fn down_right(square: usize) -> Option<u8> {
-    if DOWN_RIGHT[square] != 64 {
-        Some(DOWN_RIGHT[square])
-    } else {
-        None
-    }
-}
-fn down_left(square: usize) -> Option<u8> {
-    if DOWN_LEFT[square] != 64 {
-        Some(DOWN_LEFT[square])
-    } else {
-        None
-    }
-}
-fn up_left(square: usize) -> Option<u8> {
-    if UP_LEFT[square] != 64 {
-        Some(UP_LEFT[square])
-    } else {
-        None
-    }
-}
-fn up_right(square: usize) -> Option<u8> {
-    if UP_RIGHT[square] != 64 {
-        Some(UP_RIGHT[square])
-    } else {
-        None
-    }
-}
-
 // Trait which every piece implements. Has only one function, which generates all possible moves for that piece.
-trait PieceTrait {
-    fn generate_moves(&self, board: &Board, square: u8, moves_list: &mut [Option<Move>; 28]);
+// New kinds of pieces are defined through the fairy module rather than by implementing it.
+pub trait PieceTrait {
+    fn generate_moves(&self, board: &Board, square: Square, moves_list: &mut OnePieceMoves);
 }
 
 // For the pieces that move straight until they find an enemy piece (queen, rook, bishop)
-trait MovesInALine {
+pub trait MovesInALine {
     fn move_in_line(
         &self,
-        direction: fn(usize) -> Option<u8>,
+        direction: fn(Square) -> Option<Square>,
         board: &Board,
-        square: u8,
-        moves: &mut [Option<Move>; 28],
+        square: Square,
+        moves: &mut OnePieceMoves,
         own_color: Color,
         moves_index: &mut usize,
     ) {
-        let mut next_square = direction(square as usize);
+        let mut next_square = direction(square);
         // While there is a next valid square
         while let Some(square_in_line) = next_square {
             // What is in the square.
-            let piece_in_square = board.board[square_in_line as usize];
+            let piece_in_square = board.board[square_in_line.index()];
             // if there is a piece
             if let Some(piece) = piece_in_square {
                 // if  color is different, add that as a move and stop loop, else, stop loop
-                if piece.get_color() != own_color {
+                if piece.color != own_color {
                     moves[*moves_index] = Some(Move::RegularMove(square_in_line));
                     *moves_index += 1;
                 }
@@ -143,19 +120,49 @@ trait MovesInALine {
                 *moves_index += 1;
             }
             // go to next square in line
-            next_square = direction(next_square.unwrap() as usize);
+            next_square = direction(square_in_line);
         }
         moves[*moves_index] = None;
     }
 }
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq)]
 pub enum Move {
-    RegularMove(u8),
+    RegularMove(Square),
     CastleKingside,
     CastleQueenside,
-    EnPassant(u8),
-    PawnAdvanceTwoSquares(u8),
-    PawnPromotion(u8, Piece),
+    EnPassant(Square),
+    PawnAdvanceTwoSquares(Square),
+    PawnPromotion(Square, Piece),
+    // A piece from the hand put on an empty square, in drop variants like crazyhouse. The
+    // square is also used as the move's start square.
+    Drop(Square, Piece),
+}
+
+impl Move {
+    // The square the moving piece ends up on. Castling moves don't store it, so it depends on the color.
+    pub fn end_square(&self, color: Color) -> Square {
+        match *self {
+            Move::RegularMove(sqr)
+            | Move::EnPassant(sqr)
+            | Move::PawnAdvanceTwoSquares(sqr)
+            | Move::PawnPromotion(sqr, _)
+            | Move::Drop(sqr, _) => sqr,
+            Move::CastleKingside => Square::from_index(if color.is_white() { 6 } else { 62 }),
+            Move::CastleQueenside => Square::from_index(if color.is_white() { 2 } else { 58 }),
+        }
+    }
+
+    // Long algebraic notation, as used by UCI: "e2e4", "e1g1" or "e7e8q". Drops are "N@f3".
+    pub fn to_uci(&self, start_square: Square, color: Color) -> String {
+        if let Move::Drop(sqr, piece) = self {
+            return format!("{}@{sqr}", piece.to_char().to_ascii_uppercase());
+        }
+        let mut uci = format!("{start_square}{}", self.end_square(color));
+        if let Move::PawnPromotion(_, piece) = self {
+            uci.push(piece.to_char().to_ascii_lowercase());
+        }
+        uci
+    }
 }
 
 // A piece can be black or white.
@@ -171,6 +178,14 @@ impl Color {
     fn is_white(&self) -> bool {
         *self == Color::White
     }
+    // Index for tables kept per color: 0 for white, 1 for black.
+    pub(crate) fn index(&self) -> usize {
+        if self.is_white() {
+            0
+        } else {
+            1
+        }
+    }
     // Get the opposite of the color that came in
     pub fn reverse(&self) -> Color {
         if *self == Color::White {
@@ -180,48 +195,173 @@ impl Color {
     }
 }
 
+// What a piece is, whatever its color. Fairy pieces go by their place in the position's
+// fairy::PieceSet.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Ord, PartialOrd)]
+pub enum PieceKind {
+    Pawn,
+    Knight,
+    Bishop,
+    Rook,
+    Queen,
+    King,
+    Fairy(u8),
+}
+
+impl PieceKind {
+    // The kinds of standard chess, from pawn to king.
+    pub const STANDARD: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+    // What a pawn promotes to in standard chess, in the order the moves are generated.
+    pub const PROMOTIONS: [PieceKind; 4] = [
+        PieceKind::Bishop,
+        PieceKind::Knight,
+        PieceKind::Rook,
+        PieceKind::Queen,
+    ];
+
+    // Index of the kind, from 0 (pawn) to 5 (king), fairy pieces following from
+    // fairy::FAIRY_KIND on. Used to look pieces up in tables.
+    pub const fn index(self) -> usize {
+        match self {
+            PieceKind::Pawn => 0,
+            PieceKind::Knight => 1,
+            PieceKind::Bishop => 2,
+            PieceKind::Rook => 3,
+            PieceKind::Queen => 4,
+            PieceKind::King => 5,
+            PieceKind::Fairy(id) => fairy::FAIRY_KIND + id as usize,
+        }
+    }
+    // The kind with the index, as given by index().
+    pub const fn from_index(index: usize) -> PieceKind {
+        match index {
+            0 => PieceKind::Pawn,
+            1 => PieceKind::Knight,
+            2 => PieceKind::Bishop,
+            3 => PieceKind::Rook,
+            4 => PieceKind::Queen,
+            5 => PieceKind::King,
+            _ => PieceKind::Fairy((index - fairy::FAIRY_KIND) as u8),
+        }
+    }
+    // The standard kind for the letter, in either case. Fairy pieces are read by their
+    // PieceSet.
+    pub fn from_char(letter: char) -> Option<PieceKind> {
+        "pnbrqk"
+            .find(letter.to_ascii_lowercase())
+            .map(PieceKind::from_index)
+    }
+    // The kind's letter, lowercase. Fairy pieces only have one in their PieceSet, '?' here.
+    pub fn to_char(self) -> char {
+        match self {
+            PieceKind::Pawn => 'p',
+            PieceKind::Knight => 'n',
+            PieceKind::Bishop => 'b',
+            PieceKind::Rook => 'r',
+            PieceKind::Queen => 'q',
+            PieceKind::King => 'k',
+            PieceKind::Fairy(_) => '?',
+        }
+    }
+    // Material value of the kind, in centipawns. Fairy pieces' values are in their PieceSet.
+    pub fn value(self) -> i32 {
+        match self {
+            PieceKind::Pawn => 100,
+            PieceKind::Knight => 320,
+            PieceKind::Bishop => 330,
+            PieceKind::Rook => 500,
+            PieceKind::Queen => 900,
+            PieceKind::King => 20000,
+            PieceKind::Fairy(_) => 0,
+        }
+    }
+    // The kinds of standard chess, from pawn to king.
+    pub fn all() -> impl Iterator<Item = PieceKind> {
+        Self::STANDARD.into_iter()
+    }
+}
+
+// A piece on the board or in hand.
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Ord, PartialOrd)]
-// Possible piece types
-pub enum Piece {
-    Pawn(Pawn),
-    Knight(Knight),
-    Bishop(Bishop),
-    Rook(Rook),
-    Queen(Queen),
-    King(King),
+pub struct Piece {
+    pub kind: PieceKind,
+    pub color: Color,
 }
 
 impl Piece {
-    // Returns the piece's color
-    fn get_color(&self) -> Color {
-        match *self {
-            Piece::Pawn(piece) => piece.color,
-            Piece::Knight(piece) => piece.color,
-            Piece::Bishop(piece) => piece.color,
-            Piece::Rook(piece) => piece.color,
-            Piece::Queen(piece) => piece.color,
-            Piece::King(piece) => piece.color,
+    pub const fn new(kind: PieceKind, color: Color) -> Piece {
+        Piece { kind, color }
+    }
+    // The standard piece for the FEN letter, uppercase for white.
+    pub fn from_char(letter: char) -> Option<Piece> {
+        let color = if letter.is_ascii_uppercase() {
+            Color::White
+        } else {
+            Color::Black
+        };
+        PieceKind::from_char(letter).map(|kind| Piece::new(kind, color))
+    }
+    // Letter used for the piece in FEN, uppercase for white.
+    pub fn to_char(&self) -> char {
+        let letter = self.kind.to_char();
+        if self.color.is_white() {
+            letter.to_ascii_uppercase()
+        } else {
+            letter
         }
     }
+    // What the piece is shown as on a printed board. Fairy pieces' glyphs are in their
+    // PieceSet.
+    pub fn glyph(&self) -> char {
+        let glyphs = match self.kind {
+            PieceKind::Pawn => ['♙', '♟'],
+            PieceKind::Knight => ['♘', '♞'],
+            PieceKind::Bishop => ['♗', '♝'],
+            PieceKind::Rook => ['♖', '♜'],
+            PieceKind::Queen => ['♕', '♛'],
+            PieceKind::King => ['♔', '♚'],
+            PieceKind::Fairy(_) => ['?', '?'],
+        };
+        glyphs[self.color.index()]
+    }
+    // Material value of the piece, in centipawns.
+    pub fn value(&self) -> i32 {
+        self.kind.value()
+    }
+    // The twelve pieces of standard chess, white's first.
+    pub fn all() -> impl Iterator<Item = Piece> {
+        [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(|color| PieceKind::all().map(move |kind| Piece::new(kind, color)))
+    }
     //returns the piece's moves
-    pub fn get_moves(&self, board: &Board, piece_square: u8, moves: &mut [Option<Move>; 28]) {
-        match *self {
-            Piece::Pawn(piece) => piece.generate_moves(board, piece_square, moves),
-            Piece::Knight(piece) => piece.generate_moves(board, piece_square, moves),
-            Piece::Bishop(piece) => piece.generate_moves(board, piece_square, moves),
-            Piece::Rook(piece) => piece.generate_moves(board, piece_square, moves),
-            Piece::Queen(piece) => piece.generate_moves(board, piece_square, moves),
-            Piece::King(piece) => piece.generate_moves(board, piece_square, moves),
+    pub fn get_moves(&self, board: &Board, piece_square: Square, moves: &mut OnePieceMoves) {
+        let color = self.color;
+        match self.kind {
+            PieceKind::Pawn => Pawn { color }.generate_moves(board, piece_square, moves),
+            PieceKind::Knight => Knight { color }.generate_moves(board, piece_square, moves),
+            PieceKind::Bishop => Bishop { color }.generate_moves(board, piece_square, moves),
+            PieceKind::Rook => Rook { color }.generate_moves(board, piece_square, moves),
+            PieceKind::Queen => Queen { color }.generate_moves(board, piece_square, moves),
+            PieceKind::King => King { color }.generate_moves(board, piece_square, moves),
+            PieceKind::Fairy(id) => Fairy { color, id }.generate_moves(board, piece_square, moves),
         }
     }
     //Returns a bool corresponding to whether piece a is laterally adjacent to piece b
-    fn is_to_the_side_of(own_square: usize, other_square: u8) -> bool {
-        if let Some(square) = left(own_square) {
+    fn is_to_the_side_of(own_square: Square, other_square: Square) -> bool {
+        if let Some(square) = own_square.left() {
             if square == other_square {
                 return true;
             }
         }
-        if let Some(square) = right(own_square) {
+        if let Some(square) = own_square.right() {
             if square == other_square {
                 return true;
             }
@@ -230,7 +370,7 @@ impl Piece {
     }
 }
 
-// Each piece may implement different functions.
+// The move generators of each kind of piece.
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Ord, PartialOrd)]
 pub struct Pawn {
     color: Color,
@@ -253,65 +393,66 @@ pub struct Queen {
 }
 #[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Ord, PartialOrd)]
 pub struct King {
-    pub color: Color,
+    color: Color,
+}
+// A fairy piece, `id` being its place in the board's PieceSet.
+#[derive(Clone, Copy, Debug, PartialEq, Hash, Eq, Ord, PartialOrd)]
+pub struct Fairy {
+    color: Color,
+    id: u8,
+}
+
+impl PieceTrait for Fairy {
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
+        fairy::generate_moves(board, square, self.color, self.id, moves);
+    }
 }
 
 impl PieceTrait for Pawn {
     // Generate possible moves for a pawn
-    fn generate_moves(&self, board: &Board, piece_square: u8, moves: &mut [Option<Move>; 28]) {
+    fn generate_moves(&self, board: &Board, piece_square: Square, moves: &mut OnePieceMoves) {
         let mut moves_index = 0;
         // Create the vector which will be returned
         // First possibility for the next square (up if white, down if black)
         let end_square = if let Color::White = self.color {
-            up(piece_square as usize)
+            piece_square.up()
         } else {
-            down(piece_square as usize)
+            piece_square.down()
         };
         // If there is a square next, proceed
         if let Some(end_square) = end_square {
             // the next square in the board
-            let end_square_in_board = board.board[end_square as usize];
+            let end_square_in_board = board.board[end_square.index()];
             //if the square is empty, (i. e. there are no pieces in it), proceed
             if end_square_in_board.is_none() {
                 //if it's in the last rank, we can add in its promotions
-                if Board::get_row(end_square) == if let Color::White = self.color { 7 } else { 0 } {
-                    let own_color = self.color;
-                    moves[0] = Some(Move::PawnPromotion(
-                        end_square,
-                        Piece::Bishop(Bishop { color: own_color }),
-                    ));
-                    moves[1] = Some(Move::PawnPromotion(
-                        end_square,
-                        Piece::Knight(Knight { color: own_color }),
-                    ));
-                    moves[2] = Some(Move::PawnPromotion(
-                        end_square,
-                        Piece::Rook(Rook { color: own_color }),
-                    ));
-                    moves[3] = Some(Move::PawnPromotion(
-                        end_square,
-                        Piece::Queen(Queen { color: own_color }),
-                    ));
-                    moves_index = 4;
+                if end_square.rank().index() == if let Color::White = self.color { 7 } else { 0 } {
+                    for kind in PieceKind::PROMOTIONS {
+                        moves[moves_index] = Some(Move::PawnPromotion(
+                            end_square,
+                            Piece::new(kind, self.color),
+                        ));
+                        moves_index += 1;
+                    }
                 }
                 // if the pawn is in it's initial rank, proceed
-                else if Board::get_row(piece_square)
+                else if piece_square.rank().index()
                     == if let Color::White = self.color { 1 } else { 6 }
                 {
                     // Create a next square, as the upper (or the one below) the previous square
                     let next_square = if let Color::White = self.color {
-                        up(end_square as usize).unwrap()
+                        end_square.up().unwrap()
                     } else {
-                        down(end_square as usize).unwrap()
+                        end_square.down().unwrap()
                     };
                     // if there are no pieces in that square, add the square to the list of moves
-                    if board.board[next_square as usize].is_none() {
+                    if board.board[next_square.index()].is_none() {
                         moves[moves_index] = Some(Move::PawnAdvanceTwoSquares(next_square));
                         moves_index += 1;
                     }
                 }
                 // And we can add a move forward no matter the rank, because the square ahead is empty.
-                if Board::get_row(end_square) != if let Color::White = self.color { 7 } else { 0 } {
+                if end_square.rank().index() != if let Color::White = self.color { 7 } else { 0 } {
                     moves[moves_index] = Some(Move::RegularMove(end_square));
                     moves_index += 1;
                 }
@@ -320,34 +461,21 @@ impl PieceTrait for Pawn {
         // Check if the pawn can take anything, i'll probably make a macro or new function to avoid repetition, after i fix bugs
 
         if let Some(square) = if self.color.is_white() {
-            up_right(piece_square as usize)
+            piece_square.up_right()
         } else {
-            down_right(piece_square as usize)
+            piece_square.down_right()
         } {
             // If there is a piece to the diagonal right of the square
-            if let Some(piece) = board.board[square as usize] {
+            if let Some(piece) = board.board[square.index()] {
                 // if it's not an ally
-                if piece.get_color() != self.color {
+                if piece.color != self.color {
                     // promote it if needed, else just add it as a regular move
-                    if Board::get_row(square) == if let Color::White = self.color { 7 } else { 0 } {
-                        let own_color = self.color;
-                        moves[moves_index] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Bishop(Bishop { color: own_color }),
-                        ));
-                        moves[moves_index + 1] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Knight(Knight { color: own_color }),
-                        ));
-                        moves[moves_index + 2] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Rook(Rook { color: own_color }),
-                        ));
-                        moves[moves_index + 3] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Queen(Queen { color: own_color }),
-                        ));
-                        moves_index += 4;
+                    if square.rank().index() == if let Color::White = self.color { 7 } else { 0 } {
+                        for kind in PieceKind::PROMOTIONS {
+                            moves[moves_index] =
+                                Some(Move::PawnPromotion(square, Piece::new(kind, self.color)));
+                            moves_index += 1;
+                        }
                     } else {
                         moves[moves_index] = Some(Move::RegularMove(square));
                         moves_index += 1;
@@ -356,34 +484,21 @@ impl PieceTrait for Pawn {
             }
         }
         if let Some(square) = if self.color.is_white() {
-            up_left(piece_square as usize)
+            piece_square.up_left()
         } else {
-            down_left(piece_square as usize)
+            piece_square.down_left()
         } {
             // if there's a piece to the diagonal left of the square
-            if let Some(piece) = board.board[square as usize] {
+            if let Some(piece) = board.board[square.index()] {
                 // if it's not an ally
-                if piece.get_color() != self.color {
+                if piece.color != self.color {
                     // add promotion if needed, else a regular move.
-                    if Board::get_row(square) == if let Color::White = self.color { 7 } else { 0 } {
-                        let own_color = self.color;
-                        moves[moves_index] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Bishop(Bishop { color: own_color }),
-                        ));
-                        moves[moves_index + 1] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Knight(Knight { color: own_color }),
-                        ));
-                        moves[moves_index + 2] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Rook(Rook { color: own_color }),
-                        ));
-                        moves[moves_index + 3] = Some(Move::PawnPromotion(
-                            square,
-                            Piece::Queen(Queen { color: own_color }),
-                        ));
-                        moves_index += 4;
+                    if square.rank().index() == if let Color::White = self.color { 7 } else { 0 } {
+                        for kind in PieceKind::PROMOTIONS {
+                            moves[moves_index] =
+                                Some(Move::PawnPromotion(square, Piece::new(kind, self.color)));
+                            moves_index += 1;
+                        }
                     } else {
                         moves[moves_index] = Some(Move::RegularMove(square));
                         moves_index += 1;
@@ -394,11 +509,11 @@ impl PieceTrait for Pawn {
 
         // Check if the pawn can en passant
         if let CanEnPassant::Yes(square) = board.can_en_passant {
-            if Piece::is_to_the_side_of(piece_square as usize, square) {
+            if Piece::is_to_the_side_of(piece_square, square) {
                 moves[moves_index] = Some(if self.color.is_white() {
-                    Move::EnPassant(up(square as usize).unwrap())
+                    Move::EnPassant(square.up().unwrap())
                 } else {
-                    Move::EnPassant(down(square as usize).unwrap())
+                    Move::EnPassant(square.down().unwrap())
                 });
                 moves_index += 1;
             }
@@ -408,25 +523,25 @@ impl PieceTrait for Pawn {
 }
 
 impl PieceTrait for Knight {
-    fn generate_moves(&self, board: &Board, square: u8, moves: &mut [Option<Move>; 28]) {
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
         let mut moves_index = 0;
         // list all possible 8 knight moves, Some variant exists in board, None doesn't.
         let possible_knight_moves = [
-            up_left(up(square as usize).unwrap_or(0) as usize),
-            up_right(up(square as usize).unwrap_or(63) as usize),
-            down_left(down(square as usize).unwrap_or(0) as usize),
-            down_right(down(square as usize).unwrap_or(0) as usize),
-            up_right(right(square as usize).unwrap_or(63) as usize),
-            down_right(right(square as usize).unwrap_or(0) as usize),
-            up_left(left(square as usize).unwrap_or(63) as usize),
-            down_left(left(square as usize).unwrap_or(0) as usize),
+            square.up().and_then(Square::up_left),
+            square.up().and_then(Square::up_right),
+            square.down().and_then(Square::down_left),
+            square.down().and_then(Square::down_right),
+            square.right().and_then(Square::up_right),
+            square.right().and_then(Square::down_right),
+            square.left().and_then(Square::up_left),
+            square.left().and_then(Square::down_left),
         ];
         // for each possible move, that exists on the board,
         for poss_move in possible_knight_moves.into_iter().flatten() {
             // if there is a piece in the square
-            if let Some(piece) = board.board[poss_move as usize] {
+            if let Some(piece) = board.board[poss_move.index()] {
                 // if the color of the Knight and piece in the square are different
-                if piece.get_color() != self.color {
+                if piece.color != self.color {
                     // add that as a possible move for the knight
                     moves[moves_index] = Some(Move::RegularMove(poss_move));
                     moves_index += 1;
@@ -442,8 +557,13 @@ impl PieceTrait for Knight {
 }
 
 impl PieceTrait for Bishop {
-    fn generate_moves(&self, board: &Board, square: u8, moves: &mut [Option<Move>; 28]) {
-        let directions: [fn(usize) -> Option<u8>; 4] = [up_left, up_right, down_left, down_right];
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
+        let directions: [fn(Square) -> Option<Square>; 4] = [
+            Square::up_left,
+            Square::up_right,
+            Square::down_left,
+            Square::down_right,
+        ];
         let mut moves_index = 0;
         for function in directions {
             self.move_in_line(function, board, square, moves, self.color, &mut moves_index);
@@ -452,8 +572,9 @@ impl PieceTrait for Bishop {
 }
 
 impl PieceTrait for Rook {
-    fn generate_moves(&self, board: &Board, square: u8, moves: &mut [Option<Move>; 28]) {
-        let directions: [fn(usize) -> Option<u8>; 4] = [up, down, left, right];
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
+        let directions: [fn(Square) -> Option<Square>; 4] =
+            [Square::up, Square::down, Square::left, Square::right];
         let mut moves_index = 0;
         for function in directions {
             self.move_in_line(function, board, square, moves, self.color, &mut moves_index);
@@ -462,9 +583,16 @@ impl PieceTrait for Rook {
 }
 
 impl PieceTrait for Queen {
-    fn generate_moves(&self, board: &Board, square: u8, moves: &mut [Option<Move>; 28]) {
-        let directions: [fn(usize) -> Option<u8>; 8] = [
-            up_left, up_right, down_left, down_right, up, down, left, right,
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
+        let directions: [fn(Square) -> Option<Square>; 8] = [
+            Square::up_left,
+            Square::up_right,
+            Square::down_left,
+            Square::down_right,
+            Square::up,
+            Square::down,
+            Square::left,
+            Square::right,
         ];
         let mut moves_index = 0;
         for function in directions {
@@ -477,34 +605,34 @@ impl MovesInALine for Bishop {}
 impl MovesInALine for Rook {}
 
 impl King {
-    fn get_adjacent_squares(king: usize) -> [Option<u8>; 8] {
+    fn get_adjacent_squares(king: Square) -> [Option<Square>; 8] {
         [
-            up(king),
-            down(king),
-            left(king),
-            right(king),
-            up_left(king),
-            up_right(king),
-            down_left(king),
-            down_right(king),
+            king.up(),
+            king.down(),
+            king.left(),
+            king.right(),
+            king.up_left(),
+            king.up_right(),
+            king.down_left(),
+            king.down_right(),
         ]
     }
 }
 
 impl PieceTrait for King {
-    fn generate_moves(&self, board: &Board, square: u8, moves: &mut [Option<Move>; 28]) {
+    fn generate_moves(&self, board: &Board, square: Square, moves: &mut OnePieceMoves) {
         let mut moves_index = 0;
         let is_white = self.color.is_white();
         let kingside: bool;
         let kingisde_pieces: [usize; 2] = if is_white { [5, 6] } else { [61, 62] };
         let queenside_pieces: [usize; 3] = if is_white { [3, 2, 1] } else { [59, 58, 57] };
         let queenside: bool;
-        for square in Self::get_adjacent_squares(square as usize)
+        for square in Self::get_adjacent_squares(square)
             .into_iter()
             .flatten()
             .filter(|sqr| {
-                if let Some(piece) = board.board[*sqr as usize] {
-                    piece.get_color() != self.color
+                if let Some(piece) = board.board[sqr.index()] {
+                    piece.color != self.color
                 } else {
                     true
                 }
@@ -516,7 +644,7 @@ impl PieceTrait for King {
         }
 
         // Check for castling
-        if square == if is_white { 4 } else { 60 } {
+        if square.index() == if is_white { 4 } else { 60 } {
             let can_castle = board.can_castle;
             if is_white {
                 kingside = can_castle.white_kingside;
@@ -525,123 +653,67 @@ impl PieceTrait for King {
                 kingside = can_castle.black_kingside;
                 queenside = can_castle.black_queenside;
             }
-            if kingside {
-                if let Some(Piece::Rook(Rook { color })) =
-                    board.board[if is_white { 7 } else { 63 }]
-                {
-                    if self.color == color
-                        && kingisde_pieces
-                            .iter()
-                            .all(|sqr| board.board[*sqr].is_none())
-                    {
-                        moves[moves_index] = Some(Move::CastleKingside);
-                        moves_index += 1;
-                    }
-                }
+            if kingside
+                && board.board[if is_white { 7 } else { 63 }]
+                    == Some(Piece::new(PieceKind::Rook, self.color))
+                && kingisde_pieces
+                    .iter()
+                    .all(|sqr| board.board[*sqr].is_none())
+            {
+                moves[moves_index] = Some(Move::CastleKingside);
+                moves_index += 1;
             }
-            if queenside {
-                if let Some(Piece::Rook(Rook { color })) =
-                    board.board[if is_white { 0 } else { 56 }]
-                {
-                    if self.color == color
-                        && queenside_pieces
-                            .iter()
-                            .all(|sqr| board.board[*sqr].is_none())
-                    {
-                        moves[moves_index] = Some(Move::CastleQueenside);
-                        moves_index += 1;
-                    }
-                }
+            if queenside
+                && board.board[if is_white { 0 } else { 56 }]
+                    == Some(Piece::new(PieceKind::Rook, self.color))
+                && queenside_pieces
+                    .iter()
+                    .all(|sqr| board.board[*sqr].is_none())
+            {
+                moves[moves_index] = Some(Move::CastleQueenside);
+                moves_index += 1;
             }
         }
         moves[moves_index] = None;
     }
 }
 
+// Name of the square with the index, like "e4". Panics for indices off the board.
 pub fn convert_to_square(num: u8) -> String {
-    let column = match num % 8 {
-        0 => "a",
-        1 => "b",
-        2 => "c",
-        3 => "d",
-        4 => "e",
-        5 => "f",
-        6 => "g",
-        7 => "h",
-        _ => panic!("Number higher than squares in a chess board"),
-    };
-    let coordinate = column.to_string() + ((num / 8) + 1).to_string().as_str();
-    coordinate
+    try_convert_to_square(num).expect("Number higher than squares in a chess board")
 }
 
+// Like convert_to_square, but an index off the board is an error.
+pub fn try_convert_to_square(num: u8) -> Result<String, ChessError> {
+    Square::new(num)
+        .map(|square| square.to_string())
+        .ok_or_else(|| ChessError::InvalidSquare(num.to_string()))
+}
+
+// Index of the named square. Panics for a name that isn't a square.
 pub fn from_square(square: &str) -> u8 {
-    if !square.len() == 2 {
-        panic!("Square length should be 2 for string conversion to square");
-    }
-    square
-        .split("")
-        .filter(|item| !item.is_empty())
-        .enumerate()
-        .map(|(i, letter)| {
-            if i == 0 {
-                match letter {
-                    "a" => 0,
-                    "b" => 1,
-                    "c" => 2,
-                    "d" => 3,
-                    "e" => 4,
-                    "f" => 5,
-                    "g" => 6,
-                    "h" => 7,
-                    _ => {
-                        println!("{letter}");
-                        panic!("Invalid coordinate: column is not in board")
-                    }
-                }
-            } else {
-                let new_val = (letter
-                    .parse::<u8>()
-                    .expect("Invalid coordinate: row isn't an integer")
-                    - 1)
-                    * 8;
-                if new_val <= 56 {
-                    new_val
-                } else {
-                    panic!("Invalid coordinate: row not in board")
-                }
-            }
-        })
-        .sum()
+    try_from_square(square).expect("Not a square on a chess board")
+}
+
+// Like from_square, but a name that isn't a square is an error.
+pub fn try_from_square(square: &str) -> Result<u8, ChessError> {
+    Ok(square.parse::<Square>()?.into())
 }
 
 impl fmt::Display for Move {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            Move::RegularMove(sqr) => {
-                let square = convert_to_square(sqr);
-                write!(f, "{square}")
-            }
+            Move::RegularMove(square)
+            | Move::EnPassant(square)
+            | Move::PawnAdvanceTwoSquares(square) => write!(f, "{square}"),
             Move::CastleKingside => write!(f, "O-O"),
             Move::CastleQueenside => write!(f, "O-O-O"),
-            Move::EnPassant(sqr) => {
-                let square = convert_to_square(sqr);
-                write!(f, "{square}")
-            }
-            Move::PawnAdvanceTwoSquares(sqr) => {
-                let square = convert_to_square(sqr);
-                write!(f, "{square}")
+            // Whatever the piece, even one no rules promote to, it's written by its letter.
+            Move::PawnPromotion(square, piece) => {
+                write!(f, "{square}{}", piece.to_char().to_ascii_lowercase())
             }
-            Move::PawnPromotion(sqr, piece) => {
-                let square = convert_to_square(sqr);
-                let piece_result = match piece {
-                    Piece::Queen(_) => "q",
-                    Piece::Rook(_) => "r",
-                    Piece::Bishop(_) => "b",
-                    Piece::Knight(_) => "n",
-                    Piece::Pawn(_) => panic!("Pawn tried to promote to pawn"),
-                    Piece::King(_) => panic!("Pawn tried to promote to king"),
-                };
-                write!(f, "{square}{piece_result}")
+            Move::Drop(square, piece) => {
+                write!(f, "{}@{square}", piece.to_char().to_ascii_uppercase())
             }
         }
     }
@@ -1,11 +1,3 @@
-use fnv::FnvHashSet;
-
-use chess::multi_thread_eval;
-
 fn main() {
-    let board = chess::Board::new(); //.make_move(from_square("c2") as usize, chess::Move::RegularMove(from_square("c3")), chess::Color::White).make_move(from_square("a7") as usize, chess::Move::RegularMove(from_square("a5")), chess::Color::Black).make_move(from_square("d1") as usize, chess::Move::RegularMove(from_square("a4")), chess::Color::White);
-    println!("{board}");
-    let mut positions = FnvHashSet::default();
-    let depth = 5;
-    multi_thread_eval(&board, depth, chess::Color::White, &mut positions);
+    chess::uci::run();
 }
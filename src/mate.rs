@@ -0,0 +1,439 @@
+use crate::{zobrist, Board, BoardMove, Color};
+use fnv::FnvHashMap;
+use std::time::{Duration, Instant};
+
+// Mate solver, separate from the main search: depth-first proof-number search over the tree
+// where the attacker has a fixed number of moves left. An attacker node is proven when one of
+// its moves leads to a proven defender node, and a defender node when every reply does (or
+// there's no reply and the defender is in check). Nodes are disproven when the attacker runs
+// out of moves. Checks are tried first, and with one move left only checks are tried at all.
+
+// Proof and disproof numbers stay below this, which stands for infinity.
+const INFINITY: u32 = 1 << 30;
+// Nodes between looks at the clock.
+const NODES_BETWEEN_TIME_CHECKS: u64 = 1024;
+// Budget of find_mate.
+const DEFAULT_MAX_NODES: u64 = 10_000_000;
+
+// When to give up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MateLimits {
+    pub nodes: Option<u64>,
+    pub time: Option<Duration>,
+}
+
+// A forced mate: the attacker's move and how it goes on after every reply. A move without
+// replies is mate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MateTree {
+    pub attack: BoardMove,
+    pub replies: Vec<(BoardMove, MateTree)>,
+}
+
+impl MateTree {
+    // Attacker moves to the mate against the best defence.
+    pub fn length(&self) -> u32 {
+        1 + self
+            .replies
+            .iter()
+            .map(|(_, tree)| tree.length())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // The line against the defence that holds out longest.
+    pub fn main_line(&self) -> Vec<BoardMove> {
+        let mut line = vec![self.attack];
+        if let Some((reply, tree)) = self.replies.iter().max_by_key(|(_, tree)| tree.length()) {
+            line.push(*reply);
+            line.extend(tree.main_line());
+        }
+        line
+    }
+
+    // The whole tree in SAN, one line per reply, indented by depth.
+    pub fn to_san(&self, board: &Board) -> String {
+        let mut text = String::new();
+        self.write_san(board, 0, &mut text);
+        text
+    }
+
+    fn write_san(&self, board: &Board, indent: usize, text: &mut String) {
+        let (start_square, a_move) = self.attack;
+        text.push_str(&"  ".repeat(indent));
+        text.push_str(&board.move_to_san(start_square, a_move));
+        text.push('\n');
+        let board = board.make_move(start_square, a_move);
+        for ((reply_start, reply), tree) in &self.replies {
+            text.push_str(&"  ".repeat(indent + 1));
+            text.push_str(&board.move_to_san(*reply_start, *reply));
+            text.push('\n');
+            let next = board.make_move(*reply_start, *reply);
+            tree.write_san(&next, indent + 2, text);
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MateResult {
+    // Mate in at most the number of moves asked for, against every defence.
+    Mate(MateTree),
+    // There's no mate in that many moves.
+    NoMate,
+    // The limits ran out before the question was settled.
+    Timeout,
+}
+
+// Looks for a mate in `moves` moves by the side to move, with a budget of ten million nodes.
+pub fn find_mate(board: &Board, moves: u32) -> MateResult {
+    let limits = MateLimits {
+        nodes: Some(DEFAULT_MAX_NODES),
+        time: None,
+    };
+    find_mate_with_limits(board, moves, &limits)
+}
+
+pub fn find_mate_with_limits(board: &Board, moves: u32, limits: &MateLimits) -> MateResult {
+    let color = board.side_to_move();
+    let mut solver = Solver {
+        attacker: color,
+        table: FnvHashMap::default(),
+        nodes: 0,
+        max_nodes: limits.nodes.unwrap_or(u64::MAX),
+        deadline: limits.time.map(|time| Instant::now() + time),
+        aborted: false,
+    };
+    let depth = moves.min(u8::MAX as u32) as u8;
+    let root = Node::new(board.clone(), color, depth);
+    match solver.solve(&root) {
+        Some(true) => match solver.tree(&root) {
+            Some(tree) => MateResult::Mate(tree),
+            None => MateResult::Timeout,
+        },
+        Some(false) => MateResult::NoMate,
+        None => MateResult::Timeout,
+    }
+}
+
+#[derive(Clone)]
+struct Node {
+    board: Board,
+    color: Color,
+    // Attacker moves left, counting the one about to be played at attacker nodes.
+    depth: u8,
+    key: (u64, u8),
+}
+
+impl Node {
+    fn new(board: Board, color: Color, depth: u8) -> Self {
+        let key = (zobrist::hash(&board), depth);
+        Node {
+            board,
+            color,
+            depth,
+            key,
+        }
+    }
+}
+
+struct Solver {
+    attacker: Color,
+    // Proof and disproof numbers of the nodes seen so far.
+    table: FnvHashMap<(u64, u8), (u32, u32)>,
+    nodes: u64,
+    max_nodes: u64,
+    deadline: Option<Instant>,
+    aborted: bool,
+}
+
+impl Solver {
+    fn is_attacker_node(&self, node: &Node) -> bool {
+        node.color == self.attacker
+    }
+
+    // The moves worth trying, checks first, and only checks when they're the last chance.
+    fn moves(&self, node: &Node) -> Vec<BoardMove> {
+        let moves = node.board.legal_moves();
+        if !self.is_attacker_node(node) {
+            return moves;
+        }
+        let (checks, quiet): (Vec<BoardMove>, Vec<BoardMove>) =
+            moves.into_iter().partition(|(start_square, a_move)| {
+                node.board
+                    .make_move(*start_square, *a_move)
+                    .is_in_check(node.color.reverse())
+            });
+        if node.depth <= 1 {
+            checks
+        } else {
+            checks.into_iter().chain(quiet).collect()
+        }
+    }
+
+    fn children(&self, node: &Node) -> Vec<(BoardMove, Node)> {
+        let depth = if self.is_attacker_node(node) {
+            node.depth - 1
+        } else {
+            node.depth
+        };
+        self.moves(node)
+            .into_iter()
+            .map(|(start_square, a_move)| {
+                let board = node.board.make_move(start_square, a_move);
+                (
+                    (start_square, a_move),
+                    Node::new(board, node.color.reverse(), depth),
+                )
+            })
+            .collect()
+    }
+
+    // Proof and disproof numbers of the node, worked out from the position when it's new:
+    // exact for mates and dead ends, otherwise an estimate from the number of moves.
+    fn numbers(&mut self, node: &Node) -> (u32, u32) {
+        if let Some(numbers) = self.table.get(&node.key) {
+            return *numbers;
+        }
+        let numbers = if self.is_attacker_node(node) {
+            if node.depth == 0 {
+                (INFINITY, 0)
+            } else {
+                match self.moves(node).len() {
+                    0 => (INFINITY, 0),
+                    moves => (1, moves as u32),
+                }
+            }
+        } else {
+            match node.board.legal_moves().len() {
+                0 if node.board.is_in_check(node.color) => (0, INFINITY),
+                0 => (INFINITY, 0),
+                _ if node.depth == 0 => (INFINITY, 0),
+                moves => (moves as u32, 1),
+            }
+        };
+        self.table.insert(node.key, numbers);
+        numbers
+    }
+
+    // Proven (true), disproven (false), or None if the limits ran out.
+    fn solve(&mut self, node: &Node) -> Option<bool> {
+        loop {
+            let (proof, disproof) = self.numbers(node);
+            if proof == 0 {
+                return Some(true);
+            }
+            if disproof == 0 {
+                return Some(false);
+            }
+            if self.aborted {
+                return None;
+            }
+            self.search(node, INFINITY, INFINITY);
+        }
+    }
+
+    fn out_of_budget(&mut self) -> bool {
+        self.nodes += 1;
+        if self.nodes >= self.max_nodes {
+            self.aborted = true;
+        }
+        if let Some(deadline) = self.deadline {
+            if self.nodes.is_multiple_of(NODES_BETWEEN_TIME_CHECKS) && Instant::now() >= deadline {
+                self.aborted = true;
+            }
+        }
+        self.aborted
+    }
+
+    // Expand the node until its proof number reaches `proof_limit` or its disproof number
+    // `disproof_limit`, always going down the most proving child.
+    fn search(&mut self, node: &Node, proof_limit: u32, disproof_limit: u32) {
+        if self.out_of_budget() {
+            return;
+        }
+        let children = self.children(node);
+        let attacker = self.is_attacker_node(node);
+        loop {
+            // At attacker nodes a child has to be proven and every child disproven, the other
+            // way round at defender nodes. `to_one` is the number that takes just one child,
+            // `to_all` the one that takes them all.
+            let mut best = None;
+            let mut best_to_one = INFINITY;
+            let mut second_to_one = INFINITY;
+            let mut best_to_all = 0;
+            let mut sum_to_all: u32 = 0;
+            for (index, (_, child)) in children.iter().enumerate() {
+                let (proof, disproof) = self.numbers(child);
+                let (to_one, to_all) = if attacker {
+                    (proof, disproof)
+                } else {
+                    (disproof, proof)
+                };
+                sum_to_all = sum_to_all.saturating_add(to_all).min(INFINITY);
+                if to_one < best_to_one {
+                    second_to_one = best_to_one;
+                    best_to_one = to_one;
+                    best_to_all = to_all;
+                    best = Some(index);
+                } else if to_one < second_to_one {
+                    second_to_one = to_one;
+                }
+            }
+            let (proof, disproof) = if attacker {
+                (best_to_one, sum_to_all)
+            } else {
+                (sum_to_all, best_to_one)
+            };
+            self.table.insert(node.key, (proof, disproof));
+            let (Some(best), false) = (best, self.aborted) else {
+                return;
+            };
+            if proof >= proof_limit || disproof >= disproof_limit {
+                return;
+            }
+
+            let (one_limit, all_limit) = if attacker {
+                (proof_limit, disproof_limit)
+            } else {
+                (disproof_limit, proof_limit)
+            };
+            let child_one_limit = one_limit.min(second_to_one.saturating_add(1));
+            let child_all_limit = (all_limit as u64 - sum_to_all as u64 + best_to_all as u64)
+                .min(INFINITY as u64) as u32;
+            let (child_proof_limit, child_disproof_limit) = if attacker {
+                (child_one_limit, child_all_limit)
+            } else {
+                (child_all_limit, child_one_limit)
+            };
+            self.search(&children[best].1, child_proof_limit, child_disproof_limit);
+        }
+    }
+
+    // The proof tree of a proven attacker node, preferring the quickest mate at every step.
+    fn tree(&mut self, node: &Node) -> Option<MateTree> {
+        for depth in 1..=node.depth {
+            let shallower = Node::new(node.board.clone(), node.color, depth);
+            for (attack, child) in self.children(&shallower) {
+                if self.solve(&child)? {
+                    let mut replies = Vec::new();
+                    for (reply, grandchild) in self.children(&child) {
+                        if !self.solve(&grandchild)? {
+                            return None;
+                        }
+                        replies.push((reply, self.tree(&grandchild)?));
+                    }
+                    return Some(MateTree { attack, replies });
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATE_IN_ONE: &str = "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1";
+    const MATE_IN_TWO: &str = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+
+    fn moves(board: &Board, line: &[&str]) -> Vec<BoardMove> {
+        let mut board = board.clone();
+        line.iter()
+            .map(|uci| {
+                let (start_square, a_move) = board.move_from_uci(uci).unwrap();
+                board = board.make_move(start_square, a_move);
+                (start_square, a_move)
+            })
+            .collect()
+    }
+
+    // Every reply has an answer, and the attacker mates at the leaves.
+    fn assert_covers_every_defence(board: &Board, tree: &MateTree) {
+        let (start_square, a_move) = tree.attack;
+        let board = board.make_move(start_square, a_move);
+        let mut replies: Vec<BoardMove> = tree.replies.iter().map(|(reply, _)| *reply).collect();
+        let mut legal = board.legal_moves();
+        replies.sort_by_key(|(square, a_move)| format!("{square:?}{a_move:?}"));
+        legal.sort_by_key(|(square, a_move)| format!("{square:?}{a_move:?}"));
+        assert_eq!(replies, legal);
+        if legal.is_empty() {
+            assert!(
+                board.is_in_check(board.side_to_move()),
+                "{}",
+                board.to_fen()
+            );
+        }
+        for ((reply_start, reply), tree) in &tree.replies {
+            assert_covers_every_defence(&board.make_move(*reply_start, *reply), tree);
+        }
+    }
+
+    fn mate(fen: &str, moves: u32) -> MateTree {
+        match find_mate(&Board::from_fen(fen), moves) {
+            MateResult::Mate(tree) => tree,
+            result => panic!("no mate in {moves} found in {fen}: {result:?}"),
+        }
+    }
+
+    #[test]
+    fn mate_in_one() {
+        let board = Board::from_fen(MATE_IN_ONE);
+        let tree = mate(MATE_IN_ONE, 1);
+        assert_eq!(tree.length(), 1);
+        assert!(tree.replies.is_empty());
+        assert_eq!(tree.main_line(), moves(&board, &["a1a8"]));
+        assert_eq!(tree.to_san(&board), "Ra8#\n");
+        // More moves than needed still finds it.
+        assert_eq!(mate(MATE_IN_ONE, 3).length(), 1);
+    }
+
+    #[test]
+    fn mate_in_two_covers_every_defence() {
+        let board = Board::from_fen(MATE_IN_TWO);
+        assert_eq!(find_mate(&board, 1), MateResult::NoMate);
+        let tree = mate(MATE_IN_TWO, 2);
+        assert_eq!(tree.length(), 2);
+        assert_eq!(tree.main_line(), moves(&board, &["c6c7", "a8a7", "h1a1"]));
+        assert_covers_every_defence(&board, &tree);
+        assert_eq!(tree.to_san(&board), "Kc7\n  Ka7\n    Ra1#\n");
+    }
+
+    #[test]
+    fn longer_mates_are_proven() {
+        // Philidor's position with the rook coming round: mate in three.
+        let fen = "2k5/8/2K5/8/8/8/8/7R w - - 0 1";
+        let board = Board::from_fen(fen);
+        let tree = mate(fen, 3);
+        assert!(tree.length() <= 3);
+        assert_covers_every_defence(&board, &tree);
+    }
+
+    #[test]
+    fn no_mate_is_told_apart_from_a_timeout() {
+        let start = Board::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(find_mate(&start, 2), MateResult::NoMate);
+        // Qc7 stalemates, Qc8 is the mate.
+        let fen = "k7/8/1K6/8/8/8/8/2Q5 w - - 0 1";
+        let tree = mate(fen, 1);
+        assert_eq!(tree.main_line(), moves(&Board::from_fen(fen), &["c1c8"]));
+
+        let few_nodes = MateLimits {
+            nodes: Some(10),
+            time: None,
+        };
+        let board = Board::from_fen(MATE_IN_TWO);
+        assert_eq!(
+            find_mate_with_limits(&board, 2, &few_nodes),
+            MateResult::Timeout
+        );
+        let no_time = MateLimits {
+            nodes: None,
+            time: Some(Duration::ZERO),
+        };
+        assert_eq!(
+            find_mate_with_limits(&start, 4, &no_time),
+            MateResult::Timeout
+        );
+    }
+}
@@ -0,0 +1,408 @@
+use crate::board::PieceChanges;
+use crate::{Board, Color, Piece, PieceKind};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Neural network evaluation (NNUE): 768 inputs, one for each piece kind and color on each
+// square, into a hidden layer of any size, seen from both sides, then into one output.
+//
+//   input (768, from the side to move) -> hidden (N) \
+//                                                      -> clipped ReLU -> output
+//   input (768, from the other side)   -> hidden (N) /
+//
+// The hidden layer (the accumulator) is the expensive part, and it barely changes from one
+// position to the next, so the search keeps one per ply and only adds and removes the weights
+// of the pieces that moved. Everything is integer arithmetic; with the `simd` feature AVX2 is
+// used when the CPU has it, with exactly the same results.
+//
+// Network files are the raw weights, little-endian i16, in this order (the layout trainers
+// like bullet write for this architecture, which may pad the end to 64 bytes):
+//   hidden weights   768 x N, one row of N per input
+//   hidden biases    N
+//   output weights   2N, the first N for the side to move
+//   output bias      1
+
+pub const INPUTS: usize = 768;
+// The hidden layer is quantised by QA, the output weights by QB, and the output is scaled to
+// centipawns by SCALE.
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+
+pub struct Network {
+    hidden: usize,
+    hidden_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+// The hidden layer, from each side's point of view (white first).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+// Input index of a piece on a square, seen from a side. Black sees the board upside down and
+// its own pieces as "ours", so both sides see the same inputs in mirrored positions. The
+// network has no inputs for fairy pieces, it doesn't see them.
+fn feature(perspective: Color, piece: Piece, square: usize) -> Option<usize> {
+    if let PieceKind::Fairy(_) = piece.kind {
+        return None;
+    }
+    let theirs = piece.color != perspective;
+    let square = if perspective.is_white() {
+        square
+    } else {
+        square ^ 56
+    };
+    Some(((theirs as usize * 6 + piece.kind.index()) * 64) + square)
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Network {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Network::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let values: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        // Each hidden neuron takes 768 input weights, a bias and two output weights.
+        let per_neuron = INPUTS + 3;
+        let hidden = values.len().saturating_sub(1) / per_neuron;
+        let padding = values.len().saturating_sub(hidden * per_neuron + 1);
+        if hidden == 0 || padding >= 32 || !bytes.len().is_multiple_of(2) {
+            return Err(invalid_data(format!(
+                "{} bytes isn't a 768 -> N -> 1 network",
+                bytes.len()
+            )));
+        }
+        let (hidden_weights, rest) = values.split_at(INPUTS * hidden);
+        let (hidden_biases, rest) = rest.split_at(hidden);
+        let (output_weights, rest) = rest.split_at(2 * hidden);
+        Ok(Network {
+            hidden,
+            hidden_weights: hidden_weights.to_vec(),
+            hidden_biases: hidden_biases.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let bytes: Vec<u8> = self
+            .hidden_weights
+            .iter()
+            .chain(&self.hidden_biases)
+            .chain(&self.output_weights)
+            .chain([&self.output_bias])
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        fs::write(path, bytes)
+    }
+
+    // A network with random weights, for checking the inference code when there's no trained
+    // network around.
+    pub fn random(hidden: usize, seed: u64) -> Self {
+        let mut state = seed;
+        let mut next = |range: i16| {
+            // SplitMix64
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            (z % (2 * range as u64 + 1)) as i16 - range
+        };
+        Network {
+            hidden,
+            hidden_weights: (0..INPUTS * hidden).map(|_| next(64)).collect(),
+            hidden_biases: (0..hidden).map(|_| next(64)).collect(),
+            output_weights: (0..2 * hidden).map(|_| next(64)).collect(),
+            output_bias: next(1000),
+        }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    fn weights(&self, feature: usize) -> &[i16] {
+        &self.hidden_weights[feature * self.hidden..(feature + 1) * self.hidden]
+    }
+
+    // The accumulator of a position, from scratch.
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [self.hidden_biases.clone(), self.hidden_biases.clone()],
+        };
+        for (square, piece) in board.board.iter().enumerate() {
+            if let Some(piece) = piece {
+                for perspective in [Color::White, Color::Black] {
+                    if let Some(input) = feature(perspective, *piece, square) {
+                        simd::add(
+                            &mut accumulator.values[perspective.index()],
+                            self.weights(input),
+                        );
+                    }
+                }
+            }
+        }
+        accumulator
+    }
+
+    // Bring the accumulator over a move, by removing the weights of the pieces it took off the
+    // board and adding the ones of the pieces it put on.
+    pub fn update(&self, accumulator: &mut Accumulator, changes: &PieceChanges) {
+        for perspective in [Color::White, Color::Black] {
+            let values = &mut accumulator.values[perspective.index()];
+            for (piece, square) in changes.removed.iter().flatten() {
+                if let Some(input) = feature(perspective, *piece, square.index()) {
+                    simd::sub(values, self.weights(input));
+                }
+            }
+            for (piece, square) in changes.added.iter().flatten() {
+                if let Some(input) = feature(perspective, *piece, square.index()) {
+                    simd::add(values, self.weights(input));
+                }
+            }
+        }
+    }
+
+    // Score in centipawns for the color to move.
+    pub fn evaluate(&self, accumulator: &Accumulator, color: Color) -> i32 {
+        let (ours, theirs) = self.output_weights.split_at(self.hidden);
+        let sum = simd::clipped_dot(&accumulator.values[color.index()], ours, QA).wrapping_add(
+            simd::clipped_dot(&accumulator.values[color.reverse().index()], theirs, QA),
+        );
+        ((sum as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+    }
+
+    // Same as `evaluate`, straight from the board.
+    pub fn evaluate_board(&self, board: &Board) -> i32 {
+        self.evaluate(&self.refresh(board), board.side_to_move())
+    }
+}
+
+// The plain version of the whole network: every input, every weight, wide integers, no
+// accumulator and no SIMD. The fast path has to agree with it exactly.
+pub fn reference_evaluate(network: &Network, board: &Board) -> i32 {
+    let color = board.side_to_move();
+    let hidden = network.hidden;
+    let mut sum = 0i64;
+    for (side, perspective) in [color, color.reverse()].into_iter().enumerate() {
+        for neuron in 0..hidden {
+            let mut value = network.hidden_biases[neuron] as i64;
+            for (square, piece) in board.board.iter().enumerate() {
+                if let Some(input) = piece.and_then(|piece| feature(perspective, piece, square)) {
+                    value += network.hidden_weights[input * hidden + neuron] as i64;
+                }
+            }
+            let activation = value.clamp(0, QA as i64);
+            sum += activation * network.output_weights[side * hidden + neuron] as i64;
+        }
+    }
+    ((sum + network.output_bias as i64) * SCALE as i64 / (QA * QB) as i64) as i32
+}
+
+struct StackEntry {
+    // What the move into this ply changed on the board.
+    changes: PieceChanges,
+    accumulator: Accumulator,
+    // Whether the accumulator is up to date with the position.
+    computed: bool,
+}
+
+// One accumulator per ply of the line being searched. The root gets its accumulator from the
+// board, every ply below records the pieces the move into it changed. Accumulators are only
+// brought up to date when a position gets evaluated, from the closest ply above it that has
+// one. Going back up the tree needs nothing, the parent's accumulator is still there.
+pub struct NnueStack {
+    entries: Vec<StackEntry>,
+}
+
+impl NnueStack {
+    pub fn new(network: &Network, plies: usize) -> Self {
+        let empty = Accumulator {
+            values: [vec![0; network.hidden], vec![0; network.hidden]],
+        };
+        NnueStack {
+            entries: (0..plies)
+                .map(|_| StackEntry {
+                    changes: PieceChanges::default(),
+                    accumulator: empty.clone(),
+                    computed: false,
+                })
+                .collect(),
+        }
+    }
+
+    // The line starts from this board at ply 0.
+    pub fn set_root(&mut self, network: &Network, board: &Board) {
+        let root = &mut self.entries[0];
+        root.accumulator = network.refresh(board);
+        root.computed = true;
+    }
+
+    // The search went down to this ply by a move that made these changes (none for a null
+    // move).
+    pub fn push(&mut self, ply: usize, changes: PieceChanges) {
+        let entry = &mut self.entries[ply];
+        entry.changes = changes;
+        entry.computed = false;
+    }
+
+    // Score of the position at this ply, for the color to move.
+    pub fn evaluate(&mut self, network: &Network, ply: usize, color: Color) -> i32 {
+        let start = (0..=ply)
+            .rev()
+            .find(|ply| self.entries[*ply].computed)
+            .expect("the root is set before anything is evaluated");
+        for ply in start + 1..=ply {
+            let (above, below) = self.entries.split_at_mut(ply);
+            let entry = &mut below[0];
+            entry.accumulator.clone_from(&above[ply - 1].accumulator);
+            network.update(&mut entry.accumulator, &entry.changes);
+            entry.computed = true;
+        }
+        network.evaluate(&self.entries[ply].accumulator, color)
+    }
+}
+
+// The vector loops. Each has a plain version, and with the `simd` feature an AVX2 one that's
+// used when the CPU supports it. Both wrap on overflow the same way.
+mod simd {
+    pub fn add(values: &mut [i16], weights: &[i16]) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") && values.len().is_multiple_of(16) {
+            // Safe: AVX2 is there, and the lengths are checked.
+            return unsafe { avx2::add(values, weights) };
+        }
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_add(*weight);
+        }
+    }
+
+    pub fn sub(values: &mut [i16], weights: &[i16]) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") && values.len().is_multiple_of(16) {
+            return unsafe { avx2::sub(values, weights) };
+        }
+        for (value, weight) in values.iter_mut().zip(weights) {
+            *value = value.wrapping_sub(*weight);
+        }
+    }
+
+    // Sum of clamp(value, 0, max) * weight.
+    pub fn clipped_dot(values: &[i16], weights: &[i16], max: i32) -> i32 {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if is_x86_feature_detected!("avx2") && values.len().is_multiple_of(16) {
+            return unsafe { avx2::clipped_dot(values, weights, max as i16) };
+        }
+        values
+            .iter()
+            .zip(weights)
+            .fold(0i32, |sum, (value, weight)| {
+                sum.wrapping_add(*value.clamp(&0, &(max as i16)) as i32 * *weight as i32)
+            })
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    mod avx2 {
+        use std::arch::x86_64::*;
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn add(values: &mut [i16], weights: &[i16]) {
+            for (values, weights) in values.chunks_exact_mut(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(values.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(weights.as_ptr() as *const __m256i);
+                _mm256_storeu_si256(values.as_mut_ptr() as *mut __m256i, _mm256_add_epi16(a, b));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn sub(values: &mut [i16], weights: &[i16]) {
+            for (values, weights) in values.chunks_exact_mut(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(values.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(weights.as_ptr() as *const __m256i);
+                _mm256_storeu_si256(values.as_mut_ptr() as *mut __m256i, _mm256_sub_epi16(a, b));
+            }
+        }
+
+        #[target_feature(enable = "avx2")]
+        pub unsafe fn clipped_dot(values: &[i16], weights: &[i16], max: i16) -> i32 {
+            let zero = _mm256_setzero_si256();
+            let max = _mm256_set1_epi16(max);
+            let mut sum = _mm256_setzero_si256();
+            for (values, weights) in values.chunks_exact(16).zip(weights.chunks_exact(16)) {
+                let a = _mm256_loadu_si256(values.as_ptr() as *const __m256i);
+                let b = _mm256_loadu_si256(weights.as_ptr() as *const __m256i);
+                let clipped = _mm256_min_epi16(_mm256_max_epi16(a, zero), max);
+                sum = _mm256_add_epi32(sum, _mm256_madd_epi16(clipped, b));
+            }
+            let mut lanes = [0i32; 8];
+            _mm256_storeu_si256(lanes.as_mut_ptr() as *mut __m256i, sum);
+            lanes
+                .iter()
+                .fold(0, |total, lane| total.wrapping_add(*lane))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Random games from positions with castling, en passant, promotions and drops to come, with
+    // the incrementally updated evaluation checked against the reference at most plies.
+    #[test]
+    fn incremental_updates_match_the_reference() {
+        const FENS: [&str; 4] = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r3k2r/pppq1ppp/8/3pP3/8/8/PPPQ1PPP/R3K2R[NPnp] w KQkq d6 0 1",
+        ];
+        const GAME_LENGTH: usize = 120;
+        let network = Network::random(32, 7);
+        let mut stack = NnueStack::new(&network, GAME_LENGTH + 1);
+        let mut seed: u64 = 1;
+        let mut random = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for fen in FENS {
+            for _ in 0..10 {
+                let mut board = Board::from_fen(fen);
+                stack.set_root(&network, &board);
+                for ply in 0..GAME_LENGTH {
+                    // Skip some plies, so several moves get applied at once too.
+                    if random() % 3 != 0 {
+                        assert_eq!(
+                            stack.evaluate(&network, ply, board.side_to_move()),
+                            reference_evaluate(&network, &board),
+                            "{}",
+                            board.to_fen()
+                        );
+                    }
+                    let moves = board.legal_moves();
+                    if moves.is_empty() {
+                        break;
+                    }
+                    let (start, a_move) = moves[random() as usize % moves.len()];
+                    stack.push(ply + 1, board.piece_changes(start, a_move));
+                    board = board.make_move(start, a_move);
+                }
+            }
+        }
+    }
+}
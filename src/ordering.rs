@@ -0,0 +1,673 @@
+use crate::board::knight_squares;
+use crate::{BoardMove, Color, King, Move, Piece, PieceKind, Square};
+
+use super::Board;
+
+// Deepest ply the per-ply tables (killer moves) keep track of.
+pub const MAX_PLY: usize = 128;
+// History scores are kept in [-MAX_HISTORY, MAX_HISTORY] by the gravity formula.
+const MAX_HISTORY: i32 = 16384;
+
+// Queen promotions are tried before any capture.
+const QUEEN_PROMOTION_SCORE: i32 = 1_000_000;
+
+// Most valuable victim, least valuable attacker: taking a queen with a pawn is tried before
+// taking a pawn with a queen.
+pub fn mvv_lva(board: &Board, start_square: Square, a_move: Move) -> i32 {
+    let attacker = match board.board[start_square.index()] {
+        Some(piece) => board.piece_value(piece),
+        None => return 0,
+    };
+    let victim = match a_move {
+        Move::RegularMove(sqr) | Move::PawnPromotion(sqr, _) => {
+            board.board[sqr.index()].map_or(0, |piece| board.piece_value(piece))
+        }
+        Move::EnPassant(_) => 100,
+        _ => 0,
+    };
+    victim * 10 - attacker / 10
+}
+
+// Static exchange evaluation: the material the side moving can expect to win (or lose) if both
+// sides keep recapturing on the destination square with their least valuable piece.
+pub fn see(board: &Board, start_square: Square, a_move: Move) -> i32 {
+    let color = board.side_to_move();
+    let target = match a_move {
+        Move::CastleKingside | Move::CastleQueenside => return 0,
+        _ => a_move.end_square(color),
+    };
+    let mut board = board.clone();
+    let mut gain = [0; 32];
+    let mut depth = 0;
+
+    gain[0] = match a_move {
+        Move::EnPassant(sqr) => {
+            // Remove the captured pawn now, so anything behind it can join the exchange.
+            let captured_square = if color.is_white() {
+                sqr.down()
+            } else {
+                sqr.up()
+            };
+            if let Some(captured_square) = captured_square {
+                board.board[captured_square.index()] = None;
+            }
+            100
+        }
+        _ => board.board[target.index()].map_or(0, |piece| board.piece_value(piece)),
+    };
+    let mut piece_on_target = match a_move {
+        Move::PawnPromotion(_, new_piece) => {
+            gain[0] += new_piece.value() - 100;
+            new_piece
+        }
+        _ => match board.board[start_square.index()] {
+            Some(piece) => piece,
+            None => return 0,
+        },
+    };
+    let mut from = start_square;
+    let mut side = color;
+
+    loop {
+        depth += 1;
+        // What we'd have if the piece that just moved to the target got taken back.
+        gain[depth] = board.piece_value(piece_on_target) - gain[depth - 1];
+        if (-gain[depth - 1]).max(gain[depth]) < 0 || depth == gain.len() - 1 {
+            break;
+        }
+        board.board[from.index()] = None;
+        board.board[target.index()] = Some(piece_on_target);
+        side = side.reverse();
+        match least_valuable_attacker(&board, target, side) {
+            Some((sqr, piece)) => {
+                from = sqr;
+                piece_on_target = piece;
+            }
+            None => break,
+        }
+    }
+    // The last entry is speculative, nobody was left to recapture.
+    loop {
+        depth -= 1;
+        if depth == 0 {
+            break;
+        }
+        gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+    }
+    gain[0]
+}
+
+// The cheapest piece of the given color attacking the square, and where it stands.
+fn least_valuable_attacker(board: &Board, square: Square, by: Color) -> Option<(Square, Piece)> {
+    let own_piece = |sqr: Square| board.board[sqr.index()].filter(|piece| piece.color == by);
+
+    let pawn_squares = if by.is_white() {
+        [square.down_left(), square.down_right()]
+    } else {
+        [square.up_left(), square.up_right()]
+    };
+    for sqr in pawn_squares.into_iter().flatten() {
+        if let Some(
+            piece @ Piece {
+                kind: PieceKind::Pawn,
+                ..
+            },
+        ) = own_piece(sqr)
+        {
+            return Some((sqr, piece));
+        }
+    }
+    for sqr in knight_squares(square).into_iter().flatten() {
+        if let Some(
+            piece @ Piece {
+                kind: PieceKind::Knight,
+                ..
+            },
+        ) = own_piece(sqr)
+        {
+            return Some((sqr, piece));
+        }
+    }
+
+    // Sliders: the first piece on each line, then pick the cheapest that can move along it.
+    let diagonals: [fn(Square) -> Option<Square>; 4] = [
+        Square::up_left,
+        Square::up_right,
+        Square::down_left,
+        Square::down_right,
+    ];
+    let lines: [fn(Square) -> Option<Square>; 4] =
+        [Square::up, Square::down, Square::left, Square::right];
+    let mut best: Option<(Square, Piece)> = None;
+    let mut consider = |candidate: (Square, Piece)| {
+        if best.is_none_or(|(_, piece)| candidate.1.value() < piece.value()) {
+            best = Some(candidate);
+        }
+    };
+    for direction in diagonals {
+        if let Some((sqr, piece)) = board.first_piece_in_line(square, direction) {
+            if piece.color == by
+                && matches!(
+                    piece,
+                    Piece {
+                        kind: PieceKind::Bishop,
+                        ..
+                    } | Piece {
+                        kind: PieceKind::Queen,
+                        ..
+                    }
+                )
+            {
+                consider((sqr, piece));
+            }
+        }
+    }
+    for direction in lines {
+        if let Some((sqr, piece)) = board.first_piece_in_line(square, direction) {
+            if piece.color == by
+                && matches!(
+                    piece,
+                    Piece {
+                        kind: PieceKind::Rook,
+                        ..
+                    } | Piece {
+                        kind: PieceKind::Queen,
+                        ..
+                    }
+                )
+            {
+                consider((sqr, piece));
+            }
+        }
+    }
+    if best.is_some() {
+        return best;
+    }
+
+    King::get_adjacent_squares(square)
+        .into_iter()
+        .flatten()
+        .find_map(|sqr| match own_piece(sqr) {
+            Some(
+                piece @ Piece {
+                    kind: PieceKind::King,
+                    ..
+                },
+            ) => Some((sqr, piece)),
+            _ => None,
+        })
+}
+
+// Two quiet moves per ply that caused a beta cutoff. Sibling nodes often get refuted by the same move.
+pub struct KillerMoves {
+    moves: [[Option<BoardMove>; 2]; MAX_PLY],
+}
+
+impl KillerMoves {
+    pub fn new() -> Self {
+        KillerMoves {
+            moves: [[None; 2]; MAX_PLY],
+        }
+    }
+
+    pub fn get(&self, ply: usize) -> [Option<BoardMove>; 2] {
+        self.moves[ply.min(MAX_PLY - 1)]
+    }
+
+    // The newest killer goes first, the older one is kept as the second.
+    pub fn store(&mut self, ply: usize, killer: BoardMove) {
+        let slot = &mut self.moves[ply.min(MAX_PLY - 1)];
+        if slot[0] != Some(killer) {
+            slot[1] = slot[0];
+            slot[0] = Some(killer);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.moves = [[None; 2]; MAX_PLY];
+    }
+}
+
+impl Default for KillerMoves {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Butterfly history: how often a quiet move (by color, start and end square) was good, across the
+// whole search.
+pub struct HistoryTable {
+    scores: Box<[[[i32; 64]; 64]; 2]>,
+}
+
+impl HistoryTable {
+    pub fn new() -> Self {
+        HistoryTable {
+            scores: Box::new([[[0; 64]; 64]; 2]),
+        }
+    }
+
+    pub fn get(&self, color: Color, start_square: Square, a_move: Move) -> i32 {
+        self.scores[color as usize][start_square.index()][a_move.end_square(color).index()]
+    }
+
+    // Gravity update: the bigger the entry already is, the less a bonus moves it, so scores
+    // stay bounded and old information fades.
+    pub fn update(&mut self, color: Color, start_square: Square, a_move: Move, bonus: i32) {
+        let bonus = bonus.clamp(-MAX_HISTORY, MAX_HISTORY);
+        let entry = &mut self.scores[color as usize][start_square.index()]
+            [a_move.end_square(color).index()];
+        *entry += bonus - *entry * bonus.abs() / MAX_HISTORY;
+    }
+
+    // Shrink every entry, so a new search doesn't rely too much on the previous one.
+    pub fn age(&mut self) {
+        self.scores
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|entry| *entry /= 2);
+    }
+
+    pub fn clear(&mut self) {
+        *self.scores = [[[0; 64]; 64]; 2];
+    }
+}
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The quiet move that last refuted each previous move, indexed by its start and end squares.
+pub struct CounterMoves {
+    moves: Box<[[Option<BoardMove>; 64]; 64]>,
+}
+
+impl CounterMoves {
+    pub fn new() -> Self {
+        CounterMoves {
+            moves: Box::new([[None; 64]; 64]),
+        }
+    }
+
+    pub fn get(&self, previous_move: BoardMove, previous_color: Color) -> Option<BoardMove> {
+        self.moves[previous_move.0.index()][previous_move.1.end_square(previous_color).index()]
+    }
+
+    pub fn store(
+        &mut self,
+        previous_move: BoardMove,
+        previous_color: Color,
+        counter_move: BoardMove,
+    ) {
+        self.moves[previous_move.0.index()][previous_move.1.end_square(previous_color).index()] =
+            Some(counter_move);
+    }
+
+    pub fn clear(&mut self) {
+        *self.moves = [[None; 64]; 64];
+    }
+}
+
+impl Default for CounterMoves {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Everything the move ordering learns while searching.
+#[derive(Default)]
+pub struct OrderingTables {
+    pub killers: KillerMoves,
+    pub history: HistoryTable,
+    pub counter_moves: CounterMoves,
+}
+
+impl OrderingTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Bonus given to (and malus taken from) quiet moves at a cutoff of the given depth.
+    pub fn history_bonus(depth: i32) -> i32 {
+        (depth * depth * 16).min(MAX_HISTORY / 8)
+    }
+
+    // A quiet move failed high: make it a killer and a counter move, reward it in the history
+    // table and punish the quiet moves searched before it.
+    pub fn update_quiet_cutoff(
+        &mut self,
+        color: Color,
+        ply: usize,
+        depth: i32,
+        cutoff_move: BoardMove,
+        previous_move: Option<BoardMove>,
+        tried_quiets: &[BoardMove],
+    ) {
+        let bonus = Self::history_bonus(depth);
+        self.killers.store(ply, cutoff_move);
+        if let Some(previous_move) = previous_move {
+            self.counter_moves
+                .store(previous_move, color.reverse(), cutoff_move);
+        }
+        self.history
+            .update(color, cutoff_move.0, cutoff_move.1, bonus);
+        for quiet in tried_quiets.iter().filter(|quiet| **quiet != cutoff_move) {
+            self.history.update(color, quiet.0, quiet.1, -bonus);
+        }
+    }
+
+    // Forget everything, for a new game.
+    pub fn clear(&mut self) {
+        self.killers.clear();
+        self.history.clear();
+        self.counter_moves.clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    HashMove,
+    GenerateCaptures,
+    GoodCaptures,
+    Killers,
+    CounterMove,
+    GenerateQuiets,
+    Quiets,
+    BadCaptures,
+    Done,
+}
+
+// Hands out the moves of a position one at a time, best guesses first. Moves are generated in
+// stages, so a cutoff on the hash move or a capture never pays for generating the quiet moves.
+// The moves are pseudo-legal, the caller still has to check legality when making them.
+pub struct MovePicker<'a> {
+    board: &'a Board,
+    color: Color,
+    stage: Stage,
+    hash_move: Option<BoardMove>,
+    killers: [Option<BoardMove>; 2],
+    counter_move: Option<BoardMove>,
+    // Whether quiet moves (and losing captures) are skipped, as in quiescence search.
+    noisy_only: bool,
+    scored_moves: Vec<(BoardMove, i32)>,
+    bad_captures: Vec<BoardMove>,
+    index: usize,
+}
+
+impl<'a> MovePicker<'a> {
+    // Picker for a full-width node. The killers and counter move are looked up in the tables
+    // for this ply and the opponent's last move.
+    pub fn new(
+        board: &'a Board,
+        hash_move: Option<BoardMove>,
+        ply: usize,
+        previous_move: Option<BoardMove>,
+        tables: &OrderingTables,
+    ) -> Self {
+        let color = board.side_to_move();
+        MovePicker {
+            board,
+            color,
+            stage: Stage::HashMove,
+            hash_move: hash_move.filter(|(start, a_move)| board.is_pseudo_legal(*start, *a_move)),
+            killers: tables.killers.get(ply),
+            counter_move: previous_move
+                .and_then(|previous| tables.counter_moves.get(previous, color.reverse())),
+            noisy_only: false,
+            scored_moves: Vec::with_capacity(48),
+            bad_captures: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // Picker that only hands out captures and promotions that don't lose material.
+    pub fn noisy(board: &'a Board) -> Self {
+        MovePicker {
+            board,
+            color: board.side_to_move(),
+            stage: Stage::GenerateCaptures,
+            hash_move: None,
+            killers: [None; 2],
+            counter_move: None,
+            noisy_only: true,
+            scored_moves: Vec::with_capacity(16),
+            bad_captures: Vec::new(),
+            index: 0,
+        }
+    }
+
+    // Whether the move was already handed out by an earlier stage.
+    fn already_tried(&self, candidate: BoardMove) -> bool {
+        self.hash_move == Some(candidate)
+            || (self.stage > Stage::Killers && self.killers.contains(&Some(candidate)))
+            || (self.stage > Stage::CounterMove && self.counter_move == Some(candidate))
+    }
+
+    // Is a remembered quiet move (killer or counter move) playable here?
+    fn is_usable_quiet(&self, candidate: BoardMove) -> bool {
+        !self.already_tried(candidate)
+            && !self.board.is_noisy(candidate.1)
+            && self.board.is_pseudo_legal(candidate.0, candidate.1)
+    }
+
+    // Take the best scored move left, moving it to the front (a lazy selection sort).
+    fn pick_best(&mut self) -> Option<BoardMove> {
+        if self.index >= self.scored_moves.len() {
+            return None;
+        }
+        let mut best = self.index;
+        for i in self.index + 1..self.scored_moves.len() {
+            if self.scored_moves[i].1 > self.scored_moves[best].1 {
+                best = i;
+            }
+        }
+        self.scored_moves.swap(self.index, best);
+        self.index += 1;
+        Some(self.scored_moves[self.index - 1].0)
+    }
+
+    pub fn next(&mut self, tables: &OrderingTables) -> Option<BoardMove> {
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    if self.hash_move.is_some() {
+                        return self.hash_move;
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    let mut noisy_moves = Vec::with_capacity(16);
+                    self.board.generate_noisy_moves(&mut noisy_moves);
+                    for noisy_move in noisy_moves {
+                        if self.hash_move == Some(noisy_move) {
+                            continue;
+                        }
+                        let (start_square, a_move) = noisy_move;
+                        let score = match a_move {
+                            Move::PawnPromotion(
+                                _,
+                                Piece {
+                                    kind: PieceKind::Queen,
+                                    ..
+                                },
+                            ) => QUEEN_PROMOTION_SCORE + mvv_lva(self.board, start_square, a_move),
+                            Move::PawnPromotion(_, _) => {
+                                // Under-promotions are almost never good.
+                                self.bad_captures.push(noisy_move);
+                                continue;
+                            }
+                            _ => {
+                                if see(self.board, start_square, a_move) < 0 {
+                                    self.bad_captures.push(noisy_move);
+                                    continue;
+                                }
+                                mvv_lva(self.board, start_square, a_move)
+                            }
+                        };
+                        self.scored_moves.push((noisy_move, score));
+                    }
+                    self.stage = Stage::GoodCaptures;
+                }
+                Stage::GoodCaptures => {
+                    if let Some(best) = self.pick_best() {
+                        return Some(best);
+                    }
+                    if self.noisy_only {
+                        self.stage = Stage::Done;
+                        continue;
+                    }
+                    self.stage = Stage::Killers;
+                    self.index = 0;
+                }
+                Stage::Killers => {
+                    while self.index < 2 {
+                        let killer = self.killers[self.index];
+                        self.index += 1;
+                        if let Some(killer) = killer {
+                            if self.is_usable_quiet(killer) {
+                                return Some(killer);
+                            }
+                        }
+                    }
+                    self.stage = Stage::CounterMove;
+                }
+                Stage::CounterMove => {
+                    // Checked before leaving the stage, or it would count as already tried.
+                    let counter_move = self
+                        .counter_move
+                        .filter(|counter_move| self.is_usable_quiet(*counter_move));
+                    self.stage = Stage::GenerateQuiets;
+                    if counter_move.is_some() {
+                        return counter_move;
+                    }
+                }
+                Stage::GenerateQuiets => {
+                    let mut quiet_moves = Vec::with_capacity(32);
+                    self.board.generate_quiet_moves(&mut quiet_moves);
+                    self.scored_moves.clear();
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                    for quiet_move in quiet_moves {
+                        if self.already_tried(quiet_move) {
+                            continue;
+                        }
+                        let score = tables.history.get(self.color, quiet_move.0, quiet_move.1);
+                        self.scored_moves.push((quiet_move, score));
+                    }
+                }
+                Stage::Quiets => {
+                    if let Some(best) = self.pick_best() {
+                        return Some(best);
+                    }
+                    self.stage = Stage::BadCaptures;
+                    self.index = 0;
+                }
+                Stage::BadCaptures => {
+                    if self.index < self.bad_captures.len() {
+                        self.index += 1;
+                        return Some(self.bad_captures[self.index - 1]);
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const FENS: [&str; 3] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 b kq - 0 1",
+    ];
+
+    // Every move the picker hands out, in order.
+    fn picked(
+        board: &Board,
+        hash_move: Option<BoardMove>,
+        tables: &OrderingTables,
+    ) -> Vec<BoardMove> {
+        let previous_move = Some((
+            Square::from_index(0),
+            Move::RegularMove(Square::from_index(1)),
+        ));
+        let mut picker = MovePicker::new(board, hash_move, 0, previous_move, tables);
+        let mut moves = Vec::new();
+        while let Some(board_move) = picker.next(tables) {
+            moves.push(board_move);
+        }
+        moves
+    }
+
+    fn assert_every_legal_move_once(board: &Board, moves: &[BoardMove]) {
+        let mut counts: HashMap<BoardMove, usize> = HashMap::new();
+        for board_move in moves {
+            *counts.entry(*board_move).or_default() += 1;
+        }
+        assert!(counts.values().all(|count| *count == 1), "{moves:?}");
+        let legal_moves = board.legal_moves();
+        let legal_picked = moves
+            .iter()
+            .filter(|(start, a_move)| board.make_legal_move(*start, *a_move).is_some())
+            .count();
+        assert_eq!(legal_picked, legal_moves.len());
+        assert!(legal_moves.iter().all(|legal| counts.contains_key(legal)));
+    }
+
+    #[test]
+    fn picker_returns_every_legal_move_once() {
+        for fen in FENS {
+            let board = Board::from_fen(fen);
+            let color = board.side_to_move();
+            let legal_moves = board.legal_moves();
+            let quiets: Vec<BoardMove> = legal_moves
+                .iter()
+                .filter(|(_, a_move)| !board.is_noisy(*a_move))
+                .copied()
+                .collect();
+            let noisy = legal_moves
+                .iter()
+                .find(|(_, a_move)| board.is_noisy(*a_move));
+
+            let tables = OrderingTables::new();
+            assert_every_legal_move_once(&board, &picked(&board, None, &tables));
+
+            // Remembered moves that overlap each other, and one from another position.
+            let previous_move = (
+                Square::from_index(0),
+                Move::RegularMove(Square::from_index(1)),
+            );
+            let foreign = (
+                Square::from_index(63),
+                Move::RegularMove(Square::from_index(0)),
+            );
+            let setups = [
+                (Some(quiets[0]), [quiets[1], quiets[2]], quiets[3]),
+                (Some(quiets[0]), [quiets[0], quiets[1]], quiets[1]),
+                (noisy.copied(), [quiets[2], foreign], quiets[0]),
+                (Some(foreign), [quiets[3], quiets[4]], foreign),
+            ];
+            for (hash_move, killers, counter_move) in setups {
+                let mut tables = OrderingTables::new();
+                tables.killers.store(0, killers[1]);
+                tables.killers.store(0, killers[0]);
+                tables
+                    .counter_moves
+                    .store(previous_move, color.reverse(), counter_move);
+                let moves = picked(&board, hash_move, &tables);
+                assert_every_legal_move_once(&board, &moves);
+                if counter_move != foreign && !killers.contains(&counter_move) {
+                    assert!(moves.contains(&counter_move), "{counter_move:?} missing");
+                }
+            }
+        }
+    }
+}
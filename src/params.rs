@@ -0,0 +1,467 @@
+use crate::eval::Score;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Every weight of the evaluation, so they can be tuned and loaded from a file. The defaults are
+// hand-picked.
+//
+// The file format is one parameter per line, `name = values`, with the values separated by
+// spaces and scores written as their middlegame and endgame values one after the other. Lines
+// starting with '#' are comments. Parameters left out of a file keep their default values.
+
+// Piece-square tables, from white's point of view, written with the 8th rank first (as seen
+// from white's side of the board). Only used for the defaults.
+#[rustfmt::skip]
+const PAWN_MG: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const PAWN_EG: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    80, 80, 80, 80, 80, 80, 80, 80,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    30, 30, 30, 30, 30, 30, 30, 30,
+    20, 20, 20, 20, 20, 20, 20, 20,
+    10, 10, 10, 10, 10, 10, 10, 10,
+     5,  5,  5,  5,  5,  5,  5,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+#[rustfmt::skip]
+const KNIGHT: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+#[rustfmt::skip]
+const BISHOP: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+#[rustfmt::skip]
+const ROOK: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+#[rustfmt::skip]
+const QUEEN: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+#[rustfmt::skip]
+const KING_MG: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+#[rustfmt::skip]
+const KING_EG: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+// Middlegame and endgame tables side by side.
+const fn pst(mg: [i32; 64], eg: [i32; 64]) -> [Score; 64] {
+    let mut table = [Score::new(0, 0); 64];
+    let mut square = 0;
+    while square < 64 {
+        table[square] = Score::new(mg[square], eg[square]);
+        square += 1;
+    }
+    table
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EvalParams {
+    // Material, by piece kind (pawn to king).
+    pub material: [Score; 6],
+    // Piece-square tables by piece kind, from white's point of view with the 8th rank first.
+    pub pst: [[Score; 64]; 6],
+
+    // Pawn structure. The arrays are by rank, relative to the pawn's side.
+    pub passed: [Score; 8],
+    pub connected: [Score; 8],
+    pub candidate: [Score; 8],
+    pub isolated: Score,
+    pub doubled: Score,
+    pub backward: Score,
+    // Middlegame only. The shelter is by the relative rank of the king's own pawn on each file
+    // next to it (0 when there's none), the storm by the relative rank of the closest enemy
+    // pawn on those files.
+    pub shelter: [i32; 8],
+    pub storm: [i32; 8],
+
+    // Mobility bonus for each square a piece can go to, by piece kind.
+    pub mobility: [Score; 6],
+    // Weight of each attack on a square next to the enemy king, by the kind of the attacker.
+    // The sum gets scaled by the attacker scale (in percent) by the number of attackers.
+    pub king_attack_weights: [i32; 6],
+    pub attacker_scale: [i32; 8],
+    pub threat_by_pawn: Score,
+    pub threat_by_minor: Score,
+    pub threat_by_rook: Score,
+    pub hanging: Score,
+    pub rook_open_file: Score,
+    pub rook_semi_open_file: Score,
+    pub bishop_pair: Score,
+    // By piece kind, only knights and bishops get one.
+    pub outpost: [Score; 6],
+}
+
+pub const DEFAULT_PARAMS: EvalParams = EvalParams {
+    material: [
+        Score::new(82, 94),
+        Score::new(337, 281),
+        Score::new(365, 297),
+        Score::new(477, 512),
+        Score::new(1025, 936),
+        Score::new(0, 0),
+    ],
+    pst: [
+        pst(PAWN_MG, PAWN_EG),
+        pst(KNIGHT, KNIGHT),
+        pst(BISHOP, BISHOP),
+        pst(ROOK, ROOK),
+        pst(QUEEN, QUEEN),
+        pst(KING_MG, KING_EG),
+    ],
+    passed: [
+        Score::new(0, 0),
+        Score::new(5, 15),
+        Score::new(10, 20),
+        Score::new(15, 35),
+        Score::new(30, 60),
+        Score::new(55, 100),
+        Score::new(90, 150),
+        Score::new(0, 0),
+    ],
+    connected: [
+        Score::new(0, 0),
+        Score::new(5, 3),
+        Score::new(7, 5),
+        Score::new(10, 8),
+        Score::new(20, 15),
+        Score::new(35, 25),
+        Score::new(60, 40),
+        Score::new(0, 0),
+    ],
+    candidate: [
+        Score::new(0, 0),
+        Score::new(3, 5),
+        Score::new(5, 10),
+        Score::new(10, 20),
+        Score::new(20, 35),
+        Score::new(35, 50),
+        Score::new(0, 0),
+        Score::new(0, 0),
+    ],
+    isolated: Score::new(-8, -15),
+    doubled: Score::new(-10, -25),
+    backward: Score::new(-9, -12),
+    shelter: [-20, 20, 12, 4, 0, 0, 0, 0],
+    storm: [0, 0, -35, -20, -10, -5, 0, 0],
+
+    mobility: [
+        Score::new(0, 0),
+        Score::new(4, 4),
+        Score::new(5, 5),
+        Score::new(2, 4),
+        Score::new(1, 2),
+        Score::new(0, 0),
+    ],
+    king_attack_weights: [0, 20, 20, 40, 80, 0],
+    attacker_scale: [0, 0, 50, 75, 88, 94, 97, 99],
+    threat_by_pawn: Score::new(50, 40),
+    threat_by_minor: Score::new(30, 35),
+    threat_by_rook: Score::new(35, 25),
+    hanging: Score::new(30, 20),
+    rook_open_file: Score::new(40, 10),
+    rook_semi_open_file: Score::new(20, 7),
+    bishop_pair: Score::new(30, 50),
+    outpost: [
+        Score::new(0, 0),
+        Score::new(30, 15),
+        Score::new(15, 8),
+        Score::new(0, 0),
+        Score::new(0, 0),
+        Score::new(0, 0),
+    ],
+};
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        DEFAULT_PARAMS
+    }
+}
+
+fn scores(values: &mut [Score]) -> Vec<&mut i32> {
+    values
+        .iter_mut()
+        .flat_map(|score| [&mut score.mg, &mut score.eg])
+        .collect()
+}
+
+fn score(value: &mut Score) -> Vec<&mut i32> {
+    vec![&mut value.mg, &mut value.eg]
+}
+
+fn ints(values: &mut [i32]) -> Vec<&mut i32> {
+    values.iter_mut().collect()
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl EvalParams {
+    // Every parameter by name, as the numbers it's made of, in the order of the file.
+    fn for_each(&mut self, mut visit: impl FnMut(&'static str, Vec<&mut i32>)) {
+        visit("material", scores(&mut self.material));
+        let [pawn, knight, bishop, rook, queen, king] = &mut self.pst;
+        visit("pst_pawn", scores(pawn));
+        visit("pst_knight", scores(knight));
+        visit("pst_bishop", scores(bishop));
+        visit("pst_rook", scores(rook));
+        visit("pst_queen", scores(queen));
+        visit("pst_king", scores(king));
+        visit("passed", scores(&mut self.passed));
+        visit("connected", scores(&mut self.connected));
+        visit("candidate", scores(&mut self.candidate));
+        visit("isolated", score(&mut self.isolated));
+        visit("doubled", score(&mut self.doubled));
+        visit("backward", score(&mut self.backward));
+        visit("shelter", ints(&mut self.shelter));
+        visit("storm", ints(&mut self.storm));
+        visit("mobility", scores(&mut self.mobility));
+        visit("king_attack_weights", ints(&mut self.king_attack_weights));
+        visit("attacker_scale", ints(&mut self.attacker_scale));
+        visit("threat_by_pawn", score(&mut self.threat_by_pawn));
+        visit("threat_by_minor", score(&mut self.threat_by_minor));
+        visit("threat_by_rook", score(&mut self.threat_by_rook));
+        visit("hanging", score(&mut self.hanging));
+        visit("rook_open_file", score(&mut self.rook_open_file));
+        visit("rook_semi_open_file", score(&mut self.rook_semi_open_file));
+        visit("bishop_pair", score(&mut self.bishop_pair));
+        visit("outpost", scores(&mut self.outpost));
+    }
+
+    // All the numbers, in the order of the file. Meant for tuning.
+    pub fn values(&self) -> Vec<i32> {
+        let mut values = Vec::new();
+        self.clone()
+            .for_each(|_, numbers| values.extend(numbers.into_iter().map(|value| *value)));
+        values
+    }
+
+    // Replace all the numbers, given in the order `values` returns them.
+    pub fn set_values(&mut self, values: &[i32]) {
+        let mut values = values.iter();
+        self.for_each(|_, numbers| {
+            for (number, value) in numbers.into_iter().zip(&mut values) {
+                *number = *value;
+            }
+        });
+    }
+
+    // Parameters from the text format, starting from the defaults.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut params = EvalParams::default();
+        let mut lines = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, values) = line
+                .split_once('=')
+                .ok_or(format!("line {}: expected `name = values`", number + 1))?;
+            let values = values
+                .split_whitespace()
+                .map(|value| value.parse::<i32>())
+                .collect::<Result<Vec<i32>, _>>()
+                .map_err(|error| format!("line {}: {error}", number + 1))?;
+            lines.push((name.trim().to_string(), values, number + 1));
+        }
+        let mut error = None;
+        let mut known = Vec::new();
+        params.for_each(|name, numbers| {
+            known.push(name);
+            for (line_name, values, line) in &lines {
+                if line_name != name {
+                    continue;
+                }
+                if values.len() != numbers.len() {
+                    error.get_or_insert(format!(
+                        "line {line}: {name} takes {} values, not {}",
+                        numbers.len(),
+                        values.len()
+                    ));
+                    return;
+                }
+                for (number, value) in numbers.into_iter().zip(values) {
+                    *number = *value;
+                }
+                return;
+            }
+        });
+        if let Some((name, _, line)) = lines.iter().find(|(name, ..)| !known.contains(&&**name)) {
+            error.get_or_insert(format!("line {line}: unknown parameter {name}"));
+        }
+        match error {
+            Some(error) => Err(error),
+            None => Ok(params),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        EvalParams::parse(&fs::read_to_string(path)?).map_err(invalid_data)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut result = Ok(());
+        self.clone().for_each(|name, numbers| {
+            let values: Vec<String> = numbers.iter().map(|value| value.to_string()).collect();
+            result = result.and_then(|_| writeln!(f, "{name} = {}", values.join(" ")));
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Board;
+    use crate::eval::evaluate_with;
+
+    #[test]
+    fn the_text_format_round_trips() {
+        let mut params = EvalParams {
+            bishop_pair: Score::new(12, -34),
+            ..EvalParams::default()
+        };
+        params.pst[1][27] = Score::new(-7, 9);
+        params.storm[3] = -99;
+        assert_eq!(EvalParams::parse(&params.to_string()), Ok(params));
+    }
+
+    #[test]
+    fn missing_parameters_keep_their_defaults() {
+        let text = "# only a couple\n\nhanging = 1 2\n  isolated = -3 -4  \n";
+        let params = EvalParams::parse(text).unwrap();
+        assert_eq!(params.hanging, Score::new(1, 2));
+        assert_eq!(params.isolated, Score::new(-3, -4));
+        assert_eq!(params.material, DEFAULT_PARAMS.material);
+        assert_eq!(params.outpost, DEFAULT_PARAMS.outpost);
+    }
+
+    #[test]
+    fn bad_files_are_errors() {
+        let errors = [
+            ("hanging 1 2", "line 1: expected `name = values`"),
+            ("hanging = 1 x", "line 1: invalid digit found in string"),
+            ("\nhanging = 1 2 3", "line 2: hanging takes 2 values, not 3"),
+            (
+                "hanging = 1 2\nsparkle = 4",
+                "line 2: unknown parameter sparkle",
+            ),
+        ];
+        for (text, error) in errors {
+            assert_eq!(EvalParams::parse(text), Err(error.to_string()), "{text}");
+        }
+    }
+
+    #[test]
+    fn values_come_back_in_the_same_order() {
+        let mut values = DEFAULT_PARAMS.values();
+        let text = DEFAULT_PARAMS.to_string();
+        let numbers = text.lines().map(|line| line.split_whitespace().count() - 2);
+        assert_eq!(values.len(), numbers.sum::<usize>());
+        for (index, value) in values.iter_mut().enumerate() {
+            *value = index as i32;
+        }
+        let mut params = EvalParams::default();
+        params.set_values(&values);
+        assert_eq!(params.values(), values);
+        assert_eq!(params.material[0], Score::new(0, 1));
+        assert_eq!(
+            params.outpost[5],
+            Score::new(values.len() as i32 - 2, values.len() as i32 - 1)
+        );
+    }
+
+    #[test]
+    fn saved_files_load_back() {
+        let path = std::env::temp_dir().join(format!("params-{}.txt", std::process::id()));
+        let params = EvalParams {
+            rook_open_file: Score::new(55, 5),
+            ..EvalParams::default()
+        };
+        params.save(&path).unwrap();
+        let loaded = EvalParams::load(&path);
+        fs::write(&path, "rook_open_file = 1").unwrap();
+        let broken = EvalParams::load(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.unwrap(), params);
+        assert_eq!(broken.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn the_evaluation_uses_the_params() {
+        let board = Board::from_fen("1n2k3/1p6/8/8/8/8/1P6/R3K1N1 w - - 0 1");
+        let mut params = EvalParams {
+            rook_open_file: Score::new(0, 0),
+            ..EvalParams::default()
+        };
+        let without = evaluate_with(&board, &params);
+        params.rook_open_file = Score::new(100, 100);
+        assert_eq!(evaluate_with(&board, &params), without + 100);
+    }
+}
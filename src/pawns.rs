@@ -0,0 +1,436 @@
+use crate::eval::{write_breakdown, Score};
+use crate::params::EvalParams;
+use crate::{zobrist, Board, Color, Piece, PieceKind, Square};
+use std::fmt;
+
+// Pawn structure terms. They only depend on where the pawns (and, for the shelter and the
+// storm, the kings) are, so they're cached in a pawn hash table keyed on the pawns alone.
+
+// Terms of the breakdown, in the order they're stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PawnTerm {
+    Passed,
+    Isolated,
+    Doubled,
+    Backward,
+    Connected,
+    Candidate,
+    Shelter,
+    Storm,
+}
+
+pub const PAWN_TERMS: [PawnTerm; 8] = [
+    PawnTerm::Passed,
+    PawnTerm::Isolated,
+    PawnTerm::Doubled,
+    PawnTerm::Backward,
+    PawnTerm::Connected,
+    PawnTerm::Candidate,
+    PawnTerm::Shelter,
+    PawnTerm::Storm,
+];
+
+// Every pawn term for each side, each from that side's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PawnBreakdown {
+    terms: [[Score; 8]; 2],
+}
+
+impl PawnBreakdown {
+    pub fn get(&self, color: Color, term: PawnTerm) -> Score {
+        self.terms[color.index()][term as usize]
+    }
+
+    // Sum of the terms of one side.
+    pub fn side_total(&self, color: Color) -> Score {
+        self.terms[color.index()]
+            .iter()
+            .fold(Score::default(), |total, score| total + *score)
+    }
+
+    // Sum of every term, from white's point of view.
+    pub fn total(&self) -> Score {
+        self.side_total(Color::White) - self.side_total(Color::Black)
+    }
+}
+
+impl fmt::Display for PawnBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = PAWN_TERMS.map(|term| {
+            (
+                format!("{term:?}"),
+                self.get(Color::White, term),
+                self.get(Color::Black, term),
+            )
+        });
+        write_breakdown(f, &rows)
+    }
+}
+
+// Where each side's pawns are, by file and rank.
+struct PawnMap {
+    pawns: [[[bool; 8]; 8]; 2],
+}
+
+impl PawnMap {
+    fn new(board: &Board) -> Self {
+        let mut pawns = [[[false; 8]; 8]; 2];
+        for (square, piece) in board.board.iter().enumerate() {
+            if let Some(
+                piece @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = piece
+            {
+                pawns[piece.color.index()][square % 8][square / 8] = true;
+            }
+        }
+        PawnMap { pawns }
+    }
+
+    fn has(&self, color: Color, file: i32, rank: i32) -> bool {
+        (0..8).contains(&file)
+            && (0..8).contains(&rank)
+            && self.pawns[color.index()][file as usize][rank as usize]
+    }
+
+    // Relative ranks of the color's pawns on a file, from its own side of the board.
+    fn relative_ranks(&self, color: Color, file: i32) -> impl Iterator<Item = i32> + '_ {
+        (0..8)
+            .filter(move |rank| self.has(color, file, *rank))
+            .map(move |rank| relative(color, rank))
+    }
+}
+
+// Rank as seen from the color's side of the board.
+fn relative(color: Color, rank: i32) -> i32 {
+    if color.is_white() {
+        rank
+    } else {
+        7 - rank
+    }
+}
+
+// The terms that only depend on the pawns, for one side.
+fn structure(map: &PawnMap, color: Color, params: &EvalParams, terms: &mut [Score; 8]) {
+    let them = color.reverse();
+    let forward = if color.is_white() { 1 } else { -1 };
+    for file in 0..8 {
+        for rank in 0..8 {
+            if !map.has(color, file, rank) {
+                continue;
+            }
+            let relative_rank = relative(color, rank);
+            let adjacent = [file - 1, file + 1];
+            // Enemy pawns ahead on this file, and on the files next to it.
+            let blocked = map
+                .relative_ranks(them, file)
+                .any(|r| 7 - r > relative_rank);
+            let sentries = adjacent
+                .iter()
+                .map(|f| {
+                    map.relative_ranks(them, *f)
+                        .filter(|r| 7 - r > relative_rank)
+                        .count()
+                })
+                .sum::<usize>();
+            let own_in_front = map.relative_ranks(color, file).any(|r| r > relative_rank);
+            let neighbours: Vec<i32> = adjacent
+                .iter()
+                .flat_map(|f| map.relative_ranks(color, *f))
+                .collect();
+            let isolated = neighbours.is_empty();
+            let phalanx = adjacent.iter().any(|f| map.has(color, *f, rank));
+            let supported = adjacent.iter().any(|f| map.has(color, *f, rank - forward));
+            let passed = !blocked && sentries == 0 && !own_in_front;
+
+            if passed {
+                terms[PawnTerm::Passed as usize] += params.passed[relative_rank as usize];
+            }
+            if isolated {
+                terms[PawnTerm::Isolated as usize] += params.isolated;
+            }
+            // Only the pawn at the back of a doubled pair is penalised.
+            if own_in_front {
+                terms[PawnTerm::Doubled as usize] += params.doubled;
+            }
+            if phalanx || supported {
+                terms[PawnTerm::Connected as usize] += params.connected[relative_rank as usize];
+            }
+            // Backward: the pawns on the next files are all ahead of it, and it can't advance
+            // safely because an enemy pawn is on or guards its stop square.
+            let stop = rank + forward;
+            let stop_controlled = map.has(them, file, stop)
+                || adjacent.iter().any(|f| map.has(them, *f, stop + forward));
+            if !isolated && neighbours.iter().all(|r| *r > relative_rank) && stop_controlled {
+                terms[PawnTerm::Backward as usize] += params.backward;
+            }
+            // Candidate: not passed yet, but its file is open in front and it has at least as
+            // many pawns to help it through as there are enemy pawns to stop it.
+            let helpers = neighbours.iter().filter(|r| **r <= relative_rank).count();
+            if !passed && !blocked && !own_in_front && helpers >= sentries {
+                terms[PawnTerm::Candidate as usize] += params.candidate[relative_rank as usize];
+            }
+        }
+    }
+}
+
+// Pawn shelter in front of the king and enemy pawns storming towards it, for one side.
+fn king_safety(
+    map: &PawnMap,
+    color: Color,
+    king: Square,
+    params: &EvalParams,
+    terms: &mut [Score; 8],
+) {
+    let them = color.reverse();
+    let king_file = king.file().index() as i32;
+    let king_rank = relative(color, king.rank().index() as i32);
+    let center = king_file.clamp(1, 6);
+    let mut shelter = 0;
+    let mut storm = 0;
+    for file in center - 1..=center + 1 {
+        let own = map
+            .relative_ranks(color, file)
+            .filter(|r| *r >= king_rank)
+            .min()
+            .unwrap_or(0);
+        shelter += params.shelter[own as usize];
+        // Enemy pawns, in ranks relative to this side, closest first.
+        let enemy = map
+            .relative_ranks(them, file)
+            .map(|r| 7 - r)
+            .filter(|r| *r >= king_rank)
+            .min();
+        if let Some(enemy) = enemy {
+            // A pawn that's stopped by one of ours can't open the file.
+            let stopped = own != 0 && own + 1 == enemy;
+            storm += if stopped {
+                params.storm[enemy as usize] / 2
+            } else {
+                params.storm[enemy as usize]
+            };
+        }
+    }
+    terms[PawnTerm::Shelter as usize] = Score::new(shelter, 0);
+    terms[PawnTerm::Storm as usize] = Score::new(storm, 0);
+}
+
+fn king_squares(board: &Board) -> [Square; 2] {
+    [board.white_king_pos, board.black_king_pos]
+}
+
+// Every pawn term of the position, side by side.
+pub fn breakdown(board: &Board, params: &EvalParams) -> PawnBreakdown {
+    let map = PawnMap::new(board);
+    let mut breakdown = PawnBreakdown::default();
+    let kings = king_squares(board);
+    for color in [Color::White, Color::Black] {
+        let terms = &mut breakdown.terms[color.index()];
+        structure(&map, color, params, terms);
+        king_safety(&map, color, kings[color.index()], params, terms);
+    }
+    breakdown
+}
+
+// Pawn structure score, from white's point of view.
+pub fn evaluate(board: &Board, params: &EvalParams) -> Score {
+    breakdown(board, params).total()
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PawnEntry {
+    key: u64,
+    // The shelter and storm terms are for the kings on these squares, and get worked out
+    // again when the kings have moved.
+    king_squares: [Square; 2],
+    breakdown: PawnBreakdown,
+}
+
+// Hash table of pawn structures, indexed by the Zobrist key of the pawns.
+pub struct PawnTable {
+    entries: Vec<Option<PawnEntry>>,
+}
+
+impl PawnTable {
+    // A table that uses around the given amount of megabytes.
+    pub fn new(megabytes: usize) -> Self {
+        let size = (megabytes * 1024 * 1024 / std::mem::size_of::<Option<PawnEntry>>()).max(1);
+        PawnTable {
+            entries: vec![None; size],
+        }
+    }
+
+    // Pawn structure score of the position, from white's point of view.
+    pub fn probe(&mut self, board: &Board, params: &EvalParams) -> Score {
+        self.breakdown(board, params).total()
+    }
+
+    // Every pawn term of the position, computed only when it's not in the table already.
+    pub fn breakdown(&mut self, board: &Board, params: &EvalParams) -> PawnBreakdown {
+        let key = zobrist::pawn_hash(board);
+        let index = (key % self.entries.len() as u64) as usize;
+        let kings = king_squares(board);
+        match &mut self.entries[index] {
+            Some(entry) if entry.key == key => {
+                if entry.king_squares != kings {
+                    let map = PawnMap::new(board);
+                    for color in [Color::White, Color::Black] {
+                        let terms = &mut entry.breakdown.terms[color.index()];
+                        king_safety(&map, color, kings[color.index()], params, terms);
+                    }
+                    entry.king_squares = kings;
+                }
+                entry.breakdown
+            }
+            slot => {
+                let breakdown = breakdown(board, params);
+                *slot = Some(PawnEntry {
+                    key,
+                    king_squares: kings,
+                    breakdown,
+                });
+                breakdown
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::DEFAULT_PARAMS;
+
+    fn terms(fen: &str) -> PawnBreakdown {
+        breakdown(&Board::from_fen(fen), &DEFAULT_PARAMS)
+    }
+
+    #[test]
+    fn passed_pawns_score_by_rank() {
+        let found = terms("4k3/8/8/3P4/8/8/6p1/4K3 w - - 0 1");
+        let params = &DEFAULT_PARAMS;
+        assert_eq!(found.get(Color::White, PawnTerm::Passed), params.passed[4]);
+        assert_eq!(found.get(Color::Black, PawnTerm::Passed), params.passed[6]);
+        // A pawn on the next file ahead of it stops it being passed.
+        let found = terms("4k3/4p3/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(found.get(Color::White, PawnTerm::Passed), Score::default());
+    }
+
+    #[test]
+    fn isolated_and_doubled_pawns() {
+        let found = terms("4k3/8/8/8/8/2P5/2P5/4K3 w - - 0 1");
+        let params = &DEFAULT_PARAMS;
+        assert_eq!(
+            found.get(Color::White, PawnTerm::Isolated),
+            params.isolated * 2
+        );
+        // Only the pawn behind counts as doubled, and only the one in front as passed.
+        assert_eq!(found.get(Color::White, PawnTerm::Doubled), params.doubled);
+        assert_eq!(found.get(Color::White, PawnTerm::Passed), params.passed[2]);
+    }
+
+    #[test]
+    fn connected_pawns_side_by_side_or_defended() {
+        let params = &DEFAULT_PARAMS;
+        let phalanx = terms("4k3/8/8/8/3PP3/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            phalanx.get(Color::White, PawnTerm::Connected),
+            params.connected[3] * 2
+        );
+        let chain = terms("4k3/8/8/8/3P4/2P5/8/4K3 w - - 0 1");
+        assert_eq!(
+            chain.get(Color::White, PawnTerm::Connected),
+            params.connected[3]
+        );
+        assert_eq!(
+            chain.get(Color::White, PawnTerm::Isolated),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn backward_pawns_have_a_guarded_stop_square() {
+        let found = terms("4k3/8/8/4p3/2P5/3P4/8/4K3 w - - 0 1");
+        assert_eq!(
+            found.get(Color::White, PawnTerm::Backward),
+            DEFAULT_PARAMS.backward
+        );
+        // With nothing guarding d4 the pawn can catch up.
+        let found = terms("4k3/8/8/8/2P5/3P4/8/4K3 w - - 0 1");
+        assert_eq!(
+            found.get(Color::White, PawnTerm::Backward),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn candidates_have_enough_help_to_get_through() {
+        let found = terms("4k3/8/2p5/8/1PP5/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            found.get(Color::White, PawnTerm::Candidate),
+            DEFAULT_PARAMS.candidate[3]
+        );
+        assert_eq!(found.get(Color::White, PawnTerm::Passed), Score::default());
+    }
+
+    #[test]
+    fn shelter_and_storm_around_the_king() {
+        let params = &DEFAULT_PARAMS;
+        let sheltered = terms("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1");
+        assert_eq!(
+            sheltered.get(Color::White, PawnTerm::Shelter),
+            Score::new(params.shelter[1] * 3, 0)
+        );
+        assert_eq!(
+            sheltered.get(Color::Black, PawnTerm::Shelter),
+            Score::new(params.shelter[0] * 3, 0)
+        );
+        let stormed = terms("6k1/8/8/8/6p1/8/5P1P/6K1 w - - 0 1");
+        assert_eq!(
+            stormed.get(Color::White, PawnTerm::Storm),
+            Score::new(params.storm[3], 0)
+        );
+    }
+
+    #[test]
+    fn mirrored_structures_score_the_same_for_the_other_side() {
+        let white = terms("4k3/8/8/4p3/2P5/3P4/5P2/4K3 w - - 0 1");
+        let black = terms("4k3/5p2/3p4/2p5/4P3/8/8/4K3 b - - 0 1");
+        for term in PAWN_TERMS {
+            assert_eq!(
+                white.get(Color::White, term),
+                black.get(Color::Black, term),
+                "{term:?}"
+            );
+        }
+        assert_eq!(white.total(), -black.total());
+    }
+
+    #[test]
+    fn the_table_gives_what_breakdown_works_out() {
+        let mut table = PawnTable::new(1);
+        let before = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/6K1 w - - 0 1");
+        let after = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/2K5 b - - 0 1");
+        assert_eq!(zobrist::pawn_hash(&before), zobrist::pawn_hash(&after));
+        for board in [&before, &after, &before] {
+            assert_eq!(
+                table.breakdown(board, &DEFAULT_PARAMS),
+                breakdown(board, &DEFAULT_PARAMS)
+            );
+            assert_eq!(
+                table.probe(board, &DEFAULT_PARAMS),
+                evaluate(board, &DEFAULT_PARAMS)
+            );
+        }
+        // The king left its shelter, which the cached entry has to follow.
+        assert_ne!(
+            breakdown(&before, &DEFAULT_PARAMS).get(Color::White, PawnTerm::Shelter),
+            breakdown(&after, &DEFAULT_PARAMS).get(Color::White, PawnTerm::Shelter)
+        );
+    }
+}
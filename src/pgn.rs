@@ -0,0 +1,237 @@
+use crate::{Board, BoardMove, ChessError, Color};
+
+// How a game ended, as written at the end of its movetext.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    pub fn from_pgn(result: &str) -> Option<Self> {
+        match result {
+            "1-0" => Some(GameResult::WhiteWins),
+            "0-1" => Some(GameResult::BlackWins),
+            "1/2-1/2" => Some(GameResult::Draw),
+            "*" => Some(GameResult::Unknown),
+            _ => None,
+        }
+    }
+
+    pub fn to_pgn(self) -> &'static str {
+        match self {
+            GameResult::WhiteWins => "1-0",
+            GameResult::BlackWins => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+
+    // Points scored by the given color: 2 for a win, 1 for a draw, 0 otherwise.
+    pub fn points_for(self, color: Color) -> u32 {
+        match (self, color) {
+            (GameResult::WhiteWins, Color::White) | (GameResult::BlackWins, Color::Black) => 2,
+            (GameResult::Draw, _) => 1,
+            _ => 0,
+        }
+    }
+}
+
+// A game read from a PGN file. The moves are kept in SAN, as they were written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub moves: Vec<String>,
+    pub result: GameResult,
+}
+
+impl Game {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The position the game starts from, from its FEN tag if it has one.
+    pub fn starting_position(&self) -> Board {
+        match self.tag("FEN") {
+            Some(fen) => Board::from_fen(fen),
+            None => Board::new(),
+        }
+    }
+
+    // Like starting_position, but a FEN tag that can't be read is an error.
+    pub fn try_starting_position(&self) -> Result<Board, ChessError> {
+        match self.tag("FEN") {
+            Some(fen) => Board::try_from_fen(fen),
+            None => Ok(Board::new()),
+        }
+    }
+
+    // Like replay, but a move that isn't legal is an error instead of the end of the game.
+    pub fn try_replay(&self) -> Result<Vec<(Board, BoardMove)>, ChessError> {
+        let mut board = self.try_starting_position()?;
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for san in &self.moves {
+            let (start_square, a_move) = board.try_move_from_san(san)?;
+            let new_board = board.make_move(start_square, a_move);
+            positions.push((board, (start_square, a_move)));
+            board = new_board;
+        }
+        Ok(positions)
+    }
+
+    // Play the game's moves, stopping at the first one that isn't legal. Returns the position
+    // each move was played in, together with the move.
+    pub fn replay(&self) -> Vec<(Board, BoardMove)> {
+        let mut board = self.starting_position();
+        let mut positions = Vec::with_capacity(self.moves.len());
+        for san in &self.moves {
+            let Some((start_square, a_move)) = board.move_from_san(san) else {
+                break;
+            };
+            let new_board = board.make_move(start_square, a_move);
+            positions.push((board, (start_square, a_move)));
+            board = new_board;
+        }
+        positions
+    }
+}
+
+// Read every game in a PGN text. Comments, variations, NAGs and move numbers are skipped.
+pub fn parse_pgn(text: &str) -> Vec<Game> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('%') {
+            continue;
+        }
+        if line.starts_with('[') && line.ends_with(']') {
+            // A tag after some movetext starts the next game, even without a result.
+            if !movetext.trim().is_empty() {
+                games.push(parse_movetext(std::mem::take(&mut tags), &movetext));
+                movetext.clear();
+            }
+            if let Some(tag) = parse_tag(line) {
+                tags.push(tag);
+            }
+        } else {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    if !movetext.trim().is_empty() || !tags.is_empty() {
+        games.push(parse_movetext(tags, &movetext));
+    }
+    games
+}
+
+// [Name "value"]
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let inner = &line[1..line.len() - 1];
+    let (name, value) = inner.split_once(char::is_whitespace)?;
+    let value = value.trim().trim_matches('"').replace("\\\"", "\"");
+    Some((name.to_string(), value))
+}
+
+fn parse_movetext(tags: Vec<(String, String)>, movetext: &str) -> Game {
+    let mut moves = Vec::new();
+    let mut result = None;
+    let mut variation_depth = 0;
+    let mut in_comment = false;
+    let mut token = String::new();
+    // A trailing space makes sure the last token is handled.
+    for ch in movetext.chars().chain(std::iter::once(' ')) {
+        if in_comment {
+            in_comment = ch != '}';
+            continue;
+        }
+        if ch.is_whitespace() || matches!(ch, '{' | '(' | ')') {
+            // The token ends here, so it belongs to the line we're still in.
+            if variation_depth == 0 && !token.is_empty() {
+                if let Some(game_result) = GameResult::from_pgn(&token) {
+                    result = Some(game_result);
+                } else if let Some(san) = movetext_move(&token) {
+                    moves.push(san.to_string());
+                }
+            }
+            token.clear();
+            match ch {
+                '{' => in_comment = true,
+                '(' => variation_depth += 1,
+                ')' => variation_depth -= 1,
+                _ => (),
+            }
+        } else {
+            token.push(ch);
+        }
+    }
+    // Without a result in the movetext, fall back to the Result tag.
+    let result = result
+        .or_else(|| {
+            tags.iter()
+                .find(|(name, _)| name == "Result")
+                .and_then(|(_, value)| GameResult::from_pgn(value))
+        })
+        .unwrap_or(GameResult::Unknown);
+    Game {
+        tags,
+        moves,
+        result,
+    }
+}
+
+// The move in a movetext token, without its move number ("12.Nf3", "12...Nf3", "Nf3").
+fn movetext_move(token: &str) -> Option<&str> {
+    if token.starts_with('$') {
+        return None;
+    }
+    let san = token.trim_start_matches(|ch: char| ch.is_ascii_digit() || ch == '.');
+    if san.is_empty() {
+        None
+    } else {
+        Some(san)
+    }
+}
+
+// Write a game in PGN, with the moves in SAN.
+pub fn write_pgn(game: &Game) -> String {
+    let mut pgn = String::new();
+    for (name, value) in &game.tags {
+        pgn.push_str(&format!("[{name} \"{}\"]\n", value.replace('"', "\\\"")));
+    }
+    pgn.push('\n');
+    let mut line = String::new();
+    let offset = usize::from(game.starting_position().side_to_move() == Color::Black);
+    for (index, san) in game.moves.iter().enumerate() {
+        let ply = index + offset;
+        let token = if ply.is_multiple_of(2) {
+            format!("{}. {san}", ply / 2 + 1)
+        } else if index == 0 {
+            format!("{}... {san}", ply / 2 + 1)
+        } else {
+            san.clone()
+        };
+        if !line.is_empty() && line.len() + token.len() + 1 > 79 {
+            pgn.push_str(&line);
+            pgn.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    if !line.is_empty() {
+        line.push(' ');
+    }
+    line.push_str(game.result.to_pgn());
+    pgn.push_str(&line);
+    pgn.push('\n');
+    pgn
+}
@@ -0,0 +1,499 @@
+use crate::eval::{write_breakdown, Score};
+use crate::params::EvalParams;
+use crate::{Board, Color, File, PieceKind, Square};
+use std::fmt;
+
+// Piece terms: mobility, attacks on the king zone, threats, hanging pieces, rooks on open
+// files, the bishop pair and outposts. They're worked out from attack sets, one bit per square,
+// built straight from the board instead of generating the moves.
+
+// Number of squares a piece can go to that counts as average, fewer is a penalty. Indexed by
+// piece kind.
+const MOBILITY_BASE: [i32; 6] = [0, 4, 6, 7, 13, 0];
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 1),
+    (1, 0),
+    (1, -1),
+    (0, -1),
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, -1), (-1, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+// Terms of the breakdown, in the order they're stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PieceTerm {
+    Mobility,
+    KingAttack,
+    Threats,
+    Hanging,
+    RookFiles,
+    BishopPair,
+    Outposts,
+}
+
+pub const PIECE_TERMS: [PieceTerm; 7] = [
+    PieceTerm::Mobility,
+    PieceTerm::KingAttack,
+    PieceTerm::Threats,
+    PieceTerm::Hanging,
+    PieceTerm::RookFiles,
+    PieceTerm::BishopPair,
+    PieceTerm::Outposts,
+];
+
+// Every piece term for each side, each from that side's point of view.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PieceBreakdown {
+    terms: [[Score; 7]; 2],
+}
+
+impl PieceBreakdown {
+    pub fn get(&self, color: Color, term: PieceTerm) -> Score {
+        self.terms[color.index()][term as usize]
+    }
+
+    // Sum of the terms of one side.
+    pub fn side_total(&self, color: Color) -> Score {
+        self.terms[color.index()]
+            .iter()
+            .fold(Score::default(), |total, score| total + *score)
+    }
+
+    // Sum of every term, from white's point of view.
+    pub fn total(&self) -> Score {
+        self.side_total(Color::White) - self.side_total(Color::Black)
+    }
+}
+
+impl fmt::Display for PieceBreakdown {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = PIECE_TERMS.map(|term| {
+            (
+                format!("{term:?}"),
+                self.get(Color::White, term),
+                self.get(Color::Black, term),
+            )
+        });
+        write_breakdown(f, &rows)
+    }
+}
+
+fn bit(square: Option<Square>) -> u64 {
+    square.map_or(0, |square| 1 << square.index())
+}
+
+fn step_attacks(square: Square, steps: &[(i8, i8)]) -> u64 {
+    steps.iter().fold(0, |attacks, (df, dr)| {
+        attacks | bit(square.offset(*df, *dr))
+    })
+}
+
+// Squares a sliding piece attacks, up to and including the first piece in each direction.
+fn sliding_attacks(occupied: u64, square: Square, directions: &[(i8, i8)]) -> u64 {
+    let mut attacks = 0;
+    for (df, dr) in directions {
+        let mut next = square.offset(*df, *dr);
+        while let Some(sqr) = next {
+            attacks |= bit(next);
+            if occupied & bit(next) != 0 {
+                break;
+            }
+            next = sqr.offset(*df, *dr);
+        }
+    }
+    attacks
+}
+
+fn pawn_attacks(square: Square, color: Color) -> u64 {
+    let forward = if color.is_white() { 1 } else { -1 };
+    bit(square.offset(-1, forward)) | bit(square.offset(1, forward))
+}
+
+// Fairy pieces are counted as attacking the squares around them, like a king.
+fn attacks_of(kind: PieceKind, color: Color, square: Square, occupied: u64) -> u64 {
+    match kind {
+        PieceKind::Pawn => pawn_attacks(square, color),
+        PieceKind::Knight => step_attacks(square, &KNIGHT_STEPS),
+        PieceKind::Bishop => sliding_attacks(occupied, square, &BISHOP_DIRECTIONS),
+        PieceKind::Rook => sliding_attacks(occupied, square, &ROOK_DIRECTIONS),
+        PieceKind::Queen => {
+            sliding_attacks(occupied, square, &BISHOP_DIRECTIONS)
+                | sliding_attacks(occupied, square, &ROOK_DIRECTIONS)
+        }
+        PieceKind::King | PieceKind::Fairy(_) => step_attacks(square, &KING_STEPS),
+    }
+}
+
+struct PieceInfo {
+    square: Square,
+    kind: PieceKind,
+    color: Color,
+    attacks: u64,
+}
+
+// Where everything is and what it attacks. Sets are indexed by color.
+struct Attacks {
+    pieces: Vec<PieceInfo>,
+    occupied_by: [u64; 2],
+    pawns: [u64; 2],
+    // Squares attacked by anything, by the pawns, by the knights and bishops, and by the rooks.
+    all: [u64; 2],
+    by_pawns: [u64; 2],
+    by_minors: [u64; 2],
+    by_rooks: [u64; 2],
+}
+
+impl Attacks {
+    fn new(board: &Board) -> Self {
+        let mut occupied_by = [0; 2];
+        let mut pawns = [0; 2];
+        for (square, piece) in board.board.iter().enumerate() {
+            if let Some(piece) = piece {
+                let side = piece.color.index();
+                occupied_by[side] |= 1 << square;
+                if piece.kind == PieceKind::Pawn {
+                    pawns[side] |= 1 << square;
+                }
+            }
+        }
+        let occupied = occupied_by[0] | occupied_by[1];
+        let mut attacks = Attacks {
+            pieces: Vec::with_capacity(32),
+            occupied_by,
+            pawns,
+            all: [0; 2],
+            by_pawns: [0; 2],
+            by_minors: [0; 2],
+            by_rooks: [0; 2],
+        };
+        for (square, piece) in board.board.iter().enumerate() {
+            if let Some(piece) = piece {
+                let (kind, color) = (piece.kind, piece.color);
+                let square = Square::from_index(square as u8);
+                let piece_attacks = attacks_of(kind, color, square, occupied);
+                let side = color.index();
+                attacks.all[side] |= piece_attacks;
+                match kind {
+                    PieceKind::Pawn => attacks.by_pawns[side] |= piece_attacks,
+                    PieceKind::Knight | PieceKind::Bishop => {
+                        attacks.by_minors[side] |= piece_attacks
+                    }
+                    PieceKind::Rook => attacks.by_rooks[side] |= piece_attacks,
+                    _ => (),
+                }
+                attacks.pieces.push(PieceInfo {
+                    square,
+                    kind,
+                    color,
+                    attacks: piece_attacks,
+                });
+            }
+        }
+        attacks
+    }
+}
+
+// Squares on the files next to the square, ahead of it from the color's point of view: the
+// squares enemy pawns would have to stand on to ever attack it.
+fn pawn_attack_span(square: Square, color: Color) -> u64 {
+    let forward = if color.is_white() { 1 } else { -1 };
+    (1..8).fold(0, |span, ranks| {
+        span | bit(square.offset(-1, ranks * forward)) | bit(square.offset(1, ranks * forward))
+    })
+}
+
+fn file_mask(file: File) -> u64 {
+    0x0101_0101_0101_0101 << file.index()
+}
+
+// The terms for one side.
+fn side_terms(
+    board: &Board,
+    attacks: &Attacks,
+    color: Color,
+    params: &EvalParams,
+    terms: &mut [Score; 7],
+) {
+    let (us, them) = (color.index(), color.reverse().index());
+    let enemy_king = if color.is_white() {
+        board.black_king_pos
+    } else {
+        board.white_king_pos
+    };
+    let king_zone = step_attacks(enemy_king, &KING_STEPS) | bit(Some(enemy_king));
+    let mobility_area = !attacks.occupied_by[us] & !attacks.by_pawns[them];
+    let mut king_attackers = 0;
+    let mut king_attack_weight = 0;
+    let mut bishops = 0;
+
+    for piece in attacks.pieces.iter().filter(|piece| piece.color == color) {
+        let file = piece.square.file();
+        let relative_rank = if color.is_white() {
+            piece.square.rank()
+        } else {
+            piece.square.rank().mirror()
+        };
+        let kind = piece.kind.index();
+        if matches!(
+            piece.kind,
+            PieceKind::Knight | PieceKind::Bishop | PieceKind::Rook | PieceKind::Queen
+        ) {
+            let squares = (piece.attacks & mobility_area).count_ones() as i32;
+            terms[PieceTerm::Mobility as usize] +=
+                params.mobility[kind] * (squares - MOBILITY_BASE[kind]);
+            let zone_attacks = (piece.attacks & king_zone).count_ones() as i32;
+            if zone_attacks > 0 {
+                king_attackers += 1;
+                king_attack_weight += params.king_attack_weights[kind] * zone_attacks;
+            }
+        }
+        match piece.kind {
+            PieceKind::Bishop => bishops += 1,
+            PieceKind::Rook if attacks.pawns[us] & file_mask(file) == 0 => {
+                terms[PieceTerm::RookFiles as usize] +=
+                    if attacks.pawns[them] & file_mask(file) == 0 {
+                        params.rook_open_file
+                    } else {
+                        params.rook_semi_open_file
+                    };
+            }
+            _ => (),
+        }
+        // An outpost: well advanced, guarded by a pawn, and no enemy pawn can ever chase the
+        // piece away.
+        let square_bit = bit(Some(piece.square));
+        if matches!(piece.kind, PieceKind::Knight | PieceKind::Bishop)
+            && (3..=5).contains(&relative_rank.index())
+            && attacks.by_pawns[us] & square_bit != 0
+            && attacks.pawns[them] & pawn_attack_span(piece.square, color) == 0
+        {
+            terms[PieceTerm::Outposts as usize] += params.outpost[kind];
+        }
+    }
+    if bishops >= 2 {
+        terms[PieceTerm::BishopPair as usize] += params.bishop_pair;
+    }
+    let danger = king_attack_weight * params.attacker_scale[king_attackers.min(7)] / 100;
+    terms[PieceTerm::KingAttack as usize] = Score::new(danger, danger / 4);
+
+    // Threats on the enemy pieces: attacked by something worth less, or not defended at all.
+    for piece in attacks.pieces.iter().filter(|piece| piece.color != color) {
+        let square_bit = bit(Some(piece.square));
+        if piece.kind == PieceKind::King || attacks.all[us] & square_bit == 0 {
+            continue;
+        }
+        let above_minor = matches!(
+            piece.kind,
+            PieceKind::Rook | PieceKind::Queen | PieceKind::Fairy(_)
+        );
+        if piece.kind != PieceKind::Pawn && attacks.by_pawns[us] & square_bit != 0 {
+            terms[PieceTerm::Threats as usize] += params.threat_by_pawn;
+        } else if above_minor && attacks.by_minors[us] & square_bit != 0 {
+            terms[PieceTerm::Threats as usize] += params.threat_by_minor;
+        } else if piece.kind == PieceKind::Queen && attacks.by_rooks[us] & square_bit != 0 {
+            terms[PieceTerm::Threats as usize] += params.threat_by_rook;
+        }
+        if attacks.all[them] & square_bit == 0 {
+            terms[PieceTerm::Hanging as usize] += params.hanging;
+        }
+    }
+}
+
+// Every piece term of the position, side by side.
+pub fn breakdown(board: &Board, params: &EvalParams) -> PieceBreakdown {
+    let attacks = Attacks::new(board);
+    let mut breakdown = PieceBreakdown::default();
+    for color in [Color::White, Color::Black] {
+        side_terms(
+            board,
+            &attacks,
+            color,
+            params,
+            &mut breakdown.terms[color.index()],
+        );
+    }
+    breakdown
+}
+
+// Piece terms score, from white's point of view.
+pub fn evaluate(board: &Board, params: &EvalParams) -> Score {
+    breakdown(board, params).total()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::DEFAULT_PARAMS;
+
+    fn term(fen: &str, color: Color, term: PieceTerm) -> Score {
+        breakdown(&Board::from_fen(fen), &DEFAULT_PARAMS).get(color, term)
+    }
+
+    #[test]
+    fn mobility_counts_the_squares_a_piece_reaches() {
+        let knight = DEFAULT_PARAMS.mobility[PieceKind::Knight.index()];
+        let centre = term(
+            "7k/8/8/8/4N3/8/8/K7 w - - 0 1",
+            Color::White,
+            PieceTerm::Mobility,
+        );
+        assert_eq!(centre, knight * 4);
+        let corner = term(
+            "7k/8/8/8/8/8/8/K6N w - - 0 1",
+            Color::White,
+            PieceTerm::Mobility,
+        );
+        assert_eq!(corner, knight * -2);
+        // Squares guarded by enemy pawns don't count.
+        let guarded = term(
+            "7k/8/4p3/8/8/4N3/8/K7 w - - 0 1",
+            Color::White,
+            PieceTerm::Mobility,
+        );
+        assert_eq!(guarded, knight * 2);
+    }
+
+    #[test]
+    fn the_king_zone_needs_two_attackers() {
+        let alone = "6k1/8/8/8/8/8/8/K5R1 w - - 0 1";
+        assert_eq!(
+            term(alone, Color::White, PieceTerm::KingAttack),
+            Score::default()
+        );
+        // The rook hits g7 and g8, the knight g7.
+        let weights = DEFAULT_PARAMS.king_attack_weights;
+        let weight = weights[PieceKind::Rook.index()] * 2 + weights[PieceKind::Knight.index()];
+        let danger = weight * DEFAULT_PARAMS.attacker_scale[2] / 100;
+        let together = "6k1/8/8/5N2/8/8/8/K5R1 w - - 0 1";
+        assert_eq!(
+            term(together, Color::White, PieceTerm::KingAttack),
+            Score::new(danger, danger / 4)
+        );
+    }
+
+    #[test]
+    fn threats_and_hanging_pieces() {
+        let params = &DEFAULT_PARAMS;
+        let by_pawn = "4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(by_pawn, Color::White, PieceTerm::Threats),
+            params.threat_by_pawn
+        );
+        assert_eq!(
+            term(by_pawn, Color::White, PieceTerm::Hanging),
+            params.hanging
+        );
+        let by_minor = "4k3/8/8/3r4/8/4N3/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(by_minor, Color::White, PieceTerm::Threats),
+            params.threat_by_minor
+        );
+        // Defended, so only the threat is left.
+        let defended = "4k3/8/4p3/3r4/8/4N3/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(defended, Color::White, PieceTerm::Threats),
+            params.threat_by_minor
+        );
+        assert_eq!(
+            term(defended, Color::White, PieceTerm::Hanging),
+            Score::default()
+        );
+        let by_rook = "4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1";
+        assert_eq!(
+            term(by_rook, Color::White, PieceTerm::Threats),
+            params.threat_by_rook
+        );
+    }
+
+    #[test]
+    fn rooks_on_open_and_semi_open_files() {
+        let params = &DEFAULT_PARAMS;
+        let open = "4k3/8/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(
+            term(open, Color::White, PieceTerm::RookFiles),
+            params.rook_open_file
+        );
+        let semi_open = "4k3/p7/8/8/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(
+            term(semi_open, Color::White, PieceTerm::RookFiles),
+            params.rook_semi_open_file
+        );
+        let closed = "4k3/p7/8/8/8/8/P7/R3K3 w - - 0 1";
+        assert_eq!(
+            term(closed, Color::White, PieceTerm::RookFiles),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn the_bishop_pair() {
+        let pair = "4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1";
+        assert_eq!(
+            term(pair, Color::White, PieceTerm::BishopPair),
+            DEFAULT_PARAMS.bishop_pair
+        );
+        let one = "4k3/8/8/8/8/8/8/2N1KB2 w - - 0 1";
+        assert_eq!(
+            term(one, Color::White, PieceTerm::BishopPair),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn outposts_are_guarded_and_safe_from_pawns() {
+        let outpost = "4k3/8/8/3N4/4P3/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(outpost, Color::White, PieceTerm::Outposts),
+            DEFAULT_PARAMS.outpost[PieceKind::Knight.index()]
+        );
+        // A pawn on c7 can still chase the knight away.
+        let chased = "4k3/2p5/8/3N4/4P3/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(chased, Color::White, PieceTerm::Outposts),
+            Score::default()
+        );
+        let unguarded = "4k3/8/8/3N4/8/8/8/4K3 w - - 0 1";
+        assert_eq!(
+            term(unguarded, Color::White, PieceTerm::Outposts),
+            Score::default()
+        );
+    }
+
+    #[test]
+    fn mirrored_positions_score_the_same_for_the_other_side() {
+        let white =
+            Board::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3");
+        let black =
+            Board::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3");
+        let white = breakdown(&white, &DEFAULT_PARAMS);
+        let black = breakdown(&black, &DEFAULT_PARAMS);
+        for term in PIECE_TERMS {
+            assert_eq!(
+                white.get(Color::White, term),
+                black.get(Color::Black, term),
+                "{term:?}"
+            );
+            assert_eq!(
+                white.get(Color::Black, term),
+                black.get(Color::White, term),
+                "{term:?}"
+            );
+        }
+        assert_eq!(white.total(), -black.total());
+    }
+}
@@ -1,6 +1,22 @@
-use crate::{convert_to_square, Color, Move, OnePieceMoves, Piece, PositionMoves};
+use crate::board::PieceChanges;
+use crate::eval::{self, has_non_pawn_material};
+use crate::nnue::{Network, NnueStack};
+use crate::ordering::{MovePicker, OrderingTables, MAX_PLY};
+use crate::params::EvalParams;
+use crate::pawns::PawnTable;
+use crate::syzygy::{self, Tablebases};
+use crate::time::{TimeControl, TimeManager};
+use crate::tt::{
+    score_from_tt, score_to_tt, Bound, TranspositionTable, MATE_BOUND, MATE_SCORE, TB_WIN_SCORE,
+};
+use crate::{
+    zobrist, BoardMove, Color, Move, OnePieceMoves, Piece, PieceKind, PositionMoves, Square,
+    MAX_PIECE_MOVES,
+};
 use fnv::FnvHashSet;
-use itertools::Itertools;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use super::Board;
 
@@ -13,8 +29,13 @@ fn can_castle(
 ) -> bool {
     match each_move {
         Move::CastleKingside => {
-            board.generate_moves(start_color.reverse(), moves_list, moves);
-            !(if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            board.generate_moves_for(start_color.reverse(), moves_list, moves);
+            !(if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[14]
             } else {
                 board.board[54]
@@ -22,7 +43,12 @@ fn can_castle(
                 pawn.color != start_color
             } else {
                 false
-            } || if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            } || if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[12]
             } else {
                 board.board[52]
@@ -30,7 +56,12 @@ fn can_castle(
                 pawn.color != start_color
             } else {
                 false
-            } || if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            } || if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[15]
             } else {
                 board.board[55]
@@ -41,22 +72,27 @@ fn can_castle(
             } || is_check(
                 moves,
                 if let Color::White = start_color {
-                    4
+                    Square::from_index(4)
                 } else {
-                    60
+                    Square::from_index(60)
                 },
             ) || is_check(
                 moves,
                 if let Color::White = start_color {
-                    5
+                    Square::from_index(5)
                 } else {
-                    61
+                    Square::from_index(61)
                 },
             ))
         }
         Move::CastleQueenside => {
-            board.generate_moves(start_color.reverse(), moves_list, moves);
-            !(if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            board.generate_moves_for(start_color.reverse(), moves_list, moves);
+            !(if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[9]
             } else {
                 board.board[50]
@@ -64,7 +100,12 @@ fn can_castle(
                 pawn.color != start_color
             } else {
                 false
-            } || if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            } || if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[12]
             } else {
                 board.board[52]
@@ -72,7 +113,12 @@ fn can_castle(
                 pawn.color != start_color
             } else {
                 false
-            } || if let Some(Piece::Pawn(pawn)) = if let Color::White = start_color {
+            } || if let Some(
+                pawn @ Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                },
+            ) = if let Color::White = start_color {
                 board.board[10]
             } else {
                 board.board[49]
@@ -83,16 +129,16 @@ fn can_castle(
             } || is_check(
                 moves,
                 if let Color::White = start_color {
-                    4
+                    Square::from_index(4)
                 } else {
-                    60
+                    Square::from_index(60)
                 },
             ) || is_check(
                 moves,
                 if let Color::White = start_color {
-                    3
+                    Square::from_index(3)
                 } else {
-                    59
+                    Square::from_index(59)
                 },
             ))
         }
@@ -103,26 +149,25 @@ fn can_castle(
 pub fn multi_thread_eval(
     board: &Board,
     depth: u8,
-    start_color: Color,
     positions: &mut FnvHashSet<[Option<Piece>; 64]>,
 ) {
-    let mut moves: PositionMoves = [None; 16];
-    board.generate_moves(start_color, &mut [None; 28], &mut moves);
+    let start_color = board.side_to_move();
+    let mut moves = PositionMoves::new();
+    board.generate_moves(&mut [None; MAX_PIECE_MOVES], &mut moves);
     let mut _amount_of_moves = 0;
     let mut moves_each_tree: i32;
-    let mut moves_list = [None; 28];
+    let mut moves_list = [None; MAX_PIECE_MOVES];
 
     if depth != 0 {
-        let mut moves_iter = moves.into_iter();
-        while let Some(tuple) = moves_iter.next().unwrap_or(None) {
+        for tuple in moves.clone() {
             let mut all_moves = tuple.1.iter();
             while let Some(each_move) = *all_moves.next().unwrap_or(&None) {
-                let new_board = board.make_move(tuple.0 as usize, each_move, start_color);
+                let new_board = board.make_move(tuple.0, each_move);
 
                 //if !positions.contains(&new_board.board) {
                 let should_calc =
                     can_castle(board, each_move, start_color, &mut moves_list, &mut moves);
-                new_board.generate_moves(start_color.reverse(), &mut moves_list, &mut moves);
+                new_board.generate_moves(&mut moves_list, &mut moves);
 
                 /*  let mut should_print = false;
                 if convert_to_square(*tuple.0) == "c4" && match each_move {
@@ -139,7 +184,7 @@ pub fn multi_thread_eval(
                         },
                     )
                 {
-                    let _a = convert_to_square(tuple.0);
+                    let _a = tuple.0.to_string();
                     moves_each_tree = 0;
                     evaluate(
                         &new_board,
@@ -167,26 +212,25 @@ fn evaluate(
     board: &Board,
     depth: u8,
     start_color: Color,
-    positions: &mut FnvHashSet<[Option<Piece>; 64]>,
+    _positions: &mut FnvHashSet<[Option<Piece>; 64]>,
     moves: &mut PositionMoves,
     amount_of_moves: &mut i32,
     moves_list: &mut OnePieceMoves,
 ) {
     let depth = depth - 1;
     if depth != 0 {
-        let moves_clone = *moves; // creating a copy of the list, otherwise it throws errors
-        let mut moves_iter = moves_clone.iter();
-        while let Some(tuple) = moves_iter.next().unwrap_or(&None) {
+        // A copy of the list, since the recursion below reuses it.
+        for tuple in moves.clone() {
             let mut all_moves = tuple.1.iter();
             while let Some(each_move) = *all_moves.next().unwrap_or(&None) {
                 /*
                 let a = convert_to_square(*tuple.0);
                 if should_print {println!("{should_calc}, {a}{each_move}");} */
 
-                let new_board = board.make_move(tuple.0 as usize, each_move, start_color);
+                let new_board = board.make_move(tuple.0, each_move);
                 //if !positions.contains(&new_board.board) {
                 let should_calc = can_castle(board, each_move, start_color, moves_list, moves);
-                new_board.generate_moves(start_color.reverse(), moves_list, moves);
+                new_board.generate_moves(moves_list, moves);
 
                 if should_calc
                     && !is_check(
@@ -202,7 +246,7 @@ fn evaluate(
                         &new_board,
                         depth,
                         start_color.reverse(),
-                        positions,
+                        _positions,
                         moves,
                         amount_of_moves,
                         moves_list,
@@ -218,39 +262,1014 @@ fn evaluate(
     //positions.insert(board.board);
 }
 
-fn is_check(moves: &PositionMoves, king_pos: u8) -> bool {
+// Count the leaf nodes of the legal move tree, to check move generation against known numbers.
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
     moves
-        .iter()
-        .fold_while(false, |_, moves_option| {
-            if let Some(tuple) = moves_option {
-                if tuple
-                    .1
-                    .iter()
-                    .fold_while(false, |_, item| {
-                        if let Some(a_move) = *item {
-                            let a = match a_move {
-                                Move::RegularMove(a_square) => a_square == king_pos,
-                                Move::PawnPromotion(a_square, _) => a_square == king_pos,
-                                _ => false,
-                            };
-                            if a {
-                                itertools::FoldWhile::Done(true)
-                            } else {
-                                itertools::FoldWhile::Continue(false)
-                            }
-                        } else {
-                            itertools::FoldWhile::Done(false)
-                        }
-                    })
-                    .into_inner()
+        .into_iter()
+        .map(|(start_square, a_move)| perft(&board.make_move(start_square, a_move), depth - 1))
+        .sum()
+}
+
+fn is_check(moves: &PositionMoves, king_pos: Square) -> bool {
+    moves.iter().any(|(_, piece_moves)| {
+        piece_moves
+            .iter()
+            .map_while(|a_move| *a_move)
+            .any(|a_move| match a_move {
+                Move::RegularMove(a_square) | Move::PawnPromotion(a_square, _) => {
+                    a_square == king_pos
+                }
+                _ => false,
+            })
+    })
+}
+
+// Switches for the selective parts of the search, so the effect of each one can be measured
+// on its own. Everything is on by default.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchConfig {
+    pub null_move_pruning: bool,
+    pub late_move_reductions: bool,
+    pub futility_pruning: bool,
+    pub reverse_futility_pruning: bool,
+    pub razoring: bool,
+    pub check_extensions: bool,
+    pub principal_variation_search: bool,
+    pub aspiration_windows: bool,
+    // Tablebases are probed in positions with at most this many pieces, kings included.
+    pub syzygy_probe_limit: usize,
+    // Count wins and losses that the fifty-move rule turns into draws as draws.
+    pub syzygy_50_move_rule: bool,
+}
+
+impl SearchConfig {
+    // Plain alpha-beta, with every pruning, reduction and extension turned off.
+    pub fn plain() -> Self {
+        SearchConfig {
+            syzygy_probe_limit: syzygy::MAX_PIECES,
+            syzygy_50_move_rule: true,
+            null_move_pruning: false,
+            late_move_reductions: false,
+            futility_pruning: false,
+            reverse_futility_pruning: false,
+            razoring: false,
+            check_extensions: false,
+            principal_variation_search: false,
+            aspiration_windows: false,
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            null_move_pruning: true,
+            late_move_reductions: true,
+            futility_pruning: true,
+            reverse_futility_pruning: true,
+            razoring: true,
+            check_extensions: true,
+            principal_variation_search: true,
+            aspiration_windows: true,
+            syzygy_probe_limit: syzygy::MAX_PIECES,
+            syzygy_50_move_rule: true,
+        }
+    }
+}
+
+// What the search should stop at. Limits left as None are unlimited, and with nothing set the
+// search goes on until it's stopped through the stop handle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    // Search for exactly this long.
+    pub move_time: Option<Duration>,
+    // Stop as soon as a mate in this many moves (or fewer) is found.
+    pub mate: Option<u32>,
+    // Our clock, for the time manager to decide how long to think.
+    pub time_control: Option<TimeControl>,
+    // Ignore every other limit, only the stop handle ends the search.
+    pub infinite: bool,
+}
+
+impl SearchLimits {
+    pub fn depth(depth: u8) -> Self {
+        SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+}
+
+// Progress report, sent after every completed iteration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: i32,
+    pub seldepth: usize,
+    // Centipawns from the point of view of the side to move, see `mate_in` for mates.
+    pub score: i32,
+    pub nodes: u64,
+    pub nps: u64,
+    pub time: Duration,
+    pub pv: Vec<BoardMove>,
+    // How full the hash table is, in permille.
+    pub hashfull: u32,
+    // Positions looked up in the tablebases.
+    pub tb_hits: u64,
+}
+
+// Moves until mate if the score is a mate score: positive when the side to move mates,
+// negative when it gets mated.
+pub fn mate_in(score: i32) -> Option<i32> {
+    if score > MATE_BOUND {
+        Some((MATE_SCORE - score + 1) / 2)
+    } else if score < -MATE_BOUND {
+        Some(-(MATE_SCORE + score) / 2)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<BoardMove>,
+    // Score of the best move in centipawns, from the point of view of the side to move.
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub pv: Vec<BoardMove>,
+}
+
+// Margins (per remaining depth) for the pruning techniques.
+const REVERSE_FUTILITY_MARGIN: i32 = 80;
+const FUTILITY_MARGIN: i32 = 120;
+const RAZOR_MARGIN: i32 = 250;
+const ASPIRATION_WINDOW: i32 = 25;
+
+// Iterative deepening alpha-beta search. Keeps its hash table and move ordering tables between
+// searches, so it should be reused for a whole game.
+pub struct Searcher {
+    pub config: SearchConfig,
+    tt: TranspositionTable,
+    pawn_table: PawnTable,
+    eval_params: EvalParams,
+    // Neural network to evaluate with instead of the handcrafted evaluation, and its
+    // accumulators along the line being searched.
+    network: Option<Arc<Network>>,
+    nnue_stack: Option<NnueStack>,
+    tables: OrderingTables,
+    nodes: u64,
+    // Keys of the positions played before the root and along the line being searched,
+    // to spot repetitions.
+    history: Vec<u64>,
+    // Triangular principal variation table: pv[ply] holds the best line found from that ply.
+    pv: Box<[[Option<BoardMove>; MAX_PLY + 1]; MAX_PLY + 1]>,
+    pv_length: [usize; MAX_PLY + 1],
+    // Late move reductions, by depth and move number.
+    reductions: Box<[[i32; 64]; 64]>,
+    // Clock for the current search, if it's limited by time.
+    time: Option<TimeManager>,
+    node_limit: Option<u64>,
+    // Set from outside to abort the search.
+    stop: Arc<AtomicBool>,
+    // Set when a limit is hit or the search is stopped, every node then returns straight away.
+    stopped: bool,
+    start: Instant,
+    // Deepest ply reached in the current search, quiescence included.
+    seldepth: usize,
+    tablebases: Option<Tablebases>,
+    tb_hits: u64,
+    // The root moves that keep the tablebase result, when the root position is in them.
+    root_moves: Option<Vec<BoardMove>>,
+    // Probe the tablebases below the root. Not needed when the root moves were already
+    // filtered with the DTZ tables.
+    probe_in_search: bool,
+}
+
+// Size of the pawn hash table. Pawn structures repeat a lot, so it can stay small.
+const PAWN_TABLE_MEGABYTES: usize = 2;
+// How many nodes are searched between looks at the clock and the stop handle.
+const NODES_BETWEEN_TIME_CHECKS: u64 = 1024;
+// A score drop (from one iteration to the next) that counts as a fail-low for time management.
+const SCORE_DROP_FOR_FAIL_LOW: i32 = 30;
+
+impl Searcher {
+    pub fn new(config: SearchConfig, hash_megabytes: usize) -> Self {
+        let mut reductions = Box::new([[0; 64]; 64]);
+        for (depth, row) in reductions.iter_mut().enumerate().skip(1) {
+            for (move_number, reduction) in row.iter_mut().enumerate().skip(1) {
+                *reduction = (0.75 + (depth as f64).ln() * (move_number as f64).ln() / 2.25) as i32;
+            }
+        }
+        Searcher {
+            config,
+            tt: TranspositionTable::new(hash_megabytes),
+            pawn_table: PawnTable::new(PAWN_TABLE_MEGABYTES),
+            eval_params: EvalParams::default(),
+            network: None,
+            nnue_stack: None,
+            tables: OrderingTables::new(),
+            nodes: 0,
+            history: Vec::new(),
+            pv: Box::new([[None; MAX_PLY + 1]; MAX_PLY + 1]),
+            pv_length: [0; MAX_PLY + 1],
+            reductions,
+            time: None,
+            node_limit: None,
+            stop: Arc::new(AtomicBool::new(false)),
+            stopped: false,
+            start: Instant::now(),
+            seldepth: 0,
+            tablebases: None,
+            tb_hits: 0,
+            root_moves: None,
+            probe_in_search: false,
+        }
+    }
+
+    // Resize the hash table, which also empties it.
+    pub fn set_hash_size(&mut self, megabytes: usize) {
+        self.tt = TranspositionTable::new(megabytes);
+    }
+
+    // Weights for the evaluation. The pawn hash table was filled with the old ones, so it's
+    // emptied.
+    pub fn set_eval_params(&mut self, params: EvalParams) {
+        self.eval_params = params;
+        self.pawn_table.clear();
+    }
+
+    pub fn eval_params(&self) -> &EvalParams {
+        &self.eval_params
+    }
+
+    // Evaluate with a neural network, or with the handcrafted evaluation when it's None.
+    pub fn set_network(&mut self, network: Option<Arc<Network>>) {
+        self.nnue_stack = network
+            .as_ref()
+            .map(|network| NnueStack::new(network, MAX_PLY + 1));
+        self.network = network;
+    }
+
+    // Static evaluation of a node of the search, which has been entered (so the network has
+    // the move that led to it).
+    fn static_eval(&mut self, board: &Board, ply: usize) -> i32 {
+        match (&self.network, &mut self.nnue_stack) {
+            (Some(network), Some(stack)) => stack.evaluate(network, ply, board.side_to_move()),
+            _ => eval::evaluate_cached(board, &self.eval_params, &mut self.pawn_table),
+        }
+    }
+
+    // Syzygy tablebases to probe, at the root and during the search.
+    pub fn set_tablebases(&mut self, tablebases: Option<Tablebases>) {
+        self.tablebases = tablebases;
+    }
+
+    // Forget everything learnt, for a new game.
+    pub fn new_game(&mut self) {
+        self.tt.clear();
+        self.pawn_table.clear();
+        self.tables.clear();
+        self.history.clear();
+    }
+
+    // Zobrist keys of the positions played so far in the game, oldest first and without the
+    // current one, so the search can see repetitions.
+    pub fn set_game_history(&mut self, keys: Vec<u64>) {
+        self.history = keys;
+    }
+
+    // Search the position to the given depth, one iteration at a time.
+    pub fn search(&mut self, board: &Board, depth: u8) -> SearchResult {
+        self.search_with_limits(board, &SearchLimits::depth(depth), |_| ())
+    }
+
+    // Handle that aborts the running search when set to true. It's cleared again once the
+    // search has stopped, so it can be set from another thread at any time.
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    // Iterative deepening until one of the limits is reached, the stop handle is set, or
+    // there's nothing left to search. `on_info` gets called after every completed iteration.
+    // An iteration that gets cut short is thrown away.
+    pub fn search_with_limits(
+        &mut self,
+        board: &Board,
+        limits: &SearchLimits,
+        mut on_info: impl FnMut(&SearchInfo),
+    ) -> SearchResult {
+        self.nodes = 0;
+        self.seldepth = 0;
+        self.stopped = false;
+        self.start = Instant::now();
+        self.tb_hits = 0;
+        self.filter_root_moves(board);
+        if let (Some(network), Some(stack)) = (&self.network, &mut self.nnue_stack) {
+            stack.set_root(network, board);
+        }
+        self.tables.killers.clear();
+        self.tables.history.age();
+        let max_depth = if limits.infinite {
+            self.time = None;
+            self.node_limit = None;
+            MAX_PLY as i32 - 1
+        } else {
+            self.time = limits
+                .move_time
+                .map(TimeManager::fixed)
+                .or(limits.time_control.map(TimeManager::new));
+            self.node_limit = limits.nodes;
+            limits.depth.map_or(MAX_PLY as i32 - 1, |depth| {
+                (depth as i32).clamp(1, MAX_PLY as i32 - 1)
+            })
+        };
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            pv: Vec::new(),
+        };
+        for current_depth in 1..=max_depth {
+            let score = self.aspiration_search(board, current_depth, result.score);
+            if self.stopped {
+                break;
+            }
+            let pv: Vec<BoardMove> = self.pv[0][..self.pv_length[0]]
+                .iter()
+                .map_while(|item| *item)
+                .collect();
+            if let Some(time) = &mut self.time {
+                if current_depth > 1 && score < result.score - SCORE_DROP_FOR_FAIL_LOW {
+                    time.on_fail_low();
+                }
+                time.on_iteration_end(current_depth > 1 && pv.first() != result.pv.first());
+            }
+            result.score = score;
+            result.depth = current_depth;
+            result.best_move = pv.first().copied();
+            result.pv = pv;
+
+            let elapsed = self.start.elapsed();
+            on_info(&SearchInfo {
+                depth: current_depth,
+                seldepth: self.seldepth,
+                score,
+                nodes: self.nodes,
+                nps: (self.nodes as u128 * 1000 / elapsed.as_millis().max(1)) as u64,
+                time: elapsed,
+                pv: result.pv.clone(),
+                hashfull: self.tt.hashfull(),
+                tb_hits: self.tb_hits,
+            });
+
+            if let Some(mate) = limits.mate {
+                if !limits.infinite
+                    && mate_in(score).is_some_and(|moves| moves > 0 && moves as u32 <= mate)
+                {
+                    break;
+                }
+            }
+            if let Some(time) = &self.time {
+                if !time.should_start_iteration() {
+                    break;
+                }
+            }
+        }
+        if result.best_move.is_none() {
+            // Stopped during the first iteration: a move that already raised alpha at the
+            // root still beats a random one.
+            result.best_move = if self.pv_length[0] > 0 {
+                self.pv[0][0]
+            } else if let Some(root_moves) = &self.root_moves {
+                root_moves.first().copied()
+            } else {
+                board.legal_moves().first().copied()
+            };
+        }
+        result.nodes = self.nodes;
+        self.time = None;
+        self.node_limit = None;
+        self.stop.store(false, Ordering::Relaxed);
+        result
+    }
+
+    // When the root position is in the tablebases, only search the moves that keep its result.
+    // With DTZ tables that's all it takes to win a won position; with WDL tables alone the
+    // search still has to find the way, probing the tables below the root.
+    fn filter_root_moves(&mut self, board: &Board) {
+        self.root_moves = None;
+        self.probe_in_search = self.tablebases.is_some();
+        let Some(tablebases) = &mut self.tablebases else {
+            return;
+        };
+        let pieces = board.board.iter().flatten().count();
+        if pieces > self.config.syzygy_probe_limit.min(tablebases.max_pieces()) {
+            return;
+        }
+        // Any repetition since the last capture or pawn move makes a quick win less certain.
+        let key = zobrist::hash(board);
+        let repeated = self
+            .history
+            .iter()
+            .rev()
+            .take(board.halfmove_clock as usize)
+            .any(|old_key| *old_key == key);
+        let use_rule50 = self.config.syzygy_50_move_rule;
+        let ranked = match tablebases.rank_root_moves(board, repeated, use_rule50) {
+            Some(ranked) => Some((ranked, true)),
+            None => tablebases
+                .rank_root_moves_wdl(board, use_rule50)
+                .map(|ranked| (ranked, false)),
+        };
+        let Some((ranked, with_dtz)) = ranked else {
+            return;
+        };
+        self.tb_hits += ranked.len() as u64;
+        let best_rank = ranked.iter().map(|(_, rank)| *rank).max().unwrap_or(0);
+        self.root_moves = Some(
+            ranked
+                .into_iter()
+                .filter(|(_, rank)| *rank == best_rank)
+                .map(|(board_move, _)| board_move)
+                .collect(),
+        );
+        self.probe_in_search = !with_dtz && best_rank > 0;
+    }
+
+    // Win, draw or loss from the tablebases, as a score and the kind of bound it is. Cursed
+    // wins and blessed losses score just off the draw when the fifty-move rule counts.
+    fn probe_wdl(&mut self, board: &Board, ply: usize) -> Option<(i32, Bound)> {
+        let tablebases = self.tablebases.as_mut()?;
+        let wdl = tablebases.probe_wdl(board)?;
+        self.tb_hits += 1;
+        let draw_score = i32::from(self.config.syzygy_50_move_rule);
+        let value = wdl.value();
+        Some(if value < -draw_score {
+            (-TB_WIN_SCORE + ply as i32, Bound::Upper)
+        } else if value > draw_score {
+            (TB_WIN_SCORE - ply as i32, Bound::Lower)
+        } else {
+            (2 * value * draw_score, Bound::Exact)
+        })
+    }
+
+    // Stop the search if the node limit is reached, and every few nodes look at the stop
+    // handle and the clock.
+    fn check_limits(&mut self) {
+        if self.node_limit.is_some_and(|limit| self.nodes >= limit) {
+            self.stopped = true;
+        }
+        if self.nodes.is_multiple_of(NODES_BETWEEN_TIME_CHECKS) {
+            if self.stop.load(Ordering::Relaxed) {
+                self.stopped = true;
+            }
+            if let Some(time) = &self.time {
+                if time.is_hard_limit_reached() {
+                    self.stopped = true;
+                }
+            }
+        }
+    }
+
+    // Search with a small window around the last iteration's score, widening it when the
+    // score falls outside.
+    fn aspiration_search(&mut self, board: &Board, depth: i32, previous_score: i32) -> i32 {
+        if !self.config.aspiration_windows || depth < 4 || previous_score.abs() >= MATE_BOUND {
+            return self.negamax(board, depth, -MATE_SCORE, MATE_SCORE, 0, None, false);
+        }
+        let mut delta = ASPIRATION_WINDOW;
+        let mut alpha = previous_score - delta;
+        let mut beta = previous_score + delta;
+        loop {
+            let score = self.negamax(board, depth, alpha, beta, 0, None, false);
+            if self.stopped {
+                return score;
+            }
+            if score <= alpha {
+                if let Some(time) = &mut self.time {
+                    time.on_fail_low();
+                }
+                alpha = (alpha - delta).max(-MATE_SCORE);
+            } else if score >= beta {
+                beta = (beta + delta).min(MATE_SCORE);
+            } else {
+                return score;
+            }
+            delta *= 2;
+        }
+    }
+
+    // Has the position (with the same side to move) been seen before, in the game or the line?
+    fn is_repetition(&self, key: u64) -> bool {
+        self.history
+            .iter()
+            .rev()
+            .skip(1)
+            .step_by(2)
+            .any(|old_key| *old_key == key)
+    }
+
+    // Copy the child's principal variation behind the move that was just found to be best.
+    fn update_pv(&mut self, ply: usize, board_move: BoardMove) {
+        self.pv[ply][ply] = Some(board_move);
+        for next_ply in ply + 1..self.pv_length[ply + 1] {
+            self.pv[ply][next_ply] = self.pv[ply + 1][next_ply];
+        }
+        self.pv_length[ply] = self.pv_length[ply + 1].max(ply + 1);
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: &Board,
+        mut depth: i32,
+        mut alpha: i32,
+        beta: i32,
+        ply: usize,
+        previous_move: Option<BoardMove>,
+        allow_null_move: bool,
+    ) -> i32 {
+        let color = board.side_to_move();
+        self.pv_length[ply] = ply;
+        self.seldepth = self.seldepth.max(ply);
+        let in_check = board.is_in_check(color);
+        if in_check && self.config.check_extensions {
+            depth += 1;
+        }
+        if depth <= 0 {
+            return self.quiescence(board, alpha, beta, ply);
+        }
+        self.nodes += 1;
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+        let key = zobrist::hash(board);
+        if ply > 0 && (self.is_repetition(key) || board.halfmove_clock >= 100) {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.static_eval(board, ply);
+        }
+        let pv_node = beta - alpha > 1;
+
+        let mut hash_move = None;
+        if let Some(entry) = self.tt.probe(key) {
+            hash_move = entry.best_move;
+            let score = score_from_tt(entry.score, ply);
+            let usable = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+            if !pv_node && entry.depth >= depth && usable {
+                return score;
+            }
+        }
+
+        // Tables only know positions right after a capture or pawn move, the fifty-move
+        // counter changes the result otherwise.
+        let pieces = board.board.iter().flatten().count();
+        if ply > 0
+            && self.probe_in_search
+            && pieces <= self.config.syzygy_probe_limit
+            && board.halfmove_clock == 0
+            && !syzygy::has_castling_rights(board)
+        {
+            if let Some((score, bound)) = self.probe_wdl(board, ply) {
+                let cutoff = match bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    self.tt.store(
+                        key,
+                        None,
+                        score_to_tt(score, ply),
+                        (depth + 6).min(MAX_PLY as i32 - 1),
+                        bound,
+                    );
+                    return score;
+                }
+            }
+        }
+
+        let static_eval = if in_check {
+            -MATE_SCORE
+        } else {
+            self.static_eval(board, ply)
+        };
+        if !pv_node && !in_check {
+            // Reverse futility: so far above beta that the opponent won't get back in time.
+            if self.config.reverse_futility_pruning
+                && depth <= 6
+                && beta.abs() < MATE_BOUND
+                && static_eval - REVERSE_FUTILITY_MARGIN * depth >= beta
+            {
+                return static_eval;
+            }
+            // Razoring: hopelessly below alpha, see if any capture saves us before giving up.
+            if self.config.razoring && depth <= 2 && static_eval + RAZOR_MARGIN * depth < alpha {
+                let score = self.quiescence(board, alpha, alpha + 1, ply);
+                if score <= alpha {
+                    return score;
+                }
+            }
+            // Null move: if passing still beats beta, a real move will too. Skipped with only
+            // pawns left, where zugzwang makes passing better than any move, and right after
+            // another null move.
+            if self.config.null_move_pruning
+                && allow_null_move
+                && depth >= 3
+                && static_eval >= beta
+                && has_non_pawn_material(board, color)
+            {
+                let reduction = 3 + depth / 6;
+                let null_board = board.make_null_move();
+                if let Some(stack) = &mut self.nnue_stack {
+                    stack.push(ply + 1, PieceChanges::default());
+                }
+                self.history.push(key);
+                let score = -self.negamax(
+                    &null_board,
+                    depth - 1 - reduction,
+                    -beta,
+                    -beta + 1,
+                    ply + 1,
+                    None,
+                    false,
+                );
+                self.history.pop();
+                if self.stopped {
+                    return 0;
+                }
+                if score >= beta {
+                    // Mates found after passing aren't real.
+                    return if score >= MATE_BOUND { beta } else { score };
+                }
+            }
+        }
+        // Futility: near the leaves, quiet moves won't bring a position this bad up to alpha.
+        let futile = self.config.futility_pruning
+            && !pv_node
+            && !in_check
+            && depth <= 3
+            && alpha.abs() < MATE_BOUND
+            && static_eval + FUTILITY_MARGIN * depth <= alpha;
+
+        let original_alpha = alpha;
+        let mut best_score = -MATE_SCORE;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+        let mut tried_quiets = Vec::new();
+        let mut picker = MovePicker::new(board, hash_move, ply, previous_move, &self.tables);
+        self.history.push(key);
+        while let Some(board_move) = picker.next(&self.tables) {
+            if ply == 0
+                && self
+                    .root_moves
+                    .as_ref()
+                    .is_some_and(|root_moves| !root_moves.contains(&board_move))
+            {
+                continue;
+            }
+            let new_board = match board.make_legal_move(board_move.0, board_move.1) {
+                Some(new_board) => new_board,
+                None => continue,
+            };
+            if let Some(stack) = &mut self.nnue_stack {
+                stack.push(ply + 1, board.piece_changes(board_move.0, board_move.1));
+            }
+            legal_moves += 1;
+            let is_quiet = !board.is_noisy(board_move.1);
+            let gives_check = new_board.is_in_check(color.reverse());
+            if futile && is_quiet && !gives_check && legal_moves > 1 {
+                continue;
+            }
+
+            let score = if legal_moves == 1 {
+                -self.negamax(
+                    &new_board,
+                    depth - 1,
+                    -beta,
+                    -alpha,
+                    ply + 1,
+                    Some(board_move),
+                    true,
+                )
+            } else {
+                // Late quiet moves are probably bad, search them shallower first.
+                let reduction = if self.config.late_move_reductions
+                    && depth >= 3
+                    && legal_moves > 3
+                    && is_quiet
+                    && !in_check
+                    && !gives_check
                 {
-                    itertools::FoldWhile::Done(true)
+                    (self.reductions[depth.min(63) as usize][legal_moves.min(63)] - pv_node as i32)
+                        .clamp(0, depth - 2)
+                } else {
+                    0
+                };
+                // With PVS, only try to prove the move is worse than the best one so far.
+                let search_beta = if self.config.principal_variation_search {
+                    alpha + 1
                 } else {
-                    itertools::FoldWhile::Continue(false)
+                    beta
+                };
+                let mut score = -self.negamax(
+                    &new_board,
+                    depth - 1 - reduction,
+                    -search_beta,
+                    -alpha,
+                    ply + 1,
+                    Some(board_move),
+                    true,
+                );
+                if reduction > 0 && score > alpha {
+                    score = -self.negamax(
+                        &new_board,
+                        depth - 1,
+                        -search_beta,
+                        -alpha,
+                        ply + 1,
+                        Some(board_move),
+                        true,
+                    );
+                }
+                if search_beta != beta && score > alpha && score < beta {
+                    score = -self.negamax(
+                        &new_board,
+                        depth - 1,
+                        -beta,
+                        -alpha,
+                        ply + 1,
+                        Some(board_move),
+                        true,
+                    );
+                }
+                score
+            };
+            if self.stopped {
+                break;
+            }
+
+            if is_quiet {
+                tried_quiets.push(board_move);
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(board_move);
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, board_move);
+                    if score >= beta {
+                        if is_quiet {
+                            self.tables.update_quiet_cutoff(
+                                color,
+                                ply,
+                                depth,
+                                board_move,
+                                previous_move,
+                                &tried_quiets,
+                            );
+                        }
+                        break;
+                    }
                 }
+            }
+        }
+        self.history.pop();
+        if self.stopped {
+            return 0;
+        }
+
+        if legal_moves == 0 {
+            return if in_check {
+                -MATE_SCORE + ply as i32
             } else {
-                itertools::FoldWhile::Done(false)
+                0
+            };
+        }
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if alpha > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt
+            .store(key, best_move, score_to_tt(best_score, ply), depth, bound);
+        best_score
+    }
+
+    // Only captures and promotions, until the position is quiet enough to trust the static
+    // evaluation. When in check every evasion is searched, standing pat isn't an option then.
+    fn quiescence(&mut self, board: &Board, mut alpha: i32, beta: i32, ply: usize) -> i32 {
+        self.nodes += 1;
+        self.pv_length[ply] = ply;
+        self.seldepth = self.seldepth.max(ply);
+        self.check_limits();
+        if self.stopped {
+            return 0;
+        }
+        if ply >= MAX_PLY - 1 {
+            return self.static_eval(board, ply);
+        }
+        let in_check = board.is_in_check(board.side_to_move());
+        let mut best_score;
+        let mut picker = if in_check {
+            best_score = -MATE_SCORE + ply as i32;
+            MovePicker::new(board, None, ply, None, &self.tables)
+        } else {
+            let stand_pat = self.static_eval(board, ply);
+            if stand_pat >= beta {
+                return stand_pat;
             }
-        })
-        .into_inner()
+            alpha = alpha.max(stand_pat);
+            best_score = stand_pat;
+            MovePicker::noisy(board)
+        };
+        while let Some(board_move) = picker.next(&self.tables) {
+            let new_board = match board.make_legal_move(board_move.0, board_move.1) {
+                Some(new_board) => new_board,
+                None => continue,
+            };
+            if let Some(stack) = &mut self.nnue_stack {
+                stack.push(ply + 1, board.piece_changes(board_move.0, board_move.1));
+            }
+            let score = -self.quiescence(&new_board, -beta, -alpha, ply + 1);
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, board_move);
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+        best_score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MATE_IN_TWO: &str = "k7/8/2K5/8/8/8/8/7R w - - 0 1";
+    const OPENING: &str = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3";
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    // Plain alpha-beta with one technique turned on.
+    fn with(switch: impl Fn(&mut SearchConfig)) -> SearchConfig {
+        let mut config = SearchConfig::plain();
+        switch(&mut config);
+        config
+    }
+
+    #[test]
+    fn each_technique_still_finds_the_mate() {
+        let configs = [
+            SearchConfig::plain(),
+            with(|config| config.null_move_pruning = true),
+            with(|config| config.late_move_reductions = true),
+            with(|config| config.futility_pruning = true),
+            with(|config| config.reverse_futility_pruning = true),
+            with(|config| config.razoring = true),
+            with(|config| config.check_extensions = true),
+            with(|config| config.principal_variation_search = true),
+            with(|config| config.aspiration_windows = true),
+            SearchConfig::default(),
+        ];
+        let board = Board::from_fen(MATE_IN_TWO);
+        for config in configs {
+            let result = Searcher::new(config, 1).search(&board, 5);
+            assert_eq!(mate_in(result.score), Some(2), "{config:?}");
+        }
+    }
+
+    #[test]
+    fn pvs_and_aspiration_windows_keep_the_score() {
+        let pvs = with(|config| config.principal_variation_search = true);
+        let aspiration = with(|config| config.aspiration_windows = true);
+        for (fen, max_depth) in [(OPENING, 4), (KIWIPETE, 3)] {
+            let board = Board::from_fen(fen);
+            for depth in 1..=max_depth {
+                let plain = Searcher::new(SearchConfig::plain(), 1).search(&board, depth);
+                for config in [pvs, aspiration] {
+                    let result = Searcher::new(config, 1).search(&board, depth);
+                    assert_eq!(result.score, plain.score, "{fen} at depth {depth}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pruning_searches_fewer_nodes() {
+        for fen in [OPENING, KIWIPETE] {
+            let board = Board::from_fen(fen);
+            let plain = Searcher::new(SearchConfig::plain(), 1).search(&board, 4);
+            let pruned = Searcher::new(SearchConfig::default(), 1).search(&board, 4);
+            assert!(pruned.nodes < plain.nodes, "{fen}");
+            assert!(pruned.best_move.is_some());
+        }
+    }
+
+    #[test]
+    fn the_node_limit_stops_the_search() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let limits = SearchLimits {
+            nodes: Some(5000),
+            ..Default::default()
+        };
+        let result = searcher.search_with_limits(&Board::from_fen(KIWIPETE), &limits, |_| ());
+        assert!(result.nodes <= 5000);
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn the_stop_handle_ends_an_infinite_search() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let stop = searcher.stop_handle();
+        let stopper = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            stop.store(true, Ordering::Relaxed);
+        });
+        let limits = SearchLimits {
+            depth: Some(1),
+            infinite: true,
+            ..Default::default()
+        };
+        let result = searcher.search_with_limits(&Board::new(), &limits, |_| ());
+        stopper.join().unwrap();
+        // The depth is ignored, only the handle ended it.
+        assert!(result.depth > 1);
+        assert!(result.best_move.is_some());
+        // The handle is cleared for the next search.
+        assert!(!searcher.stop_handle().load(Ordering::Relaxed));
+        assert_eq!(searcher.search(&Board::new(), 2).depth, 2);
+    }
+
+    #[test]
+    fn move_time_is_kept_to() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let limits = SearchLimits {
+            move_time: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let result = searcher.search_with_limits(&Board::from_fen(KIWIPETE), &limits, |_| ());
+        assert!(start.elapsed() < Duration::from_millis(600));
+        assert!(result.best_move.is_some());
+    }
+
+    #[test]
+    fn the_mate_limit_stops_at_the_mate() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let limits = SearchLimits {
+            mate: Some(2),
+            ..Default::default()
+        };
+        let result = searcher.search_with_limits(&Board::from_fen(MATE_IN_TWO), &limits, |_| ());
+        assert_eq!(mate_in(result.score), Some(2));
+        assert!(result.depth < 10);
+    }
+
+    #[test]
+    fn info_comes_after_every_iteration() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let mut infos = Vec::new();
+        let result = searcher.search_with_limits(
+            &Board::from_fen(OPENING),
+            &SearchLimits::depth(5),
+            |info| infos.push(info.clone()),
+        );
+        let depths: Vec<i32> = infos.iter().map(|info| info.depth).collect();
+        assert_eq!(depths, [1, 2, 3, 4, 5]);
+        assert!(infos.windows(2).all(|pair| pair[0].nodes <= pair[1].nodes));
+        assert!(infos
+            .iter()
+            .all(|info| !info.pv.is_empty() && info.seldepth >= info.depth as usize));
+        let last = infos.last().unwrap();
+        assert_eq!(last.score, result.score);
+        assert_eq!(last.pv, result.pv);
+        assert_eq!(last.pv.first().copied(), result.best_move);
+    }
 }
@@ -0,0 +1,221 @@
+use std::fmt;
+
+// Match statistics: the Elo difference with its error bars from wins, draws and losses, and
+// the sequential probability ratio test that says when a match has been played long enough.
+// Both use the normal approximation of the score per game, which is fine for the hundreds or
+// thousands of games these matches run for.
+
+// Width of the error bars, in standard deviations: 95% of the mass of a normal distribution.
+const CONFIDENCE_SIGMAS: f64 = 1.96;
+
+// Wins, draws and losses of the first player of a match.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl MatchScore {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    // Points per game, from 0 to 1.
+    pub fn score(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Variance of the points of one game.
+    fn variance(&self) -> f64 {
+        if self.games() == 0 {
+            return 0.0;
+        }
+        let games = self.games() as f64;
+        let score = self.score();
+        let (wins, draws, losses) = (
+            self.wins as f64 / games,
+            self.draws as f64 / games,
+            self.losses as f64 / games,
+        );
+        wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2)
+    }
+
+    // Elo difference and the half width of its 95% confidence interval. The interval is
+    // worked out on the score and converted, so it isn't symmetric in Elo; the larger half
+    // is given.
+    pub fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let deviation = (self.variance() / self.games().max(1) as f64).sqrt();
+        let low = elo_from_score(score - CONFIDENCE_SIGMAS * deviation);
+        let high = elo_from_score(score + CONFIDENCE_SIGMAS * deviation);
+        let elo = elo_from_score(score);
+        (elo, (high - elo).max(elo - low))
+    }
+}
+
+impl fmt::Display for MatchScore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (elo, error) = self.elo();
+        write!(
+            f,
+            "{} games, W {} D {} L {}, score {:.1}%, Elo {elo:+.1} +/- {error:.1}",
+            self.games(),
+            self.wins,
+            self.draws,
+            self.losses,
+            self.score() * 100.0
+        )
+    }
+}
+
+// Elo difference of a player expected to score this much per game. Kept finite for scores
+// of 0 and 1.
+pub fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+pub fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Outcome of the test so far.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SprtStatus {
+    Continue,
+    // The change is no better than elo0.
+    AcceptH0,
+    // The change is at least elo1 better.
+    AcceptH1,
+}
+
+// Tests the hypothesis H0 "the first player is elo0 stronger" against H1 "the first player is
+// elo1 stronger", with at most `alpha` chance of accepting H1 when H0 holds and `beta` of
+// accepting H0 when H1 holds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Sprt {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+impl Sprt {
+    // The log-likelihood ratio the test stops at: below the first H0 is accepted, above the
+    // second H1.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Log-likelihood ratio of H1 against H0 for the games so far. Zero until both a win and
+    // a loss (or a draw) have been seen, since the variance can't be estimated before.
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        let variance = score.variance();
+        if variance <= 0.0 {
+            return 0.0;
+        }
+        let (score0, score1) = (score_from_elo(self.elo0), score_from_elo(self.elo1));
+        score.games() as f64 * (score1 - score0) * (2.0 * score.score() - score0 - score1)
+            / (2.0 * variance)
+    }
+
+    pub fn status(&self, score: &MatchScore) -> SprtStatus {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtStatus::AcceptH0
+        } else if llr >= upper {
+            SprtStatus::AcceptH1
+        } else {
+            SprtStatus::Continue
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: u32, draws: u32, losses: u32) -> MatchScore {
+        MatchScore {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    #[test]
+    fn elo_and_score_convert_both_ways() {
+        assert_eq!(elo_from_score(0.5), 0.0);
+        assert!((elo_from_score(0.75) - 190.85).abs() < 0.01);
+        assert!((elo_from_score(0.25) + 190.85).abs() < 0.01);
+        for elo in [-400.0, -35.5, 0.0, 12.0, 800.0] {
+            assert!((elo_from_score(score_from_elo(elo)) - elo).abs() < 1e-9);
+        }
+        assert!(elo_from_score(1.0).is_finite());
+        assert!(elo_from_score(0.0).is_finite());
+    }
+
+    #[test]
+    fn error_bars_shrink_with_more_games() {
+        assert_eq!(MatchScore::default().elo(), (0.0, 0.0));
+        let (elo, error) = score(30, 40, 30).elo();
+        assert_eq!(elo, 0.0);
+        let (more_elo, more_error) = score(300, 400, 300).elo();
+        assert_eq!(more_elo, 0.0);
+        assert!(more_error < error / 3.0);
+        // Fewer draws give a wider spread of results.
+        let (_, decisive_error) = score(50, 0, 50).elo();
+        assert!(decisive_error > error);
+        let (elo, _) = score(60, 20, 20).elo();
+        assert!((elo - elo_from_score(0.7)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn scores_are_shown_with_their_elo() {
+        assert_eq!(
+            score(6, 2, 2).to_string(),
+            format!(
+                "10 games, W 6 D 2 L 2, score 70.0%, Elo +{:.1} +/- {:.1}",
+                score(6, 2, 2).elo().0,
+                score(6, 2, 2).elo().1
+            )
+        );
+    }
+
+    #[test]
+    fn sprt_stops_when_the_evidence_is_strong_enough() {
+        let sprt = Sprt::default();
+        let (lower, upper) = sprt.bounds();
+        assert!((lower + 2.944).abs() < 0.001);
+        assert!((upper - 2.944).abs() < 0.001);
+
+        // No variance yet, nothing to go on.
+        assert_eq!(sprt.llr(&score(5, 0, 0)), 0.0);
+        assert_eq!(sprt.status(&score(5, 0, 0)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&score(12, 10, 8)), SprtStatus::Continue);
+        assert_eq!(sprt.status(&score(1200, 1000, 800)), SprtStatus::AcceptH1);
+        assert_eq!(sprt.status(&score(800, 1000, 1200)), SprtStatus::AcceptH0);
+        // Dead even settles for H0 in the end, since H1 wants a gain.
+        assert_eq!(sprt.status(&score(6000, 8000, 6000)), SprtStatus::AcceptH0);
+        assert!(sprt.llr(&score(110, 100, 90)) > sprt.llr(&score(100, 100, 100)));
+    }
+}
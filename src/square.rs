@@ -0,0 +1,361 @@
+use crate::{DOWN, DOWN_LEFT, DOWN_RIGHT, LEFT, RIGHT, UP, UP_LEFT, UP_RIGHT};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Typed squares, files and ranks of the 8x8 board. A Square wraps the index used by the board
+// array (a1 is 0, h1 is 7, h8 is 63), so converting back and forth costs nothing.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct File(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rank(u8);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Square(u8);
+
+// What went wrong reading a square, file or rank.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseSquareError {
+    pub input: String,
+}
+
+impl fmt::Display for ParseSquareError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "not a square: \"{}\"", self.input)
+    }
+}
+
+impl Error for ParseSquareError {}
+
+fn parse_error(input: &str) -> ParseSquareError {
+    ParseSquareError {
+        input: input.to_string(),
+    }
+}
+
+impl File {
+    // From 0 (a) to 7 (h).
+    pub const fn new(index: u8) -> Option<File> {
+        if index < 8 {
+            Some(File(index))
+        } else {
+            None
+        }
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    pub fn from_char(letter: char) -> Option<File> {
+        ('a'..='h')
+            .contains(&letter)
+            .then(|| File(letter as u8 - b'a'))
+    }
+
+    pub fn to_char(self) -> char {
+        (b'a' + self.0) as char
+    }
+
+    // The file `files` to the right, if there is one.
+    pub fn offset(self, files: i8) -> Option<File> {
+        (self.0 as i8)
+            .checked_add(files)
+            .and_then(|file| u8::try_from(file).ok())
+            .and_then(File::new)
+    }
+
+    // The file on the other side of the board: the h-file for the a-file.
+    pub const fn mirror(self) -> File {
+        File(7 - self.0)
+    }
+
+    pub fn all() -> impl Iterator<Item = File> {
+        (0..8).map(File)
+    }
+}
+
+impl Rank {
+    // From 0 (the first rank) to 7 (the eighth).
+    pub const fn new(index: u8) -> Option<Rank> {
+        if index < 8 {
+            Some(Rank(index))
+        } else {
+            None
+        }
+    }
+
+    pub const fn index(self) -> u8 {
+        self.0
+    }
+
+    pub fn from_char(digit: char) -> Option<Rank> {
+        ('1'..='8')
+            .contains(&digit)
+            .then(|| Rank(digit as u8 - b'1'))
+    }
+
+    pub fn to_char(self) -> char {
+        (b'1' + self.0) as char
+    }
+
+    // The rank `ranks` further up, if there is one.
+    pub fn offset(self, ranks: i8) -> Option<Rank> {
+        (self.0 as i8)
+            .checked_add(ranks)
+            .and_then(|rank| u8::try_from(rank).ok())
+            .and_then(Rank::new)
+    }
+
+    // The same rank seen from the other side: the first rank for the eighth.
+    pub const fn mirror(self) -> Rank {
+        Rank(7 - self.0)
+    }
+
+    pub fn all() -> impl Iterator<Item = Rank> {
+        (0..8).map(Rank)
+    }
+}
+
+impl Square {
+    // From 0 (a1) to 63 (h8).
+    pub const fn new(index: u8) -> Option<Square> {
+        if index < 64 {
+            Some(Square(index))
+        } else {
+            None
+        }
+    }
+
+    // For indices that are on the board by construction, like the ones in move lists.
+    pub(crate) const fn from_index(index: u8) -> Square {
+        debug_assert!(index < 64);
+        Square(index)
+    }
+
+    pub const fn from_file_rank(file: File, rank: Rank) -> Square {
+        Square(rank.0 * 8 + file.0)
+    }
+
+    // Index into the board array.
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub const fn file(self) -> File {
+        File(self.0 % 8)
+    }
+
+    pub const fn rank(self) -> Rank {
+        Rank(self.0 / 8)
+    }
+
+    // The square `files` to the right and `ranks` up, if it's on the board.
+    pub fn offset(self, files: i8, ranks: i8) -> Option<Square> {
+        Some(Square::from_file_rank(
+            self.file().offset(files)?,
+            self.rank().offset(ranks)?,
+        ))
+    }
+
+    // The square on the same file with the board turned around: e2 for e7.
+    pub const fn mirror(self) -> Square {
+        Square(self.0 ^ 56)
+    }
+
+    // The square on the same rank with the board turned sideways: d2 for e2.
+    pub const fn mirror_file(self) -> Square {
+        Square(self.0 ^ 7)
+    }
+
+    // Is it a dark square, like a1?
+    pub const fn is_dark(self) -> bool {
+        self.anti_diagonal().is_multiple_of(2)
+    }
+
+    // Moves a king would need to get from one square to the other.
+    pub fn distance(self, other: Square) -> u8 {
+        let files = self.file().0.abs_diff(other.file().0);
+        let ranks = self.rank().0.abs_diff(other.rank().0);
+        files.max(ranks)
+    }
+
+    // Which of the 15 diagonals (going up to the right) the square is on, from 0 (h1) to 14 (a8).
+    pub const fn diagonal(self) -> u8 {
+        7 + self.rank().0 - self.file().0
+    }
+
+    // Which of the 15 anti-diagonals (going up to the left) the square is on, from 0 (a1) to
+    // 14 (h8).
+    pub const fn anti_diagonal(self) -> u8 {
+        self.rank().0 + self.file().0
+    }
+
+    // Every square, from a1 to h8.
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..64).map(Square)
+    }
+
+    // The neighbouring squares, white's side being down. Looked up in the tables in lib.rs,
+    // where 64 stands for off the board.
+    fn neighbour(table: &[u8; 64], square: Square) -> Option<Square> {
+        let next = table[square.index()];
+        if next != 64 {
+            Some(Square(next))
+        } else {
+            None
+        }
+    }
+    pub fn up(self) -> Option<Square> {
+        Self::neighbour(&UP, self)
+    }
+    pub fn down(self) -> Option<Square> {
+        Self::neighbour(&DOWN, self)
+    }
+    pub fn left(self) -> Option<Square> {
+        Self::neighbour(&LEFT, self)
+    }
+    pub fn right(self) -> Option<Square> {
+        Self::neighbour(&RIGHT, self)
+    }
+    pub fn up_left(self) -> Option<Square> {
+        Self::neighbour(&UP_LEFT, self)
+    }
+    pub fn up_right(self) -> Option<Square> {
+        Self::neighbour(&UP_RIGHT, self)
+    }
+    pub fn down_left(self) -> Option<Square> {
+        Self::neighbour(&DOWN_LEFT, self)
+    }
+    pub fn down_right(self) -> Option<Square> {
+        Self::neighbour(&DOWN_RIGHT, self)
+    }
+}
+
+impl From<Square> for u8 {
+    fn from(square: Square) -> u8 {
+        square.0
+    }
+}
+
+impl From<Square> for usize {
+    fn from(square: Square) -> usize {
+        square.index()
+    }
+}
+
+impl fmt::Display for File {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.to_char())
+    }
+}
+
+impl fmt::Display for Square {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.file(), self.rank())
+    }
+}
+
+impl FromStr for File {
+    type Err = ParseSquareError;
+
+    fn from_str(text: &str) -> Result<File, ParseSquareError> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(letter), None) => File::from_char(letter).ok_or_else(|| parse_error(text)),
+            _ => Err(parse_error(text)),
+        }
+    }
+}
+
+impl FromStr for Rank {
+    type Err = ParseSquareError;
+
+    fn from_str(text: &str) -> Result<Rank, ParseSquareError> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next()) {
+            (Some(digit), None) => Rank::from_char(digit).ok_or_else(|| parse_error(text)),
+            _ => Err(parse_error(text)),
+        }
+    }
+}
+
+// "e4". Anything else, "z9", "e10" or "e", is an error.
+impl FromStr for Square {
+    type Err = ParseSquareError;
+
+    fn from_str(text: &str) -> Result<Square, ParseSquareError> {
+        let mut chars = text.chars();
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(letter), Some(digit), None) => {
+                match (File::from_char(letter), Rank::from_char(digit)) {
+                    (Some(file), Some(rank)) => Ok(Square::from_file_rank(file, rank)),
+                    _ => Err(parse_error(text)),
+                }
+            }
+            _ => Err(parse_error(text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_off_the_board_are_none() {
+        let h = File::new(7).unwrap();
+        let eighth = Rank::new(7).unwrap();
+        assert_eq!(h.offset(-7), File::new(0));
+        assert_eq!(h.offset(i8::MAX), None);
+        assert_eq!(File::new(0).unwrap().offset(i8::MIN), None);
+        assert_eq!(eighth.offset(i8::MAX), None);
+        assert_eq!(eighth.offset(-8), None);
+    }
+
+    #[test]
+    fn squares_read_back_what_they_write() {
+        for square in Square::all() {
+            assert_eq!(square.to_string().parse(), Ok(square));
+        }
+        assert_eq!("a1".parse(), Ok(Square::from_index(0)));
+        assert_eq!(Square::from_index(63).to_string(), "h8");
+        for file in File::all() {
+            assert_eq!(file.to_string().parse(), Ok(file));
+        }
+        for rank in Rank::all() {
+            assert_eq!(rank.to_string().parse(), Ok(rank));
+        }
+    }
+
+    #[test]
+    fn malformed_squares_are_errors() {
+        for text in ["z9", "e10", "e", "", "E4", "4e"] {
+            assert_eq!(
+                text.parse::<Square>(),
+                Err(ParseSquareError {
+                    input: text.to_string()
+                })
+            );
+        }
+        assert!("i".parse::<File>().is_err());
+        assert!("9".parse::<Rank>().is_err());
+    }
+
+    #[test]
+    fn mirrored_squares() {
+        let e2: Square = "e2".parse().unwrap();
+        assert_eq!(e2.mirror().to_string(), "e7");
+        assert_eq!(e2.mirror_file().to_string(), "d2");
+        assert!(Square::from_index(0).is_dark());
+        assert!(!e2.is_dark());
+    }
+}
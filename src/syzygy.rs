@@ -0,0 +1,1357 @@
+use crate::{fairy, Board, BoardMove, Color, Piece, PieceKind, Square};
+use fnv::FnvHashMap;
+use std::cmp::Ordering;
+use std::fs;
+use std::io;
+use std::ops::Neg;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+// Syzygy tablebases: WDL tables give win/draw/loss, DTZ tables the distance to the next capture
+// or pawn move. The file layout and the position encoding follow the format of the original
+// generator, so the usual downloadable tables work.
+
+// Every table file starts with one of these.
+pub(crate) const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+pub(crate) const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Flags stored with each sub-table.
+pub(crate) const FLAG_STM: u8 = 1;
+pub(crate) const FLAG_MAPPED: u8 = 2;
+pub(crate) const FLAG_WIN_PLIES: u8 = 4;
+pub(crate) const FLAG_LOSS_PLIES: u8 = 8;
+pub(crate) const FLAG_WIDE: u8 = 16;
+pub(crate) const FLAG_SINGLE_VALUE: u8 = 128;
+
+// The biggest tables that exist.
+pub const MAX_PIECES: usize = 7;
+
+// Rank of a root move that wins for sure, see Tablebases::rank_root_moves.
+pub const MAX_DTZ: i32 = 1 << 18;
+
+// Result of a position with perfect play, from the point of view of the side to move. Cursed
+// wins and blessed losses are wins and losses that the fifty-move rule turns into draws.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    pub(crate) fn from_value(value: i32) -> Self {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+
+    // -2 (loss) to 2 (win).
+    pub fn value(self) -> i32 {
+        self as i32 - 2
+    }
+}
+
+impl Neg for Wdl {
+    type Output = Wdl;
+
+    fn neg(self) -> Wdl {
+        Wdl::from_value(-self.value())
+    }
+}
+
+// Piece codes used inside the tables: 1 (pawn) to 6 (king), plus 8 for black.
+pub(crate) fn piece_code(piece: Piece) -> u8 {
+    piece.kind.index() as u8 + 1 + if piece.color == Color::Black { 8 } else { 0 }
+}
+
+// The table name for the position, like "KRPvKR": each side's king then its other pieces from
+// the queen down, with the given color's side first.
+pub fn material_name(board: &Board, first: Color) -> String {
+    let side = |color: Color| {
+        let mut kinds: Vec<usize> = board
+            .board
+            .iter()
+            .flatten()
+            .filter(|piece| piece.color == color)
+            .map(|piece| piece.kind.index())
+            .collect();
+        kinds.sort_by(|a, b| b.cmp(a));
+        kinds
+            .into_iter()
+            .map(|kind| b"PNBRQK"[kind] as char)
+            .collect::<String>()
+    };
+    format!("{}v{}", side(first), side(first.reverse()))
+}
+
+// What a table holds, worked out from its name. The first side of the name plays white.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Material {
+    pub(crate) piece_count: usize,
+    pub(crate) has_pawns: bool,
+    // Some piece other than a king is alone of its kind and color, so the encoding can start
+    // with three unique pieces instead of the two kings.
+    pub(crate) has_unique_pieces: bool,
+    // Pawns of the leading color (the one with fewer pawns, but some) and of the other one.
+    pub(crate) pawn_count: [usize; 2],
+    // Both sides have the same pieces, so only positions with white to move are stored.
+    pub(crate) symmetric: bool,
+}
+
+impl Material {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, letter: char| side.chars().filter(|ch| *ch == letter).count();
+        let valid =
+            |side: &str| count(side, 'K') == 1 && side.chars().all(|ch| "KQRBNP".contains(ch));
+        if !valid(white) || !valid(black) {
+            return None;
+        }
+        let piece_count = white.len() + black.len();
+        if piece_count > MAX_PIECES {
+            return None;
+        }
+        let has_unique_pieces = [white, black]
+            .iter()
+            .any(|side| "QRBNP".chars().any(|letter| count(side, letter) == 1));
+        let white_pawns = count(white, 'P');
+        let black_pawns = count(black, 'P');
+        let white_leads = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let pawn_count = if white_leads {
+            [white_pawns, black_pawns]
+        } else {
+            [black_pawns, white_pawns]
+        };
+        Some(Material {
+            piece_count,
+            has_pawns: white_pawns + black_pawns > 0,
+            has_unique_pieces,
+            pawn_count,
+            symmetric: white == black,
+        })
+    }
+}
+
+// Lookup tables for the position encoding, shared by every table.
+pub(crate) struct Indexing {
+    // a2-h7 to 0..47, higher for pawns nearer the edge and (on the same file) the first rank.
+    map_pawns: [usize; 64],
+    // Squares below the a1-h8 diagonal to 0..27.
+    map_b1h1h7: [usize; 64],
+    // The a1-d1-d4 triangle to 0..9, diagonal squares last.
+    map_a1d1d4: [usize; 64],
+    // Every legal placement of two kings with the first one in the triangle, to 0..461.
+    map_kk: [[u64; 64]; 10],
+    // binomial[k][n]: ways to choose k things out of n.
+    binomial: [[u64; 64]; MAX_PIECES],
+    lead_pawn_idx: [[u64; 64]; 6],
+    lead_pawns_size: [[u64; 4]; 6],
+}
+
+// Rank minus file: 0 on the a1-h8 diagonal, negative below it.
+fn off_diagonal(square: usize) -> i32 {
+    (square / 8) as i32 - (square % 8) as i32
+}
+
+impl Indexing {
+    #[allow(clippy::needless_range_loop)]
+    fn new() -> Self {
+        let mut map_b1h1h7 = [0; 64];
+        let mut code = 0;
+        for (square, entry) in map_b1h1h7.iter_mut().enumerate() {
+            if off_diagonal(square) < 0 {
+                *entry = code;
+                code += 1;
+            }
+        }
+
+        let mut map_a1d1d4 = [0; 64];
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in 0..=27 {
+            if off_diagonal(square) < 0 && square % 8 <= 3 {
+                map_a1d1d4[square] = code;
+                code += 1;
+            } else if off_diagonal(square) == 0 && square % 8 <= 3 {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            map_a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // If the first king is on the diagonal, the second one can't be above it. Placements
+        // with both kings on the diagonal go last.
+        let mut map_kk = [[0; 64]; 10];
+        let mut both_on_diagonal = Vec::new();
+        let mut code = 0;
+        for (index, row) in map_kk.iter_mut().enumerate() {
+            for first in 0..=27 {
+                // Squares outside the triangle are mapped to 0 as well, b1 is the real 0.
+                if map_a1d1d4[first] != index || (index == 0 && first != 1) {
+                    continue;
+                }
+                for (second, entry) in row.iter_mut().enumerate() {
+                    let file_distance = (first % 8).abs_diff(second % 8);
+                    let rank_distance = (first / 8).abs_diff(second / 8);
+                    if file_distance <= 1 && rank_distance <= 1 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) > 0 {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index, second));
+                    } else {
+                        *entry = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            map_kk[index][second] = code;
+            code += 1;
+        }
+
+        let mut binomial = [[0; 64]; MAX_PIECES];
+        binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                binomial[k][n] = if k > 0 { binomial[k - 1][n - 1] } else { 0 }
+                    + if k < n { binomial[k][n - 1] } else { 0 };
+            }
+        }
+
+        // The leading pawn is the one with the highest map_pawns value. With it on a given
+        // square, the other leading pawns have map_pawns[square] squares left.
+        let mut map_pawns = [0; 64];
+        let mut lead_pawn_idx = [[0; 64]; 6];
+        let mut lead_pawns_size = [[0; 4]; 6];
+        let mut available: usize = 47;
+        for lead_pawns in 1..=5 {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..=6 {
+                    let square = rank * 8 + file;
+                    if lead_pawns == 1 {
+                        map_pawns[square] = available;
+                        map_pawns[square ^ 7] = available.saturating_sub(1);
+                        available = available.saturating_sub(2);
+                    }
+                    lead_pawn_idx[lead_pawns][square] = index;
+                    index += binomial[lead_pawns - 1][map_pawns[square]];
+                }
+                lead_pawns_size[lead_pawns][file] = index;
+            }
+        }
+
+        Indexing {
+            map_pawns,
+            map_b1h1h7,
+            map_a1d1d4,
+            map_kk,
+            binomial,
+            lead_pawn_idx,
+            lead_pawns_size,
+        }
+    }
+}
+
+pub(crate) fn indexing() -> &'static Indexing {
+    static INDEXING: OnceLock<Indexing> = OnceLock::new();
+    INDEXING.get_or_init(Indexing::new)
+}
+
+// The order of the pieces in a sub-table, and how they're grouped for the encoding.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Layout {
+    pub(crate) pieces: [u8; MAX_PIECES],
+    // Pieces per group, zero terminated. KRvKN has (3, 1): the unique trio, then the knight.
+    pub(crate) group_len: [usize; MAX_PIECES + 1],
+    // What each group's index gets multiplied by. The entry after the last group is the size.
+    pub(crate) group_idx: [u64; MAX_PIECES + 1],
+}
+
+impl Layout {
+    // `order` says where the leading group and the other color's pawns come in the encoding.
+    pub(crate) fn new(
+        material: &Material,
+        pieces: [u8; MAX_PIECES],
+        order: [u8; 2],
+        file: usize,
+    ) -> Self {
+        let indexing = indexing();
+        let mut group_len = [0; MAX_PIECES + 1];
+        let mut group_idx = [0; MAX_PIECES + 1];
+        let mut first_len: i32 = if material.has_pawns {
+            0
+        } else if material.has_unique_pieces {
+            3
+        } else {
+            2
+        };
+        let mut n = 0;
+        group_len[0] = 1;
+        for i in 1..material.piece_count {
+            first_len -= 1;
+            if first_len > 0 || pieces[i] == pieces[i - 1] {
+                group_len[n] += 1;
+            } else {
+                n += 1;
+                group_len[n] = 1;
+            }
+        }
+        n += 1;
+        group_len[n] = 0;
+
+        let both_have_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = if both_have_pawns { 2 } else { 1 };
+        let mut free_squares = 64 - group_len[0] - if both_have_pawns { group_len[1] } else { 0 };
+        let mut index: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] as usize || k == order[1] as usize {
+            if k == order[0] as usize {
+                group_idx[0] = index;
+                index *= if material.has_pawns {
+                    indexing.lead_pawns_size[group_len[0]][file]
+                } else if material.has_unique_pieces {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] as usize {
+                group_idx[1] = index;
+                index *= indexing.binomial[group_len[1]][48 - group_len[0]];
+            } else {
+                group_idx[next] = index;
+                index *= indexing.binomial[group_len[next]][free_squares];
+                free_squares -= group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        group_idx[n] = index;
+        Layout {
+            pieces,
+            group_len,
+            group_idx,
+        }
+    }
+
+    // Number of positions in the sub-table.
+    pub(crate) fn size(&self) -> u64 {
+        let groups = self.group_len.iter().position(|len| *len == 0).unwrap_or(0);
+        self.group_idx[groups]
+    }
+}
+
+// A position as the table sees it: the stronger side as white, and the pieces in table order.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TablePosition {
+    pub(crate) squares: [usize; MAX_PIECES],
+    pub(crate) pieces: [u8; MAX_PIECES],
+    pub(crate) size: usize,
+    pub(crate) lead_pawns: usize,
+    // Pawn tables are split by the leading pawn's file, a to d.
+    pub(crate) file: usize,
+    // Side to move in the table: 0 for white, 1 for black.
+    pub(crate) stm: usize,
+}
+
+impl TablePosition {
+    // `lead_pawn` is the first piece of the table (only used when it has pawns), and
+    // `black_stronger` says the table's white pieces are black on this board.
+    pub(crate) fn new(
+        board: &Board,
+        color: Color,
+        material: &Material,
+        lead_pawn: u8,
+        black_stronger: bool,
+    ) -> Self {
+        let indexing = indexing();
+        // Symmetric tables only have white to move, so black to move gets flipped too.
+        let flip = black_stronger || (material.symmetric && color == Color::Black);
+        let flip_color = if flip { 8 } else { 0 };
+        let flip_squares = if flip { 56 } else { 0 };
+        let stm = usize::from(flip) ^ usize::from(color == Color::Black);
+
+        let mut position = TablePosition {
+            squares: [0; MAX_PIECES],
+            pieces: [0; MAX_PIECES],
+            size: 0,
+            lead_pawns: 0,
+            file: 0,
+            stm,
+        };
+        let is_lead_pawn =
+            |piece: &Piece| material.has_pawns && piece_code(*piece) == lead_pawn ^ flip_color;
+        if material.has_pawns {
+            for (square, piece) in board.board.iter().enumerate() {
+                if piece.as_ref().is_some_and(is_lead_pawn) {
+                    position.squares[position.size] = square ^ flip_squares;
+                    position.pieces[position.size] = lead_pawn;
+                    position.size += 1;
+                }
+            }
+            position.lead_pawns = position.size;
+            let lead_squares = &mut position.squares[..position.lead_pawns];
+            let leading = (0..lead_squares.len())
+                .max_by(|a, b| {
+                    indexing.map_pawns[lead_squares[*a]]
+                        .cmp(&indexing.map_pawns[lead_squares[*b]])
+                        // The first of equal ones, like the original.
+                        .then(b.cmp(a))
+                })
+                .unwrap_or(0);
+            lead_squares.swap(0, leading);
+            let file = lead_squares[0] % 8;
+            position.file = if file > 3 { 7 - file } else { file };
+        }
+        for (square, piece) in board.board.iter().enumerate() {
+            if let Some(piece) = piece {
+                if !is_lead_pawn(piece) {
+                    position.squares[position.size] = square ^ flip_squares;
+                    position.pieces[position.size] = piece_code(*piece) ^ flip_color;
+                    position.size += 1;
+                }
+            }
+        }
+        position
+    }
+
+    // Index of the position in the sub-table with the given layout.
+    pub(crate) fn encode(mut self, material: &Material, layout: &Layout) -> u64 {
+        let indexing = indexing();
+        let size = self.size;
+        let lead_pawns = self.lead_pawns;
+        let squares = &mut self.squares;
+
+        // Put the pieces in the same order as the table.
+        for i in lead_pawns..size.saturating_sub(1) {
+            for j in i + 1..size {
+                if layout.pieces[i] == self.pieces[j] {
+                    self.pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // The first piece goes to the a-d files.
+        if squares[0] % 8 > 3 {
+            squares[..size].iter_mut().for_each(|square| *square ^= 7);
+        }
+
+        let mut index;
+        if material.has_pawns {
+            index = indexing.lead_pawn_idx[lead_pawns][squares[0]];
+            squares[1..lead_pawns].sort_by_key(|square| indexing.map_pawns[*square]);
+            for (i, square) in squares.iter().enumerate().take(lead_pawns).skip(1) {
+                index += indexing.binomial[i][indexing.map_pawns[*square]];
+            }
+        } else {
+            // Without pawns the first piece also goes to ranks 1-4...
+            if squares[0] / 8 > 3 {
+                squares[..size].iter_mut().for_each(|square| *square ^= 56);
+            }
+            // ...and the first piece of the leading group that's off the a1-h8 diagonal goes
+            // below it.
+            for i in 0..layout.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if material.has_unique_pieces {
+                let [first, second, third] = [squares[0], squares[1], squares[2]];
+                let adjust1 = usize::from(second > first);
+                let adjust2 = usize::from(third > first) + usize::from(third > second);
+                let rank = |square: usize| square / 8;
+                index = if off_diagonal(first) != 0 {
+                    (indexing.map_a1d1d4[first] * 63 + (second - adjust1)) * 62 + third - adjust2
+                } else if off_diagonal(second) != 0 {
+                    (6 * 63 + rank(first) * 28 + indexing.map_b1h1h7[second]) * 62 + third - adjust2
+                } else if off_diagonal(third) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(first) * 7 * 28
+                        + (rank(second) - adjust1) * 28
+                        + indexing.map_b1h1h7[third]
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(first) * 7 * 6
+                        + (rank(second) - adjust1) * 6
+                        + (rank(third) - adjust2)
+                } as u64;
+            } else {
+                index = indexing.map_kk[indexing.map_a1d1d4[squares[0]]][squares[1]];
+            }
+        }
+
+        // The other groups, each as a combination of squares not taken by earlier groups.
+        index *= layout.group_idx[0];
+        let mut group_start = layout.group_len[0];
+        let mut remaining_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut next = 1;
+        while layout.group_len[next] != 0 {
+            let len = layout.group_len[next];
+            squares[group_start..group_start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[group_start + i];
+                let adjust = squares[..group_start]
+                    .iter()
+                    .filter(|other| square > **other)
+                    .count();
+                // Pawns can't be on the first rank, so their squares start at a2.
+                let pawn_offset = if remaining_pawns { 8 } else { 0 };
+                n += indexing.binomial[i + 1][square - adjust - pawn_offset];
+            }
+            remaining_pawns = false;
+            index += n * layout.group_idx[next];
+            group_start += len;
+            next += 1;
+        }
+        index
+    }
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// Big endian, reading zeros past the end of the file.
+fn read_u32_be(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = data.get(offset + i).copied().unwrap_or(0);
+    }
+    u32::from_be_bytes(bytes)
+}
+
+// One compressed sub-table: for a side to move and, with pawns, a file of the leading pawn.
+// Values are Huffman coded symbols, where a symbol can stand for a pair of other symbols
+// ("recursive pairing"), stored in fixed size blocks.
+#[derive(Clone, Debug, Default)]
+struct PairsData {
+    layout: Layout,
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    span: u64,
+    // Offsets into the file.
+    lowest_sym: usize,
+    btree: usize,
+    block_length: usize,
+    sparse_index: usize,
+    data: usize,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    // base64[l] is the lowest code of length min_sym_len + l, left aligned in 64 bits.
+    base64: Vec<u64>,
+    // Number of values (minus one) each symbol stands for.
+    symlen: Vec<u8>,
+    // Where the DTZ values of each result start in the value map.
+    map_idx: [usize; 4],
+}
+
+impl PairsData {
+    // Read the sizes and the symbol tables, returning the offset right after them.
+    fn read(data: &[u8], mut offset: usize, layout: Layout) -> Option<(Self, usize)> {
+        let mut pairs = PairsData {
+            layout,
+            flags: *data.get(offset)?,
+            ..Default::default()
+        };
+        offset += 1;
+        if pairs.flags & FLAG_SINGLE_VALUE != 0 {
+            // The whole sub-table is one value, kept here.
+            pairs.min_sym_len = *data.get(offset)?;
+            return Some((pairs, offset + 1));
+        }
+        let header = data.get(offset..offset + 9)?;
+        pairs.block_size = 1 << header[0];
+        pairs.span = 1 << header[1];
+        pairs.sparse_index_size = layout.size().div_ceil(pairs.span) as usize;
+        let padding = header[2] as usize;
+        pairs.num_blocks = read_u32_le(data, offset + 3)? as usize;
+        // Padded so the sparse index never points past the end.
+        pairs.block_length_size = pairs.num_blocks + padding;
+        let max_sym_len = header[7];
+        pairs.min_sym_len = header[8];
+        offset += 9;
+        if max_sym_len < pairs.min_sym_len || pairs.min_sym_len == 0 {
+            return None;
+        }
+
+        // Canonical Huffman code, with longer codes numerically lower: lowest_sym[l] is the
+        // first symbol of length l, and the codes of a length are consecutive.
+        pairs.lowest_sym = offset;
+        let lengths = (max_sym_len - pairs.min_sym_len) as usize + 1;
+        let mut base64 = vec![0u64; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = read_u16_le(data, offset + 2 * i)? as u64;
+            let next_lowest = read_u16_le(data, offset + 2 * (i + 1))? as u64;
+            base64[i] = (base64[i + 1] + lowest).checked_sub(next_lowest)? / 2;
+        }
+        for (i, base) in base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - i as u32 - pairs.min_sym_len as u32)
+                .unwrap_or(0);
+        }
+        pairs.base64 = base64;
+        offset += 2 * lengths;
+
+        let symbols = read_u16_le(data, offset)? as usize;
+        offset += 2;
+        pairs.btree = offset;
+        data.get(offset..offset + 3 * symbols)?;
+        pairs.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for symbol in 0..symbols {
+            if !visited[symbol] {
+                pairs.symlen[symbol] = pairs.set_symlen(data, symbol, &mut visited)?;
+            }
+        }
+        offset += 3 * symbols + (symbols & 1);
+        Some((pairs, offset))
+    }
+
+    // The two symbols a symbol stands for, 12 bits each. A right side of 0xFFF marks a value.
+    fn children(&self, data: &[u8], symbol: usize) -> (usize, usize) {
+        let entry = &data[self.btree + 3 * symbol..self.btree + 3 * symbol + 3];
+        let left = ((entry[1] as usize & 0xF) << 8) | entry[0] as usize;
+        let right = ((entry[2] as usize) << 4) | (entry[1] as usize >> 4);
+        (left, right)
+    }
+
+    fn set_symlen(&mut self, data: &[u8], symbol: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[symbol] = true;
+        let (left, right) = self.children(data, symbol);
+        if right == 0xFFF {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                self.symlen[child] = self.set_symlen(data, child, visited)?;
+            }
+        }
+        Some(
+            self.symlen[left]
+                .wrapping_add(self.symlen[right])
+                .wrapping_add(1),
+        )
+    }
+
+    // The value stored at the index.
+    fn decompress(&self, data: &[u8], index: u64) -> Option<u16> {
+        if self.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(self.min_sym_len as u16);
+        }
+        // The sparse index gives the block and offset of every span-th value (from the middle
+        // of each span), walk from there to the block holding ours.
+        let k = (index / self.span) as usize;
+        if k >= self.sparse_index_size {
+            return None;
+        }
+        let entry = self.sparse_index + 6 * k;
+        let mut block = read_u32_le(data, entry)? as usize;
+        let mut offset = read_u16_le(data, entry + 4)? as i64;
+        offset += (index % self.span) as i64 - (self.span / 2) as i64;
+        let block_length =
+            |block: usize| read_u16_le(data, self.block_length + 2 * block).map(|len| len as i64);
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+        if block >= self.block_length_size {
+            return None;
+        }
+
+        // Read symbols from the start of the block until we get to the one holding our value.
+        let mut pointer = self.data + block * self.block_size;
+        let mut buffer =
+            ((read_u32_be(data, pointer) as u64) << 32) | read_u32_be(data, pointer + 4) as u64;
+        pointer += 8;
+        let mut buffer_size = 64;
+        let min_sym_len = self.min_sym_len as usize;
+        let mut symbol;
+        loop {
+            let mut len = 0;
+            while buffer < self.base64[len] {
+                len += 1;
+                if len >= self.base64.len() {
+                    return None;
+                }
+            }
+            symbol = ((buffer - self.base64[len]) >> (64 - len - min_sym_len)) as usize;
+            symbol += read_u16_le(data, self.lowest_sym + 2 * len)? as usize;
+            let values = *self.symlen.get(symbol)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            len += min_sym_len;
+            buffer <<= len;
+            buffer_size -= len;
+            if buffer_size <= 32 {
+                buffer_size += 32;
+                buffer |= (read_u32_be(data, pointer) as u64) << (64 - buffer_size);
+                pointer += 4;
+            }
+        }
+
+        // The symbol stands for several values, go down the pairs to ours.
+        while self.symlen[symbol] != 0 {
+            let (left, right) = self.children(data, symbol);
+            let left_values = *self.symlen.get(left)? as i64 + 1;
+            if offset < left_values {
+                symbol = left;
+            } else {
+                offset -= left_values;
+                symbol = right;
+            }
+        }
+        Some(self.children(data, symbol).0 as u16)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableKind {
+    Wdl,
+    Dtz,
+}
+
+// A table file read into memory.
+struct Table {
+    data: Vec<u8>,
+    material: Material,
+    // Sub-tables by side to move and file.
+    items: Vec<Vec<PairsData>>,
+    // Start of the DTZ value map.
+    map: usize,
+}
+
+// What a table lookup found.
+enum Lookup {
+    // -2 to 2 from a WDL table, plies from a DTZ table.
+    Value(i32),
+    // DTZ tables only keep one side to move, and this position has the other one.
+    OtherSide,
+}
+
+impl Table {
+    fn open(path: &Path, material: Material, kind: TableKind) -> Option<Self> {
+        let data = fs::read(path).ok()?;
+        // The data is followed by a 16 byte checksum.
+        let magic = if kind == TableKind::Wdl {
+            WDL_MAGIC
+        } else {
+            DTZ_MAGIC
+        };
+        if data.len() % 64 != 16 || data.get(..4)? != magic {
+            return None;
+        }
+        let mut offset = 4;
+        let flags = *data.get(offset)?;
+        if (flags & 2 != 0) != material.has_pawns {
+            return None;
+        }
+        offset += 1;
+
+        let sides = if kind == TableKind::Wdl && !material.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if material.has_pawns { 4 } else { 1 };
+        let both_have_pawns = material.has_pawns && material.pawn_count[1] > 0;
+        let mut layouts = vec![Vec::with_capacity(files); sides];
+        for file in 0..files {
+            let order_bytes = data.get(offset..offset + 2)?;
+            let second = |byte: u8| if both_have_pawns { byte } else { 0xF };
+            let orders = [
+                [order_bytes[0] & 0xF, second(order_bytes[1] & 0xF)],
+                [order_bytes[0] >> 4, second(order_bytes[1] >> 4)],
+            ];
+            offset += 1 + usize::from(both_have_pawns);
+            let mut pieces = [[0; MAX_PIECES]; 2];
+            for (k, byte) in data
+                .get(offset..offset + material.piece_count)?
+                .iter()
+                .enumerate()
+            {
+                pieces[0][k] = byte & 0xF;
+                pieces[1][k] = byte >> 4;
+            }
+            offset += material.piece_count;
+            for (side, layouts) in layouts.iter_mut().enumerate() {
+                layouts.push(Layout::new(&material, pieces[side], orders[side], file));
+            }
+        }
+        offset += offset & 1;
+
+        let mut items: Vec<Vec<PairsData>> = vec![Vec::with_capacity(files); sides];
+        for file in 0..files {
+            for (side_items, side_layouts) in items.iter_mut().zip(&layouts) {
+                let (pairs, next) = PairsData::read(&data, offset, side_layouts[file])?;
+                side_items.push(pairs);
+                offset = next;
+            }
+        }
+
+        let map = offset;
+        if kind == TableKind::Dtz {
+            for pairs in &mut items[0] {
+                if pairs.flags & FLAG_MAPPED == 0 {
+                    continue;
+                }
+                if pairs.flags & FLAG_WIDE != 0 {
+                    offset += offset & 1;
+                    for map_idx in &mut pairs.map_idx {
+                        *map_idx = (offset - map) / 2 + 1;
+                        offset += 2 * read_u16_le(&data, offset)? as usize + 2;
+                    }
+                } else {
+                    for map_idx in &mut pairs.map_idx {
+                        *map_idx = offset - map + 1;
+                        offset += *data.get(offset)? as usize + 1;
+                    }
+                }
+            }
+            offset += offset & 1;
+        }
+
+        for file in 0..files {
+            for side in items.iter_mut() {
+                side[file].sparse_index = offset;
+                offset += 6 * side[file].sparse_index_size;
+            }
+        }
+        for file in 0..files {
+            for side in items.iter_mut() {
+                side[file].block_length = offset;
+                offset += 2 * side[file].block_length_size;
+            }
+        }
+        for file in 0..files {
+            for side in items.iter_mut() {
+                offset = (offset + 63) & !63;
+                side[file].data = offset;
+                offset += side[file].num_blocks * side[file].block_size;
+            }
+        }
+        if offset > data.len() {
+            return None;
+        }
+        Some(Table {
+            data,
+            material,
+            items,
+            map,
+        })
+    }
+
+    fn lookup(
+        &self,
+        board: &Board,
+        color: Color,
+        black_stronger: bool,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Option<Lookup> {
+        let lead_pawn = self.items[0][0].layout.pieces[0];
+        let position = TablePosition::new(board, color, &self.material, lead_pawn, black_stronger);
+        if position.size != self.material.piece_count {
+            return None;
+        }
+        let file = position.file;
+        if kind == TableKind::Dtz {
+            let stored_stm = (self.items[0][file].flags & FLAG_STM) as usize;
+            let one_side_for_both = self.material.symmetric && !self.material.has_pawns;
+            if stored_stm != position.stm && !one_side_for_both {
+                return Some(Lookup::OtherSide);
+            }
+        }
+        let pairs = &self.items[position.stm % self.items.len()][file];
+        let index = position.encode(&self.material, &pairs.layout);
+        let value = pairs.decompress(&self.data, index)?;
+        match kind {
+            TableKind::Wdl => Some(Lookup::Value(value as i32 - 2)),
+            TableKind::Dtz => self.map_dtz(file, value, wdl).map(Lookup::Value),
+        }
+    }
+
+    // Turn a stored DTZ value into plies.
+    fn map_dtz(&self, file: usize, value: u16, wdl: Wdl) -> Option<i32> {
+        let pairs = &self.items[0][file];
+        let mut value = value as i32;
+        if pairs.flags & FLAG_MAPPED != 0 {
+            // Win, loss, cursed win, blessed loss.
+            let map_index = match wdl {
+                Wdl::Win | Wdl::Draw => 0,
+                Wdl::Loss => 1,
+                Wdl::CursedWin => 2,
+                Wdl::BlessedLoss => 3,
+            };
+            let index = pairs.map_idx[map_index] + value as usize;
+            value = if pairs.flags & FLAG_WIDE != 0 {
+                read_u16_le(&self.data, self.map + 2 * index)? as i32
+            } else {
+                *self.data.get(self.map + index)? as i32
+            };
+        }
+        // Some tables count full moves instead of plies.
+        if (wdl == Wdl::Win && pairs.flags & FLAG_WIN_PLIES == 0)
+            || (wdl == Wdl::Loss && pairs.flags & FLAG_LOSS_PLIES == 0)
+            || wdl == Wdl::CursedWin
+            || wdl == Wdl::BlessedLoss
+        {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+// The DTZ of a position whose best move is a capture or a pawn move with the given result.
+fn dtz_before_zeroing(wdl: Wdl) -> i32 {
+    match wdl {
+        Wdl::Win => 1,
+        Wdl::CursedWin => 101,
+        Wdl::BlessedLoss => -101,
+        Wdl::Loss => -1,
+        Wdl::Draw => 0,
+    }
+}
+
+// Tables don't know about castling, so they can't be used while a side may still castle.
+pub fn has_castling_rights(board: &Board) -> bool {
+    let can_castle = board.can_castle;
+    let is_piece = |square: usize, kind: PieceKind, color: Color| {
+        board.board[square] == Some(Piece::new(kind, color))
+    };
+    [
+        (can_castle.white_kingside, 4, 7, Color::White),
+        (can_castle.white_queenside, 4, 0, Color::White),
+        (can_castle.black_kingside, 60, 63, Color::Black),
+        (can_castle.black_queenside, 60, 56, Color::Black),
+    ]
+    .into_iter()
+    .any(|(right, king, rook, color)| {
+        right && is_piece(king, PieceKind::King, color) && is_piece(rook, PieceKind::Rook, color)
+    })
+}
+
+fn piece_count(board: &Board) -> usize {
+    board.board.iter().flatten().count()
+}
+
+fn is_zeroing(board: &Board, start_square: Square, a_move: crate::Move) -> bool {
+    board.is_capture(a_move)
+        || matches!(
+            board.board[start_square.index()],
+            Some(Piece {
+                kind: PieceKind::Pawn,
+                ..
+            })
+        )
+}
+
+// A set of Syzygy tables found in some directories. Tables are read on first use.
+#[derive(Default)]
+pub struct Tablebases {
+    wdl_paths: FnvHashMap<String, PathBuf>,
+    dtz_paths: FnvHashMap<String, PathBuf>,
+    // Tables read so far, None when the file turned out to be unusable.
+    wdl_tables: FnvHashMap<String, Option<Table>>,
+    dtz_tables: FnvHashMap<String, Option<Table>>,
+    max_pieces: usize,
+}
+
+impl Tablebases {
+    // Find the tables in the directories, separated like the PATH variable.
+    pub fn open(paths: &str) -> io::Result<Self> {
+        let mut tablebases = Tablebases::default();
+        for directory in std::env::split_paths(paths) {
+            for entry in fs::read_dir(&directory)? {
+                let path = entry?.path();
+                let (Some(name), Some(extension)) = (
+                    path.file_stem().and_then(|stem| stem.to_str()),
+                    path.extension().and_then(|extension| extension.to_str()),
+                ) else {
+                    continue;
+                };
+                let Some(material) = Material::from_name(name) else {
+                    continue;
+                };
+                match extension {
+                    "rtbw" => {
+                        tablebases.max_pieces = tablebases.max_pieces.max(material.piece_count);
+                        tablebases.wdl_paths.insert(name.to_string(), path);
+                    }
+                    "rtbz" => {
+                        tablebases.dtz_paths.insert(name.to_string(), path);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(tablebases)
+    }
+
+    // Pieces (kings included) in the biggest WDL table found.
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Number of WDL and DTZ tables found.
+    pub fn table_count(&self) -> (usize, usize) {
+        (self.wdl_paths.len(), self.dtz_paths.len())
+    }
+
+    // Look the position up in the table for its material. None if there's no such table.
+    // `wdl` is the result of the position, which DTZ values depend on.
+    fn probe_table(
+        &mut self,
+        board: &Board,
+        color: Color,
+        kind: TableKind,
+        wdl: Wdl,
+    ) -> Option<Lookup> {
+        let (paths, tables) = match kind {
+            TableKind::Wdl => (&self.wdl_paths, &mut self.wdl_tables),
+            TableKind::Dtz => (&self.dtz_paths, &mut self.dtz_tables),
+        };
+        let white_first = material_name(board, Color::White);
+        let (name, black_stronger) = if paths.contains_key(&white_first) {
+            (white_first, false)
+        } else {
+            let black_first = material_name(board, Color::Black);
+            paths.get(&black_first)?;
+            (black_first, true)
+        };
+        let table = tables
+            .entry(name.clone())
+            .or_insert_with(|| Table::open(&paths[&name], Material::from_name(&name)?, kind));
+        table
+            .as_ref()?
+            .lookup(board, color, black_stronger, kind, wdl)
+    }
+
+    fn probe_wdl_table(&mut self, board: &Board, color: Color) -> Option<Wdl> {
+        // Two bare kings have no table.
+        if piece_count(board) == 2 {
+            return Some(Wdl::Draw);
+        }
+        match self.probe_table(board, color, TableKind::Wdl, Wdl::Draw)? {
+            Lookup::Value(value) => Some(Wdl::from_value(value)),
+            Lookup::OtherSide => None,
+        }
+    }
+
+    // Tables store "don't care" values where a capture (or, for DTZ, a pawn move) is best,
+    // since that compresses better, so those moves have to be tried before trusting them.
+    // Also says whether such a move is the best one.
+    fn search(&mut self, board: &Board, color: Color, check_zeroing: bool) -> Option<(Wdl, bool)> {
+        let legal_moves = board.legal_moves();
+        let mut best = Wdl::Loss;
+        let mut searched = 0;
+        for (start_square, a_move) in &legal_moves {
+            let is_pawn_move = matches!(
+                board.board[start_square.index()],
+                Some(Piece {
+                    kind: PieceKind::Pawn,
+                    ..
+                })
+            );
+            let zeroing = board.is_capture(*a_move) || (check_zeroing && is_pawn_move);
+            if !zeroing {
+                continue;
+            }
+            searched += 1;
+            let new_board = board.make_move(*start_square, *a_move);
+            let value = -self.search(&new_board, color.reverse(), false)?.0;
+            if value > best {
+                best = value;
+                if value >= Wdl::Win {
+                    return Some((value, true));
+                }
+            }
+        }
+        // With every move searched there's nothing the table can add, and it could even be
+        // wrong (it doesn't know about en passant).
+        let all_searched = searched > 0 && searched == legal_moves.len();
+        let value = if all_searched {
+            best
+        } else {
+            self.probe_wdl_table(board, color)?
+        };
+        if best >= value {
+            Some((best, best > Wdl::Draw || all_searched))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    // Win, draw or loss for the side to move. None if a table is missing or the position
+    // isn't one the tables can answer (castling rights, pieces in hand in drop variants, or fairy
+    // pieces).
+    pub fn probe_wdl(&mut self, board: &Board) -> Option<Wdl> {
+        if piece_count(board) > self.max_pieces
+            || has_castling_rights(board)
+            || board.pockets.is_some()
+            || fairy::has_fairy_pieces(board)
+        {
+            return None;
+        }
+        self.search(board, board.side_to_move(), false)
+            .map(|(wdl, _)| wdl)
+    }
+
+    // Plies to the next capture or pawn move (or mate) with the best play, positive when the
+    // side to move wins and negative when it loses. 0 for draws. Cursed wins and blessed
+    // losses are 100 plies further away than they really are.
+    pub fn probe_dtz(&mut self, board: &Board) -> Option<i32> {
+        if piece_count(board) > self.max_pieces
+            || has_castling_rights(board)
+            || board.pockets.is_some()
+            || fairy::has_fairy_pieces(board)
+        {
+            return None;
+        }
+        self.dtz(board, board.side_to_move())
+    }
+
+    fn dtz(&mut self, board: &Board, color: Color) -> Option<i32> {
+        let (wdl, zeroing_is_best) = self.search(board, color, true)?;
+        if wdl == Wdl::Draw {
+            return Some(0);
+        }
+        if zeroing_is_best {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        match self.probe_table(board, color, TableKind::Dtz, wdl)? {
+            Lookup::Value(dtz) => {
+                let cursed = matches!(wdl, Wdl::CursedWin | Wdl::BlessedLoss);
+                Some((dtz + if cursed { 100 } else { 0 }) * wdl.value().signum())
+            }
+            Lookup::OtherSide => {
+                // The table has the other side to move: look one ply ahead for the quickest
+                // way to keep the result.
+                let mut min_dtz = i32::MAX;
+                for (start_square, a_move) in board.legal_moves() {
+                    let zeroing = is_zeroing(board, start_square, a_move);
+                    let new_board = board.make_move(start_square, a_move);
+                    let mut dtz = if zeroing {
+                        -dtz_before_zeroing(self.search(&new_board, color.reverse(), false)?.0)
+                    } else {
+                        -self.dtz(&new_board, color.reverse())?
+                    };
+                    let mates = dtz == 1
+                        && new_board.is_in_check(color.reverse())
+                        && new_board.legal_moves().is_empty();
+                    if mates {
+                        min_dtz = 1;
+                    }
+                    if !zeroing {
+                        dtz += dtz.signum();
+                    }
+                    if dtz < min_dtz && dtz.signum() == wdl.value().signum() {
+                        min_dtz = dtz;
+                    }
+                }
+                // No legal moves: mated.
+                Some(if min_dtz == i32::MAX { -1 } else { min_dtz })
+            }
+        }
+    }
+
+    // Rank every legal move at the root by how well it keeps (or fights) the tablebase result,
+    // higher is better. Wins that can be completed before the fifty-move rule rank MAX_DTZ,
+    // losses -MAX_DTZ, draws 0, and the ones in between get closer to the draw the later the
+    // fifty-move rule would step in. With `use_rule50` off, those count as plain wins and
+    // losses. `repeated` says the position already occurred since the last capture or pawn
+    // move. None if a table is missing.
+    pub fn rank_root_moves(
+        &mut self,
+        board: &Board,
+        repeated: bool,
+        use_rule50: bool,
+    ) -> Option<Vec<(BoardMove, i32)>> {
+        let color = board.side_to_move();
+        if piece_count(board) > self.max_pieces
+            || has_castling_rights(board)
+            || board.pockets.is_some()
+            || fairy::has_fairy_pieces(board)
+        {
+            return None;
+        }
+        let halfmove_clock = board.halfmove_clock as i32;
+        let mut ranked = Vec::new();
+        for (start_square, a_move) in board.legal_moves() {
+            let new_board = board.make_move(start_square, a_move);
+            let mut dtz = if new_board.halfmove_clock == 0 {
+                dtz_before_zeroing(-self.search(&new_board, color.reverse(), false)?.0)
+            } else {
+                let dtz = -self.dtz(&new_board, color.reverse())?;
+                dtz + dtz.signum()
+            };
+            if dtz == 2
+                && new_board.is_in_check(color.reverse())
+                && new_board.legal_moves().is_empty()
+            {
+                dtz = 1;
+            }
+            let rank = match dtz.cmp(&0) {
+                Ordering::Greater if dtz + halfmove_clock <= 99 && !repeated => MAX_DTZ,
+                Ordering::Greater => MAX_DTZ / 2 - (dtz + halfmove_clock),
+                Ordering::Less if -dtz * 2 + halfmove_clock < 100 => -MAX_DTZ,
+                Ordering::Less => -MAX_DTZ / 2 + (-dtz + halfmove_clock),
+                Ordering::Equal => 0,
+            };
+            ranked.push((
+                (start_square, a_move),
+                rank_without_rule50(rank, use_rule50),
+            ));
+        }
+        Some(ranked)
+    }
+
+    // Like rank_root_moves, from WDL tables alone.
+    pub fn rank_root_moves_wdl(
+        &mut self,
+        board: &Board,
+        use_rule50: bool,
+    ) -> Option<Vec<(BoardMove, i32)>> {
+        let color = board.side_to_move();
+        if piece_count(board) > self.max_pieces
+            || has_castling_rights(board)
+            || board.pockets.is_some()
+            || fairy::has_fairy_pieces(board)
+        {
+            return None;
+        }
+        let mut ranked = Vec::new();
+        for (start_square, a_move) in board.legal_moves() {
+            let new_board = board.make_move(start_square, a_move);
+            let wdl = -self.search(&new_board, color.reverse(), false)?.0;
+            let rank = match wdl {
+                Wdl::Win => MAX_DTZ,
+                Wdl::CursedWin => MAX_DTZ - 101,
+                Wdl::Draw => 0,
+                Wdl::BlessedLoss => -MAX_DTZ + 101,
+                Wdl::Loss => -MAX_DTZ,
+            };
+            ranked.push((
+                (start_square, a_move),
+                rank_without_rule50(rank, use_rule50),
+            ));
+        }
+        Some(ranked)
+    }
+}
+
+// Without the fifty-move rule every win is as good as any other.
+fn rank_without_rule50(rank: i32, use_rule50: bool) -> i32 {
+    if use_rule50 {
+        rank
+    } else {
+        rank.signum() * MAX_DTZ
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The 3 and 4 piece tables in fixtures/syzygy.
+    fn fixtures() -> Tablebases {
+        Tablebases::open(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/syzygy"))
+            .expect("fixtures/syzygy")
+    }
+
+    // WDL and DTZ for the side to move, from the fixture tables.
+    fn probe(fen: &str) -> (Option<Wdl>, Option<i32>) {
+        let mut tablebases = fixtures();
+        let board = Board::from_fen(fen);
+        (tablebases.probe_wdl(&board), tablebases.probe_dtz(&board))
+    }
+
+    #[test]
+    fn opens_the_fixture_tables() {
+        let tablebases = Tablebases::open(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/syzygy"))
+            .expect("fixtures/syzygy");
+        assert_eq!(tablebases.table_count(), (6, 6));
+        assert_eq!(tablebases.max_pieces(), 4);
+    }
+
+    #[test]
+    fn probes_kqvk() {
+        // Qh8 mates.
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/8/7Q w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        // The king takes the queen.
+        assert_eq!(
+            probe("8/8/8/8/8/8/k7/1Q4K1 b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        let (wdl, dtz) = probe("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        assert_eq!(wdl, Some(Wdl::Win));
+        assert!(dtz.is_some_and(|dtz| dtz > 1));
+        let (wdl, dtz) = probe("4k3/8/8/8/8/8/8/3QK3 b - - 0 1");
+        assert_eq!(wdl, Some(Wdl::Loss));
+        assert!(dtz.is_some_and(|dtz| dtz < -1));
+    }
+
+    #[test]
+    fn probes_kpvk() {
+        // The pawn promotes next move, whatever black does.
+        assert_eq!(
+            probe("8/4P3/8/8/8/8/k7/4K3 w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(
+            probe("8/4P3/8/8/8/8/k7/4K3 b - - 0 1"),
+            (Some(Wdl::Loss), Some(-2))
+        );
+        // A rook pawn with the defending king in the corner.
+        assert_eq!(
+            probe("k7/8/8/8/8/8/P7/K7 w - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        // Too many pieces for the fixtures.
+        assert_eq!(probe("4k3/8/8/8/8/8/4PP2/4K3 w - - 0 1"), (None, None));
+    }
+}
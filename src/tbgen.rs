@@ -0,0 +1,773 @@
+use crate::board::{CanCastle, CanEnPassant};
+use crate::syzygy::{
+    Layout, Material, TablePosition, Tablebases, Wdl, DTZ_MAGIC, FLAG_LOSS_PLIES,
+    FLAG_SINGLE_VALUE, FLAG_WIN_PLIES, MAX_PIECES, WDL_MAGIC,
+};
+use crate::{Board, Color, Move, Piece, PieceKind, Square};
+use fnv::FnvHashMap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Builds Syzygy tables for small endgames by retrograde analysis, in the format the prober
+// reads. Meant for test fixtures: the values are exact, but they're compressed with a much
+// simpler scheme than the real generator's (runs of equal values instead of recursive pairing
+// of everything), so the files come out bigger.
+// Not handled: pawns on both sides, the same pieces on both sides, and wins or losses that
+// take more than 100 plies to the next capture or pawn move.
+
+// Compressed blocks are 2^6 bytes, and the sparse index has an entry every 2^10 values.
+const BLOCK_SIZE_LOG: u8 = 6;
+const SPAN_LOG: u8 = 10;
+// One symbol stands for at most 2^8 equal values.
+const MAX_RUN_LOG: u32 = 8;
+// The prober reads 32 bits at a time, so codes can't be longer.
+const MAX_CODE_LENGTH: usize = 32;
+// Symbols are numbered with 12 bits, and 0xFFF marks a value.
+const MAX_SYMBOLS: usize = 0xFFF;
+
+// Edges of the move graph: the id of the position after the move, or for moves that leave
+// the table, the result for the side to move there (plus 2).
+const ZEROING_EDGE: u32 = 1 << 31;
+const EXTERNAL_EDGE: u32 = 1 << 30;
+const NO_POSITION: u32 = u32::MAX;
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn piece_from_code(code: u8) -> Piece {
+    let color = if code & 8 != 0 {
+        Color::Black
+    } else {
+        Color::White
+    };
+    let kind = match code & 7 {
+        1 => PieceKind::Pawn,
+        2 => PieceKind::Knight,
+        3 => PieceKind::Bishop,
+        4 => PieceKind::Rook,
+        5 => PieceKind::Queen,
+        _ => PieceKind::King,
+    };
+    Piece::new(kind, color)
+}
+
+// The table's pieces in the order they're encoded: the pawns, the kings, then the rest with a
+// unique piece first, so the leading group has three different pieces.
+fn piece_order(name: &str, material: &Material) -> Vec<u8> {
+    let (white, black) = name.split_once('v').unwrap_or_default();
+    let code = |letter: char, black: bool| {
+        "PNBRQK".find(letter).unwrap_or(0) as u8 + 1 + if black { 8 } else { 0 }
+    };
+    let mut pawns = Vec::new();
+    let mut others = Vec::new();
+    for (side, is_black) in [(white, false), (black, true)] {
+        for letter in side.chars() {
+            match letter {
+                'P' => pawns.push(code(letter, is_black)),
+                'K' => (),
+                _ => others.push(code(letter, is_black)),
+            }
+        }
+    }
+    if material.has_unique_pieces && !material.has_pawns {
+        let unique = others
+            .iter()
+            .position(|piece| others.iter().filter(|other| *other == piece).count() == 1);
+        if let Some(unique) = unique {
+            let piece = others.remove(unique);
+            others.insert(0, piece);
+        }
+    }
+    pawns.into_iter().chain([6, 14]).chain(others).collect()
+}
+
+fn kings_touch(first: u8, second: u8) -> bool {
+    (first % 8).abs_diff(second % 8) <= 1 && (first / 8).abs_diff(second / 8) <= 1
+}
+
+// Plies to the next capture or pawn move, or mate, for every position of a table. Positive
+// when the side to move wins, negative when it loses, 0 for draws.
+struct Solution {
+    // One id per table index, sub-table after sub-table: white to move then black to move,
+    // and within a side by the file of the leading pawn.
+    offsets: Vec<usize>,
+    // Squares of one position with each id, 6 bits per piece. NO_POSITION for indices that
+    // don't stand for a legal position.
+    representatives: Vec<u32>,
+    wdl: Vec<Wdl>,
+    dtz: Vec<i16>,
+}
+
+struct Generator<'a> {
+    name: &'a str,
+    material: Material,
+    pieces: Vec<u8>,
+    files: usize,
+    layouts: Vec<Layout>,
+    offsets: Vec<usize>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(name: &'a str) -> io::Result<Self> {
+        let material = Material::from_name(name)
+            .ok_or_else(|| invalid_input(format!("{name} isn't a table name")))?;
+        let black_pawns = name
+            .split_once('v')
+            .is_some_and(|(_, black)| black.contains('P'));
+        if material.symmetric || black_pawns {
+            return Err(invalid_input(format!("can't generate {name}")));
+        }
+        let pieces = piece_order(name, &material);
+        let files = if material.has_pawns { 4 } else { 1 };
+        let mut table_pieces = [0; MAX_PIECES];
+        table_pieces[..pieces.len()].copy_from_slice(&pieces);
+        let mut layouts = Vec::new();
+        let mut offsets = vec![0];
+        for _side in 0..2 {
+            for file in 0..files {
+                let layout = Layout::new(&material, table_pieces, [0, 0xF], file);
+                offsets.push(offsets[offsets.len() - 1] + layout.size() as usize);
+                layouts.push(layout);
+            }
+        }
+        Ok(Generator {
+            name,
+            material,
+            pieces,
+            files,
+            layouts,
+            offsets,
+        })
+    }
+
+    fn size(&self) -> usize {
+        self.offsets[self.offsets.len() - 1]
+    }
+
+    fn board(&self, squares: &[u8], color: Color) -> Board {
+        let mut board = [None; 64];
+        let mut king_squares = [Square::from_index(0); 2];
+        for (code, square) in self.pieces.iter().zip(squares) {
+            board[*square as usize] = Some(piece_from_code(*code));
+            if code & 7 == 6 {
+                king_squares[(code >> 3) as usize] = Square::from_index(*square);
+            }
+        }
+        Board {
+            board,
+            can_en_passant: CanEnPassant::No,
+            can_castle: CanCastle {
+                white_kingside: false,
+                white_queenside: false,
+                black_kingside: false,
+                black_queenside: false,
+            },
+            white_king_pos: king_squares[0],
+            black_king_pos: king_squares[1],
+            is_check: false,
+            side_to_move: color,
+            halfmove_clock: 0,
+            pockets: None,
+            fairy: None,
+        }
+    }
+
+    fn unpack(&self, representative: u32, color: Color) -> Board {
+        let squares: Vec<u8> = (0..self.pieces.len())
+            .map(|i| ((representative >> (6 * i)) & 63) as u8)
+            .collect();
+        self.board(&squares, color)
+    }
+
+    fn color_of(&self, id: usize) -> Color {
+        if id >= self.offsets[self.files] {
+            Color::Black
+        } else {
+            Color::White
+        }
+    }
+
+    fn position_id(&self, board: &Board, color: Color) -> usize {
+        let position = TablePosition::new(board, color, &self.material, self.pieces[0], false);
+        let sub_table = position.stm * self.files + position.file;
+        self.offsets[sub_table] + position.encode(&self.material, &self.layouts[sub_table]) as usize
+    }
+
+    // Every legal placement of the pieces, both sides to move, keeping one position per id.
+    fn enumerate(&self, representatives: &mut [u32], squares: &mut Vec<u8>) {
+        if squares.len() == self.pieces.len() {
+            // The kings come right after the pawns.
+            let kings = self.material.pawn_count[0];
+            if kings_touch(squares[kings], squares[kings + 1]) {
+                return;
+            }
+            for color in [Color::White, Color::Black] {
+                let board = self.board(squares, color);
+                if board.is_in_check(color.reverse()) {
+                    continue;
+                }
+                let id = self.position_id(&board, color);
+                if representatives[id] == NO_POSITION {
+                    representatives[id] = squares
+                        .iter()
+                        .enumerate()
+                        .map(|(i, square)| (*square as u32) << (6 * i))
+                        .sum();
+                }
+            }
+            return;
+        }
+        let range = if self.pieces[squares.len()] & 7 == 1 {
+            8..56
+        } else {
+            0..64
+        };
+        for square in range {
+            if !squares.contains(&square) {
+                squares.push(square);
+                self.enumerate(representatives, squares);
+                squares.pop();
+            }
+        }
+    }
+
+    // The moves of every position. Captures and promotions leave the table, and are looked up
+    // in the tables already generated.
+    fn move_graph(
+        &self,
+        representatives: &[u32],
+        tablebases: &mut Tablebases,
+    ) -> io::Result<(Vec<usize>, Vec<u32>, Vec<bool>)> {
+        let mut edge_start = Vec::with_capacity(self.size() + 1);
+        let mut edges = Vec::new();
+        let mut mated = vec![false; self.size()];
+        for (id, representative) in representatives.iter().enumerate() {
+            edge_start.push(edges.len());
+            if *representative == NO_POSITION {
+                continue;
+            }
+            let color = self.color_of(id);
+            let board = self.unpack(*representative, color);
+            let moves = board.legal_moves();
+            mated[id] = moves.is_empty() && board.is_in_check(color);
+            for (start_square, a_move) in moves {
+                let is_capture = board.is_capture(a_move);
+                let is_pawn_move = matches!(
+                    board.board[start_square.index()],
+                    Some(Piece {
+                        kind: PieceKind::Pawn,
+                        ..
+                    })
+                );
+                let new_board = board.make_move(start_square, a_move);
+                if is_capture || matches!(a_move, Move::PawnPromotion(..)) {
+                    let wdl = if new_board.board.iter().flatten().count() == 2 {
+                        Wdl::Draw
+                    } else {
+                        tablebases.probe_wdl(&new_board).ok_or_else(|| {
+                            invalid_input(format!(
+                                "{} needs the tables for {}",
+                                self.name,
+                                new_board.to_fen()
+                            ))
+                        })?
+                    };
+                    edges.push(ZEROING_EDGE | EXTERNAL_EDGE | (wdl.value() + 2) as u32);
+                } else {
+                    let zeroing = if is_pawn_move { ZEROING_EDGE } else { 0 };
+                    edges.push(zeroing | self.position_id(&new_board, color.reverse()) as u32);
+                }
+            }
+        }
+        edge_start.push(edges.len());
+        Ok((edge_start, edges, mated))
+    }
+
+    fn solve(&self, tablebases: &mut Tablebases) -> io::Result<Solution> {
+        let mut representatives = vec![NO_POSITION; self.size()];
+        self.enumerate(&mut representatives, &mut Vec::new());
+        let (edge_start, edges, mated) = self.move_graph(&representatives, tablebases)?;
+        let edges_of = |id: usize| &edges[edge_start[id]..edge_start[id + 1]];
+        let is_external = |edge: u32| edge & EXTERNAL_EDGE != 0;
+        let target = |edge: u32| (edge & !(ZEROING_EDGE | EXTERNAL_EDGE)) as usize;
+
+        // Wins and losses, going backwards from the mates until nothing changes. Whatever is
+        // left is a draw.
+        let mut wdl: Vec<Option<Wdl>> = vec![None; self.size()];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in 0..self.size() {
+                if representatives[id] == NO_POSITION || wdl[id].is_some() {
+                    continue;
+                }
+                let mut all_lose = true;
+                let mut wins = false;
+                for edge in edges_of(id) {
+                    let after = if is_external(*edge) {
+                        Some(Wdl::from_value(target(*edge) as i32 - 2))
+                    } else {
+                        wdl[target(*edge)]
+                    };
+                    wins |= after == Some(Wdl::Loss);
+                    all_lose &= after == Some(Wdl::Win);
+                }
+                let stalemate = edges_of(id).is_empty() && !mated[id];
+                wdl[id] = if wins {
+                    Some(Wdl::Win)
+                } else if stalemate {
+                    Some(Wdl::Draw)
+                } else if all_lose {
+                    Some(Wdl::Loss)
+                } else {
+                    continue;
+                };
+                changed = true;
+            }
+        }
+        let wdl: Vec<Wdl> = wdl
+            .into_iter()
+            .map(|wdl| wdl.unwrap_or(Wdl::Draw))
+            .collect();
+
+        // Then the distances, one ply at a time. A win is 1 ply away if it can capture, move a
+        // pawn or mate, else one more than the quickest loss it can move to. A loss is as far
+        // as the slowest win it has to allow, and 1 ply away when mated.
+        let mut dtz = vec![0i16; self.size()];
+        let mut remaining = wdl.iter().filter(|wdl| **wdl != Wdl::Draw).count();
+        let mut ply: i16 = 1;
+        while remaining > 0 {
+            if ply > 100 {
+                return Err(invalid_input(format!(
+                    "{} has results decided by the fifty-move rule",
+                    self.name
+                )));
+            }
+            for id in 0..self.size() {
+                if wdl[id] != Wdl::Win || dtz[id] != 0 {
+                    continue;
+                }
+                let reaches = edges_of(id).iter().any(|edge| {
+                    if is_external(*edge) {
+                        return target(*edge) == Wdl::Loss.value() as usize + 2;
+                    }
+                    let after = target(*edge);
+                    wdl[after] == Wdl::Loss
+                        && (edge & ZEROING_EDGE != 0 || mated[after] || dtz[after] != 0)
+                });
+                if reaches {
+                    dtz[id] = ply;
+                    remaining -= 1;
+                }
+            }
+            for id in 0..self.size() {
+                if wdl[id] != Wdl::Loss || dtz[id] != 0 {
+                    continue;
+                }
+                let ready = edges_of(id).iter().all(|edge| {
+                    edge & ZEROING_EDGE != 0
+                        || (dtz[target(*edge)] != 0 && dtz[target(*edge)] < ply)
+                });
+                if ready {
+                    dtz[id] = -ply;
+                    remaining -= 1;
+                }
+            }
+            ply += 1;
+        }
+
+        Ok(Solution {
+            offsets: self.offsets.clone(),
+            representatives,
+            wdl,
+            dtz,
+        })
+    }
+
+    // The file's header up to the sub-tables: flags, then for each file the order of the
+    // groups and the pieces, for both sides.
+    fn header(&self, magic: [u8; 4]) -> Vec<u8> {
+        let mut bytes = magic.to_vec();
+        bytes.push(1 | if self.material.has_pawns { 2 } else { 0 });
+        for _file in 0..self.files {
+            bytes.push(0);
+            bytes.extend(self.pieces.iter().map(|code| code | (code << 4)));
+        }
+        bytes.resize(bytes.len() + (bytes.len() & 1), 0);
+        bytes
+    }
+
+    fn write(&self, solution: &Solution, directory: &Path) -> io::Result<(usize, usize)> {
+        // Indices that don't stand for a position repeat the value before them, to keep runs
+        // going.
+        let sub_table = |side: usize, file: usize, value: &dyn Fn(usize) -> Option<u16>| {
+            let sub_table = side * self.files + file;
+            let mut previous = 0;
+            (solution.offsets[sub_table]..solution.offsets[sub_table + 1])
+                .map(|id| {
+                    if let Some(value) = value(id) {
+                        previous = value;
+                    }
+                    previous
+                })
+                .collect::<Vec<u16>>()
+        };
+        let wdl_value = |id: usize| {
+            (solution.representatives[id] != NO_POSITION)
+                .then(|| (solution.wdl[id].value() + 2) as u16)
+        };
+        let dtz_value =
+            |id: usize| (solution.dtz[id] != 0).then(|| solution.dtz[id].unsigned_abs() - 1);
+
+        let mut wdl_tables = vec![Vec::new(); 2];
+        for file in 0..self.files {
+            for (side, tables) in wdl_tables.iter_mut().enumerate() {
+                tables.push(compress(&sub_table(side, file, &wdl_value), 0)?);
+            }
+        }
+        // DTZ tables keep white to move only.
+        let dtz_tables = vec![(0..self.files)
+            .map(|file| {
+                compress(
+                    &sub_table(0, file, &dtz_value),
+                    FLAG_WIN_PLIES | FLAG_LOSS_PLIES,
+                )
+            })
+            .collect::<io::Result<Vec<_>>>()?];
+
+        let wdl_bytes = layout_file(self.header(WDL_MAGIC), &wdl_tables, false);
+        let dtz_bytes = layout_file(self.header(DTZ_MAGIC), &dtz_tables, true);
+        fs::write(directory.join(format!("{}.rtbw", self.name)), &wdl_bytes)?;
+        fs::write(directory.join(format!("{}.rtbz", self.name)), &dtz_bytes)?;
+        Ok((wdl_bytes.len(), dtz_bytes.len()))
+    }
+
+    // Probe every position through the written files and compare with the solution.
+    fn verify(&self, solution: &Solution, directory: &Path) -> io::Result<()> {
+        let mut tablebases = Tablebases::open(&directory.to_string_lossy())?;
+        for (id, representative) in solution.representatives.iter().enumerate() {
+            if *representative == NO_POSITION {
+                continue;
+            }
+            let color = self.color_of(id);
+            let board = self.unpack(*representative, color);
+            let wdl = tablebases.probe_wdl(&board);
+            let dtz = tablebases.probe_dtz(&board);
+            if wdl != Some(solution.wdl[id]) || dtz != Some(solution.dtz[id] as i32) {
+                return Err(io::Error::other(format!(
+                    "{}: {wdl:?} {dtz:?} instead of {:?} {}",
+                    board.to_fen(),
+                    solution.wdl[id],
+                    solution.dtz[id]
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+// What generating a table found.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TableStats {
+    pub positions: usize,
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+    pub max_dtz: i32,
+    pub wdl_bytes: usize,
+    pub dtz_bytes: usize,
+}
+
+// Generate the WDL and DTZ tables for the material, like "KRvK", into the directory, and check
+// every position against them. Tables for the positions after captures and promotions have to
+// be in the directory already.
+pub fn generate(name: &str, directory: &Path) -> io::Result<TableStats> {
+    let generator = Generator::new(name)?;
+    let mut tablebases = Tablebases::open(&directory.to_string_lossy())?;
+    let solution = generator.solve(&mut tablebases)?;
+    let (wdl_bytes, dtz_bytes) = generator.write(&solution, directory)?;
+    generator.verify(&solution, directory)?;
+
+    let mut stats = TableStats {
+        wdl_bytes,
+        dtz_bytes,
+        ..Default::default()
+    };
+    for (id, representative) in solution.representatives.iter().enumerate() {
+        if *representative == NO_POSITION {
+            continue;
+        }
+        stats.positions += 1;
+        match solution.wdl[id] {
+            Wdl::Win => stats.wins += 1,
+            Wdl::Loss => stats.losses += 1,
+            _ => stats.draws += 1,
+        }
+        stats.max_dtz = stats.max_dtz.max(solution.dtz[id].abs() as i32);
+    }
+    Ok(stats)
+}
+
+// One sub-table, compressed.
+#[derive(Clone, Debug, Default)]
+struct Compressed {
+    // The flags and everything after them up to the symbol tree.
+    header: Vec<u8>,
+    sparse_index: Vec<u8>,
+    block_lengths: Vec<u8>,
+    blocks: Vec<u8>,
+}
+
+fn align(bytes: &mut Vec<u8>, alignment: usize) {
+    bytes.resize(bytes.len().div_ceil(alignment) * alignment, 0);
+}
+
+// Put the sub-tables (by side, then file) after the header, in the order the prober reads
+// them.
+fn layout_file(mut bytes: Vec<u8>, tables: &[Vec<Compressed>], is_dtz: bool) -> Vec<u8> {
+    let files = tables[0].len();
+    let each = |part: &dyn Fn(&Compressed) -> &Vec<u8>, bytes: &mut Vec<u8>| {
+        for file in 0..files {
+            for side in tables {
+                bytes.extend(part(&side[file]));
+            }
+        }
+    };
+    each(&|table| &table.header, &mut bytes);
+    if is_dtz {
+        align(&mut bytes, 2);
+    }
+    each(&|table| &table.sparse_index, &mut bytes);
+    each(&|table| &table.block_lengths, &mut bytes);
+    for file in 0..files {
+        for side in tables {
+            align(&mut bytes, 64);
+            bytes.extend(&side[file].blocks);
+        }
+    }
+    // The prober wants the size to be 16 more than a multiple of 64, for the checksum.
+    align(&mut bytes, 64);
+    bytes.resize(bytes.len() + 16, 0);
+    bytes
+}
+
+// Huffman code lengths for the frequencies, 0 for unused symbols. Rare symbols get their
+// frequencies raised until no code is too long.
+fn code_lengths(frequencies: &[u64]) -> Vec<usize> {
+    let mut frequencies = frequencies.to_vec();
+    loop {
+        let mut heap: BinaryHeap<Reverse<(u64, usize)>> = frequencies
+            .iter()
+            .enumerate()
+            .filter(|(_, frequency)| **frequency > 0)
+            .map(|(symbol, frequency)| Reverse((*frequency, symbol)))
+            .collect();
+        let mut parent = vec![usize::MAX; frequencies.len()];
+        while heap.len() > 1 {
+            let Reverse((first_frequency, first)) = heap.pop().unwrap_or_default();
+            let Reverse((second_frequency, second)) = heap.pop().unwrap_or_default();
+            let node = parent.len();
+            parent.push(usize::MAX);
+            parent[first] = node;
+            parent[second] = node;
+            heap.push(Reverse((first_frequency + second_frequency, node)));
+        }
+        let lengths: Vec<usize> = (0..frequencies.len())
+            .map(|symbol| {
+                let mut length = 0;
+                let mut node = symbol;
+                while parent[node] != usize::MAX {
+                    node = parent[node];
+                    length += 1;
+                }
+                length
+            })
+            .collect();
+        if lengths.iter().all(|length| *length <= MAX_CODE_LENGTH) {
+            return lengths;
+        }
+        for frequency in frequencies.iter_mut().filter(|frequency| **frequency > 0) {
+            *frequency = frequency.div_ceil(2);
+        }
+    }
+}
+
+// Compress the values of a sub-table. Runs of equal values are cut into powers of two, each
+// a symbol that pairs two of half the length, and the symbols get a canonical Huffman code.
+fn compress(values: &[u16], flags: u8) -> io::Result<Compressed> {
+    if values.iter().all(|value| *value == values[0]) {
+        return Ok(Compressed {
+            header: vec![flags | FLAG_SINGLE_VALUE, values[0] as u8],
+            ..Default::default()
+        });
+    }
+
+    // (value, log2 of the run length)
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < values.len() {
+        let run = values[i..]
+            .iter()
+            .take_while(|value| **value == values[i])
+            .count();
+        let mut left = run;
+        while left > 0 {
+            let log = left.ilog2().min(MAX_RUN_LOG);
+            tokens.push((values[i], log));
+            left -= 1 << log;
+        }
+        i += run;
+    }
+
+    // Every run length up to the longest one used, for each value, since longer runs are
+    // made of shorter ones.
+    let mut longest: FnvHashMap<u16, u32> = FnvHashMap::default();
+    for (value, log) in &tokens {
+        let entry = longest.entry(*value).or_insert(0);
+        *entry = (*entry).max(*log);
+    }
+    let mut symbols: Vec<(u16, u32)> = longest
+        .iter()
+        .flat_map(|(value, longest)| (0..=*longest).map(move |log| (*value, log)))
+        .collect();
+    symbols.sort_unstable();
+    if symbols.len() > MAX_SYMBOLS || symbols.iter().any(|(value, _)| *value >= 0xFFF) {
+        return Err(io::Error::other("too many different values to compress"));
+    }
+    let symbol_index: FnvHashMap<(u16, u32), usize> = symbols
+        .iter()
+        .enumerate()
+        .map(|(index, symbol)| (*symbol, index))
+        .collect();
+    let mut frequencies = vec![0; symbols.len()];
+    for token in &tokens {
+        frequencies[symbol_index[token]] += 1;
+    }
+    let lengths = code_lengths(&frequencies);
+
+    // Longer codes get lower symbol numbers, then come the symbols that only appear inside
+    // others.
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|symbol| (lengths[*symbol] == 0, Reverse(lengths[*symbol]), *symbol));
+    let mut id = vec![0; symbols.len()];
+    for (new_id, symbol) in order.iter().enumerate() {
+        id[*symbol] = new_id;
+    }
+    let used = lengths.iter().filter(|length| **length > 0);
+    let min_length = *used.clone().min().unwrap_or(&1);
+    let max_length = *used.max().unwrap_or(&1);
+    let mut count = vec![0; max_length + 2];
+    for length in lengths.iter().filter(|length| **length > 0) {
+        count[*length] += 1;
+    }
+    let mut lowest_symbol = vec![0; max_length + 2];
+    let mut base = vec![0u64; max_length + 2];
+    for length in (min_length..max_length).rev() {
+        lowest_symbol[length] = lowest_symbol[length + 1] + count[length + 1];
+        base[length] = (base[length + 1] + count[length + 1] as u64) / 2;
+    }
+    let code = |symbol: usize| {
+        let length = lengths[symbol];
+        (
+            base[length] + (id[symbol] - lowest_symbol[length]) as u64,
+            length,
+        )
+    };
+
+    // Fill the blocks. A symbol never straddles two of them.
+    let block_size = 1 << BLOCK_SIZE_LOG;
+    let mut blocks = Vec::new();
+    let mut block_starts = Vec::new();
+    let mut block_values = Vec::new();
+    let mut bits: Vec<bool> = Vec::new();
+    let mut values_in_block = 0;
+    let mut value_index = 0;
+    let finish_block = |bits: &mut Vec<bool>, blocks: &mut Vec<u8>| {
+        let mut block = vec![0u8; block_size];
+        for (bit, set) in bits.iter().enumerate() {
+            if *set {
+                block[bit / 8] |= 0x80 >> (bit % 8);
+            }
+        }
+        blocks.extend(block);
+        bits.clear();
+    };
+    for token in &tokens {
+        let (code, length) = code(symbol_index[token]);
+        let run = 1 << token.1;
+        if !bits.is_empty()
+            && (bits.len() + length > 8 * block_size || values_in_block + run > 1 << 16)
+        {
+            finish_block(&mut bits, &mut blocks);
+            block_values.push(values_in_block);
+            values_in_block = 0;
+        }
+        if bits.is_empty() {
+            block_starts.push(value_index);
+        }
+        bits.extend((0..length).rev().map(|bit| (code >> bit) & 1 == 1));
+        values_in_block += run;
+        value_index += run;
+    }
+    finish_block(&mut bits, &mut blocks);
+    block_values.push(values_in_block);
+    let num_blocks = block_values.len();
+
+    // One padding block after the last one, for the sparse index entries past the end.
+    let mut block_lengths = Vec::new();
+    for values in block_values.iter().chain(&[1]) {
+        block_lengths.extend(((values - 1) as u16).to_le_bytes());
+    }
+
+    let span = 1 << SPAN_LOG;
+    let mut sparse_index = Vec::new();
+    for k in 0..values.len().div_ceil(span) {
+        let middle = k * span + span / 2;
+        let (block, offset) = if middle < values.len() {
+            let block = block_starts.partition_point(|start| *start <= middle) - 1;
+            (block, middle - block_starts[block])
+        } else {
+            (num_blocks, middle - values.len())
+        };
+        sparse_index.extend((block as u32).to_le_bytes());
+        sparse_index.extend((offset as u16).to_le_bytes());
+    }
+
+    let mut header = vec![flags, BLOCK_SIZE_LOG, SPAN_LOG, 1];
+    header.extend((num_blocks as u32).to_le_bytes());
+    header.push(max_length as u8);
+    header.push(min_length as u8);
+    for lowest in &lowest_symbol[min_length..=max_length] {
+        header.extend((*lowest as u16).to_le_bytes());
+    }
+    header.extend((symbols.len() as u16).to_le_bytes());
+    let mut tree = vec![[0u8; 3]; symbols.len()];
+    for (symbol, (value, log)) in symbols.iter().enumerate() {
+        let (left, right) = if *log == 0 {
+            (*value as usize, 0xFFF)
+        } else {
+            let half = id[symbol_index[&(*value, log - 1)]];
+            (half, half)
+        };
+        tree[id[symbol]] = [
+            left as u8,
+            ((left >> 8) | ((right & 0xF) << 4)) as u8,
+            (right >> 4) as u8,
+        ];
+    }
+    header.extend(tree.into_iter().flatten());
+    if symbols.len() % 2 == 1 {
+        header.push(0);
+    }
+
+    Ok(Compressed {
+        header,
+        sparse_index,
+        block_lengths,
+        blocks,
+    })
+}
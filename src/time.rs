@@ -0,0 +1,213 @@
+use std::time::{Duration, Instant};
+
+// Moves we assume are left in the game when the clock doesn't say.
+const DEFAULT_MOVES_TO_GO: u32 = 30;
+// Never plan for more moves than this, even if the time control says so.
+const MAX_MOVES_TO_GO: u32 = 50;
+// How far the soft limit may be stretched on fail-lows and best move changes.
+const MAX_SOFT_SCALE: f64 = 3.0;
+
+// The state of our clock when the search starts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeControl {
+    // Time left on our clock.
+    pub remaining: Duration,
+    // Time added to our clock after each move.
+    pub increment: Duration,
+    // Moves until the next time control, if the clock has one.
+    pub moves_to_go: Option<u32>,
+    // Time lost per move between the engine and the clock (GUI, network, ...).
+    pub move_overhead: Duration,
+}
+
+// Decides how long a search may go on. Iterative deepening doesn't start a new iteration after
+// the soft limit, and an iteration is aborted when the hard limit is reached. The soft limit
+// is stretched when the search looks unsure of its best move.
+#[derive(Clone, Debug)]
+pub struct TimeManager {
+    start: Instant,
+    base_soft_limit: Duration,
+    hard_limit: Duration,
+    // Extra time for iterations that failed low, i. e. the best move's score dropped.
+    fail_low_scale: f64,
+    failed_low_this_iteration: bool,
+    // Extra time for best move changes between iterations, fading out when the move is stable.
+    instability: f64,
+}
+
+impl TimeManager {
+    // Compute the limits for a move, starting the clock now.
+    pub fn new(control: TimeControl) -> Self {
+        let usable = control.remaining.saturating_sub(control.move_overhead);
+        let moves_to_go = control
+            .moves_to_go
+            .unwrap_or(DEFAULT_MOVES_TO_GO)
+            .clamp(1, MAX_MOVES_TO_GO);
+
+        let (soft_limit, hard_limit) = if moves_to_go == 1 {
+            // Last move before the time control: use most of what's left, but keep a margin.
+            (usable.mul_f64(0.5), usable.mul_f64(0.9))
+        } else {
+            let soft = usable / moves_to_go + control.increment.mul_f64(0.75);
+            // Spending the increment is fine, spending more than a fraction of the clock is not.
+            let soft = soft.min(usable.mul_f64(0.4));
+            (soft, (soft * 4).min(usable.mul_f64(0.75)))
+        };
+        TimeManager {
+            start: Instant::now(),
+            base_soft_limit: soft_limit,
+            hard_limit: hard_limit.max(soft_limit),
+            fail_low_scale: 1.0,
+            failed_low_this_iteration: false,
+            instability: 0.0,
+        }
+    }
+
+    // Search for exactly this long, as with "go movetime".
+    pub fn fixed(move_time: Duration) -> Self {
+        TimeManager {
+            start: Instant::now(),
+            base_soft_limit: move_time,
+            hard_limit: move_time,
+            fail_low_scale: 1.0,
+            failed_low_this_iteration: false,
+            instability: 0.0,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    // Time after which no new iteration is started, including the current extensions.
+    pub fn soft_limit(&self) -> Duration {
+        let scale = (self.fail_low_scale * (1.0 + self.instability)).min(MAX_SOFT_SCALE);
+        self.base_soft_limit.mul_f64(scale).min(self.hard_limit)
+    }
+
+    // Time after which the search has to stop, even in the middle of an iteration.
+    pub fn hard_limit(&self) -> Duration {
+        self.hard_limit
+    }
+
+    // Should iterative deepening start another iteration?
+    pub fn should_start_iteration(&self) -> bool {
+        self.elapsed() < self.soft_limit()
+    }
+
+    pub fn is_hard_limit_reached(&self) -> bool {
+        self.elapsed() >= self.hard_limit
+    }
+
+    // The score fell below the aspiration window: the best move is in trouble, take more time
+    // to find a replacement.
+    pub fn on_fail_low(&mut self) {
+        self.fail_low_scale = (self.fail_low_scale * 1.5).min(2.0);
+        self.failed_low_this_iteration = true;
+    }
+
+    // Called after every completed iteration, with whether its best move differs from the
+    // previous iteration's one.
+    pub fn on_iteration_end(&mut self, best_move_changed: bool) {
+        self.instability *= 0.5;
+        if best_move_changed {
+            self.instability += 0.8;
+        }
+        // A fail-low only matters until the search settles again.
+        if !self.failed_low_this_iteration {
+            self.fail_low_scale = 1.0 + (self.fail_low_scale - 1.0) * 0.5;
+        }
+        self.failed_low_this_iteration = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Board, SearchConfig, SearchLimits, Searcher};
+
+    fn control(remaining: u64, increment: u64, moves_to_go: Option<u32>) -> TimeControl {
+        TimeControl {
+            remaining: Duration::from_millis(remaining),
+            increment: Duration::from_millis(increment),
+            moves_to_go,
+            move_overhead: Duration::from_millis(50),
+        }
+    }
+
+    fn millis(duration: Duration) -> u128 {
+        duration.as_millis()
+    }
+
+    #[test]
+    fn the_clock_is_shared_out_over_the_moves_to_go() {
+        // 59.95s over 30 moves plus three quarters of the increment.
+        let time = TimeManager::new(control(60_000, 1_000, None));
+        assert_eq!(millis(time.soft_limit()), 2748);
+        assert_eq!(time.hard_limit(), time.soft_limit() * 4);
+        let time = TimeManager::new(control(60_000, 0, Some(10)));
+        assert_eq!(millis(time.soft_limit()), 5995);
+    }
+
+    #[test]
+    fn the_last_move_before_the_control_keeps_a_margin() {
+        let time = TimeManager::new(control(10_050, 0, Some(1)));
+        assert_eq!(millis(time.soft_limit()), 5000);
+        assert_eq!(millis(time.hard_limit()), 9000);
+    }
+
+    #[test]
+    fn a_short_clock_is_never_overspent() {
+        for (remaining, increment) in [(1_000, 5_000), (100, 0), (30, 1_000), (300, 300)] {
+            let time = TimeManager::new(control(remaining, increment, None));
+            assert!(time.soft_limit() <= time.hard_limit());
+            assert!(millis(time.hard_limit()) * 4 <= 3 * remaining.saturating_sub(50) as u128);
+        }
+    }
+
+    #[test]
+    fn unsure_searches_get_more_time_up_to_the_hard_limit() {
+        let mut time = TimeManager::new(control(60_000, 0, None));
+        let base = time.soft_limit();
+        time.on_fail_low();
+        time.on_iteration_end(false);
+        assert_eq!(time.soft_limit(), base.mul_f64(1.5));
+        time.on_iteration_end(true);
+        assert!(time.soft_limit() > base.mul_f64(1.5));
+        for _ in 0..5 {
+            time.on_fail_low();
+            time.on_iteration_end(true);
+            assert!(time.soft_limit() <= base.mul_f64(MAX_SOFT_SCALE));
+            assert!(time.soft_limit() <= time.hard_limit());
+        }
+        // Once the best move settles the extensions fade away.
+        for _ in 0..20 {
+            time.on_iteration_end(false);
+        }
+        assert!(time.soft_limit() < base.mul_f64(1.01));
+    }
+
+    #[test]
+    fn fixed_move_time_has_one_limit() {
+        let time = TimeManager::fixed(Duration::from_millis(200));
+        assert_eq!(time.soft_limit(), time.hard_limit());
+        assert!(time.should_start_iteration());
+        assert!(!time.is_hard_limit_reached());
+    }
+
+    #[test]
+    fn a_search_on_the_clock_stops_in_time() {
+        let mut searcher = Searcher::new(SearchConfig::default(), 1);
+        let limits = SearchLimits {
+            time_control: Some(control(2_000, 0, None)),
+            ..Default::default()
+        };
+        let hard_limit = TimeManager::new(control(2_000, 0, None)).hard_limit();
+        let start = Instant::now();
+        let result = searcher.search_with_limits(&Board::new(), &limits, |_| ());
+        // The clock is looked at every thousand nodes or so, leave room for those. Well
+        // short of flagging either way.
+        assert!(start.elapsed() < hard_limit + Duration::from_millis(500));
+        assert!(result.best_move.is_some());
+    }
+}
@@ -0,0 +1,855 @@
+use crate::endgame::{self, MaterialSignature};
+use crate::fairy;
+use crate::nnue::Network;
+use crate::params::EvalParams;
+use crate::pgn::{self, GameResult};
+use crate::search::{SearchConfig, SearchLimits, Searcher};
+use crate::syzygy::Tablebases;
+use crate::time::TimeControl;
+use crate::tt::MATE_SCORE;
+use crate::{zobrist, Board, BoardMove, Color, PieceKind};
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{fs, thread};
+
+// Games between two players, each either a configuration of this engine searched in-process
+// or an external engine spoken to over UCI. Games end by the rules, or are adjudicated by the
+// tablebases, by the scores the players report, or when they go on for too long.
+
+// Time an external engine gets on top of its clock or move time before it loses on time.
+const TIME_MARGIN: Duration = Duration::from_millis(100);
+// How long an external engine may take to start up or to say it's ready.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Neither side can ever mate: no pawns, rooks or queens, and at most one minor piece each.
+pub fn is_insufficient_material(board: &Board) -> bool {
+    // Pieces in hand can always be dropped to mate with, and fairy pieces are taken to be
+    // able to mate.
+    if board.pockets.is_some() || fairy::has_fairy_pieces(board) {
+        return false;
+    }
+    let material = MaterialSignature::of(board);
+    [Color::White, Color::Black].iter().all(|&color| {
+        material.count(color, PieceKind::Pawn) == 0
+            && material.count(color, PieceKind::Rook) == 0
+            && material.count(color, PieceKind::Queen) == 0
+            && material.piece_count(color) <= 1
+    })
+}
+
+// The result and the reason if the game is over with the color to move, by the rules: mate,
+// stalemate, threefold repetition, the fifty-move rule or insufficient material. `history`
+// holds the keys of the earlier positions of the game.
+pub fn game_over(
+    board: &Board,
+    color: Color,
+    history: &[u64],
+) -> Option<(GameResult, &'static str)> {
+    if board.legal_moves().is_empty() {
+        return Some(if !board.is_in_check(color) {
+            (GameResult::Draw, "stalemate")
+        } else if color.is_white() {
+            (GameResult::BlackWins, "checkmate")
+        } else {
+            (GameResult::WhiteWins, "checkmate")
+        });
+    }
+    let key = zobrist::hash(board);
+    let repetitions = history
+        .iter()
+        .rev()
+        .take(board.halfmove_clock as usize)
+        .filter(|old_key| **old_key == key)
+        .count();
+    if repetitions >= 2 {
+        Some((GameResult::Draw, "threefold repetition"))
+    } else if board.halfmove_clock >= 100 {
+        Some((GameResult::Draw, "fifty-move rule"))
+    } else if is_insufficient_material(board) {
+        Some((GameResult::Draw, "insufficient material"))
+    } else {
+        None
+    }
+}
+
+// Starting positions, one FEN (or EPD, only the first four fields count) per line, or the
+// final positions of the games of a PGN file when the name ends in ".pgn".
+pub fn load_openings(path: &str) -> io::Result<Vec<String>> {
+    let text = fs::read_to_string(path)?;
+    if path.ends_with(".pgn") {
+        return Ok(pgn::parse_pgn(&text)
+            .iter()
+            .map(|game| match game.replay().last() {
+                Some((board, (start_square, a_move))) => {
+                    board.make_move(*start_square, *a_move).to_fen()
+                }
+                None => game.starting_position().to_fen(),
+            })
+            .collect());
+    }
+    Ok(text
+        .lines()
+        .map(|line| line.split_whitespace().take(4).collect::<Vec<_>>())
+        .filter(|fields| fields.len() == 4)
+        .map(|fields| format!("{} 0 1", fields.join(" ")))
+        .collect())
+}
+
+// How long the players may think. Whatever is set applies, and at least one limit must be, or
+// the searches never end.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchLimits {
+    pub nodes: Option<u64>,
+    pub depth: Option<u8>,
+    pub move_time: Option<Duration>,
+    // Starting time on each clock and the increment per move.
+    pub clock: Option<(Duration, Duration)>,
+}
+
+// Both clocks and the increment, when the game is played with a clock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Clocks {
+    pub remaining: [Duration; 2],
+    pub increment: Duration,
+}
+
+// The game so far, as a player gets to see it before its move.
+pub struct GamePosition<'a> {
+    pub start_fen: &'a str,
+    pub moves: &'a [String],
+    pub board: &'a Board,
+    pub history: &'a [u64],
+}
+
+pub trait Player {
+    fn name(&self) -> &str;
+
+    fn new_game(&mut self) -> io::Result<()>;
+
+    // The move to play, with the score for the side to move if the player gave one.
+    fn go(
+        &mut self,
+        position: &GamePosition,
+        limits: &MatchLimits,
+        clocks: Option<Clocks>,
+    ) -> io::Result<(BoardMove, Option<i32>)>;
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+// This engine, searched in the calling thread.
+pub struct InternalPlayer {
+    name: String,
+    searcher: Searcher,
+}
+
+impl InternalPlayer {
+    // Options are the ones of the UCI interface that make sense here (Hash, EvalFile,
+    // NNUEFile, SyzygyPath, SyzygyProbeLimit), and the switches of SearchConfig by their
+    // field names, so a single feature can be turned off: null_move_pruning=false.
+    pub fn new(name: &str, options: &[(String, String)]) -> io::Result<Self> {
+        let mut config = SearchConfig::default();
+        let mut hash_megabytes = 16;
+        let mut params = None;
+        let mut network = None;
+        let mut tablebases = None;
+        for (option, value) in options {
+            let switch = || match value.as_str() {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(invalid_input(format!("{option} takes true or false"))),
+            };
+            let number = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| invalid_input(format!("{option} takes a number")))
+            };
+            match option.to_lowercase().as_str() {
+                "hash" => hash_megabytes = number()?.max(1),
+                "evalfile" => {
+                    params =
+                        Some(EvalParams::load(value).map_err(|error| {
+                            invalid_input(format!("can't load {value}: {error}"))
+                        })?)
+                }
+                "nnuefile" => network = Some(Arc::new(Network::load(value)?)),
+                "syzygypath" => tablebases = Some(Tablebases::open(value)?),
+                "syzygyprobelimit" => config.syzygy_probe_limit = number()?,
+                "syzygy50moverule" => config.syzygy_50_move_rule = switch()?,
+                "null_move_pruning" => config.null_move_pruning = switch()?,
+                "late_move_reductions" => config.late_move_reductions = switch()?,
+                "futility_pruning" => config.futility_pruning = switch()?,
+                "reverse_futility_pruning" => config.reverse_futility_pruning = switch()?,
+                "razoring" => config.razoring = switch()?,
+                "check_extensions" => config.check_extensions = switch()?,
+                "principal_variation_search" => config.principal_variation_search = switch()?,
+                "aspiration_windows" => config.aspiration_windows = switch()?,
+                _ => return Err(invalid_input(format!("unknown option {option}"))),
+            }
+        }
+        let mut searcher = Searcher::new(config, hash_megabytes);
+        if let Some(params) = params {
+            searcher.set_eval_params(params);
+        }
+        searcher.set_network(network);
+        searcher.set_tablebases(tablebases);
+        endgame::init();
+        Ok(InternalPlayer {
+            name: name.to_string(),
+            searcher,
+        })
+    }
+}
+
+impl Player for InternalPlayer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.searcher.new_game();
+        Ok(())
+    }
+
+    fn go(
+        &mut self,
+        position: &GamePosition,
+        limits: &MatchLimits,
+        clocks: Option<Clocks>,
+    ) -> io::Result<(BoardMove, Option<i32>)> {
+        let search_limits = SearchLimits {
+            nodes: limits.nodes,
+            depth: limits.depth,
+            move_time: limits.move_time,
+            time_control: clocks.map(|clocks| TimeControl {
+                remaining: clocks.remaining[position.board.side_to_move().index()],
+                increment: clocks.increment,
+                moves_to_go: None,
+                move_overhead: Duration::ZERO,
+            }),
+            ..Default::default()
+        };
+        self.searcher.set_game_history(position.history.to_vec());
+        let result = self
+            .searcher
+            .search_with_limits(position.board, &search_limits, |_| ());
+        let best_move = result
+            .best_move
+            .ok_or_else(|| invalid_input("no move in a position that has some".to_string()))?;
+        Ok((best_move, Some(result.score)))
+    }
+}
+
+// An external engine, run as a child process.
+pub struct UciPlayer {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    // Lines the engine writes, read on a thread of their own so waiting for them can time out.
+    lines: Receiver<String>,
+}
+
+impl UciPlayer {
+    pub fn start(name: &str, command: &str, options: &[(String, String)]) -> io::Result<Self> {
+        let mut child = Command::new(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| {
+                io::Error::new(error.kind(), format!("can't run {command}: {error}"))
+            })?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        let mut player = UciPlayer {
+            name: name.to_string(),
+            child,
+            stdin,
+            lines,
+        };
+        player.send("uci")?;
+        player.wait_for("uciok", Some(STARTUP_TIMEOUT))?;
+        for (option, value) in options {
+            player.send(&format!("setoption name {option} value {value}"))?;
+        }
+        Ok(player)
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()
+    }
+
+    fn read_line(&mut self, deadline: Option<Instant>) -> io::Result<String> {
+        let line = match deadline {
+            Some(deadline) => self
+                .lines
+                .recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => self
+                .lines
+                .recv()
+                .map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        line.map_err(|error| match error {
+            RecvTimeoutError::Timeout => {
+                io::Error::new(io::ErrorKind::TimedOut, format!("{} timed out", self.name))
+            }
+            RecvTimeoutError::Disconnected => {
+                io::Error::new(io::ErrorKind::UnexpectedEof, format!("{} quit", self.name))
+            }
+        })
+    }
+
+    fn wait_for(&mut self, expected: &str, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        while self.read_line(deadline)?.trim() != expected {}
+        Ok(())
+    }
+}
+
+impl Player for UciPlayer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn new_game(&mut self) -> io::Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.wait_for("readyok", Some(STARTUP_TIMEOUT))
+    }
+
+    fn go(
+        &mut self,
+        position: &GamePosition,
+        limits: &MatchLimits,
+        clocks: Option<Clocks>,
+    ) -> io::Result<(BoardMove, Option<i32>)> {
+        let mut command = format!("position fen {}", position.start_fen);
+        if !position.moves.is_empty() {
+            command.push_str(" moves ");
+            command.push_str(&position.moves.join(" "));
+        }
+        self.send(&command)?;
+
+        let mut command = "go".to_string();
+        let mut time_limit = None;
+        if let Some(clocks) = clocks {
+            command.push_str(&format!(
+                " wtime {} btime {} winc {} binc {}",
+                clocks.remaining[0].as_millis(),
+                clocks.remaining[1].as_millis(),
+                clocks.increment.as_millis(),
+                clocks.increment.as_millis()
+            ));
+            time_limit = Some(clocks.remaining[position.board.side_to_move().index()]);
+        }
+        if let Some(nodes) = limits.nodes {
+            command.push_str(&format!(" nodes {nodes}"));
+        }
+        if let Some(depth) = limits.depth {
+            command.push_str(&format!(" depth {depth}"));
+        }
+        if let Some(move_time) = limits.move_time {
+            command.push_str(&format!(" movetime {}", move_time.as_millis()));
+            time_limit = Some(time_limit.map_or(move_time, |limit: Duration| limit.min(move_time)));
+        }
+        self.send(&command)?;
+
+        let deadline = time_limit.map(|limit| Instant::now() + limit + TIME_MARGIN);
+        let mut score = None;
+        loop {
+            let line = self.read_line(deadline)?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.first().copied() {
+                Some("info") => {
+                    if let Some(index) = tokens.iter().position(|token| *token == "score") {
+                        let value = tokens.get(index + 2).and_then(|value| value.parse().ok());
+                        match (tokens.get(index + 1).copied(), value) {
+                            (Some("cp"), Some(value)) => score = Some(value),
+                            (Some("mate"), Some(moves)) => {
+                                score = Some(if moves > 0 {
+                                    MATE_SCORE - 2 * moves + 1
+                                } else {
+                                    -MATE_SCORE - 2 * moves
+                                })
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+                Some("bestmove") => {
+                    let uci_move = tokens.get(1).copied().unwrap_or("0000");
+                    let best_move = position.board.move_from_uci(uci_move).ok_or_else(|| {
+                        invalid_input(format!("{} played illegal move {uci_move}", self.name))
+                    })?;
+                    return Ok((best_move, score));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl Drop for UciPlayer {
+    // Ask the engine to quit, and kill it if it doesn't listen.
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..100 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+// A player as given on the command line: an external engine when there's a command, this
+// engine otherwise.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PlayerSpec {
+    pub name: String,
+    pub command: Option<String>,
+    pub options: Vec<(String, String)>,
+}
+
+impl PlayerSpec {
+    pub fn start(&self) -> io::Result<Box<dyn Player>> {
+        Ok(match &self.command {
+            Some(command) => Box::new(UciPlayer::start(&self.name, command, &self.options)?),
+            None => Box::new(InternalPlayer::new(&self.name, &self.options)?),
+        })
+    }
+}
+
+// When to call a game before it's over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Adjudication {
+    // A side resigns when the scores of both players have put it this far behind for this
+    // many plies in a row.
+    pub resign_score: Option<i32>,
+    pub resign_plies: usize,
+    // A draw is agreed from this ply on, when the scores have stayed this close to zero for
+    // this many plies in a row.
+    pub draw_score: Option<i32>,
+    pub draw_plies: usize,
+    pub draw_after_ply: usize,
+    // Games still going after this many plies are drawn.
+    pub max_plies: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Adjudication {
+            resign_score: Some(1000),
+            resign_plies: 6,
+            draw_score: Some(10),
+            draw_plies: 10,
+            draw_after_ply: 80,
+            max_plies: 600,
+        }
+    }
+}
+
+impl Adjudication {
+    // The result the scores so far (for white, one per ply) call for, if any.
+    fn by_score(&self, scores: &[Option<i32>]) -> Option<(GameResult, &'static str)> {
+        let last = |plies: usize| {
+            (scores.len() >= plies)
+                .then(|| &scores[scores.len() - plies..])
+                .filter(|last| last.iter().all(|score| score.is_some()))
+                .map(|last| last.iter().flatten())
+        };
+        if let Some(resign_score) = self.resign_score {
+            if let Some(mut last) = last(self.resign_plies.max(1)) {
+                if last.clone().all(|score| *score >= resign_score) {
+                    return Some((GameResult::WhiteWins, "adjudicated by score"));
+                }
+                if last.all(|score| *score <= -resign_score) {
+                    return Some((GameResult::BlackWins, "adjudicated by score"));
+                }
+            }
+        }
+        if let Some(draw_score) = self.draw_score {
+            if scores.len() >= self.draw_after_ply {
+                if let Some(mut last) = last(self.draw_plies.max(1)) {
+                    if last.all(|score| score.abs() <= draw_score) {
+                        return Some((GameResult::Draw, "adjudicated by score"));
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// How a game ended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameOutcome {
+    pub result: GameResult,
+    pub reason: String,
+    pub plies: usize,
+}
+
+// Plays a game from the position, white first in `players`. A player that fails (crashes,
+// plays an illegal move, runs out of time) loses.
+pub fn play_game(
+    mut players: [&mut dyn Player; 2],
+    start_fen: &str,
+    limits: &MatchLimits,
+    adjudication: &Adjudication,
+    mut tablebases: Option<&mut Tablebases>,
+) -> GameOutcome {
+    let mut board = Board::from_fen(start_fen);
+    let mut history = Vec::new();
+    let mut moves = Vec::new();
+    // The score each move was played with, for white.
+    let mut scores = Vec::new();
+    let mut clocks = limits.clock.map(|(base, increment)| Clocks {
+        remaining: [base; 2],
+        increment,
+    });
+    let loss_for = |color: Color| {
+        if color.is_white() {
+            GameResult::BlackWins
+        } else {
+            GameResult::WhiteWins
+        }
+    };
+
+    for (player, color) in players.iter_mut().zip([Color::White, Color::Black]) {
+        if let Err(error) = player.new_game() {
+            return GameOutcome {
+                result: loss_for(color),
+                reason: error.to_string(),
+                plies: 0,
+            };
+        }
+    }
+    let (result, reason) = loop {
+        let color = board.side_to_move();
+        if let Some((result, reason)) = game_over(&board, color, &history) {
+            break (result, reason.to_string());
+        }
+        if let Some(tablebases) = tablebases.as_deref_mut() {
+            if let Some(wdl) = tablebases.probe_wdl(&board) {
+                let result = match wdl.value() {
+                    2 => loss_for(color.reverse()),
+                    -2 => loss_for(color),
+                    _ => GameResult::Draw,
+                };
+                break (result, "adjudicated by tablebases".to_string());
+            }
+        }
+        if let Some((result, reason)) = adjudication.by_score(&scores) {
+            break (result, reason.to_string());
+        }
+        if moves.len() >= adjudication.max_plies {
+            break (GameResult::Draw, "too long".to_string());
+        }
+
+        let position = GamePosition {
+            start_fen,
+            moves: &moves,
+            board: &board,
+            history: &history,
+        };
+        let started = Instant::now();
+        let played = players[color.index()].go(&position, limits, clocks);
+        let elapsed = started.elapsed();
+        let ((start_square, a_move), score) = match played {
+            Ok(played) => played,
+            Err(error) => break (loss_for(color), error.to_string()),
+        };
+        if let Some(clocks) = &mut clocks {
+            let remaining = &mut clocks.remaining[color.index()];
+            if elapsed > *remaining + TIME_MARGIN {
+                let name = players[color.index()].name();
+                break (loss_for(color), format!("{name} lost on time"));
+            }
+            *remaining = remaining.saturating_sub(elapsed) + clocks.increment;
+        }
+        scores.push(score.map(|score| if color.is_white() { score } else { -score }));
+        moves.push(a_move.to_uci(start_square, color));
+        history.push(zobrist::hash(&board));
+        board = board.make_move(start_square, a_move);
+    };
+    GameOutcome {
+        result,
+        reason,
+        plies: moves.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Plays the moves it's given, one per turn, with a score for the side to move.
+    struct Script {
+        moves: Vec<&'static str>,
+        score: Option<i32>,
+    }
+
+    impl Player for Script {
+        fn name(&self) -> &str {
+            "script"
+        }
+
+        fn new_game(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn go(
+            &mut self,
+            position: &GamePosition,
+            _: &MatchLimits,
+            _: Option<Clocks>,
+        ) -> io::Result<(BoardMove, Option<i32>)> {
+            let uci = self.moves.remove(0);
+            let a_move = position
+                .board
+                .move_from_uci(uci)
+                .ok_or_else(|| invalid_input(format!("script broke at {uci}")))?;
+            Ok((a_move, self.score))
+        }
+    }
+
+    fn script(moves: &[&'static str], score: Option<i32>) -> Script {
+        Script {
+            moves: moves.to_vec(),
+            score,
+        }
+    }
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    const NO_SCORES: Adjudication = Adjudication {
+        resign_score: None,
+        resign_plies: 6,
+        draw_score: None,
+        draw_plies: 10,
+        draw_after_ply: 80,
+        max_plies: 600,
+    };
+
+    fn over(fen: &str, history: &[u64]) -> Option<(GameResult, &'static str)> {
+        let board = Board::from_fen(fen);
+        game_over(&board, board.side_to_move(), history)
+    }
+
+    #[test]
+    fn games_end_by_the_rules() {
+        let mate = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert_eq!(over(mate, &[]), Some((GameResult::BlackWins, "checkmate")));
+        let stalemate = "k7/2Q5/1K6/8/8/8/8/8 b - - 0 1";
+        assert_eq!(over(stalemate, &[]), Some((GameResult::Draw, "stalemate")));
+        let fifty = "4k3/8/8/8/8/8/8/R3K3 w - - 100 80";
+        assert_eq!(
+            over(fifty, &[]),
+            Some((GameResult::Draw, "fifty-move rule"))
+        );
+        let bare = "4k3/8/8/8/8/8/8/2B1K3 w - - 0 1";
+        assert_eq!(
+            over(bare, &[]),
+            Some((GameResult::Draw, "insufficient material"))
+        );
+        assert_eq!(over(START, &[]), None);
+
+        let repeated = "4k3/8/8/8/8/8/8/R3K3 w - - 8 20";
+        let key = zobrist::hash(&Board::from_fen(repeated));
+        let threefold = Some((GameResult::Draw, "threefold repetition"));
+        assert_eq!(over(repeated, &[key, 1, 2, 3, key, 4, 5, 6]), threefold);
+        assert_eq!(over(repeated, &[key, 4, 5, 6]), None);
+        // Repetitions from before the last capture or pawn move don't count.
+        let reset = "4k3/8/8/8/8/8/8/R3K3 w - - 3 20";
+        assert_eq!(over(reset, &[key, 1, 2, 3, key, 4, 5, 6]), None);
+    }
+
+    #[test]
+    fn only_lone_minor_pieces_are_insufficient() {
+        let insufficient = |fen: &str| is_insufficient_material(&Board::from_fen(fen));
+        assert!(insufficient("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert!(insufficient("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(insufficient("4kn2/8/8/8/8/8/8/2B1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert!(!insufficient("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"));
+    }
+
+    #[test]
+    fn scores_adjudicate_wins_and_draws() {
+        let adjudication = Adjudication {
+            resign_score: Some(500),
+            resign_plies: 3,
+            draw_score: Some(10),
+            draw_plies: 4,
+            draw_after_ply: 6,
+            max_plies: 600,
+        };
+        let win = Some((GameResult::WhiteWins, "adjudicated by score"));
+        let loss = Some((GameResult::BlackWins, "adjudicated by score"));
+        let draw = Some((GameResult::Draw, "adjudicated by score"));
+        assert_eq!(
+            adjudication.by_score(&[Some(0), Some(600), Some(500), Some(900)]),
+            win
+        );
+        assert_eq!(
+            adjudication.by_score(&[Some(-600), Some(-700), Some(-800)]),
+            loss
+        );
+        assert_eq!(
+            adjudication.by_score(&[Some(600), Some(400), Some(900)]),
+            None
+        );
+        // A player that gives no score can't be adjudicated against.
+        assert_eq!(adjudication.by_score(&[Some(600), None, Some(900)]), None);
+
+        let quiet = [Some(5); 6];
+        assert_eq!(adjudication.by_score(&quiet), draw);
+        assert_eq!(adjudication.by_score(&quiet[..5]), None);
+        let mut lively = quiet;
+        lively[3] = Some(30);
+        assert_eq!(adjudication.by_score(&lively), None);
+    }
+
+    #[test]
+    fn scripted_games_are_played_to_the_end() {
+        let mut white = script(&["f2f3", "g2g4"], None);
+        let mut black = script(&["e7e5", "d8h4"], None);
+        let outcome = play_game(
+            [&mut white, &mut black],
+            START,
+            &MatchLimits::default(),
+            &NO_SCORES,
+            None,
+        );
+        assert_eq!(
+            outcome,
+            GameOutcome {
+                result: GameResult::BlackWins,
+                reason: "checkmate".to_string(),
+                plies: 4,
+            }
+        );
+
+        let too_long = Adjudication {
+            max_plies: 2,
+            ..NO_SCORES
+        };
+        let mut white = script(&["g1f3", "f3g1"], None);
+        let mut black = script(&["g8f6", "f6g8"], None);
+        let outcome = play_game(
+            [&mut white, &mut black],
+            START,
+            &MatchLimits::default(),
+            &too_long,
+            None,
+        );
+        assert_eq!(
+            (outcome.result, outcome.reason),
+            (GameResult::Draw, "too long".to_string())
+        );
+    }
+
+    #[test]
+    fn failing_players_lose() {
+        let mut white = script(&["e2e4", "e1e3"], None);
+        let mut black = script(&["e7e5"], None);
+        let outcome = play_game(
+            [&mut white, &mut black],
+            START,
+            &MatchLimits::default(),
+            &NO_SCORES,
+            None,
+        );
+        assert_eq!(outcome.result, GameResult::BlackWins);
+        assert_eq!(outcome.reason, "script broke at e1e3");
+        assert_eq!(outcome.plies, 2);
+    }
+
+    #[test]
+    fn reported_scores_end_the_game() {
+        let resign = Adjudication {
+            resign_score: Some(1000),
+            resign_plies: 2,
+            ..NO_SCORES
+        };
+        // Both players see white winning: white's score is for white, black's for black.
+        let mut white = script(&["g1f3", "f3g1"], Some(1500));
+        let mut black = script(&["g8f6", "f6g8"], Some(-1500));
+        let outcome = play_game(
+            [&mut white, &mut black],
+            START,
+            &MatchLimits::default(),
+            &resign,
+            None,
+        );
+        assert_eq!(outcome.result, GameResult::WhiteWins);
+        assert_eq!(outcome.reason, "adjudicated by score");
+        assert_eq!(outcome.plies, 2);
+    }
+
+    #[test]
+    fn internal_players_take_search_options() {
+        let option = |name: &str, value: &str| vec![(name.to_string(), value.to_string())];
+        assert!(InternalPlayer::new("a", &option("null_move_pruning", "false")).is_ok());
+        assert!(InternalPlayer::new("a", &option("Hash", "4")).is_ok());
+        for (name, value) in [("Hash", "lots"), ("razoring", "maybe"), ("sparkle", "1")] {
+            let error = InternalPlayer::new("a", &option(name, value))
+                .err()
+                .unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        }
+
+        let limits = MatchLimits {
+            depth: Some(4),
+            ..Default::default()
+        };
+        let mut white = InternalPlayer::new("white", &option("Hash", "1")).unwrap();
+        let mut black = InternalPlayer::new("black", &option("Hash", "1")).unwrap();
+        let outcome = play_game(
+            [&mut white, &mut black],
+            "k7/8/2K5/8/8/8/8/7R w - - 0 1",
+            &limits,
+            &Adjudication::default(),
+            None,
+        );
+        assert_eq!(outcome.result, GameResult::WhiteWins);
+        assert_eq!(outcome.reason, "checkmate");
+    }
+
+    #[test]
+    fn openings_keep_the_position_fields() {
+        let path = std::env::temp_dir().join(format!("openings-{}.epd", std::process::id()));
+        fs::write(
+            &path,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 id \"e4\";\n\n\
+             r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3\n",
+        )
+        .unwrap();
+        let openings = load_openings(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            openings.unwrap(),
+            [
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::ordering::MAX_PLY;
+use crate::BoardMove;
+
+// Scores above this are mates, and get adjusted by the distance to the root when stored.
+pub const MATE_SCORE: i32 = 32000;
+pub const MATE_BOUND: i32 = MATE_SCORE - 1000;
+// Wins found in the tablebases score just below the mates, also minus the distance to the
+// root, so they get adjusted the same way.
+pub const TB_WIN_SCORE: i32 = MATE_BOUND - 1 - MAX_PLY as i32;
+pub const TB_WIN_BOUND: i32 = TB_WIN_SCORE - MAX_PLY as i32;
+
+// What kind of score an entry holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    // The score is exact (a PV node).
+    Exact,
+    // The real score is at least this much (the node failed high).
+    Lower,
+    // The real score is at most this much (the node failed low).
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub key: u64,
+    pub best_move: Option<BoardMove>,
+    pub score: i32,
+    pub depth: i32,
+    pub bound: Bound,
+}
+
+// Hash table of searched positions, indexed by their Zobrist key.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+}
+
+impl TranspositionTable {
+    // A table that uses around the given amount of megabytes.
+    pub fn new(megabytes: usize) -> Self {
+        let size = (megabytes * 1024 * 1024 / std::mem::size_of::<Option<Entry>>()).max(1);
+        TranspositionTable {
+            entries: vec![None; size],
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    pub fn probe(&self, key: u64) -> Option<Entry> {
+        self.entries[self.index(key)].filter(|entry| entry.key == key)
+    }
+
+    // Replace the slot unless it holds a deeper search of the same position.
+    pub fn store(
+        &mut self,
+        key: u64,
+        best_move: Option<BoardMove>,
+        score: i32,
+        depth: i32,
+        bound: Bound,
+    ) {
+        let index = self.index(key);
+        let best_move = match self.entries[index] {
+            Some(old) if old.key == key => {
+                if old.depth > depth + 2 && bound != Bound::Exact {
+                    return;
+                }
+                // Keep the old move if this search didn't find one.
+                best_move.or(old.best_move)
+            }
+            _ => best_move,
+        };
+        self.entries[index] = Some(Entry {
+            key,
+            best_move,
+            score,
+            depth,
+            bound,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+    }
+
+    // How full the table is, in permille, from a sample of the first thousand slots.
+    pub fn hashfull(&self) -> u32 {
+        let sample = self.entries.len().min(1000);
+        let used = self.entries[..sample]
+            .iter()
+            .filter(|entry| entry.is_some())
+            .count();
+        (used * 1000 / sample) as u32
+    }
+}
+
+// Mate (and tablebase win) scores are stored relative to the node, not the root, so they stay
+// right when the position is reached through a different path.
+pub fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score > TB_WIN_BOUND {
+        score + ply as i32
+    } else if score < -TB_WIN_BOUND {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+pub fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score > TB_WIN_BOUND {
+        score - ply as i32
+    } else if score < -TB_WIN_BOUND {
+        score + ply as i32
+    } else {
+        score
+    }
+}
@@ -0,0 +1,638 @@
+use crate::book::{BookSelection, OpeningBook};
+use crate::epd::{self, Epd};
+use crate::mate::{self, MateLimits, MateResult};
+use crate::nnue::Network;
+use crate::params::EvalParams;
+use crate::search::{mate_in, perft, SearchInfo, SearchLimits};
+use crate::syzygy::{self, Tablebases};
+use crate::time::TimeControl;
+use crate::{endgame, pawns, pieces};
+use crate::{eval, zobrist, Board, BoardMove, Color, SearchConfig, Searcher};
+use std::fs;
+use std::io::{self, BufRead};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const ENGINE_NAME: &str = "chess";
+const DEFAULT_HASH_MEGABYTES: usize = 16;
+const DEFAULT_MOVE_OVERHEAD: u64 = 30;
+
+// State kept between the commands of a UCI session.
+struct Uci {
+    board: Board,
+    // Keys of the positions before the current one, for repetition detection.
+    game_history: Vec<u64>,
+    searcher: Arc<Mutex<Searcher>>,
+    stop: Arc<AtomicBool>,
+    // Set by "stop" (and "quit"), so an infinite search knows it may send its best move.
+    stop_requested: Arc<AtomicBool>,
+    search_thread: Option<JoinHandle<()>>,
+    move_overhead: Duration,
+    // Polyglot book used when "OwnBook" is on, loaded from the "Book File" option.
+    book: Option<OpeningBook>,
+    own_book: bool,
+    book_selection: BookSelection,
+    // Network loaded from the "NNUEFile" option, evaluated with when "UseNNUE" is on.
+    network: Option<Arc<Network>>,
+    use_nnue: bool,
+}
+
+// Read UCI commands from stdin until "quit" (or the end of the input).
+pub fn run() {
+    let mut uci = Uci::new();
+    for line in io::stdin().lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => break,
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first().copied() {
+            Some("uci") => {
+                println!("id name {ENGINE_NAME}");
+                println!("id author LeandroVandari");
+                println!(
+                    "option name Hash type spin default {DEFAULT_HASH_MEGABYTES} min 1 max 4096"
+                );
+                println!(
+                    "option name Move Overhead type spin default {DEFAULT_MOVE_OVERHEAD} min 0 max 5000"
+                );
+                println!("option name Clear Hash type button");
+                println!("option name OwnBook type check default false");
+                println!("option name Book File type string default <empty>");
+                println!("option name Book Best Move type check default false");
+                println!("option name EvalFile type string default <empty>");
+                println!("option name UseNNUE type check default false");
+                println!("option name NNUEFile type string default <empty>");
+                println!("option name SyzygyPath type string default <empty>");
+                println!(
+                    "option name SyzygyProbeLimit type spin default {} min 0 max {}",
+                    syzygy::MAX_PIECES,
+                    syzygy::MAX_PIECES
+                );
+                println!("option name Syzygy50MoveRule type check default true");
+                println!("uciok");
+            }
+            Some("isready") => {
+                endgame::init();
+                println!("readyok");
+            }
+            Some("ucinewgame") => {
+                uci.wait_for_search();
+                uci.searcher.lock().unwrap().new_game();
+            }
+            Some("setoption") => uci.set_option(&tokens[1..]),
+            Some("position") => uci.set_position(&tokens[1..]),
+            Some("go") => uci.go(&tokens[1..]),
+            Some("stop") => uci.stop_search(),
+            // The ponder search was right, its best move can be sent once it's done.
+            Some("ponderhit") => uci.stop_requested.store(true, Ordering::Relaxed),
+            Some("d") => {
+                println!("{}", uci.board);
+                println!("Fen: {}", uci.board.to_fen());
+            }
+            // Not part of UCI: the static evaluation, with the pawn and piece terms broken down.
+            Some("eval") => {
+                let params = uci.searcher.lock().unwrap().eval_params().clone();
+                print!("{}", pawns::breakdown(&uci.board, &params));
+                print!("{}", pieces::breakdown(&uci.board, &params));
+                let score = eval::evaluate_with(&uci.board, &params);
+                println!("Evaluation: {score}");
+                if let Some(network) = uci.network.as_ref().filter(|_| uci.use_nnue) {
+                    let score = network.evaluate_board(&uci.board);
+                    println!("NNUE evaluation: {score}");
+                }
+            }
+            // Not part of UCI either: run an EPD test suite.
+            Some("epd") => uci.epd(&tokens[1..]),
+            // Nor this one: prove or disprove a mate with the mate solver.
+            Some("findmate") => uci.find_mate(&tokens[1..]),
+            Some("quit") => {
+                uci.stop_search();
+                break;
+            }
+            _ => (),
+        }
+    }
+    uci.stop_search();
+}
+
+impl Uci {
+    fn new() -> Self {
+        let searcher = Searcher::new(SearchConfig::default(), DEFAULT_HASH_MEGABYTES);
+        Uci {
+            board: Board::new(),
+            game_history: Vec::new(),
+            stop: searcher.stop_handle(),
+            searcher: Arc::new(Mutex::new(searcher)),
+            stop_requested: Arc::new(AtomicBool::new(false)),
+            search_thread: None,
+            move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            book: None,
+            own_book: false,
+            book_selection: BookSelection::default(),
+            network: None,
+            use_nnue: false,
+        }
+    }
+
+    fn stop_search(&mut self) {
+        self.stop_requested.store(true, Ordering::Relaxed);
+        if self.search_thread.is_some() {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+        self.wait_for_search();
+    }
+
+    fn wait_for_search(&mut self) {
+        if let Some(handle) = self.search_thread.take() {
+            let _ = handle.join();
+        }
+    }
+
+    // "setoption name <name> value <value>", names can have spaces in them.
+    fn set_option(&mut self, tokens: &[&str]) {
+        let value_index = tokens.iter().position(|token| *token == "value");
+        let name = tokens[1.min(tokens.len())..value_index.unwrap_or(tokens.len())].join(" ");
+        let value = value_index.map(|index| tokens[index + 1..].join(" "));
+        self.wait_for_search();
+        match (name.to_lowercase().as_str(), value) {
+            ("hash", Some(value)) => {
+                if let Ok(megabytes) = value.parse::<usize>() {
+                    self.searcher
+                        .lock()
+                        .unwrap()
+                        .set_hash_size(megabytes.max(1));
+                }
+            }
+            ("move overhead", Some(value)) => {
+                if let Ok(milliseconds) = value.parse() {
+                    self.move_overhead = Duration::from_millis(milliseconds);
+                }
+            }
+            ("clear hash", _) => self.searcher.lock().unwrap().new_game(),
+            ("ownbook", Some(value)) => self.own_book = value == "true",
+            ("book file", Some(path)) => {
+                self.book = None;
+                if !path.is_empty() && path != "<empty>" {
+                    match OpeningBook::open(&path) {
+                        Ok(book) => self.book = Some(book),
+                        Err(error) => println!("info string can't open book {path}: {error}"),
+                    }
+                }
+            }
+            ("evalfile", Some(path)) => {
+                let mut params = EvalParams::default();
+                if !path.is_empty() && path != "<empty>" {
+                    match EvalParams::load(&path) {
+                        Ok(loaded) => params = loaded,
+                        Err(error) => println!("info string can't load {path}: {error}"),
+                    }
+                }
+                self.searcher.lock().unwrap().set_eval_params(params);
+            }
+            ("usennue", Some(value)) => {
+                self.use_nnue = value == "true";
+                self.update_network();
+            }
+            ("nnuefile", Some(path)) => {
+                self.network = None;
+                if !path.is_empty() && path != "<empty>" {
+                    match Network::load(&path) {
+                        Ok(network) => {
+                            println!(
+                                "info string loaded network with {} hidden neurons",
+                                network.hidden_size()
+                            );
+                            self.network = Some(Arc::new(network));
+                        }
+                        Err(error) => println!("info string can't load network {path}: {error}"),
+                    }
+                }
+                self.update_network();
+            }
+            ("syzygypath", Some(path)) => {
+                let mut tablebases = None;
+                if !path.is_empty() && path != "<empty>" {
+                    match Tablebases::open(&path) {
+                        Ok(found) => {
+                            let (wdl, dtz) = found.table_count();
+                            println!(
+                                "info string found {wdl} WDL and {dtz} DTZ tables, up to {} pieces",
+                                found.max_pieces()
+                            );
+                            tablebases = Some(found);
+                        }
+                        Err(error) => println!("info string can't open tablebases {path}: {error}"),
+                    }
+                }
+                self.searcher.lock().unwrap().set_tablebases(tablebases);
+            }
+            ("syzygyprobelimit", Some(value)) => {
+                if let Ok(pieces) = value.parse::<usize>() {
+                    self.searcher.lock().unwrap().config.syzygy_probe_limit = pieces;
+                }
+            }
+            ("syzygy50moverule", Some(value)) => {
+                self.searcher.lock().unwrap().config.syzygy_50_move_rule = value == "true";
+            }
+            ("book best move", Some(value)) => {
+                self.book_selection = if value == "true" {
+                    BookSelection::BestMove
+                } else {
+                    BookSelection::WeightedRandom
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Hand the network to the searcher if it's switched on, falling back to the handcrafted
+    // evaluation without one.
+    fn update_network(&mut self) {
+        if self.use_nnue && self.network.is_none() {
+            println!("info string no network loaded, using the handcrafted evaluation");
+        }
+        let network = self.network.clone().filter(|_| self.use_nnue);
+        self.searcher.lock().unwrap().set_network(network);
+    }
+
+    // "position [startpos | fen <fen>] [moves <move>...]"
+    fn set_position(&mut self, tokens: &[&str]) {
+        let moves_index = tokens.iter().position(|token| *token == "moves");
+        let board = match tokens.first() {
+            Some(&"fen") => {
+                match Board::try_from_fen(&tokens[1..moves_index.unwrap_or(tokens.len())].join(" "))
+                {
+                    Ok(position) => position,
+                    Err(error) => {
+                        println!("info string {error}");
+                        return;
+                    }
+                }
+            }
+            _ => Board::new(),
+        };
+        self.board = board;
+        self.game_history.clear();
+        if let Some(moves_index) = moves_index {
+            for uci_move in &tokens[moves_index + 1..] {
+                match self.board.move_from_uci(uci_move) {
+                    Some((start_square, a_move)) => {
+                        self.game_history.push(zobrist::hash(&self.board));
+                        self.board = self.board.make_move(start_square, a_move);
+                    }
+                    None => {
+                        println!("info string illegal move {uci_move}");
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    fn go(&mut self, tokens: &[&str]) {
+        self.wait_for_search();
+        if tokens.first() == Some(&"perft") {
+            let depth = tokens
+                .get(1)
+                .and_then(|depth| depth.parse().ok())
+                .unwrap_or(1);
+            self.perft(depth);
+            return;
+        }
+
+        let (limits, ponder) = self.parse_limits(tokens);
+        // Book moves are played straight away, unless the GUI wants to be the one to stop us.
+        if self.own_book && !limits.infinite && !ponder {
+            if let Some(book) = &mut self.book {
+                if let Some((start_square, a_move)) =
+                    book.choose_move(&self.board, self.book_selection)
+                {
+                    println!("info string book move");
+                    let color = self.board.side_to_move();
+                    println!("bestmove {}", a_move.to_uci(start_square, color));
+                    return;
+                }
+            }
+        }
+        let board = self.board.clone();
+        let color = board.side_to_move();
+        let searcher = Arc::clone(&self.searcher);
+        let stop_requested = Arc::clone(&self.stop_requested);
+        let game_history = self.game_history.clone();
+        self.stop_requested.store(false, Ordering::Relaxed);
+        self.search_thread = Some(thread::spawn(move || {
+            let mut searcher = searcher.lock().unwrap();
+            searcher.set_game_history(game_history);
+            let result = searcher.search_with_limits(&board, &limits, |info| {
+                println!("{}", format_info(info, color))
+            });
+            // The best move may only be sent once the GUI stops an infinite search.
+            if limits.infinite || ponder {
+                while !stop_requested.load(Ordering::Relaxed) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+            }
+            match result.best_move {
+                Some((start_square, a_move)) => {
+                    println!("bestmove {}", a_move.to_uci(start_square, color))
+                }
+                None => println!("bestmove 0000"),
+            }
+        }));
+    }
+
+    // Turn the arguments of "go" into search limits. Also says whether it's a ponder search.
+    fn parse_limits(&self, tokens: &[&str]) -> (SearchLimits, bool) {
+        let mut limits = SearchLimits::default();
+        let mut ponder = false;
+        let mut clock = [None; 2];
+        let mut increment = [Duration::ZERO; 2];
+        let mut moves_to_go = None;
+        let value = |index: usize| {
+            tokens
+                .get(index + 1)
+                .and_then(|value| value.parse::<u64>().ok())
+        };
+        for (index, token) in tokens.iter().enumerate() {
+            match *token {
+                "wtime" => clock[Color::White as usize] = value(index).map(Duration::from_millis),
+                "btime" => clock[Color::Black as usize] = value(index).map(Duration::from_millis),
+                "winc" => {
+                    increment[Color::White as usize] =
+                        Duration::from_millis(value(index).unwrap_or(0))
+                }
+                "binc" => {
+                    increment[Color::Black as usize] =
+                        Duration::from_millis(value(index).unwrap_or(0))
+                }
+                "movestogo" => moves_to_go = value(index).map(|moves| moves as u32),
+                "depth" => limits.depth = value(index).map(|depth| depth.min(u8::MAX as u64) as u8),
+                "nodes" => limits.nodes = value(index),
+                "movetime" => limits.move_time = value(index).map(Duration::from_millis),
+                "mate" => limits.mate = value(index).map(|moves| moves as u32),
+                "infinite" => limits.infinite = true,
+                "ponder" => ponder = true,
+                _ => (),
+            }
+        }
+        let color = self.board.side_to_move();
+        if let Some(remaining) = clock[color as usize] {
+            limits.time_control = Some(TimeControl {
+                remaining,
+                increment: increment[color as usize],
+                moves_to_go,
+                move_overhead: self.move_overhead,
+            });
+        }
+        (limits, ponder)
+    }
+
+    // "epd <file> [movetime <ms> | nodes <n> | depth <n>] [out <file>]": search every
+    // position of the file, say which ones were solved, and with "out" write them back
+    // annotated with the results. One second per position unless told otherwise.
+    fn epd(&mut self, tokens: &[&str]) {
+        self.wait_for_search();
+        let Some(path) = tokens.first() else {
+            println!("usage: epd <file> [movetime <ms> | nodes <n> | depth <n>] [out <file>]");
+            return;
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(error) => {
+                println!("can't read {path}: {error}");
+                return;
+            }
+        };
+        let mut positions = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match Epd::parse(line) {
+                Ok(epd) => positions.push(epd),
+                Err(error) => println!("line {}: {error}", number + 1),
+            }
+        }
+        let (mut limits, _) = self.parse_limits(&tokens[1..]);
+        limits.time_control = None;
+        if limits.depth.is_none() && limits.nodes.is_none() && limits.move_time.is_none() {
+            limits.move_time = Some(Duration::from_secs(1));
+        }
+
+        let mut annotated = Vec::new();
+        let mut searcher = self.searcher.lock().unwrap();
+        let score = epd::run_suite(&mut searcher, &positions, &limits, |epd, result, solved| {
+            let played = result
+                .best_move
+                .map_or("none".to_string(), |(start, a_move)| {
+                    epd.board.move_to_san(start, a_move)
+                });
+            let expected: Vec<String> = ["bm", "am", "dm"]
+                .iter()
+                .filter_map(|opcode| {
+                    let operands = epd.get(opcode)?;
+                    Some(format!("{opcode} {}", operands.join(" ")))
+                })
+                .collect();
+            let expected = if expected.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", expected.join("; "))
+            };
+            let score = match mate_in(result.score) {
+                Some(moves) => format!("mate {moves}"),
+                None => format!("cp {}", result.score),
+            };
+            println!(
+                "{}: {} {played}{expected}, score {score}, depth {}, nodes {}",
+                epd.id().unwrap_or("?"),
+                match solved {
+                    Some(true) => "solved",
+                    Some(false) => "failed",
+                    None => "searched",
+                },
+                result.depth,
+                result.nodes
+            );
+            annotated.push(epd.annotated(result).to_string());
+        });
+        drop(searcher);
+        println!("{score}");
+
+        if let Some(index) = tokens.iter().position(|token| *token == "out") {
+            let Some(out_path) = tokens.get(index + 1) else {
+                return;
+            };
+            let mut text = annotated.join("\n");
+            text.push('\n');
+            if let Err(error) = fs::write(out_path, text) {
+                println!("can't write {out_path}: {error}");
+            }
+        }
+    }
+
+    // "findmate <moves> [movetime <ms>] [nodes <n>]": the full solution of a mate in that
+    // many moves, or whether there's none or the solver gave up.
+    fn find_mate(&mut self, tokens: &[&str]) {
+        let Some(moves) = tokens.first().and_then(|moves| moves.parse().ok()) else {
+            println!("usage: findmate <moves> [movetime <ms>] [nodes <n>]");
+            return;
+        };
+        let (search_limits, _) = self.parse_limits(&tokens[1..]);
+        let limits = MateLimits {
+            nodes: search_limits.nodes,
+            time: search_limits.move_time,
+        };
+        match mate::find_mate_with_limits(&self.board, moves, &limits) {
+            MateResult::Mate(tree) => {
+                println!("mate in {}", tree.length());
+                print!("{}", tree.to_san(&self.board));
+            }
+            MateResult::NoMate => println!("no mate in {moves}"),
+            MateResult::Timeout => println!("timeout"),
+        }
+    }
+
+    // "go perft <depth>": move counts per root move, then the total.
+    fn perft(&self, depth: u8) {
+        let mut total = 0;
+        for (start_square, a_move) in self.board.legal_moves() {
+            let new_board = self.board.make_move(start_square, a_move);
+            let nodes = perft(&new_board, depth.saturating_sub(1));
+            println!(
+                "{}: {nodes}",
+                a_move.to_uci(start_square, self.board.side_to_move())
+            );
+            total += nodes;
+        }
+        println!("\nNodes searched: {total}");
+    }
+}
+
+// The "info" line for a finished iteration.
+pub fn format_info(info: &SearchInfo, color: Color) -> String {
+    let score = match mate_in(info.score) {
+        Some(moves) => format!("mate {moves}"),
+        None => format!("cp {}", info.score),
+    };
+    format!(
+        "info depth {} seldepth {} score {score} nodes {} nps {} time {} hashfull {} tbhits {} pv {}",
+        info.depth,
+        info.seldepth,
+        info.nodes,
+        info.nps,
+        info.time.as_millis(),
+        info.hashfull,
+        info.tb_hits,
+        pv_to_uci(&info.pv, color)
+    )
+}
+
+// The moves of a line in UCI notation, the first one played by the given color.
+pub fn pv_to_uci(pv: &[BoardMove], color: Color) -> String {
+    let mut color = color;
+    let mut moves = Vec::with_capacity(pv.len());
+    for (start_square, a_move) in pv {
+        moves.push(a_move.to_uci(*start_square, color));
+        color = color.reverse();
+    }
+    moves.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn go_arguments_become_limits() {
+        let uci = Uci::new();
+        let tokens =
+            "wtime 60000 btime 30000 winc 1000 binc 500 movestogo 20 depth 12 nodes 5000 mate 3";
+        let tokens: Vec<&str> = tokens.split_whitespace().collect();
+        let (limits, ponder) = uci.parse_limits(&tokens);
+        assert!(!ponder);
+        assert_eq!(limits.depth, Some(12));
+        assert_eq!(limits.nodes, Some(5000));
+        assert_eq!(limits.mate, Some(3));
+        assert_eq!(
+            limits.time_control,
+            Some(TimeControl {
+                remaining: Duration::from_millis(60000),
+                increment: Duration::from_millis(1000),
+                moves_to_go: Some(20),
+                move_overhead: Duration::from_millis(DEFAULT_MOVE_OVERHEAD),
+            })
+        );
+        let (limits, ponder) = uci.parse_limits(&["ponder", "movetime", "250", "infinite"]);
+        assert!(ponder && limits.infinite);
+        assert_eq!(limits.move_time, Some(Duration::from_millis(250)));
+        assert_eq!(limits.time_control, None);
+    }
+
+    #[test]
+    fn the_clock_of_the_side_to_move_is_used() {
+        let mut uci = Uci::new();
+        uci.set_position(&["startpos", "moves", "e2e4"]);
+        uci.set_option(&["name", "Move", "Overhead", "value", "100"]);
+        let (limits, _) = uci.parse_limits(&["wtime", "60000", "btime", "30000", "binc", "500"]);
+        let control = limits.time_control.unwrap();
+        assert_eq!(control.remaining, Duration::from_millis(30000));
+        assert_eq!(control.increment, Duration::from_millis(500));
+        assert_eq!(control.move_overhead, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn a_ponder_search_waits_for_ponderhit() {
+        let mut uci = Uci::new();
+        uci.go(&["ponder", "movetime", "20"]);
+        // The search itself is long over, but the move isn't ours to play yet.
+        thread::sleep(Duration::from_millis(300));
+        assert!(!uci.search_thread.as_ref().unwrap().is_finished());
+        // What "ponderhit" does.
+        uci.stop_requested.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        while !uci.search_thread.as_ref().unwrap().is_finished() {
+            assert!(start.elapsed() < Duration::from_secs(1));
+            thread::sleep(Duration::from_millis(1));
+        }
+        uci.wait_for_search();
+    }
+
+    #[test]
+    fn stop_ends_an_infinite_search() {
+        let mut uci = Uci::new();
+        uci.go(&["infinite"]);
+        thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        uci.stop_search();
+        assert!(uci.search_thread.is_none());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn info_lines_give_mates_in_moves() {
+        let board = Board::new();
+        let e4 = board.move_from_uci("e2e4").unwrap();
+        let e5 = board.make_move(e4.0, e4.1).move_from_uci("e7e5").unwrap();
+        let info = SearchInfo {
+            depth: 3,
+            seldepth: 5,
+            // Mate in two, three plies away.
+            score: crate::tt::MATE_SCORE - 3,
+            nodes: 1000,
+            nps: 2000,
+            time: Duration::from_millis(500),
+            pv: vec![e4, e5],
+            hashfull: 7,
+            tb_hits: 0,
+        };
+        assert_eq!(
+            format_info(&info, Color::White),
+            "info depth 3 seldepth 5 score mate 2 nodes 1000 nps 2000 time 500 hashfull 7 tbhits 0 pv e2e4 e7e5"
+        );
+    }
+}
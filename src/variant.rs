@@ -0,0 +1,541 @@
+use crate::board::Pockets;
+use crate::fairy::PieceSet;
+use crate::pgn::GameResult;
+use crate::{
+    Board, BoardMove, ChessError, Color, King, Move, Piece, PieceKind, Square, MAX_PIECE_MOVES,
+};
+
+// Chess variants that play on the usual board with the usual pieces but change the rules: which
+// moves are legal, what a move does besides moving the piece, and how the game is won. The
+// board and its move generator stay those of standard chess; a VariantPosition filters and
+// extends the pseudo-legal moves and applies the side effects after Board::make_move.
+//
+// Rules are those of lichess (and so of python-chess and Fairy-Stockfish, which the published
+// perft numbers come from):
+//   three-check    giving a third check wins. The FEN has the checks each side still has to
+//                  give after the en passant field ("3+3"), or the ones given at the end ("+0+0").
+//   king of the hill  a king reaching d4, e4, d5 or e5 wins.
+//   antichess      captures are compulsory, kings are ordinary pieces and pawns may promote
+//                  to one, there's no castling, and losing every piece or being stalemated wins.
+//   atomic         a capture explodes the capturing piece, the captured one and every piece
+//                  but pawns next to the square. Kings can't capture, a move is legal if it
+//                  keeps the mover's king on the board and either blows up the other king or
+//                  leaves the mover's king unattacked, and kings next to each other can't be
+//                  checked. Losing the king loses.
+//   horde          white has no king and only loses by losing every piece. Its pawns on the
+//                  first rank may also advance two squares, without allowing en passant.
+//   racing kings   no move may give check, and the first king on the eighth rank wins. If
+//                  white gets there first black has one move left to draw by getting there too.
+//   crazyhouse     captured pieces change sides and go to the taker's hand, from where they
+//                  can be dropped instead of moving. The board does the bookkeeping itself
+//                  (see Board::pockets), the variant only makes sure there are pockets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Variant {
+    Standard,
+    ThreeCheck,
+    KingOfTheHill,
+    Antichess,
+    Atomic,
+    Horde,
+    RacingKings,
+    Crazyhouse,
+}
+
+pub const VARIANTS: [Variant; 8] = [
+    Variant::Standard,
+    Variant::ThreeCheck,
+    Variant::KingOfTheHill,
+    Variant::Antichess,
+    Variant::Atomic,
+    Variant::Horde,
+    Variant::RacingKings,
+    Variant::Crazyhouse,
+];
+
+// d4, e4, d5 and e5.
+const HILL: [Square; 4] = [
+    Square::from_index(27),
+    Square::from_index(28),
+    Square::from_index(35),
+    Square::from_index(36),
+];
+// Checks that win a three-check game.
+const CHECKS_TO_WIN: u8 = 3;
+
+impl Variant {
+    // The name UCI_Variant uses.
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Antichess => "antichess",
+            Variant::Atomic => "atomic",
+            Variant::Horde => "horde",
+            Variant::RacingKings => "racingkings",
+            Variant::Crazyhouse => "crazyhouse",
+        }
+    }
+
+    // The UCI_Variant name or one of the other names the variant goes by.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase().replace(['-', '_', ' '], "");
+        match name.as_str() {
+            "chess" | "standard" | "normal" => Some(Variant::Standard),
+            "3check" | "threecheck" => Some(Variant::ThreeCheck),
+            "kingofthehill" | "koth" => Some(Variant::KingOfTheHill),
+            "antichess" | "giveaway" => Some(Variant::Antichess),
+            "atomic" => Some(Variant::Atomic),
+            "horde" => Some(Variant::Horde),
+            "racingkings" => Some(Variant::RacingKings),
+            "crazyhouse" | "zh" => Some(Variant::Crazyhouse),
+            _ => None,
+        }
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::Standard | Variant::KingOfTheHill | Variant::Atomic => {
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+            }
+            Variant::ThreeCheck => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1",
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Horde => {
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+            }
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+        }
+    }
+
+    // Does the variant have kings that can be checked?
+    fn has_checks(self) -> bool {
+        self != Variant::Antichess
+    }
+}
+
+// A position of a variant: the board, the side to move, and whatever else the rules need.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VariantPosition {
+    pub variant: Variant,
+    pub board: Board,
+    // Checks given by each side, indexed by Color::index. Only three-check counts them.
+    pub checks_given: [u8; 2],
+}
+
+impl VariantPosition {
+    pub fn start(variant: Variant) -> Self {
+        Self::from_fen(variant, variant.start_fen()).expect("start positions are valid")
+    }
+
+    pub fn from_fen(variant: Variant, fen: &str) -> Result<Self, ChessError> {
+        Self::read_fen(variant, fen, None)
+    }
+
+    // Like from_fen, for a position that plays with the fairy pieces of the set.
+    pub fn from_fen_with_pieces(
+        variant: Variant,
+        fen: &str,
+        pieces: PieceSet,
+    ) -> Result<Self, ChessError> {
+        Self::read_fen(variant, fen, Some(pieces))
+    }
+
+    fn read_fen(variant: Variant, fen: &str, pieces: Option<PieceSet>) -> Result<Self, ChessError> {
+        let mut fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.is_empty() {
+            return Err(ChessError::InvalidFen("empty FEN".to_string()));
+        }
+        let mut checks_given = [0; 2];
+        // "3+3" is what's left to give, "+0+0" what's been given.
+        if let Some(index) = fields.iter().skip(1).position(|field| field.contains('+')) {
+            let field = fields.remove(index + 1);
+            let (given, counts) = match field.strip_prefix('+') {
+                Some(counts) => (true, counts),
+                None => (false, field),
+            };
+            let (white, black) = counts
+                .split_once('+')
+                .and_then(|(white, black)| {
+                    Some((white.parse::<u8>().ok()?, black.parse::<u8>().ok()?))
+                })
+                .ok_or_else(|| ChessError::InvalidFen(format!("bad check counter \"{field}\"")))?;
+            checks_given = if given {
+                [white.min(CHECKS_TO_WIN), black.min(CHECKS_TO_WIN)]
+            } else {
+                [
+                    CHECKS_TO_WIN.saturating_sub(white),
+                    CHECKS_TO_WIN.saturating_sub(black),
+                ]
+            };
+        }
+        let fen = fields.join(" ");
+        let mut board = match pieces {
+            Some(pieces) => Board::try_from_fen_with_pieces(&fen, pieces)?,
+            None => Board::try_from_fen(&fen)?,
+        };
+        if variant == Variant::Crazyhouse && board.pockets.is_none() {
+            board.pockets = Some(Pockets::default());
+        }
+        Ok(VariantPosition {
+            variant,
+            board,
+            checks_given,
+        })
+    }
+
+    pub fn side_to_move(&self) -> Color {
+        self.board.side_to_move()
+    }
+
+    // The FEN, with the checks still to give after the en passant field in three-check.
+    pub fn to_fen(&self) -> String {
+        let fen = self.board.to_fen();
+        if self.variant != Variant::ThreeCheck {
+            return fen;
+        }
+        let mut fields: Vec<String> = fen.split_whitespace().map(str::to_string).collect();
+        fields.insert(
+            4,
+            format!(
+                "{}+{}",
+                CHECKS_TO_WIN - self.checks_given[Color::White.index()],
+                CHECKS_TO_WIN - self.checks_given[Color::Black.index()]
+            ),
+        );
+        fields.join(" ")
+    }
+
+    // Where the color's king is, if it has one. Antichess can have several, the first found is
+    // given.
+    pub fn king_square(&self, color: Color) -> Option<Square> {
+        king_square(&self.board, color)
+    }
+
+    // Is the side to move in check, by the rules of the variant?
+    pub fn is_in_check(&self) -> bool {
+        self.is_attacked_king(&self.board, self.side_to_move())
+    }
+
+    fn is_attacked_king(&self, board: &Board, color: Color) -> bool {
+        if !self.variant.has_checks() {
+            return false;
+        }
+        let Some(king) = king_square(board, color) else {
+            return false;
+        };
+        if self.variant == Variant::Atomic {
+            match king_square(board, color.reverse()) {
+                Some(other_king) if !are_adjacent(king, other_king) => (),
+                _ => return false,
+            }
+        }
+        board.is_attacked(king, color.reverse())
+    }
+
+    // Every move the pieces of the side to move can make, before the king's safety (or the
+    // compulsory captures of antichess) is looked at.
+    pub fn pseudo_legal_moves(&self) -> Vec<BoardMove> {
+        let color = self.side_to_move();
+        let mut moves_list = [None; MAX_PIECE_MOVES];
+        let mut moves = Vec::with_capacity(48);
+        for (index, piece) in self.board.board.iter().enumerate() {
+            let Some(piece) = *piece else {
+                continue;
+            };
+            if piece.color != color {
+                continue;
+            }
+            let start_square = Square::from_index(index as u8);
+            piece.get_moves(&self.board, start_square, &mut moves_list);
+            for a_move in moves_list.into_iter().map_while(|item| item) {
+                match (self.variant, a_move) {
+                    (Variant::Antichess | Variant::RacingKings, Move::CastleKingside)
+                    | (Variant::Antichess | Variant::RacingKings, Move::CastleQueenside) => {
+                        continue
+                    }
+                    (Variant::Atomic, _)
+                        if matches!(
+                            piece,
+                            Piece {
+                                kind: PieceKind::King,
+                                ..
+                            }
+                        ) && self.board.is_capture(a_move) =>
+                    {
+                        continue
+                    }
+                    (
+                        Variant::Antichess,
+                        Move::PawnPromotion(
+                            sqr,
+                            Piece {
+                                kind: PieceKind::Queen,
+                                ..
+                            },
+                        ),
+                    ) => {
+                        moves.push((start_square, a_move));
+                        moves.push((
+                            start_square,
+                            Move::PawnPromotion(sqr, Piece::new(PieceKind::King, color)),
+                        ));
+                        continue;
+                    }
+                    _ => (),
+                }
+                moves.push((start_square, a_move));
+            }
+            // Horde pawns on the first rank can advance two squares too.
+            if self.variant == Variant::Horde
+                && color.is_white()
+                && matches!(
+                    piece,
+                    Piece {
+                        kind: PieceKind::Pawn,
+                        ..
+                    }
+                )
+                && start_square.rank().index() == 0
+            {
+                let next = start_square.up().unwrap();
+                let two_ahead = next.up().unwrap();
+                if self.board.board[next.index()].is_none()
+                    && self.board.board[two_ahead.index()].is_none()
+                {
+                    moves.push((start_square, Move::RegularMove(two_ahead)));
+                }
+            }
+        }
+        self.board.generate_drops(&mut moves);
+        moves
+    }
+
+    // The legal moves of the side to move, none once the game has been won by the rules of the
+    // variant.
+    pub fn legal_moves(&self) -> Vec<BoardMove> {
+        if self.variant_outcome().is_some() {
+            return Vec::new();
+        }
+        self.legal_moves_unchecked()
+    }
+
+    fn legal_moves_unchecked(&self) -> Vec<BoardMove> {
+        let moves = self.pseudo_legal_moves();
+        if self.variant == Variant::Antichess {
+            let (captures, quiet): (Vec<BoardMove>, Vec<BoardMove>) = moves
+                .into_iter()
+                .partition(|(_, a_move)| self.board.is_capture(*a_move));
+            return if captures.is_empty() { quiet } else { captures };
+        }
+        moves
+            .into_iter()
+            .filter(|(start_square, a_move)| self.is_legal(*start_square, *a_move))
+            .collect()
+    }
+
+    // Is the pseudo-legal move legal?
+    fn is_legal(&self, start_square: Square, a_move: Move) -> bool {
+        let color = self.side_to_move();
+        // White has nothing to lose but its pieces in horde, so every move goes.
+        if self.variant == Variant::Horde && color.is_white() {
+            return true;
+        }
+        let passing_square = match (a_move, color) {
+            (Move::CastleKingside, Color::White) => Some(Square::from_index(5)),
+            (Move::CastleKingside, Color::Black) => Some(Square::from_index(61)),
+            (Move::CastleQueenside, Color::White) => Some(Square::from_index(3)),
+            (Move::CastleQueenside, Color::Black) => Some(Square::from_index(59)),
+            _ => None,
+        };
+        if let Some(passing_square) = passing_square {
+            // In atomic the squares next to the other king are safe: nothing can take there
+            // without blowing that king up too.
+            let next_to_king = self.variant == Variant::Atomic
+                && king_square(&self.board, color.reverse())
+                    .is_some_and(|king| are_adjacent(king, passing_square));
+            if self.is_in_check()
+                || (self.board.is_attacked(passing_square, color.reverse()) && !next_to_king)
+            {
+                return false;
+            }
+        }
+        let board = self.board_after(start_square, a_move);
+        match self.variant {
+            Variant::Atomic => {
+                if king_square(&board, color).is_none() {
+                    return false;
+                }
+                if king_square(&board, color.reverse()).is_none() {
+                    return true;
+                }
+                !self.is_attacked_king(&board, color)
+            }
+            Variant::RacingKings => {
+                !self.is_attacked_king(&board, color)
+                    && !self.is_attacked_king(&board, color.reverse())
+            }
+            _ => !self.is_attacked_king(&board, color),
+        }
+    }
+
+    // The board after the move, explosions included.
+    fn board_after(&self, start_square: Square, a_move: Move) -> Board {
+        let mut board = self.board.make_move(start_square, a_move);
+        if self.variant == Variant::Atomic && self.board.is_capture(a_move) {
+            let center = a_move.end_square(self.side_to_move());
+            board.board[center.index()] = None;
+            for sqr in King::get_adjacent_squares(center).into_iter().flatten() {
+                if !matches!(
+                    board.board[sqr.index()],
+                    Some(Piece {
+                        kind: PieceKind::Pawn,
+                        ..
+                    })
+                ) {
+                    board.board[sqr.index()] = None;
+                }
+            }
+            // Rooks and kings blown up on their starting squares take the castling with them.
+            let can_castle = &mut board.can_castle;
+            let has = |sqr: usize, kind: PieceKind, color: Color| {
+                board.board[sqr] == Some(Piece::new(kind, color))
+            };
+            let white_king = has(4, PieceKind::King, Color::White);
+            let black_king = has(60, PieceKind::King, Color::Black);
+            let rights = [
+                white_king && has(7, PieceKind::Rook, Color::White),
+                white_king && has(0, PieceKind::Rook, Color::White),
+                black_king && has(63, PieceKind::Rook, Color::Black),
+                black_king && has(56, PieceKind::Rook, Color::Black),
+            ];
+            can_castle.white_kingside &= rights[0];
+            can_castle.white_queenside &= rights[1];
+            can_castle.black_kingside &= rights[2];
+            can_castle.black_queenside &= rights[3];
+        }
+        board
+    }
+
+    // Play the move, which has to be legal.
+    pub fn make_move(&self, start_square: Square, a_move: Move) -> Self {
+        let board = self.board_after(start_square, a_move);
+        let mut checks_given = self.checks_given;
+        if self.variant == Variant::ThreeCheck
+            && self.is_attacked_king(&board, self.side_to_move().reverse())
+        {
+            checks_given[self.side_to_move().index()] += 1;
+        }
+        VariantPosition {
+            variant: self.variant,
+            board,
+            checks_given,
+        }
+    }
+
+    // The result if the game is over: won by the rules of the variant, or because the side to
+    // move has no moves left. Draws by repetition, the fifty-move rule or material are left to
+    // the caller.
+    pub fn outcome(&self) -> Option<GameResult> {
+        if let Some(result) = self.variant_outcome() {
+            return Some(result);
+        }
+        if !self.legal_moves_unchecked().is_empty() {
+            return None;
+        }
+        Some(if self.variant == Variant::Antichess {
+            win_for(self.side_to_move())
+        } else if self.is_in_check() {
+            win_for(self.side_to_move().reverse())
+        } else {
+            GameResult::Draw
+        })
+    }
+
+    // A result the variant's own winning conditions give, whatever moves there are.
+    fn variant_outcome(&self) -> Option<GameResult> {
+        match self.variant {
+            Variant::Standard | Variant::Antichess | Variant::Crazyhouse => None,
+            Variant::ThreeCheck => [Color::White, Color::Black]
+                .into_iter()
+                .find(|color| self.checks_given[color.index()] >= CHECKS_TO_WIN)
+                .map(win_for),
+            Variant::KingOfTheHill => [Color::White, Color::Black]
+                .into_iter()
+                .find(|color| {
+                    self.king_square(*color)
+                        .is_some_and(|king| HILL.contains(&king))
+                })
+                .map(win_for),
+            Variant::Atomic => [Color::White, Color::Black]
+                .into_iter()
+                .find(|color| self.king_square(*color).is_none())
+                .map(|color| win_for(color.reverse())),
+            Variant::Horde => self
+                .board
+                .board
+                .iter()
+                .flatten()
+                .all(|piece| piece.color == Color::Black)
+                .then_some(GameResult::BlackWins),
+            Variant::RacingKings => {
+                let on_last_rank = |color| {
+                    self.king_square(color)
+                        .is_some_and(|king| king.rank().index() == 7)
+                };
+                match (on_last_rank(Color::White), on_last_rank(Color::Black)) {
+                    (false, false) => None,
+                    (true, true) => Some(GameResult::Draw),
+                    (false, true) => Some(GameResult::BlackWins),
+                    // Black gets to move once more if it can reach the last rank with it.
+                    (true, false) => {
+                        let can_draw = self.side_to_move() == Color::Black
+                            && self
+                                .legal_moves_unchecked()
+                                .iter()
+                                .any(|(start_square, a_move)| {
+                                    Some(*start_square) == self.king_square(Color::Black)
+                                        && a_move.end_square(Color::Black).rank().index() == 7
+                                });
+                        (!can_draw).then_some(GameResult::WhiteWins)
+                    }
+                }
+            }
+        }
+    }
+
+    // Count the leaf nodes of the legal move tree.
+    pub fn perft(&self, depth: u8) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        moves
+            .into_iter()
+            .map(|(start_square, a_move)| self.make_move(start_square, a_move).perft(depth - 1))
+            .sum()
+    }
+}
+
+fn win_for(color: Color) -> GameResult {
+    if color.is_white() {
+        GameResult::WhiteWins
+    } else {
+        GameResult::BlackWins
+    }
+}
+
+fn king_square(board: &Board, color: Color) -> Option<Square> {
+    let is_king =
+        |sqr: Square| board.board[sqr.index()] == Some(Piece::new(PieceKind::King, color));
+    board
+        .king_square(color)
+        .or_else(|| Square::all().find(|sqr| is_king(*sqr)))
+}
+
+fn are_adjacent(square: Square, other: Square) -> bool {
+    square.distance(other) == 1
+}
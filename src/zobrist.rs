@@ -0,0 +1,131 @@
+use crate::board::{CanEnPassant, Pockets};
+use crate::fairy::MAX_FAIRY_PIECES;
+use crate::{Board, Color, Piece, PieceKind};
+
+// Random keys for every (piece, square) pair, castling right, en passant file and the side to move,
+// and for the number of pieces of each kind in hand in drop variants.
+// They're generated at compile time from a fixed seed, so hashes are the same on every run.
+static PIECE_KEYS: [[u64; 64]; 12] = generate_square_keys::<12>(0x1234_5678_9ABC_DEF0);
+// Indexed by fairy piece id, white ones first.
+static FAIRY_KEYS: [[u64; 64]; 2 * MAX_FAIRY_PIECES] =
+    generate_square_keys::<{ 2 * MAX_FAIRY_PIECES }>(0x8EBC_6AF0_9C88_C6E3);
+static CASTLING_KEYS: [u64; 4] = generate_keys::<4>(0x2545_F491_4F6C_DD1D);
+static EN_PASSANT_KEYS: [u64; 8] = generate_keys::<8>(0x9E37_79B9_7F4A_7C15);
+static WHITE_TO_MOVE_KEY: u64 = generate_keys::<1>(0xD1B5_4A32_D192_ED03)[0];
+// Indexed by color, kind (pawn to queen) and count, 17 counts per kind.
+static POCKET_KEYS: [u64; 170] = generate_keys::<170>(0xA076_1D64_78BD_642F);
+
+// SplitMix64, a small generator that's good enough for hash keys.
+const fn split_mix(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    (state, z ^ (z >> 31))
+}
+
+const fn generate_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+    while i < N {
+        let (new_state, key) = split_mix(state);
+        state = new_state;
+        keys[i] = key;
+        i += 1;
+    }
+    keys
+}
+
+const fn generate_square_keys<const N: usize>(seed: u64) -> [[u64; 64]; N] {
+    let mut keys = [[0; 64]; N];
+    let mut state = seed;
+    let mut piece = 0;
+    while piece < N {
+        let mut square = 0;
+        while square < 64 {
+            let (new_state, key) = split_mix(state);
+            state = new_state;
+            keys[piece][square] = key;
+            square += 1;
+        }
+        piece += 1;
+    }
+    keys
+}
+
+// Index of a piece in the key tables: white pieces first, then black ones.
+fn piece_key_index(piece_kind: usize, color: Color) -> usize {
+    piece_kind + if color.is_white() { 0 } else { 6 }
+}
+
+// Zobrist hash of the position, side to move included.
+pub fn hash(board: &Board) -> u64 {
+    let color = board.side_to_move();
+    let mut key = 0;
+    for (square, piece) in board.board.iter().enumerate() {
+        if let Some(piece) = piece {
+            key ^= match piece {
+                Piece {
+                    kind: PieceKind::Fairy(id),
+                    ..
+                } => {
+                    let index = *id as usize
+                        + if piece.color.is_white() {
+                            0
+                        } else {
+                            MAX_FAIRY_PIECES
+                        };
+                    FAIRY_KEYS[index][square]
+                }
+                _ => PIECE_KEYS[piece_key_index(piece.kind.index(), piece.color)][square],
+            };
+        }
+    }
+    let can_castle = board.can_castle;
+    for (index, right) in [
+        can_castle.white_kingside,
+        can_castle.white_queenside,
+        can_castle.black_kingside,
+        can_castle.black_queenside,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if right {
+            key ^= CASTLING_KEYS[index];
+        }
+    }
+    if let CanEnPassant::Yes(square) = board.can_en_passant {
+        key ^= EN_PASSANT_KEYS[square.file().index() as usize];
+    }
+    if let Some(pockets) = board.pockets {
+        for color in [Color::White, Color::Black] {
+            for (slot, kind) in Pockets::KINDS.into_iter().enumerate() {
+                let count = pockets.count(color, kind).min(16) as usize;
+                key ^= POCKET_KEYS[(color.index() * 5 + slot) * 17 + count];
+            }
+        }
+    }
+    if color.is_white() {
+        key ^= WHITE_TO_MOVE_KEY;
+    }
+    key
+}
+
+// Zobrist hash of just the pawns, for the pawn hash table.
+pub fn pawn_hash(board: &Board) -> u64 {
+    let mut key = 0;
+    for (square, piece) in board.board.iter().enumerate() {
+        if let Some(
+            piece @ Piece {
+                kind: PieceKind::Pawn,
+                ..
+            },
+        ) = piece
+        {
+            key ^= PIECE_KEYS[piece_key_index(0, piece.color)][square];
+        }
+    }
+    key
+}